@@ -0,0 +1,663 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::gltf::Gltf;
+use crate::object_value::ObjectValue;
+use crate::packed_bit_vector::PackedBitVector;
+use crate::scene::{self, SceneNode};
+use crate::Result;
+
+const TRANSFORM_CLASS_ID: i32 = 4;
+const AVATAR_CLASS_ID: i32 = 90;
+
+/// transform attributes of generic bindings, with the curves each one spans
+const TRANSFORM_ATTRIBUTES: [(&str, &[&str]); 4] = [
+    ("m_LocalPosition", &["x", "y", "z"]),
+    ("m_LocalRotation", &["x", "y", "z", "w"]),
+    ("m_LocalScale", &["x", "y", "z"]),
+    ("localEulerAnglesRaw", &["x", "y", "z"]),
+];
+
+/// where a curve was read from
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveSource {
+    Editor,         // m_*Curves, kept for legacy clips and in the editor
+    Compressed,     // m_CompressedRotationCurves of legacy clips
+    Streamed,       // m_MuscleClip, hermite keys
+    Dense,          // m_MuscleClip, sampled at a fixed rate
+    Constant,       // m_MuscleClip, one value for the whole clip
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Key {
+    pub time: f32,
+    pub value: f32,
+    pub in_slope: f32,      // infinite for stepped keys
+    pub out_slope: f32,
+}
+
+/// one scalar curve, vector properties split by component ("m_LocalPosition.x")
+#[derive(Serialize, Clone, Debug)]
+pub struct Curve {
+    pub path: String,       // transform path relative to the animated root
+    pub attribute: String,
+    pub class_id: i32,
+    pub source: CurveSource,
+    pub keys: Vec<Key>,
+}
+
+impl Curve {
+    /// hermite interpolation of the keys, clamped outside of them
+    pub fn evaluate(&self, t: f32) -> Option<f32> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if t <= first.time {
+            return Some(first.value);
+        }
+        if t >= last.time {
+            return Some(last.value);
+        }
+        let i = self.keys.partition_point(|k| k.time <= t) - 1;
+        let (k0, k1) = (&self.keys[i], &self.keys[i + 1]);
+        let dt = k1.time - k0.time;
+        if !k0.out_slope.is_finite() || !k1.in_slope.is_finite() || dt <= 0.0 {
+            return Some(k0.value);
+        }
+        let s = (t - k0.time) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        Some((2.0 * s3 - 3.0 * s2 + 1.0) * k0.value
+            + (s3 - 2.0 * s2 + s) * dt * k0.out_slope
+            + (-2.0 * s3 + 3.0 * s2) * k1.value
+            + (s3 - s2) * dt * k1.in_slope)
+    }
+}
+
+/// AnimationClip with editor and runtime curves
+#[derive(Serialize, Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub legacy: bool,
+    pub sample_rate: f32,
+    pub wrap_mode: i64,
+    pub start: f32,
+    pub stop: f32,
+    pub curves: Vec<Curve>,
+}
+
+impl AnimationClip {
+    pub const CLASS_ID: i32 = 74;
+
+    /// reads a clip; binding hashes of the muscle clip are named from `paths`
+    pub fn from_value(v: &ObjectValue, paths: &HashMap<u32, String>) -> Result<AnimationClip> {
+        let mut clip = AnimationClip{
+            name: v.string("m_Name")?.to_string(),
+            legacy: v.flag("m_Legacy"),
+            sample_rate: v.get("m_SampleRate").and_then(|r| r.as_f64()).unwrap_or(60.0) as f32,
+            wrap_mode: v.get("m_WrapMode").and_then(|w| w.as_i64()).unwrap_or(0),
+            start: 0.0,
+            stop: 0.0,
+            curves: Vec::new(),
+        };
+        clip.read_editor_curves(v)?;
+        if let Some(muscle) = v.get("m_MuscleClip") {
+            clip.start = muscle.get("m_StartTime").and_then(|t| t.as_f64()).unwrap_or(0.0) as f32;
+            clip.stop = muscle.get("m_StopTime").and_then(|t| t.as_f64()).unwrap_or(0.0) as f32;
+            if let Some(bindings) = v.get("m_ClipBindingConstant") {
+                let names = clip.attribute_names();
+                clip.read_muscle_clip(muscle, bindings, paths, &names)?;
+            }
+        }
+        if clip.stop <= clip.start {
+            clip.stop = clip.curves.iter().filter_map(|c| c.keys.last()).map(|k| k.time).fold(clip.start, f32::max);
+        }
+        Ok(clip)
+    }
+
+    fn read_editor_curves(&mut self, v: &ObjectValue) -> Result<()> {
+        for (list, attribute, components) in [
+            ("m_PositionCurves", "m_LocalPosition", &["x", "y", "z"][..]),
+            ("m_RotationCurves", "m_LocalRotation", &["x", "y", "z", "w"][..]),
+            ("m_ScaleCurves", "m_LocalScale", &["x", "y", "z"][..]),
+            ("m_EulerCurves", "localEulerAnglesRaw", &["x", "y", "z"][..]),
+        ] {
+            for c in v.array(list) {
+                let path = c.string("path")?;
+                for component in components {
+                    self.curves.push(Curve{
+                        path: path.to_string(),
+                        attribute: format!("{}.{}", attribute, component),
+                        class_id: TRANSFORM_CLASS_ID,
+                        source: CurveSource::Editor,
+                        keys: keys(c.field("curve")?, Some(component))?,
+                    });
+                }
+            }
+        }
+        for c in v.array("m_FloatCurves") {
+            self.curves.push(Curve{
+                path: c.string("path")?.to_string(),
+                attribute: c.string("attribute")?.to_string(),
+                class_id: c.int("classID")? as i32,
+                source: CurveSource::Editor,
+                keys: keys(c.field("curve")?, None)?,
+            });
+        }
+        for c in v.array("m_CompressedRotationCurves") {
+            self.read_compressed_rotation(c)?;
+        }
+        Ok(())
+    }
+
+    /// legacy compressed rotations: times in hundredths of a second since the previous key,
+    /// packed quaternions and slopes
+    fn read_compressed_rotation(&mut self, c: &ObjectValue) -> Result<()> {
+        let times: Vec<f32> = PackedBitVector::from_value(c.field("m_Times")?)?.ints()?.iter()
+            .scan(0u64, |sum, delta| {
+                *sum += *delta as u64;
+                Some(*sum as f32 * 0.01)
+            })
+            .collect();
+        let values = PackedBitVector::from_value(c.field("m_Values")?)?.quats()?;
+        let slopes = PackedBitVector::from_value(c.field("m_Slopes")?)?.floats()?;
+        if values.len() < times.len() || slopes.len() < times.len() * 8 {
+            return Err("compressed rotation curve with missing keys".into());
+        }
+        for (component, name) in ["x", "y", "z", "w"].iter().enumerate() {
+            self.curves.push(Curve{
+                path: c.string("m_Path")?.to_string(),
+                attribute: format!("m_LocalRotation.{}", name),
+                class_id: TRANSFORM_CLASS_ID,
+                source: CurveSource::Compressed,
+                keys: times.iter().enumerate()
+                    .map(|(j, time)| Key{
+                        time: *time,
+                        value: values[j][component],
+                        in_slope: slopes[j * 8 + component],
+                        out_slope: slopes[j * 8 + 4 + component],
+                    })
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    /// CRC32 of the attribute names known from editor curves
+    fn attribute_names(&self) -> HashMap<u32, String> {
+        self.curves.iter()
+            .filter(|c| c.class_id != TRANSFORM_CLASS_ID)
+            .map(|c| (crc32fast::hash(c.attribute.as_bytes()), c.attribute.clone()))
+            .collect()
+    }
+
+    /// streamed, dense and constant curves of m_MuscleClip, in that order of curve index
+    fn read_muscle_clip(&mut self, muscle: &ObjectValue, bindings: &ObjectValue, paths: &HashMap<u32, String>,
+            names: &HashMap<u32, String>) -> Result<()> {
+        let slots = curve_slots(bindings, paths, names)?;
+        let clip = muscle.field("m_Clip")?.field("data")?;
+        let mut curves: Vec<(CurveSource, Vec<Key>)> = Vec::new();
+
+        if let Some(streamed) = clip.get("m_StreamedClip") {
+            let count = streamed.int("curveCount")? as usize;
+            curves.extend((0..count).map(|_| (CurveSource::Streamed, Vec::new())));
+            read_streamed(streamed, &mut curves)?;
+        }
+        if let Some(dense) = clip.get("m_DenseClip") {
+            let count = dense.int("m_CurveCount")? as usize;
+            let frames = dense.int("m_FrameCount")? as usize;
+            let rate = dense.float("m_SampleRate")? as f32;
+            let begin = dense.float("m_BeginTime")? as f32;
+            let samples = floats(dense.field("m_SampleArray")?);
+            if samples.len() < frames * count {
+                return Err(format!("dense clip has {} of {} samples", samples.len(), frames * count).into());
+            }
+            for c in 0..count {
+                let values: Vec<f32> = (0..frames).map(|f| samples[f * count + c]).collect();
+                curves.push((CurveSource::Dense, sampled_keys(&values, begin, rate)));
+            }
+        }
+        if let Some(constant) = clip.get("m_ConstantClip") {
+            for value in floats(constant.field("data")?) {
+                let key = |time| Key{ time, value, in_slope: 0.0, out_slope: 0.0 };
+                curves.push((CurveSource::Constant, vec![key(self.start), key(self.stop)]));
+            }
+        }
+
+        if curves.len() > slots.len() {
+            warn!("{}: {} curves but {} bindings", self.name, curves.len(), slots.len());
+        }
+        for ((source, keys), (path, attribute, class_id)) in curves.into_iter().zip(slots) {
+            self.curves.push(Curve{ path, attribute, class_id, source, keys });
+        }
+        Ok(())
+    }
+}
+
+/// path, attribute and class of every float curve of a ClipBindingConstant
+fn curve_slots(bindings: &ObjectValue, paths: &HashMap<u32, String>, names: &HashMap<u32, String>)
+        -> Result<Vec<(String, String, i32)>> {
+    let mut slots = Vec::new();
+    for b in bindings.array("genericBindings") {
+        if b.flag("isPPtrCurve") {
+            continue;
+        }
+        let hash = b.int("path")? as u32;
+        let path = paths.get(&hash).cloned().unwrap_or_else(|| format!("path_{:08x}", hash));
+        let attribute = b.int("attribute")? as u32;
+        // typeID since 2017, classID before
+        let class_id = b.get("typeID").or_else(|| b.get("classID")).and_then(|c| c.as_i64()).unwrap_or(0) as i32;
+        match (class_id, TRANSFORM_ATTRIBUTES.get((attribute as usize).wrapping_sub(1))) {
+            (TRANSFORM_CLASS_ID, Some((name, components))) => for component in components.iter() {
+                slots.push((path.clone(), format!("{}.{}", name, component), class_id));
+            },
+            _ => slots.push((path, names.get(&attribute).cloned().unwrap_or_else(|| format!("0x{:08x}", attribute)), class_id)),
+        }
+    }
+    Ok(slots)
+}
+
+/// StreamedClip frames: time, key count, then per key the curve index and cubic coefficients
+///
+/// The first frame sits at -FLT_MAX and the last one at infinity; neither holds keys.
+fn read_streamed(streamed: &ObjectValue, curves: &mut [(CurveSource, Vec<Key>)]) -> Result<()> {
+    let words: Vec<u32> = match streamed.field("data")? {
+        ObjectValue::Array(a) => a.iter().filter_map(|w| w.as_u64()).map(|w| w as u32).collect(),
+        ObjectValue::Bytes(b) => b.chunks_exact(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect(),
+        _ => return Err("StreamedClip data is not an array".into()),
+    };
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos + 2 <= words.len() {
+        let time = f32::from_bits(words[pos]);
+        let count = words[pos + 1] as usize;
+        pos += 2;
+        let end = pos + count * 5;
+        let keys = words.get(pos..end).ok_or("StreamedClip frame truncated")?;
+        frames.push((time, keys.chunks_exact(5)
+            .map(|k| (k[0] as usize, [f32::from_bits(k[1]), f32::from_bits(k[2]), f32::from_bits(k[3]), f32::from_bits(k[4])]))
+            .collect::<Vec<(usize, [f32; 4])>>()));
+        pos = end;
+    }
+    let mut previous: HashMap<usize, (f32, [f32; 4])> = HashMap::new();
+    let frame_count = frames.len();
+    for (f, (time, keys)) in frames.into_iter().enumerate() {
+        for (index, coeff) in keys {
+            let in_slope = match previous.get(&index) {
+                Some((t0, c)) if t0.is_finite() && *t0 > f32::MIN => {
+                    let dt = time - t0;
+                    3.0 * c[0] * dt * dt + 2.0 * c[1] * dt + c[2]
+                },
+                _ => coeff[2],
+            };
+            previous.insert(index, (time, coeff));
+            if f == 0 || f + 1 == frame_count {
+                continue;
+            }
+            let curve = curves.get_mut(index).ok_or_else(|| format!("streamed key for curve {}", index))?;
+            curve.1.push(Key{ time, value: coeff[3], in_slope, out_slope: coeff[2] });
+        }
+    }
+    Ok(())
+}
+
+/// keys of evenly sampled values, slopes taken from the neighbours
+fn sampled_keys(values: &[f32], begin: f32, rate: f32) -> Vec<Key> {
+    let dt = 1.0 / rate;
+    let last = values.len().saturating_sub(1);
+    let slope = |a: usize| match last {
+        0 => 0.0,
+        _ => (values[a + 1] - values[a]) / dt,
+    };
+    (0..values.len())
+        .map(|i| Key{
+            time: begin + i as f32 * dt,
+            value: values[i],
+            in_slope: slope(i.max(1) - 1),
+            out_slope: slope(i.min(last.max(1) - 1)),
+        })
+        .collect()
+}
+
+/// keys of an AnimationCurve, taking `component` of vector values
+fn keys(curve: &ObjectValue, component: Option<&str>) -> Result<Vec<Key>> {
+    let read = |k: &ObjectValue, name: &str| -> Result<f32> {
+        Ok(match component {
+            Some(c) => k.field(name)?.float(c)?,
+            None => k.float(name)?,
+        } as f32)
+    };
+    curve.array("m_Curve").iter()
+        .map(|k| Ok(Key{
+            time: k.float("time")? as f32,
+            value: read(k, "value")?,
+            in_slope: read(k, "inSlope")?,
+            out_slope: read(k, "outSlope")?,
+        }))
+        .collect()
+}
+
+fn floats(v: &ObjectValue) -> Vec<f32> {
+    match v {
+        ObjectValue::Array(a) => a.iter().filter_map(|f| f.as_f64()).map(|f| f as f32).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// transform paths by CRC32, from Avatars and from every hierarchy of `bundles`
+pub fn path_table(bundles: &[AssetBundle]) -> HashMap<u32, String> {
+    fn walk(node: &SceneNode, prefix: &str, table: &mut HashMap<u32, String>) {
+        for child in &node.children {
+            let path = match prefix.is_empty() {
+                true => child.name.clone(),
+                false => format!("{}/{}", prefix, child.name),
+            };
+            walk(child, &path, table);
+            table.insert(crc32fast::hash(path.as_bytes()), path);
+        }
+    }
+    let mut table = HashMap::new();
+    table.insert(0, String::new());
+    for bundle in bundles {
+        for root in scene::roots(bundles, bundle) {
+            walk(&root, "", &mut table);
+        }
+        for asset in bundle.assets() {
+            for obj in asset.objects().iter().filter(|o| asset.class_id(o) == Some(AVATAR_CLASS_ID)) {
+                let avatar = match asset.object_value(obj) {
+                    Ok(a) => a,
+                    Err(e) => {
+                        warn!("avatar {}: {}", obj.path_id, e);
+                        continue;
+                    },
+                };
+                for pair in avatar.array("m_TOS") {
+                    if let (Some(hash), Some(path)) = (pair.get("first").and_then(|h| h.as_u64()), pair.get("second").and_then(|p| p.as_str())) {
+                        table.insert(hash as u32, path.to_string());
+                    }
+                }
+            }
+        }
+    }
+    table
+}
+
+/// transform curves of one path, by component
+#[derive(Default)]
+struct TransformCurves<'c> {
+    position: [Option<&'c Curve>; 3],
+    rotation: [Option<&'c Curve>; 4],
+    euler: [Option<&'c Curve>; 3],
+    scale: [Option<&'c Curve>; 3],
+}
+
+/// glTF animation of the transform curves, sampled at the clip rate on nodes named after the paths
+pub fn to_glb(clip: &AnimationClip) -> Option<Vec<u8>> {
+    let mut targets: Vec<(&str, TransformCurves)> = Vec::new();
+    for curve in clip.curves.iter().filter(|c| c.class_id == TRANSFORM_CLASS_ID) {
+        // attributes outside the transform channels, such as unknown `0x%08x` slots, are skipped
+        let (property, c) = match curve.attribute.rsplit_once('.')
+            .and_then(|(p, component)| Some((p, ["x", "y", "z", "w"].iter().position(|n| *n == component)?))) {
+            Some(pc) => pc,
+            None => continue,
+        };
+        let i = match targets.iter().position(|(p, _)| *p == curve.path) {
+            Some(i) => i,
+            None => {
+                targets.push((&curve.path, TransformCurves::default()));
+                targets.len() - 1
+            },
+        };
+        let t = &mut targets[i].1;
+        match (property, c) {
+            ("m_LocalPosition", 0..=2) => t.position[c] = Some(curve),
+            ("m_LocalRotation", _) => t.rotation[c] = Some(curve),
+            ("localEulerAnglesRaw", 0..=2) => t.euler[c] = Some(curve),
+            ("m_LocalScale", 0..=2) => t.scale[c] = Some(curve),
+            _ => {},
+        }
+    }
+    if targets.is_empty() {
+        return None;
+    }
+
+    let mut gltf = Gltf::new();
+    let root = gltf.add_node(json!({ "name": clip.name }));
+    gltf.add_root(root);
+    let mut nodes: HashMap<String, usize> = HashMap::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    nodes.insert(String::new(), root);
+    for (path, _) in &targets {
+        let mut parent = root;
+        let mut prefix = String::new();
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            parent = match nodes.get(&prefix) {
+                Some(n) => *n,
+                None => {
+                    let n = gltf.add_node(json!({ "name": part }));
+                    nodes.insert(prefix.clone(), n);
+                    children.entry(parent).or_default().push(n);
+                    n
+                },
+            };
+        }
+    }
+    for (node, c) in children {
+        gltf.node_mut(node)["children"] = json!(c);
+    }
+
+    let rate = match clip.sample_rate > 0.0 {
+        true => clip.sample_rate,
+        false => 30.0,
+    };
+    let frames = ((clip.stop - clip.start) * rate).round().max(0.0) as usize + 1;
+    let times: Vec<f32> = (0..frames).map(|i| (clip.start + i as f32 / rate).min(clip.stop.max(clip.start))).collect();
+    let input = gltf.floats(&times, 1, true, None);
+    let sample = |curves: &[Option<&Curve>], defaults: &[f32], t: f32| -> Vec<f32> {
+        curves.iter().zip(defaults)
+            .map(|(c, d)| c.and_then(|c| c.evaluate(t)).unwrap_or(*d))
+            .collect()
+    };
+
+    let mut samplers = Vec::new();
+    let mut channels = Vec::new();
+    for (path, t) in &targets {
+        let node = nodes[*path];
+        let mut outputs: Vec<(&str, Vec<f32>, usize)> = Vec::new();
+        if t.position.iter().any(|c| c.is_some()) {
+            let values = times.iter()
+                .flat_map(|time| {
+                    let p = sample(&t.position, &[0.0; 3], *time);
+                    [-p[0], p[1], p[2]]
+                })
+                .collect();
+            outputs.push(("translation", values, 3));
+        }
+        if t.rotation.iter().any(|c| c.is_some()) || t.euler.iter().any(|c| c.is_some()) {
+            let values = times.iter()
+                .flat_map(|time| {
+                    let q = match t.rotation.iter().any(|c| c.is_some()) {
+                        true => normalize(sample(&t.rotation, &[0.0, 0.0, 0.0, 1.0], *time)),
+                        false => euler_to_quat(sample(&t.euler, &[0.0; 3], *time)),
+                    };
+                    [q[0], -q[1], -q[2], q[3]]
+                })
+                .collect();
+            outputs.push(("rotation", values, 4));
+        }
+        if t.scale.iter().any(|c| c.is_some()) {
+            let values = times.iter().flat_map(|time| sample(&t.scale, &[1.0; 3], *time)).collect();
+            outputs.push(("scale", values, 3));
+        }
+        for (target, values, width) in outputs {
+            let output = gltf.floats(&values, width, false, None);
+            samplers.push(json!({ "input": input, "output": output, "interpolation": "LINEAR" }));
+            channels.push(json!({ "sampler": samplers.len() - 1, "target": { "node": node, "path": target } }));
+        }
+    }
+    gltf.add_animation(json!({ "name": clip.name, "samplers": samplers, "channels": channels }));
+    Some(gltf.to_glb())
+}
+
+fn normalize(q: Vec<f32>) -> [f32; 4] {
+    let l = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    match l > 0.0 {
+        true => [q[0] / l, q[1] / l, q[2] / l, q[3] / l],
+        false => [0.0, 0.0, 0.0, 1.0],
+    }
+}
+
+/// Unity euler angles in degrees, applied z, then x, then y
+fn euler_to_quat(e: Vec<f32>) -> [f32; 4] {
+    let half = |d: f32| (d.to_radians() * 0.5).sin_cos();
+    let ((sx, cx), (sy, cy), (sz, cz)) = (half(e[0]), half(e[1]), half(e[2]));
+    let mul = |a: [f32; 4], b: [f32; 4]| [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ];
+    mul(mul([0.0, sy, 0.0, cy], [sx, 0.0, 0.0, cx]), [0.0, 0.0, sz, cz])
+}
+
+/// writes every AnimationClip of `bundles[0]` into `dst` as JSON curves or glTF animations;
+/// binding hashes are resolved with the Avatars and hierarchies of all `bundles`
+pub fn export(bundles: &[AssetBundle], dst: &Path, gltf: bool) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let paths = path_table(bundles);
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(AnimationClip::CLASS_ID) {
+                continue;
+            }
+            let clip = match asset.object_value(obj).and_then(|v| AnimationClip::from_value(&v, &paths)) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let (bytes, ext) = match gltf {
+                true => match to_glb(&clip) {
+                    Some(glb) => (glb, "glb"),
+                    None => {
+                        warn!("{}: no transform curves", clip.name);
+                        continue;
+                    },
+                },
+                false => (serde_json::to_vec_pretty(&clip)?, "json"),
+            };
+            let path = export::unique_path(dst, &clip.name, ext);
+            fs::write(&path, bytes)?;
+            info!("{} curves {:?}", clip.curves.len(), path);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(fields: &[(&str, ObjectValue)]) -> ObjectValue {
+        ObjectValue::Struct(fields.iter().map(|(n, v)| (n.to_string(), v.clone())).collect())
+    }
+
+    fn packed(count: u64, bit_size: u64, data: Vec<u8>) -> ObjectValue {
+        value(&[
+            ("m_NumItems", ObjectValue::UInt(count)),
+            ("m_Range", ObjectValue::Float(1.0)),
+            ("m_Start", ObjectValue::Float(0.0)),
+            ("m_BitSize", ObjectValue::UInt(bit_size)),
+            ("m_Data", ObjectValue::Bytes(data)),
+        ])
+    }
+
+    #[test]
+    fn compressed_rotation_times_accumulate() {
+        // deltas of 0, 50 and 25 hundredths, 32 bit packed quaternions and constant slopes
+        let c = value(&[
+            ("m_Path", ObjectValue::String("root/arm".to_string())),
+            ("m_Times", packed(3, 8, vec![0, 50, 25])),
+            ("m_Values", packed(3, 0, vec![0; 12])),
+            ("m_Slopes", packed(24, 0, Vec::new())),
+        ]);
+        let mut clip = AnimationClip{ name: "clip".to_string(), legacy: true, sample_rate: 60.0, wrap_mode: 0,
+            start: 0.0, stop: 0.0, curves: Vec::new() };
+        clip.read_compressed_rotation(&c).unwrap();
+        assert_eq!(clip.curves.len(), 4);
+        assert_eq!(clip.curves[3].attribute, "m_LocalRotation.w");
+        let times: Vec<f32> = clip.curves[0].keys.iter().map(|k| k.time).collect();
+        assert_eq!(times, vec![0.0, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn streamed_frames() {
+        let mut words = Vec::new();
+        for (time, keys) in [
+            (f32::MIN, vec![(0, [0.0, 0.0, 1.0, 5.0])]),
+            (0.0, vec![(0, [0.0, 0.0, 2.0, 5.0]), (1, [0.0, 0.0, 0.0, 3.0])]),
+            (1.0, vec![(0, [0.0, 0.0, 0.0, 7.0])]),
+            (f32::INFINITY, vec![]),
+        ] {
+            words.extend([time.to_bits(), keys.len() as u32]);
+            for (index, coeff) in keys {
+                words.push(index);
+                words.extend(coeff.iter().map(|c: &f32| c.to_bits()));
+            }
+        }
+        let streamed = value(&[("data", ObjectValue::Bytes(words.iter().flat_map(|w| w.to_le_bytes()).collect()))]);
+        let mut curves = vec![(CurveSource::Streamed, Vec::new()), (CurveSource::Streamed, Vec::new())];
+        read_streamed(&streamed, &mut curves).unwrap();
+        let keys: Vec<(f32, f32, f32, f32)> = curves[0].1.iter().map(|k| (k.time, k.value, k.in_slope, k.out_slope)).collect();
+        assert_eq!(keys, vec![(0.0, 5.0, 2.0, 2.0), (1.0, 7.0, 2.0, 0.0)]);
+        assert_eq!(curves[1].1.len(), 1);
+
+        let truncated = value(&[("data", ObjectValue::Array(vec![ObjectValue::UInt(0), ObjectValue::UInt(2)]))]);
+        assert!(read_streamed(&truncated, &mut curves).is_err());
+    }
+
+    #[test]
+    fn dense_and_constant_curves() {
+        let binding = |attribute: i64, class_id: i64| value(&[
+            ("path", ObjectValue::UInt(7)),
+            ("attribute", ObjectValue::UInt(attribute as u64)),
+            ("typeID", ObjectValue::Int(class_id)),
+            ("isPPtrCurve", ObjectValue::Bool(false)),
+        ]);
+        let bindings = value(&[("genericBindings", ObjectValue::Array(vec![binding(1, 4), binding(0x1234, 114)]))]);
+        let dense = value(&[
+            ("m_CurveCount", ObjectValue::UInt(3)),
+            ("m_FrameCount", ObjectValue::UInt(2)),
+            ("m_SampleRate", ObjectValue::Float(2.0)),
+            ("m_BeginTime", ObjectValue::Float(0.0)),
+            ("m_SampleArray", ObjectValue::Array([0.0, 1.0, 2.0, 2.0, 3.0, 4.0].iter().map(|f| ObjectValue::Float(*f)).collect())),
+        ]);
+        let constant = value(&[("data", ObjectValue::Array(vec![ObjectValue::Float(9.0)]))]);
+        let clip_data = value(&[("m_DenseClip", dense), ("m_ConstantClip", constant)]);
+        let muscle = value(&[("m_Clip", value(&[("data", clip_data)]))]);
+        let paths: HashMap<u32, String> = vec![(7, "root".to_string())].into_iter().collect();
+
+        let mut clip = AnimationClip{ name: "clip".to_string(), legacy: false, sample_rate: 60.0, wrap_mode: 0,
+            start: 0.0, stop: 2.0, curves: Vec::new() };
+        clip.read_muscle_clip(&muscle, &bindings, &paths, &HashMap::new()).unwrap();
+        let names: Vec<&str> = clip.curves.iter().map(|c| c.attribute.as_str()).collect();
+        assert_eq!(names, vec!["m_LocalPosition.x", "m_LocalPosition.y", "m_LocalPosition.z", "0x00001234"]);
+        assert_eq!(clip.curves[0].path, "root");
+        let x: Vec<(f32, f32, f32)> = clip.curves[0].keys.iter().map(|k| (k.time, k.value, k.out_slope)).collect();
+        assert_eq!(x, vec![(0.0, 0.0, 4.0), (0.5, 2.0, 4.0)]);
+        assert_eq!(clip.curves[3].source, CurveSource::Constant);
+        assert_eq!(clip.curves[3].evaluate(1.0), Some(9.0));
+    }
+}
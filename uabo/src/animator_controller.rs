@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, PPtr};
+use crate::Result;
+
+/// destination states from here on are sub state machines
+const STATE_MACHINE_DESTINATION: u32 = 30000;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: &'static str,         // Float, Int, Bool or Trigger
+    pub default: Value,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Condition {
+    pub parameter: String,
+    pub mode: &'static str,         // If, IfNot, Greater, Less, Equals, NotEqual
+    pub threshold: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Transition {
+    pub name: Option<String>,
+    pub destination: String,        // state name, "Exit" or "StateMachine<n>"
+    pub duration: f32,
+    pub fixed_duration: bool,
+    pub offset: f32,
+    pub exit_time: Option<f32>,     // normalized, when the transition waits for it
+    pub can_transition_to_self: bool,
+    pub conditions: Vec<Condition>,
+}
+
+/// clip or blend tree of a state, with its position in the parent blend tree
+#[derive(Serialize, Clone, Debug)]
+pub struct Motion {
+    pub clip: Option<String>,
+    pub blend_type: Option<&'static str>,
+    pub parameters: Vec<String>,    // x and y of 2D trees, one for 1D trees
+    pub position: Vec<f32>,         // threshold or 2D position in the parent
+    pub direct_parameter: Option<String>,
+    pub children: Vec<Motion>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct State {
+    pub name: String,
+    pub path: String,               // full path, "Base Layer.Idle"
+    pub tag: Option<String>,
+    pub speed: f32,
+    pub speed_parameter: Option<String>,
+    pub cycle_offset: f32,
+    pub mirror: bool,
+    pub write_defaults: bool,
+    pub ik_on_feet: bool,
+    pub motion: Option<Motion>,
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub blending: &'static str,     // Override or Additive
+    pub default_weight: f32,
+    pub ik_pass: bool,
+    pub state_machine: usize,
+    pub motion_set: usize,          // non zero for synced layers
+    pub default_state: Option<String>,
+    pub any_state_transitions: Vec<Transition>,
+    pub states: Vec<State>,
+}
+
+/// runtime ControllerConstant of an AnimatorController, names resolved through m_TOS
+#[derive(Serialize, Clone, Debug)]
+pub struct AnimatorController {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub layers: Vec<Layer>,
+    pub clips: Vec<String>,
+}
+
+impl AnimatorController {
+    pub const CLASS_ID: i32 = 91;
+
+    /// reads a controller of `asset`, resolving clip pointers through `bundles`
+    pub fn from_value(bundles: &[AssetBundle], asset: &Asset, v: &ObjectValue) -> Result<AnimatorController> {
+        let mut names = HashMap::new();
+        for pair in v.array("m_TOS") {
+            names.insert(pair.int("first")? as u32, pair.string("second")?.to_string());
+        }
+        let clips: Vec<String> = v.array("m_AnimationClips").iter()
+            .map(|c| {
+                let ptr = PPtr::from_value(c)?;
+                let clip = pptr::resolve(bundles, asset, &ptr)?;
+                let value = clip.asset.object_value(clip.object).ok()?;
+                value.get("m_Name").and_then(|n| n.as_str()).map(String::from)
+            })
+            .enumerate()
+            .map(|(i, name)| name.unwrap_or_else(|| format!("Missing{}", i)))
+            .collect();
+        let reader = Reader{ names: &names, clips: &clips };
+
+        let controller = v.field("m_Controller")?;
+        let parameters = reader.parameters(controller)?;
+        let machines = controller.array("m_StateMachineArray");
+        let mut layers = Vec::new();
+        for layer in controller.array("m_LayerArray").iter().map(ObjectValue::offset_ptr) {
+            let index = layer.int("m_StateMachineIndex")? as usize;
+            let motion_set = layer.get("m_StateMachineMotionSetIndex").and_then(|m| m.as_u64()).unwrap_or(0) as usize;
+            let machine = machines.get(index).map(ObjectValue::offset_ptr)
+                .ok_or_else(|| format!("layer state machine {} of {}", index, machines.len()))?;
+            let states: Vec<State> = machine.array("m_StateConstantArray").iter()
+                .map(|s| reader.state(s.offset_ptr(), motion_set, machine))
+                .collect::<Result<_>>()?;
+            let any_state_transitions = machine.array("m_AnyStateTransitionConstantArray").iter()
+                .map(|t| reader.transition(t.offset_ptr(), machine))
+                .collect::<Result<_>>()?;
+            let default_state = machine.get("m_DefaultState").and_then(|d| d.as_u64())
+                .and_then(|d| states.get(d as usize))
+                .map(|s| s.name.clone());
+            layers.push(Layer{
+                name: reader.name(layer.int("m_Binding")? as u32),
+                blending: match layer.get("m_LayerBlendingMode").and_then(|m| m.as_i64()) {
+                    Some(1) => "Additive",
+                    _ => "Override",
+                },
+                default_weight: layer.get("m_DefaultWeight").and_then(|w| w.as_f64()).unwrap_or(1.0) as f32,
+                ik_pass: layer.flag("m_IKPass"),
+                state_machine: index,
+                motion_set,
+                default_state,
+                any_state_transitions,
+                states,
+            });
+        }
+        Ok(AnimatorController{ name: v.string("m_Name")?.to_string(), parameters, layers, clips })
+    }
+
+    /// Graphviz digraph with a cluster per layer, entry and any state nodes and labeled transitions
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph \"{}\" {{", escape(&self.name));
+        let _ = writeln!(dot, "  rankdir=LR;\n  node [shape=box, style=rounded];");
+        for (l, layer) in self.layers.iter().enumerate() {
+            let _ = writeln!(dot, "  subgraph cluster_{} {{\n    label=\"{} ({})\";", l, escape(&layer.name), layer.blending);
+            let _ = writeln!(dot, "    L{}_entry [label=\"Entry\", shape=ellipse];", l);
+            let _ = writeln!(dot, "    L{}_any [label=\"Any State\", shape=ellipse];", l);
+            let _ = writeln!(dot, "    L{}_exit [label=\"Exit\", shape=ellipse];", l);
+            let node = |name: &str| match layer.states.iter().position(|s| s.name == name) {
+                Some(s) => format!("L{}_S{}", l, s),
+                None => format!("L{}_exit", l),
+            };
+            for (s, state) in layer.states.iter().enumerate() {
+                let motion = state.motion.as_ref().map(Motion::summary).unwrap_or_default();
+                let style = match layer.default_state.as_deref() == Some(&state.name) {
+                    true => ", penwidth=2",
+                    false => "",
+                };
+                let _ = writeln!(dot, "    L{}_S{} [label=\"{}\\n{}\"{}];", l, s, escape(&state.name), escape(&motion), style);
+            }
+            if let Some(default) = &layer.default_state {
+                let _ = writeln!(dot, "    L{}_entry -> {};", l, node(default));
+            }
+            let edges = layer.any_state_transitions.iter().map(|t| (format!("L{}_any", l), t))
+                .chain(layer.states.iter().enumerate()
+                    .flat_map(|(s, state)| state.transitions.iter().map(move |t| (format!("L{}_S{}", l, s), t))));
+            for (from, t) in edges {
+                let _ = writeln!(dot, "    {} -> {} [label=\"{}\"];", from, node(&t.destination), escape(&t.label()));
+            }
+            let _ = writeln!(dot, "  }}");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl Transition {
+    /// conditions one per line, then the exit time
+    fn label(&self) -> String {
+        let mut lines: Vec<String> = self.conditions.iter()
+            .map(|c| match c.mode {
+                "If" => c.parameter.clone(),
+                "IfNot" => format!("!{}", c.parameter),
+                "Greater" => format!("{} > {}", c.parameter, c.threshold),
+                "Less" => format!("{} < {}", c.parameter, c.threshold),
+                "Equals" => format!("{} == {}", c.parameter, c.threshold),
+                "NotEqual" => format!("{} != {}", c.parameter, c.threshold),
+                mode => format!("{} {} {}", c.parameter, mode, c.threshold),
+            })
+            .collect();
+        if let Some(exit) = self.exit_time {
+            lines.push(format!("exit {}", exit));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Motion {
+    /// clip name, or blend type and parameters
+    fn summary(&self) -> String {
+        match (&self.clip, self.blend_type) {
+            (Some(clip), _) => clip.clone(),
+            (None, Some(blend)) => format!("{}({}) x{}", blend, self.parameters.join(", "), self.children.len()),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// names and clips shared while reading one controller
+struct Reader<'c> {
+    names: &'c HashMap<u32, String>,
+    clips: &'c [String],
+}
+
+impl<'c> Reader<'c> {
+    fn name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_else(|| format!("0x{:08x}", id))
+    }
+
+    /// name of an optional parameter or tag id, 0 meaning none
+    fn optional(&self, v: &ObjectValue, field: &str) -> Option<String> {
+        match v.get(field).and_then(|i| i.as_u64()) {
+            None | Some(0) => None,
+            Some(id) => Some(self.name(id as u32)),
+        }
+    }
+
+    /// parameters of m_Values with their defaults from m_DefaultValues
+    fn parameters(&self, controller: &ObjectValue) -> Result<Vec<Parameter>> {
+        let values = controller.field("m_Values")?.offset_ptr();
+        let defaults = controller.get("m_DefaultValues").map(ObjectValue::offset_ptr);
+        let default = |array_name: &str, index: usize| -> Value {
+            defaults.and_then(|d| d.get(array_name))
+                .and_then(|a| match a {
+                    ObjectValue::Array(a) => a.get(index),
+                    _ => None,
+                })
+                .map(|v| match v {
+                    ObjectValue::Bool(b) => json!(b),
+                    ObjectValue::Float(f) => json!(f),
+                    v => json!(v.as_i64()),
+                })
+                .unwrap_or(Value::Null)
+        };
+        values.array("m_ValueArray").iter()
+            .map(|p| {
+                let index = p.int("m_Index")? as usize;
+                let (kind, default) = match p.int("m_Type")? {
+                    1 => ("Float", default("m_FloatValues", index)),
+                    3 => ("Int", default("m_IntValues", index)),
+                    4 => ("Bool", default("m_BoolValues", index)),
+                    9 => ("Trigger", default("m_BoolValues", index)),
+                    _ => ("Unknown", Value::Null),
+                };
+                Ok(Parameter{ name: self.name(p.int("m_ID")? as u32), kind, default })
+            })
+            .collect()
+    }
+
+    fn state(&self, s: &ObjectValue, motion_set: usize, machine: &ObjectValue) -> Result<State> {
+        let tree = match s.get("m_BlendTreeConstantIndexArray") {
+            Some(ObjectValue::Array(a)) => a.get(motion_set).and_then(|i| i.as_i64()).unwrap_or(-1),
+            _ => -1,
+        };
+        let motion = match s.array("m_BlendTreeConstantArray").get(tree.max(0) as usize) {
+            Some(t) if tree >= 0 => self.blend_tree(t.offset_ptr())?,
+            _ => None,
+        };
+        Ok(State{
+            name: self.name(s.int("m_NameID")? as u32),
+            path: self.name(s.int("m_FullPathID")? as u32),
+            tag: self.optional(s, "m_TagID"),
+            speed: s.get("m_Speed").and_then(|f| f.as_f64()).unwrap_or(1.0) as f32,
+            speed_parameter: self.optional(s, "m_SpeedParamID"),
+            cycle_offset: s.get("m_CycleOffset").and_then(|f| f.as_f64()).unwrap_or(0.0) as f32,
+            mirror: s.flag("m_Mirror"),
+            write_defaults: s.flag("m_WriteDefaultValues"),
+            ik_on_feet: s.flag("m_IKOnFeet"),
+            motion,
+            transitions: s.array("m_TransitionConstantArray").iter()
+                .map(|t| self.transition(t.offset_ptr(), machine))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn transition(&self, t: &ObjectValue, machine: &ObjectValue) -> Result<Transition> {
+        let destination = t.int("m_DestinationState")? as u32;
+        let states = machine.array("m_StateConstantArray");
+        let destination = match states.get(destination as usize) {
+            Some(s) => self.name(s.offset_ptr().int("m_NameID")? as u32),
+            None if destination >= STATE_MACHINE_DESTINATION && destination != u32::MAX =>
+                format!("StateMachine{}", destination - STATE_MACHINE_DESTINATION),
+            None => "Exit".to_string(),
+        };
+        let conditions = t.array("m_ConditionConstantArray").iter()
+            .map(ObjectValue::offset_ptr)
+            .map(|c| Ok(Condition{
+                parameter: self.name(c.int("m_EventID")? as u32),
+                mode: match c.int("m_ConditionMode")? {
+                    1 => "If",
+                    2 => "IfNot",
+                    3 => "Greater",
+                    4 => "Less",
+                    5 => "ExitTime",
+                    6 => "Equals",
+                    7 => "NotEqual",
+                    _ => "Unknown",
+                },
+                threshold: c.float("m_EventThreshold")? as f32,
+            }))
+            .collect::<Result<_>>()?;
+        Ok(Transition{
+            name: self.optional(t, "m_ID"),
+            destination,
+            duration: t.float("m_TransitionDuration")? as f32,
+            fixed_duration: t.flag("m_HasFixedDuration"),
+            offset: t.get("m_TransitionOffset").and_then(|o| o.as_f64()).unwrap_or(0.0) as f32,
+            exit_time: match t.get("m_HasExitTime").and_then(|h| h.as_bool()).unwrap_or(true) {
+                true => t.get("m_ExitTime").and_then(|e| e.as_f64()).map(|e| e as f32),
+                false => None,
+            },
+            can_transition_to_self: t.flag("m_CanTransitionToSelf"),
+            conditions,
+        })
+    }
+
+    /// tree rooted at node 0 of a BlendTreeConstant; a plain clip state is a single leaf
+    fn blend_tree(&self, tree: &ObjectValue) -> Result<Option<Motion>> {
+        let nodes: Vec<&ObjectValue> = tree.array("m_NodeArray").iter().map(ObjectValue::offset_ptr).collect();
+        match nodes.is_empty() {
+            true => Ok(None),
+            false => self.motion(&nodes, 0, Vec::new(), None, 0).map(Some),
+        }
+    }
+
+    fn motion(&self, nodes: &[&ObjectValue], index: usize, position: Vec<f32>, direct_parameter: Option<String>, depth: usize)
+            -> Result<Motion> {
+        let node = nodes.get(index).ok_or_else(|| format!("blend tree node {} of {}", index, nodes.len()))?;
+        if depth > nodes.len() {
+            return Err("cyclic blend tree".into());
+        }
+        let children: Vec<usize> = match node.get("m_ChildIndices") {
+            Some(ObjectValue::Array(c)) => c.iter().filter_map(|i| i.as_u64()).map(|i| i as usize).collect(),
+            _ => Vec::new(),
+        };
+        if children.is_empty() {
+            let clip = node.get("m_ClipID").or_else(|| node.get("m_ClipIndex")).and_then(|c| c.as_u64())
+                .filter(|c| *c != u32::MAX as u64)
+                .map(|c| self.clips.get(c as usize).cloned().unwrap_or_else(|| format!("Clip{}", c)));
+            return Ok(Motion{ clip, blend_type: None, parameters: Vec::new(), position, direct_parameter, children: Vec::new() });
+        }
+
+        let blend_type = node.get("m_BlendType").and_then(|b| b.as_u64()).unwrap_or(0);
+        let mut parameters = vec![self.name(node.int("m_BlendEventID")? as u32)];
+        if (1..=3).contains(&blend_type) {
+            parameters.push(self.name(node.int("m_BlendEventYID")? as u32));
+        }
+        let items = |block: &str, field: &str| -> Vec<&ObjectValue> {
+            node.get(block).map(ObjectValue::offset_ptr).map(|b| b.array(field).iter().collect()).unwrap_or_default()
+        };
+        let thresholds = items("m_Blend1dData", "m_ChildThresholdArray");
+        let positions = items("m_Blend2dData", "m_ChildPositionArray");
+        let direct = items("m_BlendDirectData", "m_ChildBlendEventIDArray");
+        let children = children.iter().enumerate()
+            .map(|(i, child)| {
+                let position = match blend_type {
+                    0 => thresholds.get(i).and_then(|t| t.as_f64()).map(|t| vec![t as f32]).unwrap_or_default(),
+                    1..=3 => positions.get(i)
+                        .and_then(|p| Some(vec![p.get("x")?.as_f64()? as f32, p.get("y")?.as_f64()? as f32]))
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+                let direct_parameter = direct.get(i).and_then(|d| d.as_u64()).map(|d| self.name(d as u32));
+                self.motion(nodes, *child, position, direct_parameter, depth + 1)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Motion{
+            clip: None,
+            blend_type: Some(match blend_type {
+                0 => "Simple1D",
+                1 => "SimpleDirectional2D",
+                2 => "FreeformDirectional2D",
+                3 => "FreeformCartesian2D",
+                _ => "Direct",
+            }),
+            parameters: match blend_type {
+                4 => Vec::new(),
+                _ => parameters,
+            },
+            position,
+            direct_parameter,
+            children,
+        })
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// writes every AnimatorController of `bundles[0]` into `dst` as `<name>.json` and `<name>.dot`
+pub fn export(bundles: &[AssetBundle], dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(AnimatorController::CLASS_ID) {
+                continue;
+            }
+            let controller = match asset.object_value(obj).and_then(|v| AnimatorController::from_value(bundles, asset, &v)) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let path = export::unique_path(dst, &controller.name, "json");
+            fs::write(&path, serde_json::to_string_pretty(&controller)?)?;
+            fs::write(path.with_extension("dot"), controller.to_dot())?;
+            info!("{} layers {:?}", controller.layers.len(), path);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(fields: Vec<(&str, ObjectValue)>) -> ObjectValue {
+        ObjectValue::Struct(fields.into_iter().map(|(n, v)| (n.to_string(), v)).collect())
+    }
+
+    fn transition(destination: u32, exit_time: Option<f64>, conditions: &[(u64, i64, f64)]) -> ObjectValue {
+        let conditions = conditions.iter()
+            .map(|(event, mode, threshold)| value(vec![
+                ("m_ConditionMode", ObjectValue::Int(*mode)),
+                ("m_EventID", ObjectValue::UInt(*event)),
+                ("m_EventThreshold", ObjectValue::Float(*threshold)),
+            ]))
+            .collect();
+        value(vec![
+            ("m_ConditionConstantArray", ObjectValue::Array(conditions)),
+            ("m_DestinationState", ObjectValue::UInt(destination as u64)),
+            ("m_TransitionDuration", ObjectValue::Float(0.25)),
+            ("m_ExitTime", ObjectValue::Float(exit_time.unwrap_or(0.0))),
+            ("m_HasExitTime", ObjectValue::Bool(exit_time.is_some())),
+        ])
+    }
+
+    #[test]
+    fn transitions_in_graph() {
+        let names: HashMap<u32, String> = vec![(1, "Idle"), (2, "Run"), (3, "speed"), (4, "jump")].into_iter()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect();
+        let reader = Reader{ names: &names, clips: &[] };
+        let state = |name: u64| value(vec![("m_NameID", ObjectValue::UInt(name))]);
+        let machine = value(vec![("m_StateConstantArray", ObjectValue::Array(vec![state(1), state(2)]))]);
+
+        let run = reader.transition(&transition(1, None, &[(3, 3, 0.5)]), &machine).unwrap();
+        assert_eq!((run.destination.as_str(), run.exit_time), ("Run", None));
+        let jump = reader.transition(&transition(u32::MAX, Some(0.75), &[(4, 1, 0.0)]), &machine).unwrap();
+        assert_eq!((jump.destination.as_str(), jump.exit_time), ("Exit", Some(0.75)));
+        let nested = reader.transition(&transition(30002, None, &[]), &machine).unwrap();
+        assert_eq!(nested.destination, "StateMachine2");
+
+        let state = |name: &str, transitions: Vec<Transition>| State{
+            name: name.to_string(), path: format!("Base Layer.{}", name), tag: None, speed: 1.0, speed_parameter: None,
+            cycle_offset: 0.0, mirror: false, write_defaults: true, ik_on_feet: false, motion: None, transitions,
+        };
+        let controller = AnimatorController{
+            name: "player".to_string(),
+            parameters: Vec::new(),
+            layers: vec![Layer{
+                name: "Base Layer".to_string(), blending: "Override", default_weight: 1.0, ik_pass: false,
+                state_machine: 0, motion_set: 0, default_state: Some("Idle".to_string()),
+                any_state_transitions: vec![nested],
+                states: vec![state("Idle", vec![run, jump]), state("Run", Vec::new())],
+            }],
+            clips: Vec::new(),
+        };
+        let dot = controller.to_dot();
+        assert!(dot.contains("L0_entry -> L0_S0;"));
+        assert!(dot.contains("L0_S0 -> L0_S1 [label=\"speed > 0.5\"];"));
+        assert!(dot.contains("L0_S0 -> L0_exit [label=\"jump\\nexit 0.75\"];"));
+        assert!(dot.contains("L0_any -> L0_exit [label=\"\"];"));
+    }
+}
@@ -9,10 +9,10 @@ pub fn app() -> App<'static, 'static> {
     .about("Unity AssetBundle Deserialize Tool")
     .setting(AppSettings::UnifiedHelpMessage)
     .setting(AppSettings::AllArgsOverrideSelf)
-    .usage("uabo --src /path/to/foo.unity3d --dst /path/to/baa.json")
+    .usage("uabo --src /path/to/foo.unity3d --dst /path/to/baa.json [--export texture [--mips] | dds | ktx2 | sprite [--mesh-mask] | audio [--vorbis-setup dir, Vorbis clips are skipped without it] | obj | gltf | scene [--root name] | animation | animation-gltf | animator | shader | shader-code | material | text | video | script [--player-types list|dir] | link-xml | scene-bundle | player | archive | scan | web | cache] [--dependency other.unity3d] [--unity-version 2018.4.2f1] [--entry name] [--offset bytes]")
     .arg(
         clap::Arg::with_name("src")
-        .help("asset bundle pathe")
+        .help("asset bundle pathe, a player's *_Data folder, an APK, OBB or ZIP archive, a WebGL .data package (.gz, .br, .unityweb), directory of bundles with --export link-xml, or a copied bundle cache with --export cache")
         .short("s")
         .long("src")
         .takes_value(true)
@@ -24,6 +24,58 @@ pub fn app() -> App<'static, 'static> {
         .long("dst")
         .takes_value(true)
         .required(true)
+    ).arg(
+        clap::Arg::with_name("export")
+        .help("export objects into the dst directory instead of writing a json overview; dds and ktx2 keep the GPU payload of textures as is")
+        .short("e")
+        .long("export")
+        .takes_value(true)
+        .possible_values(&["texture", "dds", "ktx2", "sprite", "audio", "obj", "gltf", "scene", "animation", "animation-gltf", "animator", "shader", "shader-code", "material", "text", "video", "script", "link-xml", "scene-bundle", "player", "archive", "scan", "web", "cache"])
+    ).arg(
+        clap::Arg::with_name("mips")
+        .help("export every mip level of textures")
+        .long("mips")
+    ).arg(
+        clap::Arg::with_name("mesh-mask")
+        .help("clear texels outside of the sprite mesh of tightly packed sprites")
+        .long("mesh-mask")
+    ).arg(
+        clap::Arg::with_name("vorbis-setup")
+        .help("directory of Vorbis setup headers stripped from FSB5 banks, one <crc32>.bin per header; needed to export Vorbis clips as Ogg, as uabo ships no setup headers and lists Vorbis clips with an error without them")
+        .long("vorbis-setup")
+        .takes_value(true)
+    ).arg(
+        clap::Arg::with_name("root")
+        .help("name or path id of the root GameObject exported as a glTF scene, every root when omitted")
+        .long("root")
+        .takes_value(true)
+    ).arg(
+        clap::Arg::with_name("dependency")
+        .help("bundle, or directory of bundles, searched for objects referenced from src")
+        .long("dependency")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+    ).arg(
+        clap::Arg::with_name("player-types")
+        .help("list of assemblies and types, or Managed directory of dlls, the player ships; scripts outside it are reported")
+        .long("player-types")
+        .takes_value(true)
+    ).arg(
+        clap::Arg::with_name("entry")
+        .help("bundle inside the src archive or WebGL package to read, the player data inside it when omitted")
+        .long("entry")
+        .takes_value(true)
+    ).arg(
+        clap::Arg::with_name("offset")
+        .help("byte offset of the bundle in src, after a custom header or inside a pack file; --export scan lists the offsets")
+        .long("offset")
+        .takes_value(true)
+    ).arg(
+        clap::Arg::with_name("unity-version")
+        .help("unity version assumed when the asset version is stripped (e.g. 2018.4.2f1)")
+        .long("unity-version")
+        .takes_value(true)
     );
     app
 }
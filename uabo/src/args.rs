@@ -1,12 +1,41 @@
+use std::fs;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use log::warn;
 use crate::app;
 use crate::asset_bundle::AssetBundle;
+use crate::gpu_texture::Container;
+use crate::mesh::MeshFormat;
+use crate::unity_version::UnityVersion;
+use crate::web_data::WebData;
+use crate::zip_archive::{self, ZipArchive};
 use crate::Result;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Command {
     Files,
+    Textures,
+    Containers(Container),
+    Sprites,
+    Audio,
+    Meshes(MeshFormat),
+    Scenes,
+    Animations(bool),       // glTF when set, JSON curves otherwise
+    Animators,
+    Shaders,
+    ShaderPrograms,
+    Materials,
+    TextAssets,
+    Videos,
+    Scripts,
+    LinkXml,
+    SceneBundles,
+    PlayerData,
+    Archive,
+    Scan,
+    Web,
+    Cache,
 }
 
 #[derive(Clone, Debug)]
@@ -16,6 +45,16 @@ pub struct Args(Arc<ArgsImp>);
 struct ArgsImp {
     src: PathBuf,
     dst: PathBuf,
+    unity_version: Option<UnityVersion>,
+    export: Option<String>,
+    mips: bool,
+    mesh_mask: bool,
+    dependencies: Vec<PathBuf>,
+    vorbis_setup: Option<PathBuf>,
+    root: Option<String>,
+    player_types: Option<PathBuf>,
+    entry: Option<String>,
+    offset: Option<u64>,
 }
 
 impl Args {
@@ -23,18 +62,181 @@ impl Args {
         let matches = app::app().get_matches();
         let src = Path::new(matches.value_of("src").unwrap());
         let dst = Path::new(matches.value_of("dst").unwrap());
+        let offset = match matches.value_of("offset") {
+            Some(o) => Some(o.parse::<u64>().map_err(|e| format!("offset {}: {}", o, e))?),
+            None => None,
+        };
+        let unity_version = match matches.value_of("unity-version") {
+            Some(v) => Some(UnityVersion::parse(v)?),
+            None => None,
+        };
         Ok(Args(Arc::new(ArgsImp{
             src: src.to_path_buf(),
             dst: dst.to_path_buf(),
+            unity_version,
+            export: matches.value_of("export").map(String::from),
+            mips: matches.is_present("mips"),
+            mesh_mask: matches.is_present("mesh-mask"),
+            dependencies: matches.values_of("dependency")
+                .map(|v| v.map(PathBuf::from).collect())
+                .unwrap_or_default(),
+            vorbis_setup: matches.value_of("vorbis-setup").map(PathBuf::from),
+            root: matches.value_of("root").map(String::from),
+            player_types: matches.value_of("player-types").map(PathBuf::from),
+            entry: matches.value_of("entry").map(String::from),
+            offset,
         })))
     }
 
     pub fn command(&self) -> Result<Command>{
-        Ok(Command::Files)
+        match self.0.export.as_deref() {
+            None => Ok(Command::Files),
+            Some("texture") => Ok(Command::Textures),
+            Some("dds") => Ok(Command::Containers(Container::Dds)),
+            Some("ktx2") => Ok(Command::Containers(Container::Ktx2)),
+            Some("sprite") => Ok(Command::Sprites),
+            Some("audio") => Ok(Command::Audio),
+            Some("obj") => Ok(Command::Meshes(MeshFormat::Obj)),
+            Some("gltf") => Ok(Command::Meshes(MeshFormat::Glb)),
+            Some("scene") => Ok(Command::Scenes),
+            Some("animation") => Ok(Command::Animations(false)),
+            Some("animation-gltf") => Ok(Command::Animations(true)),
+            Some("animator") => Ok(Command::Animators),
+            Some("shader") => Ok(Command::Shaders),
+            Some("shader-code") => Ok(Command::ShaderPrograms),
+            Some("material") => Ok(Command::Materials),
+            Some("text") => Ok(Command::TextAssets),
+            Some("video") => Ok(Command::Videos),
+            Some("script") => Ok(Command::Scripts),
+            Some("link-xml") => Ok(Command::LinkXml),
+            Some("scene-bundle") => Ok(Command::SceneBundles),
+            Some("player") => Ok(Command::PlayerData),
+            Some("archive") => Ok(Command::Archive),
+            Some("scan") => Ok(Command::Scan),
+            Some("web") => Ok(Command::Web),
+            Some("cache") => Ok(Command::Cache),
+            Some(e) => Err(format!("unknown export : {}", e).into()),
+        }
     }
 
+    /// bundle at --src, starting --offset bytes in when given; a player's Data folder is read
+    /// as one bundle, and so is the Data folder inside an archive or a WebGL package unless
+    /// --entry names a bundle in it
     pub fn evaluates(&self) -> Result<AssetBundle>{
-        AssetBundle::load(&self.0.src)
+        let unity_version = self.0.unity_version.as_ref();
+        if let Some(offset) = self.0.offset {
+            return AssetBundle::load_at(&self.0.src, offset, unity_version);
+        }
+        if ZipArchive::is_archive(&self.0.src) {
+            let archive = self.archive()?;
+            if let Some(name) = &self.0.entry {
+                let entry = archive.entry(name).ok_or_else(|| format!("no entry {} in {:?}", name, self.0.src))?;
+                return AssetBundle::read(archive.seekable(entry)?, unity_version);
+            }
+            return match zip_archive::player_data_dir(&archive) {
+                Some(dir) => AssetBundle::from_player_files(zip_archive::player_files(&archive, &dir)?, unity_version),
+                None => Err(format!("no player data in {:?}; name a bundle with --entry", self.0.src).into()),
+            };
+        }
+        if WebData::is_web_data(&self.0.src) {
+            let web = self.web_data()?;
+            return match &self.0.entry {
+                Some(name) => {
+                    let file = web.file(name).ok_or_else(|| format!("no file {} in {:?}", name, self.0.src))?;
+                    AssetBundle::read(Cursor::new(web.read(file)), unity_version)
+                },
+                None => AssetBundle::from_player_files(web.player_files(), unity_version),
+            };
+        }
+        match AssetBundle::is_player_data(&self.0.src) {
+            true => AssetBundle::load_player_data(&self.0.src, unity_version),
+            false => AssetBundle::load(&self.0.src, unity_version),
+        }
+    }
+
+    pub fn source(&self) -> &PathBuf {
+        &self.0.src
+    }
+
+    /// ZIP, APK or OBB archive at --src
+    pub fn archive(&self) -> Result<ZipArchive> {
+        ZipArchive::open(&self.0.src)
+    }
+
+    /// WebGL data package at --src, gzip or Brotli compressed or not
+    pub fn web_data(&self) -> Result<WebData> {
+        WebData::open(&self.0.src)
+    }
+
+    pub fn unity_version(&self) -> Option<&UnityVersion> {
+        self.0.unity_version.as_ref()
+    }
+
+    pub fn dest_dir(&self) -> &Path {
+        &self.0.dst
+    }
+
+    pub fn mips(&self) -> bool {
+        self.0.mips
+    }
+
+    pub fn mesh_mask(&self) -> bool {
+        self.0.mesh_mask
+    }
+
+    /// directory of Vorbis setup headers named by their CRC32
+    pub fn vorbis_setup(&self) -> Option<&Path> {
+        self.0.vorbis_setup.as_deref()
+    }
+
+    /// name or path id of the root GameObject exported as a scene
+    pub fn root(&self) -> Option<&str> {
+        self.0.root.as_deref()
+    }
+
+    /// assembly and type list, or Managed directory, of the player the bundles run in
+    pub fn player_types(&self) -> Option<&Path> {
+        self.0.player_types.as_deref()
+    }
+
+    /// bundles given with --dependency; directories contribute every bundle inside
+    pub fn dependencies(&self) -> Result<Vec<AssetBundle>> {
+        let mut bundles = Vec::new();
+        for path in &self.0.dependencies {
+            bundles.extend(self.load_all(path)?);
+        }
+        Ok(bundles)
+    }
+
+    /// bundle at --src, or every bundle inside when it is a directory other than a player's Data folder
+    pub fn sources(&self) -> Result<Vec<AssetBundle>> {
+        match self.0.src.is_dir() && !AssetBundle::is_player_data(&self.0.src) {
+            true => self.load_all(&self.0.src),
+            false => Ok(vec![self.evaluates()?]),
+        }
+    }
+
+    /// bundle at `path`, or every bundle of the directory, skipping files that fail to load
+    fn load_all(&self, path: &Path) -> Result<Vec<AssetBundle>> {
+        let files = match path.is_dir() {
+            true => {
+                let mut files = fs::read_dir(path)?
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.is_file())
+                    .collect::<Vec<PathBuf>>();
+                files.sort();
+                files
+            },
+            false => vec![path.to_path_buf()],
+        };
+        let mut bundles = Vec::new();
+        for file in files {
+            match AssetBundle::load(&file, self.0.unity_version.as_ref()) {
+                Ok(b) => bundles.push(b),
+                Err(e) => warn!("{:?}: {}", file, e),
+            }
+        }
+        Ok(bundles)
     }
 
     pub fn dest(&self) -> String {
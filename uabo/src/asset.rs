@@ -1,5 +1,7 @@
 use std::io::{Seek, SeekFrom};
 use std::io::Cursor;
+use std::ops::Range;
+use std::sync::Arc;
 use log::{info};
 use serde::{Serialize, Deserialize};
 
@@ -7,9 +9,12 @@ use crate::binary_reader::BinaryReader;
 use crate::Result;
 use crate::endian::Endian;
 use crate::class_info::ClassInfo;
+use crate::constants;
 use crate::object_info::ObjectInfo;
 use crate::reference::Reference;
 use crate::local_object_entry::LocalObjectEntry;
+use crate::unity_version::UnityVersion;
+use crate::object_value::ObjectValue;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Asset {
@@ -19,6 +24,7 @@ pub struct Asset {
     format: u32,                // フォーマットバージョン
     endian: Endian,             // アセットバイナリエンディアン
     generator_version: String,  // アセット生成バージョン
+    unity_version: UnityVersion, // 解析に用いたUnityバージョン
     target_platform: i32,       // 対象プラットフォーム
     has_type_trees: bool,       // タイプ情報有無
     with_path_id: bool,         // 
@@ -28,18 +34,35 @@ pub struct Asset {
     references: Vec<Reference>,
     classes: Vec<ClassInfo>,
     objects: Vec<ObjectInfo>,
+    #[serde(skip)]
+    data: Arc<Vec<u8>>,         // シリアライズファイル本体, shared with the other nodes of its bundle
+    #[serde(skip)]
+    data_offset: u64,           // オブジェクトデータ開始位置 in `data`
 }
 
 impl Asset {
-    pub fn read(name: &String, status: u32, data: &[u8]) -> Result<Asset>{
+    /// `fallback_version` is used when the generator version has been stripped
+    pub fn read(name: &String, status: u32, data: Vec<u8>, fallback_version: &UnityVersion) -> Result<Asset>{
+        let len = data.len();
+        Asset::read_node(name, status, &Arc::new(data), 0..len, fallback_version)
+    }
+
+    /// reads the serialized file at `node` of `buffer`, keeping the buffer rather than a copy of the file
+    pub fn read_node(name: &String, status: u32, buffer: &Arc<Vec<u8>>, node: Range<usize>, fallback_version: &UnityVersion) -> Result<Asset>{
+        let data = buffer.get(node.clone())
+            .ok_or_else(|| format!("{}: {}..{} is outside of the {} byte buffer", name, node.start, node.end, buffer.len()))?;
+        // sizes, format and data offset, then the format 22 header or the endianness
+        if data.len() < 48 {
+            return Err(format!("{}: {} bytes are too short for a serialized file", name, data.len()).into());
+        }
         let mut cursor = BinaryReader::new(Cursor::new(data), Endian::Big);
-        let mut meta_size = cursor.uint32();
-        let mut file_size = cursor.uint32() as u64;
-        let format    = cursor.uint32();
-        let mut offset    = cursor.uint32() as u64;
+        let mut meta_size = cursor.uint32()?;
+        let mut file_size = cursor.uint32()? as u64;
+        let format    = cursor.uint32()?;
+        let mut offset    = cursor.uint32()? as u64;
         let endian    = match format >= 9 {
             true => {
-                let e = match cursor.uint8() != 0 {
+                let e = match cursor.uint8()? != 0 {
                     true => Endian::Big, 
                     false => Endian::Little,
                 };
@@ -48,7 +71,7 @@ impl Asset {
             },
             false => {
                 cursor.as_mut_ref().seek(SeekFrom::End(meta_size as i64))?;
-                match cursor.uint8() != 0 {
+                match cursor.uint8()? != 0 {
                     true => Endian::Big,
                     false => Endian::Little,
                 }
@@ -56,9 +79,9 @@ impl Asset {
         };
 
         if format >= 22 {
-            meta_size = cursor.uint32();
-            file_size = cursor.uint64();
-            offset = cursor.uint64();
+            meta_size = cursor.uint32()?;
+            file_size = cursor.uint64()?;
+            offset = cursor.uint64()?;
             cursor.as_mut_ref().seek(SeekFrom::Current(8))?;
         }
         let meta_size = meta_size;
@@ -75,69 +98,83 @@ impl Asset {
         cursor.set_endian(endian.clone());
 
         let generator_version = match format >= 7 {
-            true => cursor.cstr(),
+            true => cursor.cstr()?,
             false => String::from("")
         };
+        let unity_version = match UnityVersion::parse(&generator_version) {
+            Ok(v) if !v.is_stripped() => v,
+            _ => fallback_version.clone(),
+        };
         let target_platform = match format >= 8 {
-            true => cursor.int32(),
+            true => cursor.int32()?,
             false => -1,
         };
         let has_type_trees = match format >= 13 {
-            true => cursor.boolean(),
+            true => cursor.boolean()?,
             false => true
         };
-        let type_count = cursor.uint32();
-        info!("gen_ver {}, plat {}, type_tree {}, type_count {}", generator_version, target_platform, has_type_trees, type_count);
+        // a class id at least
+        let type_count = cursor.count(4)?;
+        info!("gen_ver {} ({}), plat {}, type_tree {}, type_count {}", generator_version, unity_version, target_platform, has_type_trees, type_count);
 
         let mut classes: Vec<ClassInfo> = Vec::new();
         for _ in 0..type_count {
-            classes.push(ClassInfo::new(&mut cursor, format, has_type_trees));
+            classes.push(ClassInfo::new(&mut cursor, format, has_type_trees, &unity_version)?);
         }
 
-        let wide_path_id = format >= 14 || format >= 7 && cursor.int32() != 0;
+        let wide_path_id = format >= 14 || format >= 7 && cursor.int32()? != 0;
         info!("wide_path_id {}", wide_path_id);
 
-        let object_count = cursor.uint32();
+        // path id, offset, size and type at least
+        let object_count = cursor.count(16)?;
         info!("object_count : {}", object_count);
         let mut objects: Vec<ObjectInfo> = Vec::new();
         for _ in 0..object_count {
-            let obj = ObjectInfo::new(&mut cursor, format, wide_path_id);
+            let mut obj = ObjectInfo::new(&mut cursor, format, wide_path_id)?;
+            let class_id = match obj.class_id {
+                Some(id) => Some(id as i32),
+                None => Asset::resolve_class(&classes, &obj).map(|c| c.class_id),
+            };
+            obj.class_name = class_id
+                .and_then(|id| constants::class_name(id, &unity_version))
+                .map(String::from);
             //info!("{:?}", obj);
             objects.push(obj);
         }
         let mut add_ids: Vec<LocalObjectEntry> = Vec::new();
         if format >= 11 {
-            let add_id_count = cursor.uint32();
+            let add_id_count = cursor.count(8)?;
             info!("add_id_count {}", add_id_count);
             for _ in 0..add_id_count {
                 if format >= 14 {
                     cursor.align(4);
                 }
-                let file_id = cursor.int32();
+                let file_id = cursor.int32()?;
                 let local_id = match wide_path_id {
-                    true => cursor.int64(),
-                    false => cursor.int32() as i64,
+                    true => cursor.int64()?,
+                    false => cursor.int32()? as i64,
                 };
                 add_ids.push( LocalObjectEntry::new(file_id, local_id));
             }
         }
-        let reference_count = cursor.uint32();
+        // two empty paths at least
+        let reference_count = cursor.count(2)?;
         info!("reference_count {}", reference_count);
         let mut references: Vec<Reference> = Vec::new();
         for _ in 0..reference_count {
             let path = match format >= 6 {
-                true => cursor.cstr(),
+                true => cursor.cstr()?,
                 false => String::from(""),
             };
             let guid = match format >= 5 {
-                true => Some(cursor.read(16)),
+                true => Some(cursor.read(16)?),
                 false => None,
             };
             let type_ = match format >= 5 {
-                true => Some(cursor.int32()),
+                true => Some(cursor.int32()?),
                 false => None,
             };
-            let file_path = cursor.cstr();
+            let file_path = cursor.cstr()?;
             references.push(
                 Reference::new(
                     path,
@@ -148,31 +185,179 @@ impl Asset {
             );
         }
 
-        let comment = cursor.cstr();
+        let comment = cursor.cstr()?;
         info!("comment {}", comment);
 
         for o in &mut objects {
-            cursor.as_mut_ref().seek(SeekFrom::Start(offset + o.offset))?;
-            let b = cursor.read(o.size as usize);
-            o.hash = Some(blake3::hash(&b).as_bytes().into_iter().map(|h| format!("{:02X}", h)).collect::<String>());
+            let start = offset.checked_add(o.offset)
+                .filter(|s| s.checked_add(o.size as u64).map(|end| end <= data.len() as u64).unwrap_or(false))
+                .ok_or_else(|| format!("{}: object {} at {}..+{} is outside of the file", name, o.path_id, o.offset, o.size))?;
+            let b = &data[start as usize .. start as usize + o.size as usize];
+            o.hash = Some(blake3::hash(b).as_bytes().iter().map(|h| format!("{:02X}", h)).collect::<String>());
         }
 
         Ok(Asset{
             name: name.to_string(),
-            meta_size: meta_size,
-            file_size: file_size,
-            format: format,
-            endian: endian,
+            meta_size,
+            file_size,
+            format,
+            endian,
             generator_version: generator_version.to_string(),
-            target_platform: target_platform,
-            has_type_trees: has_type_trees,
+            unity_version,
+            target_platform,
+            has_type_trees,
             with_path_id: wide_path_id,
             comment: comment.to_string(),
-            status: status,
-            classes: classes,
-            objects: objects,
-            add_ids: add_ids,
-            references: references,
+            status,
+            classes,
+            objects,
+            add_ids,
+            references,
+            data: Arc::clone(buffer),
+            data_offset: node.start as u64 + offset,
         })
     }
-}
\ No newline at end of file
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// byte order of the object data
+    pub fn endian(&self) -> &Endian {
+        &self.endian
+    }
+
+    /// Unity version the file was parsed with
+    pub fn unity_version(&self) -> &UnityVersion {
+        &self.unity_version
+    }
+
+    pub fn objects(&self) -> &[ObjectInfo] {
+        &self.objects
+    }
+
+    /// class entries of the type table
+    pub fn classes(&self) -> &[ClassInfo] {
+        &self.classes
+    }
+
+    /// MonoScripts used by the file's MonoBehaviours, indexed by `ClassInfo::script_id`
+    pub fn script_types(&self) -> &[LocalObjectEntry] {
+        &self.add_ids
+    }
+
+    /// object by path id
+    pub fn object(&self, path_id: i64) -> Option<&ObjectInfo> {
+        self.objects.iter().find(|o| o.path_id == path_id)
+    }
+
+    /// external file referenced by a PPtr file id (1 based, 0 is this file)
+    pub fn reference(&self, file_id: i32) -> Option<&Reference> {
+        match file_id > 0 {
+            true => self.references.get(file_id as usize - 1),
+            false => None,
+        }
+    }
+
+    /// raw serialized bytes of an object
+    pub fn object_data(&self, obj: &ObjectInfo) -> &[u8] {
+        let start = (self.data_offset + obj.offset) as usize;
+        &self.data[start .. start + obj.size as usize]
+    }
+
+    /// class entry describing an object
+    pub fn class_of(&self, obj: &ObjectInfo) -> Option<&ClassInfo> {
+        Asset::resolve_class(&self.classes, obj)
+    }
+
+    /// class id of an object; negative ids of old formats are MonoBehaviours
+    pub fn class_id(&self, obj: &ObjectInfo) -> Option<i32> {
+        match obj.class_id {
+            Some(id) => Some(id as i32),
+            None => self.class_of(obj).map(|c| c.class_id),
+        }
+    }
+
+    /// deserializes an object along its type tree
+    pub fn object_value(&self, obj: &ObjectInfo) -> Result<ObjectValue> {
+        let class = self.class_of(obj)
+            .ok_or_else(|| format!("no class for object {}", obj.path_id))?;
+        let root = class.types.as_ref()
+            .and_then(|t| t.first())
+            .ok_or_else(|| format!("no type tree for class {}", class.class_id))?;
+        let mut reader = BinaryReader::new(Cursor::new(self.object_data(obj)), self.endian.clone());
+        ObjectValue::read(&mut reader, root)
+    }
+
+    fn resolve_class<'a>(classes: &'a [ClassInfo], obj: &ObjectInfo) -> Option<&'a ClassInfo> {
+        match (obj.class_idx, obj.type_id) {
+            (Some(idx), _) => classes.get(idx as usize),
+            (None, Some(type_id)) => classes.iter().find(|c| c.class_id == type_id),
+            _ => None,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// format 17 little-endian serialized file with one class and one 4 byte object
+    fn serialized_file() -> Vec<u8> {
+        let mut meta = b"2019.4.0f1\0".to_vec();
+        meta.extend(5i32.to_le_bytes());        // platform
+        meta.push(0);                           // no type trees
+        meta.extend(1u32.to_le_bytes());        // classes
+        meta.extend(1i32.to_le_bytes());
+        meta.push(0);
+        meta.extend((-1i16).to_le_bytes());
+        meta.extend([0xab; 16]);
+        meta.extend(1u32.to_le_bytes());        // objects
+        while !(20 + meta.len()).is_multiple_of(4) {
+            meta.push(0);
+        }
+        meta.extend(1i64.to_le_bytes());
+        meta.extend(0u32.to_le_bytes());
+        meta.extend(4u32.to_le_bytes());
+        meta.extend(0u32.to_le_bytes());
+        meta.extend(0u32.to_le_bytes());        // add ids
+        meta.extend(0u32.to_le_bytes());        // references
+        meta.push(0);                           // comment
+        let offset = 20 + meta.len() as u32;
+        let mut file = Vec::new();
+        for field in [meta.len() as u32, offset + 4, 17, offset] {
+            file.extend(field.to_be_bytes());
+        }
+        file.extend([0; 4]);
+        file.extend(meta);
+        file.extend([1, 2, 3, 4]);
+        file
+    }
+
+    #[test]
+    fn read_truncated_serialized_file() {
+        let file = serialized_file();
+        let name = "CAB-test".to_string();
+        let version = UnityVersion::default();
+        let asset = Asset::read(&name, 0, file.clone(), &version).unwrap();
+        assert_eq!(asset.unity_version().release(), (2019, 4));
+        assert_eq!(asset.objects().len(), 1);
+
+        for len in 0..file.len() {
+            assert!(Asset::read(&name, 0, file[..len].to_vec(), &version).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn read_node_of_shared_buffer() {
+        let file = serialized_file();
+        let mut buffer = vec![0xee; 5];
+        buffer.extend(&file);
+        buffer.extend([0xee; 3]);
+        let buffer = Arc::new(buffer);
+        let name = "CAB-test".to_string();
+        let asset = Asset::read_node(&name, 0, &buffer, 5..5 + file.len(), &UnityVersion::default()).unwrap();
+        assert_eq!(asset.object_data(&asset.objects()[0]), &[1, 2, 3, 4]);
+        assert_eq!(Arc::strong_count(&buffer), 2);
+        assert!(Asset::read_node(&name, 0, &buffer, 5..buffer.len() + 1, &UnityVersion::default()).is_err());
+    }
+}
@@ -1,14 +1,90 @@
-use std::path::PathBuf;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
 use std::io::{BufReader, Read, Seek, SeekFrom, Cursor};
+use std::slice;
+use std::sync::Arc;
+use log::warn;
 use serde::{Serialize, Deserialize};
 
-use crate::decompress::decompress_chunk;
+use crate::decompress::{self, decompress_chunk};
 use crate::asset::Asset;
+use crate::resource_file::ResourceFile;
 use crate::binary_reader::BinaryReader;
 use crate::endian::Endian;
+use crate::unity_version::UnityVersion;
+use crate::window::Window;
 use crate::Result;
 
+/// node flag marking serialized files
+pub const NODE_SERIALIZED_FILE: u32 = 0x04;
+
+/// bundle flag storing the block info after the blocks
+const FLAG_BLOCKS_INFO_AT_END: u32 = 0x80;
+/// bundle flag padding the blocks to 16 bytes after the block info
+const FLAG_BLOCKS_PADDING: u32 = 0x200;
+
+/// bytes read to parse a header, enough for both version strings
+pub const HEADER_PEEK: u64 = 256;
+
+/// files marking a player's Data folder
+const PLAYER_DATA_MARKERS: [&str; 3] = ["globalgamemanagers", "mainData", "data.unity3d"];
+
+/// fixed fields of a UnityFS header
+#[derive(Serialize, Clone, Debug)]
+pub struct BundleHeader {
+    pub file_version: u32,
+    pub lower_player_version: String,
+    pub upper_player_version: String,
+    pub total_file_size: u64,
+    pub compressed_block_info_size: u32,
+    pub decompressed_block_info_size: u32,
+    pub flags: u32,
+}
+
+impl BundleHeader {
+    /// header at the start of `head`, None unless it is a plausible UnityFS header
+    pub fn parse(head: &[u8]) -> Option<BundleHeader> {
+        let mut pos = b"UnityFS\0".len();
+        if !head.starts_with(b"UnityFS\0") {
+            return None;
+        }
+        let u32_be = |pos: &mut usize| -> Option<u32> {
+            let v = head.get(*pos..*pos + 4)?;
+            *pos += 4;
+            Some(u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+        };
+        let file_version = u32_be(&mut pos).filter(|v| (6..=8).contains(v))?;
+        let cstr = |pos: &mut usize| -> Option<String> {
+            let len = head.get(*pos..)?.iter().position(|b| *b == 0)?;
+            let s = std::str::from_utf8(&head[*pos..*pos + len]).ok()
+                .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_graphic()))?;
+            *pos += len + 1;
+            Some(s.to_string())
+        };
+        let lower_player_version = cstr(&mut pos)?;
+        let upper_player_version = cstr(&mut pos)?;
+        let total = head.get(pos..pos + 8)?;
+        let total_file_size = u64::from_be_bytes([total[0], total[1], total[2], total[3], total[4], total[5], total[6], total[7]]);
+        pos += 8;
+        let compressed_block_info_size = u32_be(&mut pos)?;
+        let decompressed_block_info_size = u32_be(&mut pos)?;
+        let flags = u32_be(&mut pos).filter(|f| f & 0x3f <= 4)?;
+        if total_file_size != 0 && total_file_size < pos as u64 + compressed_block_info_size as u64 {
+            return None;
+        }
+        Some(BundleHeader{
+            file_version,
+            lower_player_version,
+            upper_player_version,
+            total_file_size,
+            compressed_block_info_size,
+            decompressed_block_info_size,
+            flags,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AssetBundle{
     signiture: String,                  //Unityアセットバンドル定型文
@@ -20,120 +96,401 @@ pub struct AssetBundle{
     decompressed_block_info_size: u32,  //解凍後管理情報サイズ
     flags: u32,                         //フラグ群
     assets: Vec<Asset>,                 //各アセット情報
+    resources: Vec<ResourceFile>,       //リソースファイル(.resS, .resource)
+    #[serde(skip)]
+    block_flags: Vec<u32>,              //データブロックのフラグ
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    issues: Vec<String>,                // unreadable files of a player's Data folder
 }
 
 impl AssetBundle {
     /// 指定パスよりアセットバンドル情報を抽出します
-    pub fn load(src: &PathBuf) -> Result<AssetBundle> {
-        let mut file = BinaryReader::new(BufReader::new(File::open(src).unwrap()), Endian::Big);
+    ///
+    /// `unity_version` overrides the player version of assets whose version has been stripped
+    pub fn load(src: &PathBuf, unity_version: Option<&UnityVersion>) -> Result<AssetBundle> {
+        let file = File::open(src).map_err(|e| format!("{:?}: {}", src, e))?;
+        AssetBundle::read(BufReader::new(file), unity_version)
+    }
+
+    /// reads the bundle starting `offset` bytes into `src`, after a custom header or inside a pack file
+    ///
+    /// The bundle spans its recorded total file size, or the rest of the file when that is unset.
+    pub fn load_at(src: &PathBuf, offset: u64, unity_version: Option<&UnityVersion>) -> Result<AssetBundle> {
+        let mut file = File::open(src).map_err(|e| format!("{:?}: {}", src, e))?;
+        let file_len = file.metadata()?.len();
+        let mut head = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        (&mut file).take(HEADER_PEEK).read_to_end(&mut head)?;
+        let header = BundleHeader::parse(&head)
+            .ok_or_else(|| format!("no UnityFS header at offset {} of {:?}", offset, src))?;
+        let len = match header.total_file_size {
+            0 => file_len.saturating_sub(offset),
+            n => n,
+        };
+        AssetBundle::read(Window::new(BufReader::new(file), offset, len)?, unity_version)
+    }
 
-        let signiture = file.cstr();
+    /// reads a bundle from any seekable stream, such as an archive entry
+    pub fn read<T: Read + Seek>(reader: T, unity_version: Option<&UnityVersion>) -> Result<AssetBundle> {
+        let mut file = BinaryReader::new(reader, Endian::Big);
+
+        let signiture = file.cstr()?;
         match &*signiture {
-            "UnityFS" => AssetBundle::read_asset_bundle(&mut file),
-            _         => Err(format!("invalid signature").into())
+            "UnityFS" => AssetBundle::read_asset_bundle(&mut file, unity_version),
+            _         => Err("invalid signature".into())
         }
     }
 
     /// アセットバンドル情報抽出
-    fn read_asset_bundle<T: Read + Seek>(file: &mut BinaryReader<T>) -> Result<AssetBundle>{
+    fn read_asset_bundle<T: Read + Seek>(file: &mut BinaryReader<T>, unity_version: Option<&UnityVersion>) -> Result<AssetBundle>{
          //file version
         //4byte big-endian 
-        let file_version = file.uint32();
+        let file_version = file.uint32()?;
 
         //min player revision
         //0-terminated; 
-        let lower_player_version = file.cstr();
+        let lower_player_version = file.cstr()?;
 
         //max player version
         //0-terminated; 
-        let upper_player_version = file.cstr();
+        let upper_player_version = file.cstr()?;
 
         //total file size
         //8byte big-endian
-        let total_file_size = file.int64();
+        let total_file_size = file.int64()?;
 
         //4byte big-endian
-        let compressed_block_info_size = file.uint32();
+        let compressed_block_info_size = file.uint32()?;
 
         //4byte big-endian
-        let decompressed_block_info_size = file.uint32();
+        let decompressed_block_info_size = file.uint32()?;
 
         //4byte big-endian
-        let flags = file.uint32();
+        let flags = file.uint32()?;
 
         //read block infos
         let mut compressed_buf = vec![0u8; compressed_block_info_size as usize];
-        match flags & 0x80 != 0 {
+        // the header is padded to 16 bytes since version 7
+        if file_version >= 7 {
+            file.align(16);
+        }
+        match flags & FLAG_BLOCKS_INFO_AT_END != 0 {
             true => {
-                let pos = file.as_mut_ref().seek(SeekFrom::Current(0))?;
-                file.as_mut_ref().seek(SeekFrom::End(compressed_block_info_size as i64))?;
+                let pos = file.as_mut_ref().stream_position()?;
+                file.as_mut_ref().seek(SeekFrom::End(-(compressed_block_info_size as i64)))?;
                 file.as_mut_ref().read_exact(&mut compressed_buf)?;
                 file.as_mut_ref().seek(SeekFrom::Start(pos))?;
             },
             false => {
-                if file_version >= 7 {
-                    let pos = file.as_mut_ref().seek(SeekFrom::Current(0))?;
-                    file.as_mut_ref().seek(SeekFrom::Current(pos as i64 & 16))?;
-                }
                 file.as_mut_ref().read_exact(&mut compressed_buf)?;
             }
         }
+        if flags & FLAG_BLOCKS_PADDING != 0 {
+            file.align(16);
+        }
 
         // decompress block infos
         let mut block_info_cursor = BinaryReader::new(
-            Cursor::new(decompress_chunk(&compressed_buf, decompressed_block_info_size as i32, flags).unwrap()),
+            Cursor::new(decompress_chunk(&compressed_buf, decompressed_block_info_size as i32, flags)?),
             Endian::Big
         );
 
         // read hash
         let hash: &mut[u8] = &mut [0u8; 16];
-        block_info_cursor.as_mut_ref().read_exact(hash).unwrap();
+        block_info_cursor.as_mut_ref().read_exact(hash)?;
 
         // read block info
-        let block_count = block_info_cursor.int32();
+        if block_info_cursor.remaining() < 4 {
+            return Err("block info truncated".into());
+        }
+        let block_count = block_info_cursor.int32()?;
+        // each block takes 10 bytes, followed by the node count
+        if block_count < 0 || block_count as u64 * 10 + 4 > block_info_cursor.remaining() {
+            return Err(format!("block info truncated, {} blocks", block_count).into());
+        }
         let mut block_infos: Vec<(i32, i32, u32)> = Vec::new();
-        let mut total_block_decompress_size = 0;
         for _ in 0..block_count {
-            let d_size = block_info_cursor.int32();
-            let c_size = block_info_cursor.int32();
-            let flags  = block_info_cursor.int16() as u32;
+            let d_size = block_info_cursor.int32()?;
+            let c_size = block_info_cursor.int32()?;
+            let flags  = block_info_cursor.int16()? as u32;
             //info!("d_size : {}, c_size : {}, flags : {}", d_size, c_size, flags);
-            total_block_decompress_size += d_size;
+            if d_size < 0 || c_size < 0 {
+                return Err(format!("invalid block sizes {} / {}", c_size, d_size).into());
+            }
             block_infos.push( (d_size, c_size, flags) );
         }
 
         // decompress asset data
-        let mut raw_asset_cursor = Cursor::new(vec![0u8; total_block_decompress_size as usize]);
+        let mut raw_asset_cursor = Cursor::new(Vec::new());
+        let block_flags = block_infos.iter().map(|i| i.2).collect();
         for i in block_infos {
             let mut buf = vec![0u8; i.1 as usize];
-            file.as_mut_ref().read_exact(&mut buf).unwrap();
-            buf = decompress_chunk(buf.as_slice(), i.0, i.2).unwrap();
-            std::io::copy(&mut buf.as_slice(), &mut raw_asset_cursor).unwrap();
+            file.as_mut_ref().read_exact(&mut buf)?;
+            buf = decompress_chunk(buf.as_slice(), i.0, i.2)?;
+            std::io::copy(&mut buf.as_slice(), &mut raw_asset_cursor)?;
         }
         let raw_asset_buf = raw_asset_cursor.into_inner();
 
         // 各アセット情報抽出
-        let asset_count = block_info_cursor.int32();
+        let fallback_version = match unity_version {
+            Some(v) => v.clone(),
+            None => UnityVersion::parse(&upper_player_version).unwrap_or_default(),
+        };
+        let raw_asset_buf = Arc::new(raw_asset_buf);
+        let asset_count = block_info_cursor.int32()?;
         let mut assets: Vec<Asset> = Vec::new();
+        let mut resources: Vec<ResourceFile> = Vec::new();
         for _ in 0..asset_count {
-            let offset = block_info_cursor.uint64() as usize;
-            let size   = block_info_cursor.uint64() as usize;
-            let status = block_info_cursor.uint32();
-            let name   = block_info_cursor.cstr();
-            let data   = &raw_asset_buf[offset .. offset + size];
-            let asset = Asset::read(&name, status, &data).unwrap();
-            assets.push(asset);
+            // offset, size and status take 20 bytes before the name
+            if block_info_cursor.remaining() < 20 {
+                return Err(format!("node info truncated, {} nodes", asset_count).into());
+            }
+            let offset = block_info_cursor.uint64()? as usize;
+            let size   = block_info_cursor.uint64()? as usize;
+            let status = block_info_cursor.uint32()?;
+            let name   = block_info_cursor.cstr()?;
+            let node   = offset.checked_add(size).filter(|end| *end <= raw_asset_buf.len()).map(|end| offset .. end)
+                .ok_or_else(|| format!("{}: {}..+{} is outside of the {} decompressed bytes", name, offset, size, raw_asset_buf.len()))?;
+            match status & NODE_SERIALIZED_FILE != 0 {
+                true => assets.push(Asset::read_node(&name, status, &raw_asset_buf, node, &fallback_version)?),
+                false => resources.push(ResourceFile::shared(name, &raw_asset_buf, node)),
+            }
         }
         Ok(AssetBundle{
             signiture: String::from("UnityFS"),
-            file_version: file_version,
-            lower_player_version: lower_player_version,
-            upper_player_version: upper_player_version,
-            total_file_size: total_file_size,
-            compressed_block_info_size: compressed_block_info_size,
-            decompressed_block_info_size: decompressed_block_info_size,
-            flags: flags,
-            assets: assets,
+            file_version,
+            lower_player_version,
+            upper_player_version,
+            total_file_size,
+            compressed_block_info_size,
+            decompressed_block_info_size,
+            flags,
+            assets,
+            resources,
+            block_flags,
+            issues: Vec::new(),
         })
     }
+
+    /// whether `dir` is the Data folder of a built player (`*_Data`, `assets/bin/Data` of an APK)
+    pub fn is_player_data(dir: &Path) -> bool {
+        dir.is_dir() && PLAYER_DATA_MARKERS.iter().any(|m| dir.join(m).is_file())
+    }
+
+    /// reads the serialized files of a player's Data folder as one bundle
+    ///
+    /// .resS and .resource files are only sized here and read once a payload in them is needed.
+    pub fn load_player_data(dir: &Path, unity_version: Option<&UnityVersion>) -> Result<AssetBundle> {
+        let mut files = Vec::new();
+        let mut resources = Vec::new();
+        for dir in [dir.to_path_buf(), dir.join("Resources")].iter().filter(|d| d.is_dir()) {
+            for path in fs::read_dir(dir)?.filter_map(|e| e.ok().map(|e| e.path())).filter(|p| p.is_file()) {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                match name.ends_with(".resS") || name.ends_with(".resource") {
+                    true => resources.push(ResourceFile::on_disk(name, path.clone(), fs::metadata(&path)?.len())),
+                    false => files.push((name, fs::read(&path)?)),
+                }
+            }
+        }
+        let mut bundle = AssetBundle::from_player_files(files, unity_version)
+            .map_err(|e| format!("{:?}: {}", dir, e))?;
+        bundle.total_file_size += resources.iter().map(|r| r.size as i64).sum::<i64>();
+        bundle.resources.extend(resources);
+        Ok(bundle)
+    }
+
+    /// builds one bundle of the files of a player's Data folder, given by file name
+    ///
+    /// globalgamemanagers, level*, sharedassets*.assets, resources.assets and the
+    /// built-in resources become assets named by their file name; .resS and
+    /// .resource files become resources. Files split into `.splitN` parts are
+    /// joined, and data.unity3d of compressed builds is read as a bundle. Files
+    /// that fail to parse are skipped and listed in `issues`.
+    pub fn from_player_files(files: Vec<(String, Vec<u8>)>, unity_version: Option<&UnityVersion>) -> Result<AssetBundle> {
+        let fallback_version = unity_version.cloned().unwrap_or_default();
+        let mut joined: BTreeMap<String, Vec<(u32, Vec<u8>)>> = BTreeMap::new();
+        for (name, data) in files {
+            let split = name.rsplit_once(".split").and_then(|(base, n)| Some((base.to_string(), n.parse::<u32>().ok()?)));
+            let (name, part) = split.unwrap_or((name, 0));
+            joined.entry(name).or_default().push((part, data));
+        }
+        let mut assets: Vec<Asset> = Vec::new();
+        let mut resources: Vec<ResourceFile> = Vec::new();
+        let mut issues = Vec::new();
+        let mut total_file_size = 0;
+        for (name, mut parts) in joined {
+            parts.sort_by_key(|p| p.0);
+            let data = parts.into_iter().flat_map(|p| p.1).collect::<Vec<u8>>();
+            total_file_size += data.len() as i64;
+            let read = if data.starts_with(b"UnityFS\0") {
+                AssetBundle::read(Cursor::new(data), unity_version).map(|bundle| {
+                    assets.extend(bundle.assets);
+                    resources.extend(bundle.resources);
+                })
+            } else if is_serialized_file(&data) {
+                Asset::read(&name, NODE_SERIALIZED_FILE, data, &fallback_version).map(|asset| assets.push(asset))
+            } else {
+                if name.ends_with(".resS") || name.ends_with(".resource") {
+                    resources.push(ResourceFile::new(name.clone(), data));
+                }
+                Ok(())
+            };
+            if let Err(e) = read {
+                warn!("{}: {}", name, e);
+                issues.push(format!("{}: {}", name, e));
+            }
+        }
+        if assets.is_empty() {
+            return Err(match issues.is_empty() {
+                true => "no serialized file".into(),
+                false => format!("no readable serialized file; {}", issues.join("; ")).into(),
+            });
+        }
+        let version = assets[0].unity_version().to_string();
+        Ok(AssetBundle{
+            signiture: String::from("PlayerData"),
+            file_version: 0,
+            lower_player_version: version.clone(),
+            upper_player_version: version,
+            total_file_size,
+            compressed_block_info_size: 0,
+            decompressed_block_info_size: 0,
+            flags: 0,
+            assets,
+            resources,
+            block_flags: Vec::new(),
+            issues,
+        })
+    }
+
+    pub fn assets(&self) -> &[Asset] {
+        &self.assets
+    }
+
+    /// non serialized nodes (.resS, .resource)
+    pub fn resources(&self) -> &[ResourceFile] {
+        &self.resources
+    }
+
+    /// compressions of the data blocks, e.g. lz4 for a bundle recompressed by Caching
+    pub fn block_compressions(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.block_flags.iter().map(|f| decompress::compression_name(*f)).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// resource node such as "CAB-xxxx.resS"
+    pub fn resource(&self, name: &str) -> Option<&ResourceFile> {
+        self.resources.iter().find(|r| r.name == name)
+    }
+
+    /// files of a player's Data folder that could not be read
+    pub fn issues(&self) -> &[String] {
+        &self.issues
+    }
+}
+
+/// file size recorded in a serialized file header, None when `header` is not one
+pub fn serialized_file_size(header: &[u8]) -> Option<u64> {
+    let u32_at = |pos: usize| header.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    let format = u32_at(8).filter(|f| (9..=50).contains(f))?;
+    match format >= 22 {
+        true => header.get(24..32).map(|b| u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+        false => u32_at(4).map(|s| s as u64),
+    }
+}
+
+/// Unity content of a bundle or serialized file found inside a larger container
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Analysis {
+    pub unity_version: Option<String>,
+    pub files: Vec<String>,         // serialized files of a bundle
+    pub objects: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Unity version, file names and object count of serialized files
+pub fn describe(assets: &[Asset]) -> Option<(String, Vec<String>, usize)> {
+    let first = assets.first()?;
+    Some((first.unity_version().to_string(),
+        assets.iter().map(|a| a.name().to_string()).collect(),
+        assets.iter().map(|a| a.objects().len()).sum()))
+}
+
+/// parses the `name`d file of `kind` "bundle" or "serialized" in place, from the reader `open`
+/// returns, and describes it; other kinds are not opened
+pub fn analyse<R: Read + Seek>(kind: &str, name: &str, open: impl FnOnce() -> Result<R>, unity_version: Option<&UnityVersion>) -> Analysis {
+    let file_name = name.rsplit('/').next().unwrap_or(name).to_string();
+    let described = match kind {
+        "bundle" => open().and_then(|r| AssetBundle::read(r, unity_version)).map(|b| describe(b.assets())),
+        "serialized" => open().and_then(|mut r| {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data)?;
+            Asset::read(&file_name, NODE_SERIALIZED_FILE, data, &unity_version.cloned().unwrap_or_default())
+        }).map(|a| describe(slice::from_ref(&a))),
+        _ => Ok(None),
+    };
+    let mut analysis = Analysis::default();
+    match described {
+        Ok(Some((version, files, objects))) => {
+            analysis.unity_version = Some(version);
+            analysis.files = files;
+            analysis.objects = Some(objects);
+        },
+        Ok(None) => {},
+        Err(e) => {
+            warn!("{}: {}", name, e);
+            analysis.error = Some(e.to_string());
+        },
+    }
+    analysis
+}
+
+/// whether `data` starts with a serialized file header matching its length
+fn is_serialized_file(data: &[u8]) -> bool {
+    serialized_file_size(data) == Some(data.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, GAME_OBJECT_CLASS_ID};
+
+    fn level() -> Vec<u8> {
+        fixture::serialized_file(&[(GAME_OBJECT_CLASS_ID, fixture::game_object_type())],
+            &[(1, 0, fixture::game_object("root", &[]))])
+    }
+
+    #[test]
+    fn skip_unreadable_player_files() {
+        let mut corrupt = level();
+        corrupt[36..40].copy_from_slice(&u32::MAX.to_le_bytes());      // class count
+        let files = vec![
+            ("level0".to_string(), level()),
+            ("level1".to_string(), corrupt.clone()),
+            ("sharedassets0.assets.resS".to_string(), vec![7; 8]),
+        ];
+        let bundle = AssetBundle::from_player_files(files, None).unwrap();
+        assert_eq!(bundle.assets().len(), 1);
+        assert_eq!(bundle.issues().len(), 1);
+        assert!(bundle.issues()[0].starts_with("level1: "));
+        assert_eq!(bundle.resource("sharedassets0.assets.resS").unwrap().data().unwrap(), &[7; 8]);
+
+        assert!(AssetBundle::from_player_files(vec![("level1".to_string(), corrupt)], None).is_err());
+    }
+
+    #[test]
+    fn read_player_resources_on_use() {
+        let dir = std::env::temp_dir().join(format!("uabo-player-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("level0"), level()).unwrap();
+        fs::write(dir.join("level0.resS"), [1, 2, 3]).unwrap();
+        let bundle = AssetBundle::load_player_data(&dir, None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let resource = bundle.resource("level0.resS").unwrap();
+        assert_eq!(resource.size, 3);
+        assert!(resource.data().is_err());
+    }
 }
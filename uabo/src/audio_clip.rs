@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::fsb5::{Codec, Fsb5};
+use crate::object_value::ObjectValue;
+use crate::streaming_info::StreamingInfo;
+use crate::Result;
+
+/// AudioClip as serialized since 5.0: an FSB5 bank in m_Resource
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioClip {
+    pub name: String,
+    pub subsound_index: usize,  // sample of the bank played by the clip
+    pub resource: StreamingInfo,
+}
+
+impl AudioClip {
+    pub const CLASS_ID: i32 = 83;
+
+    pub fn from_value(v: &ObjectValue) -> Result<AudioClip> {
+        let name = v.string("m_Name")?.to_string();
+        let resource = v.get("m_Resource")
+            .and_then(StreamingInfo::from_value)
+            .ok_or_else(|| format!("{}: no m_Resource, clips older than 5.0 are not supported", name))?;
+        Ok(AudioClip{
+            subsound_index: v.get("m_SubsoundIndex").and_then(|i| i.as_u64()).unwrap_or(0) as usize,
+            name,
+            resource,
+        })
+    }
+}
+
+/// one exported sample, listed in audio_clips.json
+#[derive(Serialize, Clone, Debug)]
+struct ClipSummary {
+    name: String,
+    codec: Codec,
+    channels: u16,
+    frequency: u32,
+    samples: u64,           // length in sample frames
+    seconds: f64,
+    file: Option<String>,   // exported file
+    error: Option<String>,  // why the sample was not exported, e.g. a missing Vorbis setup header
+}
+
+/// writes the sample of every AudioClip into `dst`, PCM as WAV and Vorbis as Ogg,
+/// and lists every clip in `dst/audio_clips.json`
+///
+/// Vorbis setup headers are looked up in `vorbis_setup`.
+pub fn export(bundle: &AssetBundle, dst: &Path, vorbis_setup: Option<&Path>) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut summaries = Vec::new();
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(AudioClip::CLASS_ID) {
+                continue;
+            }
+            let loaded = asset.object_value(obj)
+                .and_then(|v| AudioClip::from_value(&v))
+                .and_then(|clip| {
+                    let bank = Fsb5::parse(clip.resource.load(bundle)?)?;
+                    Ok((clip, bank))
+                });
+            let (clip, bank) = match loaded {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let sample = match bank.samples.get(clip.subsound_index) {
+                Some(s) => s,
+                None => {
+                    warn!("{}: no sample {} in a bank of {}", clip.name, clip.subsound_index, bank.samples.len());
+                    continue;
+                },
+            };
+            let written = match bank.codec {
+                Codec::Vorbis => bank.to_ogg(clip.subsound_index, vorbis_setup).map(|b| (b, "ogg")),
+                _ => bank.to_wav(clip.subsound_index).map(|b| (b, "wav")),
+            };
+            let (file, error) = match written {
+                Ok((bytes, ext)) => {
+                    let path = export::unique_path(dst, &clip.name, ext);
+                    fs::write(&path, bytes)?;
+                    info!("{:?} {}ch {}Hz {:?}", bank.codec, sample.channels, sample.frequency, path);
+                    count += 1;
+                    (path.file_name().map(|f| f.to_string_lossy().into_owned()), None)
+                },
+                Err(e) => {
+                    warn!("{}: {}", clip.name, e);
+                    (None, Some(e.to_string()))
+                },
+            };
+            summaries.push(ClipSummary{
+                name: clip.name.clone(),
+                codec: bank.codec,
+                channels: sample.channels,
+                frequency: sample.frequency,
+                samples: sample.samples,
+                seconds: sample.samples as f64 / sample.frequency.max(1) as f64,
+                file,
+                error,
+            });
+        }
+    }
+    fs::write(dst.join("audio_clips.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(count)
+}
@@ -1,19 +1,34 @@
+use std::convert::TryInto;
 use std::io::{Read,Seek,SeekFrom};
 use crate::endian::Endian;
+use crate::Result;
 
 macro_rules! read_primitive {
     ($reader:expr, $T:tt, $size:tt, $endian:expr) => {
        {
            let mut buf: [u8;$size] = [0;$size];
-           $reader.read(&mut buf).unwrap();
-           match $endian {
+           $reader.read_exact(&mut buf)?;
+           Ok(match $endian {
                Endian::Big => $T::from_be_bytes(buf),
                Endian::Little => $T::from_le_bytes(buf),
-           }
+           })
        } 
     };
 }
 
+/// little-endian integers at `pos` of `b`, None past its end
+pub fn u16_at(b: &[u8], pos: usize) -> Option<u16> {
+    b.get(pos..pos + 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub fn u32_at(b: &[u8], pos: usize) -> Option<u32> {
+    b.get(pos..pos + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub fn u64_at(b: &[u8], pos: usize) -> Option<u64> {
+    b.get(pos..pos + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
 pub struct BinaryReader<T>{
     io: T,
     endian: Endian,
@@ -22,59 +37,76 @@ pub struct BinaryReader<T>{
 impl<T: Read+Seek> BinaryReader<T>{
     pub fn new(io: T, endian: Endian) -> BinaryReader<T> {
         BinaryReader{
-            io: io,
-            endian: endian
+            io,
+            endian
         }
     }
     pub fn as_mut_ref(&mut self) -> &mut T{ &mut self.io }
     pub fn set_endian(&mut self, endian: Endian) {
         self.endian = endian;
     }
-    pub fn int16(&mut self) -> i16 {
+    pub fn int8(&mut self) -> Result<i8> {
+        read_primitive!(self.as_mut_ref(), i8, 1, self.endian)
+    }
+    pub fn int16(&mut self) -> Result<i16> {
         read_primitive!(self.as_mut_ref(), i16, 2, self.endian)
     }
-    pub fn int32(&mut self) -> i32{
+    pub fn int32(&mut self) -> Result<i32> {
         read_primitive!(self.as_mut_ref(), i32, 4, self.endian)
     }
-    pub fn int64(&mut self) -> i64 {
+    pub fn int64(&mut self) -> Result<i64> {
         read_primitive!(self.as_mut_ref(), i64, 8, self.endian)
     }
-    pub fn uint8(&mut self) -> u8 {
+    pub fn uint8(&mut self) -> Result<u8> {
         read_primitive!(self.as_mut_ref(), u8, 1, self.endian)
     }
-    pub fn uint16(&mut self) -> u16 {
+    pub fn uint16(&mut self) -> Result<u16> {
         read_primitive!( self.as_mut_ref(), u16, 2, self.endian)
     }
-    pub fn uint32(&mut self) -> u32 {
+    pub fn uint32(&mut self) -> Result<u32> {
         read_primitive!( self.as_mut_ref(), u32, 4, self.endian)
     }
-    pub fn uint64(&mut self) -> u64 {
+    pub fn uint64(&mut self) -> Result<u64> {
         read_primitive!( self.as_mut_ref(), u64, 8, self.endian)
     }
-    pub fn boolean(&mut self) -> bool {
-        self.uint8() != 0
+    pub fn float32(&mut self) -> Result<f32> {
+        read_primitive!( self.as_mut_ref(), f32, 4, self.endian)
+    }
+    pub fn float64(&mut self) -> Result<f64> {
+        read_primitive!( self.as_mut_ref(), f64, 8, self.endian)
     }
-    pub fn cstr(&mut self) -> String {
+    pub fn boolean(&mut self) -> Result<bool> {
+        Ok(self.uint8()? != 0)
+    }
+    pub fn cstr(&mut self) -> Result<String> {
         let mut buf = Vec::new();
         let mut tmp = [0u8; 1];
         loop {
-            let readed = self.as_mut_ref().read(&mut tmp).unwrap();
+            let readed = self.as_mut_ref().read(&mut tmp)?;
             if readed == 0 { break; }
             if tmp[0] == 0 { break; }
             buf.push(tmp[0]);
         }
-        String::from_utf8(buf).expect("can not read signature.")
+        Ok(String::from_utf8_lossy(&buf).into_owned())
     }
 
     pub fn pos(&mut self) -> u64 {
-        self.as_mut_ref().seek(SeekFrom::Current(0)).unwrap()
+        self.as_mut_ref().stream_position().unwrap()
     }
 
-    pub fn indexed_cstr(&mut self, idx: u64 ) -> String {
+    /// bytes left until the end of the stream
+    pub fn remaining(&mut self) -> u64 {
         let pos = self.pos();
-        self.as_mut_ref().seek(SeekFrom::Start(idx)).unwrap();
-        let s = self.cstr();
+        let end = self.as_mut_ref().seek(SeekFrom::End(0)).unwrap();
         self.as_mut_ref().seek(SeekFrom::Start(pos)).unwrap();
+        end.saturating_sub(pos)
+    }
+
+    pub fn indexed_cstr(&mut self, idx: u64 ) -> Result<String> {
+        let pos = self.pos();
+        self.as_mut_ref().seek(SeekFrom::Start(idx))?;
+        let s = self.cstr();
+        self.as_mut_ref().seek(SeekFrom::Start(pos))?;
         s
     }
 
@@ -85,15 +117,23 @@ impl<T: Read+Seek> BinaryReader<T>{
         };
     }
     pub fn align(&mut self, val: u64) {
-        let pos = self.as_mut_ref().seek(SeekFrom::Current(0)).unwrap();
+        let pos = self.as_mut_ref().stream_position().unwrap();
         let offset = pos % val;
         if offset > 0 {
             self.as_mut_ref().seek(SeekFrom::Current((val - offset) as i64)).unwrap();
         }
     }
-    pub fn read(&mut self, val: usize) -> Vec<u8> {
+    /// reads a count and checks that as many `unit` sized entries fit in the rest of the stream
+    pub fn count(&mut self, unit: u64) -> Result<u32> {
+        let count = self.uint32()?;
+        match count as u64 * unit <= self.remaining() {
+            true => Ok(count),
+            false => Err(format!("invalid count {} at {}", count, self.pos()).into()),
+        }
+    }
+    pub fn read(&mut self, val: usize) -> Result<Vec<u8>> {
         let mut buf = vec![0u8; val];
-        self.as_mut_ref().read_exact(&mut buf).unwrap();
-        buf
+        self.as_mut_ref().read_exact(&mut buf)?;
+        Ok(buf)
     }
 }
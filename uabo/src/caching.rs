@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::{self, AssetBundle, BundleHeader, HEADER_PEEK};
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+const DATA_FILE: &str = "__data";
+const INFO_FILE: &str = "__info";
+
+/// `__info` of a cached version
+///
+/// The file holds lines of integers, among them the time the version was last used in unix
+/// seconds and the number of cached files, followed by the names of those files.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CacheInfo {
+    pub values: Vec<i64>,           // leading integer lines, as written
+    pub last_used: Option<i64>,     // unix seconds; Caching expires versions from it
+    pub files: Vec<String>,
+}
+
+impl CacheInfo {
+    pub fn parse(text: &str) -> CacheInfo {
+        let mut info = CacheInfo::default();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match line.parse::<i64>() {
+                Ok(v) if info.files.is_empty() => info.values.push(v),
+                _ => info.files.push(line.to_string()),
+            }
+        }
+        // the only value large enough to be a date since 2001
+        info.last_used = info.values.iter().copied().find(|v| *v >= 1_000_000_000);
+        info
+    }
+
+    /// file count recorded before the names, when one matches them
+    fn file_count_matches(&self) -> bool {
+        self.values.last().map(|c| *c == self.files.len() as i64).unwrap_or(false)
+    }
+}
+
+/// version of a bundle in the cache, `<name>/<hash>/`
+#[derive(Serialize, Clone, Debug)]
+pub struct CachedBundle {
+    pub name: String,
+    pub hash: String,               // Hash128 the version was cached under
+    pub path: PathBuf,
+    pub size: Option<u64>,          // of __data
+    pub info: Option<CacheInfo>,
+    pub compression: Vec<&'static str>,    // of the data blocks, lz4 when Caching recompressed it
+    pub unity_version: Option<String>,
+    pub files: Vec<String>,         // serialized files of the bundle
+    pub objects: Option<usize>,
+    pub issues: Vec<String>,
+}
+
+/// cache.json
+#[derive(Serialize, Debug, Default)]
+pub struct CacheReport {
+    pub bundles: Vec<CachedBundle>,
+    pub multiple_versions: BTreeMap<String, Vec<String>>,   // hashes of names cached more than once, last used first
+}
+
+/// directories of `dir` holding `__data` or `__info`, searched recursively
+fn version_dirs(dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.join(DATA_FILE).is_file() || dir.join(INFO_FILE).is_file() {
+        found.push(dir.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            version_dirs(&path, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// reads the cached version in `dir`, `root/<name>/<hash>`
+fn cached_bundle(root: &Path, dir: &Path, unity_version: Option<&UnityVersion>) -> CachedBundle {
+    let relative = dir.strip_prefix(root).unwrap_or(dir);
+    let hash = relative.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let name = relative.parent()
+        .map(|p| p.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+        .unwrap_or_default();
+    let mut bundle = CachedBundle{
+        name, hash, path: relative.to_path_buf(),
+        size: None, info: None, compression: Vec::new(), unity_version: None, files: Vec::new(), objects: None, issues: Vec::new(),
+    };
+    if bundle.name.is_empty() {
+        bundle.issues.push("version directory directly in the cache root, no bundle name".to_string());
+    }
+    if bundle.hash.len() != 32 || !bundle.hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bundle.issues.push(format!("{} is not a Hash128", bundle.hash));
+    }
+
+    match fs::read_to_string(dir.join(INFO_FILE)) {
+        Ok(text) => {
+            let info = CacheInfo::parse(&text);
+            if !info.file_count_matches() {
+                bundle.issues.push(format!("__info lists {} files after {:?}", info.files.len(), info.values));
+            }
+            for file in info.files.iter().filter(|f| !dir.join(f).is_file()) {
+                bundle.issues.push(format!("{} listed in __info is missing", file));
+            }
+            bundle.info = Some(info);
+        },
+        Err(e) => bundle.issues.push(format!("__info: {}", e)),
+    }
+
+    let data = dir.join(DATA_FILE);
+    let size = match fs::metadata(&data) {
+        Ok(m) => m.len(),
+        Err(e) => {
+            bundle.issues.push(format!("__data: {}", e));
+            return bundle;
+        },
+    };
+    bundle.size = Some(size);
+    // interrupted downloads leave a bundle shorter than its header says
+    let mut head = Vec::new();
+    let header = File::open(&data)
+        .and_then(|f| f.take(HEADER_PEEK).read_to_end(&mut head))
+        .ok()
+        .and_then(|_| BundleHeader::parse(&head));
+    match header {
+        None => bundle.issues.push("__data is not a UnityFS bundle".to_string()),
+        Some(h) if h.total_file_size != size => {
+            bundle.issues.push(format!("__data is {} bytes, its header records {}", size, h.total_file_size));
+        },
+        Some(_) => match AssetBundle::load(&data, unity_version) {
+            Ok(b) => {
+                bundle.compression = b.block_compressions();
+                if let Some((version, files, objects)) = asset_bundle::describe(b.assets()) {
+                    bundle.unity_version = Some(version);
+                    bundle.files = files;
+                    bundle.objects = Some(objects);
+                }
+            },
+            Err(e) => bundle.issues.push(format!("__data: {}", e)),
+        },
+    }
+    bundle
+}
+
+/// walks the cache copied to `root` (`<name>/<hash>/__data` and `__info`)
+pub fn report(root: &Path, unity_version: Option<&UnityVersion>) -> Result<CacheReport> {
+    if !root.is_dir() {
+        return Err(format!("{:?} is not a cache directory", root).into());
+    }
+    let mut dirs = Vec::new();
+    version_dirs(root, &mut dirs)?;
+    let mut report = CacheReport::default();
+    for dir in dirs {
+        let bundle = cached_bundle(root, &dir, unity_version);
+        for issue in &bundle.issues {
+            warn!("{:?}: {}", bundle.path, issue);
+        }
+        report.bundles.push(bundle);
+    }
+    let last_used = |b: &CachedBundle| b.info.as_ref().and_then(|i| i.last_used);
+    report.bundles.sort_by(|a, b| a.name.cmp(&b.name).then(last_used(b).cmp(&last_used(a))));
+
+    let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for bundle in &report.bundles {
+        versions.entry(bundle.name.clone()).or_default().push(bundle.hash.clone());
+    }
+    report.multiple_versions = versions.into_iter().filter(|(_, hashes)| hashes.len() > 1).collect();
+    Ok(report)
+}
+
+/// writes the report of the cache at `root` into `dst/cache.json`
+pub fn export(root: &Path, dst: &Path, unity_version: Option<&UnityVersion>) -> Result<CacheReport> {
+    fs::create_dir_all(dst)?;
+    let report = report(root, unity_version)?;
+    info!("{} cached versions, {} names cached more than once", report.bundles.len(), report.multiple_versions.len());
+    fs::write(dst.join("cache.json"), serde_json::to_string_pretty(&report)?)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_info() {
+        let info = CacheInfo::parse("-1\n1750000000\n2\n__data\nCAB-1234.resS\n");
+        assert_eq!(info.values, vec![-1, 1_750_000_000, 2]);
+        assert_eq!(info.last_used, Some(1_750_000_000));
+        assert_eq!(info.files, vec!["__data", "CAB-1234.resS"]);
+        assert!(info.file_count_matches());
+    }
+
+    #[test]
+    fn parse_info_without_date_or_matching_count() {
+        // names that look like numbers once the names started stay names
+        let info = CacheInfo::parse("  -1\r\n\n3\n__data\n42\n");
+        assert_eq!(info.values, vec![-1, 3]);
+        assert_eq!(info.last_used, None);
+        assert_eq!(info.files, vec!["__data", "42"]);
+        assert!(!info.file_count_matches());
+        assert!(!CacheInfo::parse("").file_count_matches());
+    }
+}
@@ -3,10 +3,15 @@ use log::{info};
 use serde::{Serialize, Deserialize};
 use crate::binary_reader::BinaryReader;
 use crate::type_info::TypeInfo;
+use crate::constants;
+use crate::unity_version::UnityVersion;
+use crate::Result;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ClassInfo {
     pub class_id: i32,
+    pub class_name: Option<String>,
+    pub base_class_name: Option<String>,
     pub stripped: Option<bool>,
     pub script_id: Option<i16>,
     pub hash: Option<String>,
@@ -14,14 +19,14 @@ pub struct ClassInfo {
 }
 
 impl ClassInfo {
-    pub fn new<T: Read+Seek>(reader: &mut BinaryReader<T>, format: u32, has_type_tree: bool) -> ClassInfo{
-        let class_id = reader.int32();
+    pub fn new<T: Read+Seek>(reader: &mut BinaryReader<T>, format: u32, has_type_tree: bool, unity_version: &UnityVersion) -> Result<ClassInfo>{
+        let class_id = reader.int32()?;
         let stripped = match format >= 16 {
-            true => Some(reader.boolean()),
+            true => Some(reader.boolean()?),
             false => None,
         };
         let script_id = match format >= 17 {
-            true => Some(reader.int16()),
+            true => Some(reader.int16()?),
             false => None,
         };
         let hash = match format >= 13 {
@@ -30,7 +35,10 @@ impl ClassInfo {
                     true => 32,
                     false => 16,
                 };
-                let buf = reader.read(size);
+                if reader.remaining() < size as u64 {
+                    return Err(format!("class {} truncated", class_id).into());
+                }
+                let buf = reader.read(size)?;
                 Some(buf.into_iter().map(|h| format!("{:02X}", h)).collect::<String>())
             },
             false => None,
@@ -38,17 +46,20 @@ impl ClassInfo {
         info!("class_id {}, stripped {:?}, script_id {:?}", class_id, stripped, script_id);
         let type_tree = match has_type_tree {
             true => {
-                Some(TypeInfo::load(reader, format))
+                Some(TypeInfo::load(reader, format, unity_version)?)
             },
             false => None,
         };
-        ClassInfo{
-            class_id: class_id,
-            stripped: stripped,
-            script_id: script_id,
-            hash: hash,
+        let record = constants::class_record(class_id, unity_version);
+        Ok(ClassInfo{
+            class_id,
+            class_name: record.map(|c| c.name.to_string()),
+            base_class_name: record.and_then(|c| c.base).map(String::from),
+            stripped,
+            script_id,
+            hash,
             types: type_tree,
-        }
+        })
     }
 }
 
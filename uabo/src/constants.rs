@@ -1,133 +1,514 @@
 use std::io::{Read,Seek};
 use crate::binary_reader::BinaryReader;
 use std::collections::HashMap;
+use crate::unity_version::UnityVersion;
+use crate::Result;
 
-pub fn get_string_or_default<T:Read+Seek>(pos: u32, reader: &mut BinaryReader<T>) -> String
+pub fn get_string_or_default<T:Read+Seek>(pos: u32, reader: &mut BinaryReader<T>, unity_version: &UnityVersion) -> Result<String>
 {
-    match pos & 0x80000000 == 0 {
-        true => reader.indexed_cstr(pos as u64), // buffer.unpack1("@#{pos}Z*")
+    Ok(match pos & 0x80000000 == 0 {
+        true => reader.indexed_cstr(pos as u64)?, // buffer.unpack1("@#{pos}Z*")
         false => {
             let idx = pos & 0x7fffffff;
-            match HASHMAP.get(&idx) {
+            match common_strings(unity_version).get(&idx) {
                 Some(s) => s.to_string(),
                 None => String::from(""),
             }
         }
+    })
+}
+
+/// common string table used by the player that generated the asset
+///
+/// Unity only ever appends to its common string buffer, so an unknown or
+/// stripped generator version falls back to the newest table.
+pub fn common_strings(unity_version: &UnityVersion) -> &'static HashMap<u32, &'static str> {
+    let tables = &*COMMON_STRINGS;
+    let latest = &tables[tables.len() - 1].1;
+    match unity_version.is_stripped() {
+        true => latest,
+        false => tables.iter().rev()
+            .find(|(since, _)| *since <= unity_version.release())
+            .map(|(_, m)| m)
+            .unwrap_or(latest),
+    }
+}
+
+/// class name for a class id as of the player that generated the asset
+pub fn class_name(class_id: i32, unity_version: &UnityVersion) -> Option<&'static str> {
+    class_record(class_id, unity_version).map(|c| c.name)
+}
+
+/// registry entry for a class id as of the player that generated the asset
+///
+/// Ids reused across releases resolve to the class alive in that release;
+/// for an unknown version, or a class outside its recorded lifetime, the
+/// newest registration wins.
+pub fn class_record(class_id: i32, unity_version: &UnityVersion) -> Option<&'static ClassRecord> {
+    if class_id < 0 {
+        // serialized files before format 16 store script types as negative ids
+        return class_record(114, unity_version);
+    }
+    let records = CLASSES.get(&class_id)?;
+    match unity_version.is_stripped() {
+        true => None,
+        false => records.iter().find(|c| c.contains(unity_version)),
+    }.or_else(|| records.last())
+}
+
+/// (major, minor) release numbers
+type Release = (u32, u32);
+
+/// release of a registry version bound ("2018.2" -> (2018, 2))
+fn release(version: &str) -> Option<Release> {
+    UnityVersion::parse(version).ok().map(|v| v.release())
+}
+
+/// runtime class known to the player
+#[derive(Clone, Debug)]
+pub struct ClassRecord {
+    pub name: &'static str,                   // class name
+    pub base: Option<&'static str>,           // base class name
+    pub first_version: Option<&'static str>,  // first release shipping the class
+    pub last_version: Option<&'static str>,   // last release shipping the class
+}
+
+impl ClassRecord {
+    fn contains(&self, version: &UnityVersion) -> bool {
+        let version = version.release();
+        let first = self.first_version.and_then(release).unwrap_or((0, 0));
+        let last = self.last_version.and_then(release).unwrap_or((u32::MAX, u32::MAX));
+        first <= version && version <= last
     }
 }
 
+/// common string buffer revisions; each release appends to the previous ones
+const COMMON_STRING_REVISIONS: &[(Release, &[(u32, &str)])] = &[
+    ((0, 0), &[
+        (0, "AABB"),
+        (5, "AnimationClip"),
+        (19, "AnimationCurve"),
+        (34, "AnimationState"),
+        (49, "Array"),
+        (55, "Base"),
+        (60, "BitField"),
+        (69, "bitset"),
+        (76, "bool"),
+        (81, "char"),
+        (86, "ColorRGBA"),
+        (96, "Component"),
+        (106, "data"),
+        (111, "deque"),
+        (117, "double"),
+        (124, "dynamic_array"),
+        (138, "FastPropertyName"),
+        (155, "first"),
+        (161, "float"),
+        (167, "Font"),
+        (172, "GameObject"),
+        (183, "Generic Mono"),
+        (196, "GradientNEW"),
+        (208, "GUID"),
+        (213, "GUIStyle"),
+        (222, "int"),
+        (226, "list"),
+        (231, "long long"),
+        (241, "map"),
+        (245, "Matrix4x4f"),
+        (256, "MdFour"),
+        (263, "MonoBehaviour"),
+        (277, "MonoScript"),
+        (288, "m_ByteSize"),
+        (299, "m_Curve"),
+        (307, "m_EditorClassIdentifier"),
+        (331, "m_EditorHideFlags"),
+        (349, "m_Enabled"),
+        (359, "m_ExtensionPtr"),
+        (374, "m_GameObject"),
+        (387, "m_Index"),
+        (395, "m_IsArray"),
+        (405, "m_IsStatic"),
+        (416, "m_MetaFlag"),
+        (427, "m_Name"),
+        (434, "m_ObjectHideFlags"),
+        (452, "m_PrefabInternal"),
+        (469, "m_PrefabParentObject"),
+        (490, "m_Script"),
+        (499, "m_StaticEditorFlags"),
+        (519, "m_Type"),
+        (526, "m_Version"),
+        (536, "Object"),
+        (543, "pair"),
+        (548, "PPtr<Component>"),
+        (564, "PPtr<GameObject>"),
+        (581, "PPtr<Material>"),
+        (596, "PPtr<MonoBehaviour>"),
+        (616, "PPtr<MonoScript>"),
+        (633, "PPtr<Object>"),
+        (646, "PPtr<Prefab>"),
+        (659, "PPtr<Sprite>"),
+        (672, "PPtr<TextAsset>"),
+        (688, "PPtr<Texture>"),
+        (702, "PPtr<Texture2D>"),
+        (718, "PPtr<Transform>"),
+        (734, "Prefab"),
+        (741, "Quaternionf"),
+        (753, "Rectf"),
+        (759, "RectInt"),
+        (767, "RectOffset"),
+        (778, "second"),
+        (785, "set"),
+        (789, "short"),
+        (795, "size"),
+        (800, "SInt16"),
+        (807, "SInt32"),
+        (814, "SInt64"),
+        (821, "SInt8"),
+        (827, "staticvector"),
+        (840, "string"),
+        (847, "TextAsset"),
+        (857, "TextMesh"),
+        (866, "Texture"),
+        (874, "Texture2D"),
+        (884, "Transform"),
+        (894, "TypelessData"),
+        (907, "UInt16"),
+        (914, "UInt32"),
+        (921, "UInt64"),
+        (928, "UInt8"),
+        (934, "unsigned int"),
+        (947, "unsigned long long"),
+        (966, "unsigned short"),
+        (981, "vector"),
+        (988, "Vector2f"),
+        (997, "Vector3f"),
+        (1006, "Vector4f"),
+    ]),
+    ((5, 0), &[
+        (1015, "m_ScriptingClassIdentifier"),
+        (1042, "Gradient"),
+    ]),
+    ((5, 5), &[
+        (1051, "Type*"),
+    ]),
+    ((2017, 1), &[
+        (1057, "int2_storage"),
+        (1070, "int3_storage"),
+        (1083, "BoundsInt"),
+    ]),
+    ((2018, 2), &[
+        (1093, "m_CorrespondingSourceObject"),
+    ]),
+    ((2018, 3), &[
+        (1121, "m_PrefabInstance"),
+        (1138, "m_PrefabAsset"),
+    ]),
+    ((2020, 1), &[
+        (1152, "FileSize"),
+        (1161, "Hash128"),
+    ]),
+    ((2021, 2), &[
+        (1169, "RenderingLayerMask"),
+    ]),
+];
+
+/// (id, name, base, first release, last release)
+type ClassRow = (i32, &'static str, Option<&'static str>, Option<&'static str>, Option<&'static str>);
+
+/// runtime classes
+const CLASS_REGISTRY: &[ClassRow] = &[
+    (0, "Object", None, None, None),
+    (1, "GameObject", Some("EditorExtension"), None, None),
+    (2, "Component", Some("EditorExtension"), None, None),
+    (3, "LevelGameManager", Some("GameManager"), None, None),
+    (4, "Transform", Some("Component"), None, None),
+    (5, "TimeManager", Some("GlobalGameManager"), None, None),
+    (6, "GlobalGameManager", Some("GameManager"), None, None),
+    (8, "Behaviour", Some("Component"), None, None),
+    (9, "GameManager", Some("Object"), None, None),
+    (11, "AudioManager", Some("GlobalGameManager"), None, None),
+    (12, "ParticleAnimator", Some("Component"), None, Some("2018.2")),
+    (13, "InputManager", Some("GlobalGameManager"), None, None),
+    (15, "EllipsoidParticleEmitter", Some("ParticleEmitter"), None, Some("2018.2")),
+    (17, "Pipeline", Some("Component"), None, Some("4.7")),
+    (18, "EditorExtension", Some("Object"), None, None),
+    (19, "Physics2DSettings", Some("GlobalGameManager"), None, None),
+    (20, "Camera", Some("Behaviour"), None, None),
+    (21, "Material", Some("NamedObject"), None, None),
+    (23, "MeshRenderer", Some("Renderer"), None, None),
+    (25, "Renderer", Some("Component"), None, None),
+    (26, "ParticleRenderer", Some("Renderer"), None, Some("2018.2")),
+    (27, "Texture", Some("NamedObject"), None, None),
+    (28, "Texture2D", Some("Texture"), None, None),
+    (29, "OcclusionCullingSettings", Some("LevelGameManager"), None, None),
+    (30, "GraphicsSettings", Some("GlobalGameManager"), None, None),
+    (33, "MeshFilter", Some("Component"), None, None),
+    (41, "OcclusionPortal", Some("Component"), None, None),
+    (43, "Mesh", Some("NamedObject"), None, None),
+    (45, "Skybox", Some("Behaviour"), None, None),
+    (47, "QualitySettings", Some("GlobalGameManager"), None, None),
+    (48, "Shader", Some("NamedObject"), None, None),
+    (49, "TextAsset", Some("NamedObject"), None, None),
+    (50, "Rigidbody2D", Some("Component"), None, None),
+    (53, "Collider2D", Some("Behaviour"), None, None),
+    (54, "Rigidbody", Some("Component"), None, None),
+    (55, "PhysicsManager", Some("GlobalGameManager"), None, None),
+    (56, "Collider", Some("Component"), None, None),
+    (57, "Joint", Some("Component"), None, None),
+    (58, "CircleCollider2D", Some("Collider2D"), None, None),
+    (59, "HingeJoint", Some("Joint"), None, None),
+    (60, "PolygonCollider2D", Some("Collider2D"), None, None),
+    (61, "BoxCollider2D", Some("Collider2D"), None, None),
+    (62, "PhysicsMaterial2D", Some("NamedObject"), None, None),
+    (64, "MeshCollider", Some("Collider"), None, None),
+    (65, "BoxCollider", Some("Collider"), None, None),
+    (66, "CompositeCollider2D", Some("Collider2D"), Some("2017.2"), None),
+    (68, "EdgeCollider2D", Some("Collider2D"), None, None),
+    (70, "CapsuleCollider2D", Some("Collider2D"), Some("5.5"), None),
+    (72, "ComputeShader", Some("NamedObject"), None, None),
+    (74, "AnimationClip", Some("Motion"), None, None),
+    (75, "ConstantForce", Some("Behaviour"), None, None),
+    (78, "TagManager", Some("GlobalGameManager"), None, None),
+    (81, "AudioListener", Some("AudioBehaviour"), None, None),
+    (82, "AudioSource", Some("AudioBehaviour"), None, None),
+    (83, "AudioClip", Some("SampleClip"), None, None),
+    (84, "RenderTexture", Some("Texture"), None, None),
+    (86, "CustomRenderTexture", Some("RenderTexture"), Some("5.6"), None),
+    (87, "MeshParticleEmitter", Some("ParticleEmitter"), None, Some("2018.2")),
+    (88, "ParticleEmitter", Some("Component"), None, Some("2018.2")),
+    (89, "Cubemap", Some("Texture"), None, None),
+    (90, "Avatar", Some("NamedObject"), None, None),
+    (91, "AnimatorController", Some("RuntimeAnimatorController"), None, None),
+    (92, "GUILayer", Some("Behaviour"), None, Some("2019.2")),
+    (93, "RuntimeAnimatorController", Some("NamedObject"), None, None),
+    (94, "ScriptMapper", Some("GameManager"), None, None),
+    (95, "Animator", Some("Behaviour"), None, None),
+    (96, "TrailRenderer", Some("Renderer"), None, None),
+    (98, "DelayedCallManager", Some("GlobalGameManager"), None, None),
+    (102, "TextMesh", Some("Component"), None, None),
+    (104, "RenderSettings", Some("LevelGameManager"), None, None),
+    (108, "Light", Some("Behaviour"), None, None),
+    (111, "Animation", Some("Behaviour"), None, None),
+    (114, "MonoBehaviour", Some("Behaviour"), None, None),
+    (115, "MonoScript", Some("TextAsset"), None, None),
+    (116, "MonoManager", Some("GlobalGameManager"), None, None),
+    (117, "Texture3D", Some("Texture"), None, None),
+    (119, "Projector", Some("Behaviour"), None, None),
+    (120, "LineRenderer", Some("Renderer"), None, None),
+    (121, "Flare", Some("NamedObject"), None, None),
+    (122, "Halo", Some("Behaviour"), None, None),
+    (123, "LensFlare", Some("Behaviour"), None, None),
+    (124, "FlareLayer", Some("Behaviour"), None, None),
+    (125, "HaloLayer", Some("Behaviour"), None, None),
+    (126, "NavMeshProjectSettings", Some("GlobalGameManager"), None, None),
+    (128, "Font", Some("NamedObject"), None, None),
+    (129, "PlayerSettings", Some("GlobalGameManager"), None, None),
+    (130, "NamedObject", Some("EditorExtension"), None, None),
+    (131, "GUITexture", Some("GUIElement"), None, Some("2019.2")),
+    (132, "GUIText", Some("GUIElement"), None, Some("2019.2")),
+    (133, "GUIElement", Some("Behaviour"), None, Some("2019.2")),
+    (134, "PhysicMaterial", Some("NamedObject"), None, None),
+    (135, "SphereCollider", Some("Collider"), None, None),
+    (136, "CapsuleCollider", Some("Collider"), None, None),
+    (137, "SkinnedMeshRenderer", Some("Renderer"), None, None),
+    (138, "FixedJoint", Some("Joint"), None, None),
+    (141, "BuildSettings", Some("GlobalGameManager"), None, None),
+    (142, "AssetBundle", Some("NamedObject"), None, None),
+    (143, "CharacterController", Some("Collider"), None, None),
+    (144, "CharacterJoint", Some("Joint"), None, None),
+    (145, "SpringJoint", Some("Joint"), None, None),
+    (146, "WheelCollider", Some("Collider"), None, None),
+    (147, "ResourceManager", Some("GlobalGameManager"), None, None),
+    (148, "NetworkView", Some("Behaviour"), None, Some("2018.1")),
+    (149, "NetworkManager", Some("GlobalGameManager"), None, Some("2018.1")),
+    (150, "PreloadData", Some("NamedObject"), None, None),
+    (152, "MovieTexture", Some("BaseVideoTexture"), None, Some("2019.4")),
+    (153, "ConfigurableJoint", Some("Joint"), None, None),
+    (154, "TerrainCollider", Some("Collider"), None, None),
+    (155, "MasterServerInterface", Some("GlobalGameManager"), None, Some("2018.1")),
+    (156, "TerrainData", Some("NamedObject"), None, None),
+    (157, "LightmapSettings", Some("LevelGameManager"), None, None),
+    (158, "WebCamTexture", Some("BaseVideoTexture"), None, None),
+    (164, "AudioReverbFilter", Some("AudioBehaviour"), None, None),
+    (165, "AudioHighPassFilter", Some("AudioBehaviour"), None, None),
+    (166, "AudioChorusFilter", Some("AudioBehaviour"), None, None),
+    (167, "AudioReverbZone", Some("Behaviour"), None, None),
+    (168, "AudioEchoFilter", Some("AudioBehaviour"), None, None),
+    (169, "AudioLowPassFilter", Some("AudioBehaviour"), None, None),
+    (170, "AudioDistortionFilter", Some("AudioBehaviour"), None, None),
+    (171, "SparseTexture", Some("Texture"), None, None),
+    (180, "AudioBehaviour", Some("Behaviour"), None, None),
+    (181, "AudioFilter", Some("Behaviour"), None, None),
+    (182, "WindZone", Some("Component"), None, None),
+    (183, "Cloth", Some("Component"), None, None),
+    (184, "SubstanceArchive", Some("NamedObject"), None, Some("2017.4")),
+    (185, "ProceduralMaterial", Some("Material"), None, Some("2017.4")),
+    (186, "ProceduralTexture", Some("Texture"), None, Some("2017.4")),
+    (187, "Texture2DArray", Some("Texture"), Some("5.4"), None),
+    (188, "CubemapArray", Some("Texture"), Some("5.4"), None),
+    (191, "OffMeshLink", Some("Component"), None, None),
+    (192, "OcclusionArea", Some("Component"), None, None),
+    (193, "Tree", Some("Component"), None, None),
+    (195, "NavMeshAgent", Some("Behaviour"), None, None),
+    (196, "NavMeshSettings", Some("LevelGameManager"), None, None),
+    (198, "ParticleSystem", Some("Component"), None, None),
+    (199, "ParticleSystemRenderer", Some("Renderer"), None, None),
+    (200, "ShaderVariantCollection", Some("NamedObject"), None, None),
+    (205, "LODGroup", Some("Component"), None, None),
+    (206, "BlendTree", Some("Motion"), None, None),
+    (207, "Motion", Some("NamedObject"), None, None),
+    (208, "NavMeshObstacle", Some("Behaviour"), None, None),
+    (210, "SortingGroup", Some("Behaviour"), Some("5.5"), None),
+    (212, "SpriteRenderer", Some("Renderer"), None, None),
+    (213, "Sprite", Some("NamedObject"), None, None),
+    (215, "ReflectionProbe", Some("Behaviour"), None, None),
+    (218, "Terrain", Some("Behaviour"), None, None),
+    (220, "LightProbeGroup", Some("Behaviour"), None, None),
+    (221, "AnimatorOverrideController", Some("RuntimeAnimatorController"), None, None),
+    (222, "CanvasRenderer", Some("Component"), None, None),
+    (223, "Canvas", Some("Behaviour"), None, None),
+    (224, "RectTransform", Some("Transform"), None, None),
+    (225, "CanvasGroup", Some("Behaviour"), None, None),
+    (226, "BillboardAsset", Some("NamedObject"), None, None),
+    (227, "BillboardRenderer", Some("Renderer"), None, None),
+    (228, "SpeedTreeWindAsset", Some("NamedObject"), None, None),
+    (229, "AnchoredJoint2D", Some("Joint2D"), None, None),
+    (230, "Joint2D", Some("Behaviour"), None, None),
+    (231, "SpringJoint2D", Some("AnchoredJoint2D"), None, None),
+    (232, "DistanceJoint2D", Some("AnchoredJoint2D"), None, None),
+    (233, "HingeJoint2D", Some("AnchoredJoint2D"), None, None),
+    (234, "SliderJoint2D", Some("AnchoredJoint2D"), None, None),
+    (235, "WheelJoint2D", Some("AnchoredJoint2D"), None, None),
+    (236, "ClusterInputManager", Some("GlobalGameManager"), None, None),
+    (237, "BaseVideoTexture", Some("Texture"), None, None),
+    (238, "NavMeshData", Some("NamedObject"), None, None),
+    (240, "AudioMixer", Some("NamedObject"), None, None),
+    (246, "PhysicsUpdateBehaviour2D", Some("Behaviour"), None, None),
+    (247, "ConstantForce2D", Some("PhysicsUpdateBehaviour2D"), None, None),
+    (248, "Effector2D", Some("Behaviour"), None, None),
+    (249, "AreaEffector2D", Some("Effector2D"), None, None),
+    (250, "PointEffector2D", Some("Effector2D"), None, None),
+    (251, "PlatformEffector2D", Some("Effector2D"), None, None),
+    (252, "SurfaceEffector2D", Some("Effector2D"), None, None),
+    (253, "BuoyancyEffector2D", Some("Effector2D"), Some("5.3"), None),
+    (254, "RelativeJoint2D", Some("Joint2D"), None, None),
+    (255, "FixedJoint2D", Some("AnchoredJoint2D"), None, None),
+    (256, "FrictionJoint2D", Some("AnchoredJoint2D"), None, None),
+    (257, "TargetJoint2D", Some("AnchoredJoint2D"), None, None),
+    (258, "LightProbes", Some("NamedObject"), None, None),
+    (259, "LightProbeProxyVolume", Some("Behaviour"), None, None),
+    (271, "SampleClip", Some("NamedObject"), None, None),
+    (272, "AudioMixerSnapshot", Some("NamedObject"), None, None),
+    (273, "AudioMixerGroup", Some("NamedObject"), None, None),
+    (290, "AssetBundleManifest", Some("NamedObject"), None, None),
+    (300, "RuntimeInitializeOnLoadManager", Some("GlobalGameManager"), None, None),
+    (310, "UnityConnectSettings", Some("GlobalGameManager"), None, None),
+    (319, "AvatarMask", Some("NamedObject"), None, None),
+    (320, "PlayableDirector", Some("Behaviour"), Some("2017.1"), None),
+    (328, "VideoPlayer", Some("Behaviour"), Some("5.6"), None),
+    (329, "VideoClip", Some("NamedObject"), Some("5.6"), None),
+    (330, "ParticleSystemForceField", Some("Behaviour"), Some("2018.3"), None),
+    (331, "SpriteMask", Some("Renderer"), Some("2017.1"), None),
+    (362, "WorldAnchor", Some("Component"), None, None),
+    (363, "OcclusionCullingData", Some("NamedObject"), None, None),
+    (1001, "Prefab", Some("Object"), None, Some("2018.2")),
+    (1001, "PrefabInstance", Some("Object"), Some("2018.3"), None),
+    (1101, "AnimatorStateTransition", Some("AnimatorTransitionBase"), None, None),
+    (1102, "AnimatorState", Some("Object"), None, None),
+    (1107, "AnimatorStateMachine", Some("Object"), None, None),
+    (1109, "AnimatorTransition", Some("AnimatorTransitionBase"), None, None),
+    (1111, "AnimatorTransitionBase", Some("Object"), None, None),
+    (19719996, "TilemapCollider2D", Some("Collider2D"), Some("2017.2"), None),
+    (73398921, "VFXRenderer", Some("Renderer"), Some("2018.3"), None),
+    (156049354, "Grid", Some("GridLayout"), Some("2017.2"), None),
+    (382020655, "PluginBuildInfo", Some("Object"), Some("2018.1"), None),
+    (483693784, "TilemapRenderer", Some("Renderer"), Some("2017.2"), None),
+    (641289076, "AudioBuildInfo", Some("Object"), Some("2018.1"), None),
+    (644342135, "CachedSpriteAtlasRuntimeData", Some("Object"), Some("2017.1"), None),
+    (668709126, "BuiltAssetBundleInfoSet", Some("Object"), Some("2018.1"), None),
+    (687078895, "SpriteAtlas", Some("NamedObject"), Some("2017.1"), None),
+    (825902497, "RayTracingShader", Some("NamedObject"), Some("2019.3"), None),
+    (850595691, "LightingSettings", Some("NamedObject"), Some("2020.1"), None),
+    (895512359, "AimConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (937362698, "VFXManager", Some("GlobalGameManager"), Some("2018.3"), None),
+    (1001480554, "Prefab", Some("Object"), Some("2018.3"), None),
+    (1183024399, "LookAtConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (1403656975, "StreamingManager", Some("GlobalGameManager"), Some("2018.2"), None),
+    (1480428607, "LowerResBlitTexture", Some("Object"), Some("2018.2"), None),
+    (1542919678, "StreamingController", Some("Behaviour"), Some("2018.2"), None),
+    (1660057539, "SceneRoots", Some("Object"), Some("2019.1"), None),
+    (1742807556, "GridLayout", Some("Behaviour"), Some("2017.2"), None),
+    (1773428102, "ParentConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (1818360608, "PositionConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (1818360609, "RotationConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (1818360610, "ScaleConstraint", Some("Behaviour"), Some("2018.1"), None),
+    (1839735485, "Tilemap", Some("GridLayout"), Some("2017.2"), None),
+    (1953259897, "TerrainLayer", Some("NamedObject"), Some("2018.3"), None),
+    (1971053207, "SpriteShapeRenderer", Some("Renderer"), Some("2018.1"), None),
+    (2058629509, "VisualEffectAsset", Some("VisualEffectObject"), Some("2018.3"), None),
+    (2058629511, "VisualEffectResource", Some("NamedObject"), Some("2018.3"), None),
+    (2059678085, "VisualEffectObject", Some("NamedObject"), Some("2018.3"), None),
+    (2083052967, "VisualEffect", Some("Behaviour"), Some("2018.3"), None),
+    (2083778819, "LocalizationAsset", Some("NamedObject"), Some("2018.2"), None),
+];
+
 lazy_static! {
-    static ref HASHMAP: HashMap<u32, &'static str> = {
+    static ref COMMON_STRINGS: Vec<(Release, HashMap<u32, &'static str>)> = {
+        let mut tables = Vec::new();
         let mut m = HashMap::new();
-        m.insert(0 , "AABB");
-        m.insert(5 , "AnimationClip");
-        m.insert(19 , "AnimationCurve");
-        m.insert(34 , "AnimationState");
-        m.insert(49 , "Array");
-        m.insert(55 , "Base");
-        m.insert(60 , "BitField");
-        m.insert(69 , "bitset");
-        m.insert(76 , "bool");
-        m.insert(81 , "char");
-        m.insert(86 , "ColorRGBA");
-        m.insert(96 , "Component");
-        m.insert(106 , "data");
-        m.insert(111 , "deque");
-        m.insert(117 , "double");
-        m.insert(124 , "dynamic_array");
-        m.insert(138 , "FastPropertyName");
-        m.insert(155 , "first");
-        m.insert(161 , "float");
-        m.insert(167 , "Font");
-        m.insert(172 , "GameObject");
-        m.insert(183 , "Generic Mono");
-        m.insert(196 , "GradientNEW");
-        m.insert(208 , "GUID");
-        m.insert(213 , "GUIStyle");
-        m.insert(222 , "int");
-        m.insert(226 , "list");
-        m.insert(231 , "long long");
-        m.insert(241 , "map");
-        m.insert(245 , "Matrix4x4f");
-        m.insert(256 , "MdFour");
-        m.insert(263 , "MonoBehaviour");
-        m.insert(277 , "MonoScript");
-        m.insert(288 , "m_ByteSize");
-        m.insert(299 , "m_Curve");
-        m.insert(307 , "m_EditorClassIdentifier");
-        m.insert(331 , "m_EditorHideFlags");
-        m.insert(349 , "m_Enabled");
-        m.insert(359 , "m_ExtensionPtr");
-        m.insert(374 , "m_GameObject");
-        m.insert(387 , "m_Index");
-        m.insert(395 , "m_IsArray");
-        m.insert(405 , "m_IsStatic");
-        m.insert(416 , "m_MetaFlag");
-        m.insert(427 , "m_Name");
-        m.insert(434 , "m_ObjectHideFlags");
-        m.insert(452 , "m_PrefabInternal");
-        m.insert(469 , "m_PrefabParentObject");
-        m.insert(490 , "m_Script");
-        m.insert(499 , "m_StaticEditorFlags");
-        m.insert(519 , "m_Type");
-        m.insert(526 , "m_Version");
-        m.insert(536 , "Object");
-        m.insert(543 , "pair");
-        m.insert(548 , "PPtr<Component>");
-        m.insert(564 , "PPtr<GameObject>");
-        m.insert(581 , "PPtr<Material>");
-        m.insert(596 , "PPtr<MonoBehaviour>");
-        m.insert(616 , "PPtr<MonoScript>");
-        m.insert(633 , "PPtr<Object>");
-        m.insert(646 , "PPtr<Prefab>");
-        m.insert(659 , "PPtr<Sprite>");
-        m.insert(672 , "PPtr<TextAsset>");
-        m.insert(688 , "PPtr<Texture>");
-        m.insert(702 , "PPtr<Texture2D>");
-        m.insert(718 , "PPtr<Transform>");
-        m.insert(734 , "Prefab");
-        m.insert(741 , "Quaternionf");
-        m.insert(753 , "Rectf");
-        m.insert(759 , "RectInt");
-        m.insert(767 , "RectOffset");
-        m.insert(778 , "second");
-        m.insert(785 , "set");
-        m.insert(789 , "short");
-        m.insert(795 , "size");
-        m.insert(800 , "SInt16");
-        m.insert(807 , "SInt32");
-        m.insert(814 , "SInt64");
-        m.insert(821 , "SInt8");
-        m.insert(827 , "staticvector");
-        m.insert(840 , "string");
-        m.insert(847 , "TextAsset");
-        m.insert(857 , "TextMesh");
-        m.insert(866 , "Texture");
-        m.insert(874 , "Texture2D");
-        m.insert(884 , "Transform");
-        m.insert(894 , "TypelessData");
-        m.insert(907 , "UInt16");
-        m.insert(914 , "UInt32");
-        m.insert(921 , "UInt64");
-        m.insert(928 , "UInt8");
-        m.insert(934 , "unsigned int");
-        m.insert(947 , "unsigned long long");
-        m.insert(966 , "unsigned short");
-        m.insert(981 , "vector");
-        m.insert(988 , "Vector2f");
-        m.insert(997 , "Vector3f");
-        m.insert(1006 , "Vector4f");
-        m.insert(1015 , "m_ScriptingClassIdentifier");
-        m.insert(1042 , "Gradient");
-        m.insert(1051 , "Type*");
-        m.insert(1057 , "int2_storage");
-        m.insert(1070 , "int3_storage");
-        m.insert(1083 , "BoundsInt");
-        m.insert(1093 , "m_CorrespondingSourceObject");
-        m.insert(1121 , "m_PrefabInstance");
-        m.insert(1138 , "m_PrefabAsset");
+        for (since, strings) in COMMON_STRING_REVISIONS {
+            for (idx, s) in strings.iter() {
+                m.insert(*idx, *s);
+            }
+            tables.push((*since, m.clone()));
+        }
+        tables
+    };
+    static ref CLASSES: HashMap<i32, Vec<ClassRecord>> = {
+        let mut m: HashMap<i32, Vec<ClassRecord>> = HashMap::new();
+        for (id, name, base, first_version, last_version) in CLASS_REGISTRY {
+            m.entry(*id).or_default().push(ClassRecord{
+                name,
+                base: *base,
+                first_version: *first_version,
+                last_version: *last_version,
+            });
+        }
         m
     };
-    static ref COUNT: usize = HASHMAP.len();
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::endian::Endian;
+
+    fn version(s: &str) -> UnityVersion {
+        UnityVersion::parse(s).unwrap()
+    }
+
+    #[test]
+    fn reused_class_ids() {
+        assert_eq!(class_name(1001, &version("2018.2.0f1")), Some("Prefab"));
+        assert_eq!(class_name(1001, &version("2019.4.0f1")), Some("PrefabInstance"));
+        assert_eq!(class_name(1001, &version("0.0.0")), Some("PrefabInstance"));
+        assert_eq!(class_name(-3, &version("5.6.0f1")), Some("MonoBehaviour"));
+        assert_eq!(class_record(224, &version("2019.4.0f1")).unwrap().base, Some("Transform"));
+        assert_eq!(class_name(7, &version("2019.4.0f1")), None);
+    }
+
+    #[test]
+    fn common_string_revisions() {
+        let old = common_strings(&version("2017.4.0f1"));
+        assert_eq!(old.get(&1121), None);
+        assert_eq!(common_strings(&version("2019.4.0f1")).get(&1121), Some(&"m_PrefabInstance"));
+        assert_eq!(common_strings(&version("0.0.0")).get(&1169), Some(&"RenderingLayerMask"));
+
+        let mut reader = BinaryReader::new(Cursor::new(b"m_Name\0".to_vec()), Endian::Little);
+        let v = version("2019.4.0f1");
+        assert_eq!(get_string_or_default(0x80000000 | 172, &mut reader, &v).unwrap(), "GameObject");
+        assert_eq!(get_string_or_default(0x80000000 | 1, &mut reader, &v).unwrap(), "");
+        assert_eq!(get_string_or_default(0, &mut reader, &v).unwrap(), "m_Name");
+    }
+}
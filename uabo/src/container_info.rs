@@ -1,6 +1,14 @@
-
+use std::collections::HashMap;
+use std::slice;
 use serde::{Serialize, Deserialize};
 
+use crate::asset_bundle::AssetBundle;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, PPtr};
+
+const ASSET_BUNDLE_CLASS_ID: i32 = 142;
+
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ContainerInfo {
     name: String,
@@ -8,4 +16,32 @@ pub struct ContainerInfo {
     preload_size: u32,
     file_id: u32,
     path_id: u32,
-}
\ No newline at end of file
+}
+
+/// container path of each object listed in the AssetBundle's m_Container, by asset name and path id
+pub fn container_paths(bundle: &AssetBundle) -> HashMap<(String, i64), String> {
+    let mut paths = HashMap::new();
+    for asset in bundle.assets() {
+        for obj in asset.objects().iter().filter(|o| asset.class_id(o) == Some(ASSET_BUNDLE_CLASS_ID)) {
+            let v = match asset.object_value(obj) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let entries = match v.get("m_Container") {
+                Some(ObjectValue::Array(entries)) => entries,
+                _ => continue,
+            };
+            for entry in entries {
+                let path = entry.get("first").and_then(|p| p.as_str());
+                let ptr = entry.get("second").and_then(|s| s.get("asset")).and_then(PPtr::from_value);
+                if let (Some(path), Some(ptr)) = (path, ptr) {
+                    if let Some(target) = pptr::resolve(slice::from_ref(bundle), asset, &ptr) {
+                        // several entries may name one object; keep the first
+                        paths.entry((target.asset.name().to_string(), target.object.path_id)).or_insert_with(|| path.to_string());
+                    }
+                }
+            }
+        }
+    }
+    paths
+}
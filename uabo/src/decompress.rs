@@ -3,8 +3,19 @@ use crate::Result;
 pub fn decompress_chunk(src: &[u8], dst_size: i32, flags: u32) -> Result<Vec<u8>> {
     match flags & 0x3F {
         0   => Ok(src.to_vec()),
-        1   => Ok(lzma::decompress(&src).unwrap()),
-        2|3 => Ok(lz4::block::decompress(&src, Some(dst_size)).unwrap()),
+        1   => lzma::decompress(src).map_err(|e| format!("lzma: {}", e).into()),
+        2|3 => lz4::block::decompress(src, Some(dst_size)).map_err(|e| format!("lz4: {}", e).into()),
         _   => Err(format!("invalid flag : {}", flags).into())
     }
-}
\ No newline at end of file
+}
+
+/// name of the compression selected by the `flags` of a block or bundle header
+pub fn compression_name(flags: u32) -> &'static str {
+    match flags & 0x3F {
+        0 => "none",
+        1 => "lzma",
+        2 => "lz4",
+        3 => "lz4hc",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+
+/// file name safe version of an object or container name
+pub fn sanitize(name: &str) -> String {
+    let s: String = name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match s.trim().is_empty() {
+        true => String::from("unnamed"),
+        false => s,
+    }
+}
+
+/// `dir/name.ext`, suffixed with a counter when the file already exists; an empty `ext`
+/// names a directory
+pub fn unique_path(dir: &Path, name: &str, ext: &str) -> PathBuf {
+    let name = sanitize(name);
+    let dot = match ext.is_empty() {
+        true => "",
+        false => ".",
+    };
+    let mut path = dir.join(format!("{}{}{}", name, dot, ext));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}_{}{}{}", name, n, dot, ext));
+        n += 1;
+    }
+    path
+}
+
+/// `dir` joined with a container path such as `assets/data/config.json`, each component
+/// sanitized and `..` dropped, suffixed like `unique_path` when the file already exists
+pub fn container_path(dir: &Path, container: &str) -> PathBuf {
+    let mut parts: Vec<String> = container.split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != "." && *p != "..")
+        .map(sanitize)
+        .collect();
+    let file = parts.pop().unwrap_or_else(|| String::from("unnamed"));
+    let parent = parts.iter().fold(dir.to_path_buf(), |p, c| p.join(c));
+    match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => unique_path(&parent, stem, ext),
+        _ => {
+            let mut path = parent.join(&file);
+            let mut n = 1;
+            while path.exists() {
+                path = parent.join(format!("{}_{}", file, n));
+                n += 1;
+            }
+            path
+        },
+    }
+}
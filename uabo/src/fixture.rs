@@ -0,0 +1,208 @@
+//! serialized files with type trees, written for unit tests
+
+use crate::asset_bundle::AssetBundle;
+
+/// type tree flag asking for 4 byte alignment after the field
+pub const ALIGN: u32 = 0x4000;
+
+/// type tree node as laid out depth first: level, type, name and flags; "Array" nodes are arrays
+pub type Node = (u8, &'static str, &'static str, u32);
+
+/// format 17 little-endian serialized file of 2019.4.0f1 with type trees
+///
+/// `classes` are class ids with their type trees, `objects` path ids, class
+/// indices and data.
+pub fn serialized_file(classes: &[(i32, Vec<Node>)], objects: &[(i64, u32, Vec<u8>)]) -> Vec<u8> {
+    file(true, classes, objects)
+}
+
+/// serialized file as built for players, without type trees
+pub fn stripped_file(class_ids: &[i32], objects: &[(i64, u32, Vec<u8>)]) -> Vec<u8> {
+    let classes: Vec<(i32, Vec<Node>)> = class_ids.iter().map(|c| (*c, Vec::new())).collect();
+    file(false, &classes, objects)
+}
+
+fn file(type_trees: bool, classes: &[(i32, Vec<Node>)], objects: &[(i64, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut meta = b"2019.4.0f1\0".to_vec();
+    meta.extend(5i32.to_le_bytes());        // platform
+    meta.push(type_trees as u8);
+    meta.extend((classes.len() as u32).to_le_bytes());
+    for (class_id, nodes) in classes {
+        meta.extend(class_id.to_le_bytes());
+        meta.push(0);
+        meta.extend((-1i16).to_le_bytes());
+        meta.extend(vec![0xab; if *class_id == 114 { 32 } else { 16 }]);
+        let mut strings = Vec::new();
+        let mut offset = |s: &str| -> u32 {
+            let at = strings.len() as u32;
+            strings.extend(s.bytes().chain([0]));
+            at
+        };
+        let mut tree = Vec::new();
+        for (index, (level, type_str, name, flags)) in nodes.iter().enumerate() {
+            tree.extend(1u16.to_le_bytes());
+            tree.push(*level);
+            tree.push((*type_str == "Array") as u8);
+            tree.extend(offset(type_str).to_le_bytes());
+            tree.extend(offset(name).to_le_bytes());
+            tree.extend((-1i32).to_le_bytes());
+            tree.extend((index as u32).to_le_bytes());
+            tree.extend(flags.to_le_bytes());
+        }
+        if type_trees {
+            meta.extend((nodes.len() as u32).to_le_bytes());
+            meta.extend((strings.len() as u32).to_le_bytes());
+            meta.extend(tree);
+            meta.extend(strings);
+        }
+    }
+    meta.extend((objects.len() as u32).to_le_bytes());
+    let mut data = Vec::new();
+    for (path_id, class_index, bytes) in objects {
+        while !(20 + meta.len()).is_multiple_of(4) {
+            meta.push(0);
+        }
+        meta.extend(path_id.to_le_bytes());
+        meta.extend((data.len() as u32).to_le_bytes());
+        meta.extend((bytes.len() as u32).to_le_bytes());
+        meta.extend(class_index.to_le_bytes());
+        data.extend(bytes);
+        data.resize(data.len().div_ceil(8) * 8, 0);
+    }
+    meta.extend(0u32.to_le_bytes());        // add ids
+    meta.extend(0u32.to_le_bytes());        // references
+    meta.push(0);                           // comment
+    let offset = (20 + meta.len()).div_ceil(16) * 16;
+    let mut file = Vec::new();
+    for field in [meta.len(), offset + data.len(), 17, offset] {
+        file.extend((field as u32).to_be_bytes());
+    }
+    file.extend([0; 4]);
+    file.extend(meta);
+    file.resize(offset, 0);
+    file.extend(data);
+    file
+}
+
+/// player data bundle of named serialized files
+pub fn bundle(files: Vec<(&str, Vec<u8>)>) -> AssetBundle {
+    let files = files.into_iter().map(|(name, data)| (name.to_string(), data)).collect();
+    AssetBundle::from_player_files(files, None).unwrap()
+}
+
+/// nodes of a PPtr field at `level`
+pub fn pptr(level: u8, type_str: &'static str, name: &'static str) -> Vec<Node> {
+    vec![(level, type_str, name, 0), (level + 1, "int", "m_FileID", 0), (level + 1, "SInt64", "m_PathID", 0)]
+}
+
+/// little-endian object data
+#[derive(Default)]
+pub struct Writer(pub Vec<u8>);
+
+impl Writer {
+    pub fn int(mut self, v: i32) -> Writer {
+        self.0.extend(v.to_le_bytes());
+        self
+    }
+
+    pub fn uint(self, v: u32) -> Writer {
+        self.int(v as i32)
+    }
+
+    pub fn short(mut self, v: u16) -> Writer {
+        self.0.extend(v.to_le_bytes());
+        self
+    }
+
+    pub fn float(mut self, v: f32) -> Writer {
+        self.0.extend(v.to_le_bytes());
+        self
+    }
+
+    pub fn boolean(mut self, v: bool) -> Writer {
+        self.0.push(v as u8);
+        self
+    }
+
+    pub fn pptr(mut self, path_id: i64) -> Writer {
+        self.0.extend(0i32.to_le_bytes());
+        self.0.extend(path_id.to_le_bytes());
+        self
+    }
+
+    /// length prefixed string aligned to 4 bytes
+    pub fn string(mut self, s: &str) -> Writer {
+        self = self.int(s.len() as i32);
+        self.0.extend(s.as_bytes());
+        self.align()
+    }
+
+    pub fn align(mut self) -> Writer {
+        self.0.resize(self.0.len().div_ceil(4) * 4, 0);
+        self
+    }
+}
+
+pub const GAME_OBJECT_CLASS_ID: i32 = 1;
+
+/// GameObject type tree of 2019.4
+pub fn game_object_type() -> Vec<Node> {
+    let mut nodes = vec![
+        (0, "GameObject", "Base", 0),
+        (1, "vector", "m_Component", 0),
+        (2, "Array", "Array", 0),
+        (3, "int", "size", 0),
+        (3, "ComponentPair", "data", 0),
+    ];
+    nodes.extend(pptr(4, "PPtr<Component>", "component"));
+    nodes.extend([
+        (1, "unsigned int", "m_Layer", 0),
+        (1, "string", "m_Name", 0),
+        (2, "Array", "Array", ALIGN),
+        (3, "int", "size", 0),
+        (3, "char", "data", 0),
+        (1, "UInt16", "m_Tag", 0),
+        (1, "bool", "m_IsActive", ALIGN),
+    ]);
+    nodes
+}
+
+/// data of an active untagged GameObject on the default layer
+pub fn game_object(name: &str, components: &[i64]) -> Vec<u8> {
+    let mut w = Writer::default().int(components.len() as i32);
+    for c in components {
+        w = w.pptr(*c);
+    }
+    w.uint(0).string(name).short(0).boolean(true).align().0
+}
+
+/// Transform type tree of 2019.4
+pub fn transform_type() -> Vec<Node> {
+    let mut nodes = vec![(0, "Transform", "Base", 0)];
+    nodes.extend(pptr(1, "PPtr<GameObject>", "m_GameObject"));
+    for (type_str, name, components) in [
+        ("Quaternionf", "m_LocalRotation", &["x", "y", "z", "w"][..]),
+        ("Vector3f", "m_LocalPosition", &["x", "y", "z"][..]),
+        ("Vector3f", "m_LocalScale", &["x", "y", "z"][..]),
+    ] {
+        nodes.push((1, type_str, name, 0));
+        nodes.extend(components.iter().map(|c| (2, "float", *c, 0)));
+    }
+    nodes.extend([(1, "vector", "m_Children", 0), (2, "Array", "Array", 0), (3, "int", "size", 0)]);
+    nodes.extend(pptr(3, "PPtr<Transform>", "data"));
+    nodes.extend(pptr(1, "PPtr<Transform>", "m_Father"));
+    nodes
+}
+
+/// data of a Transform at `position`, unrotated and unscaled
+pub fn transform(game_object: i64, position: [f32; 3], children: &[i64], father: i64) -> Vec<u8> {
+    let mut w = Writer::default().pptr(game_object);
+    for v in [0.0, 0.0, 0.0, 1.0].iter().chain(&position).chain(&[1.0, 1.0, 1.0]) {
+        w = w.float(*v);
+    }
+    w = w.int(children.len() as i32);
+    for c in children {
+        w = w.pptr(*c);
+    }
+    w.pptr(father).0
+}
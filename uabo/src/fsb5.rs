@@ -0,0 +1,362 @@
+use std::fs;
+use std::path::Path;
+use log::warn;
+use serde::Serialize;
+
+use crate::binary_reader;
+use crate::ogg::OggWriter;
+use crate::Result;
+
+/// FSB5 sample rates by the 4 bit index of the sample header
+const FREQUENCIES: [u32; 11] = [4000, 8000, 11000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 96000];
+
+// sample header chunk types
+const CHUNK_CHANNELS: u32 = 1;
+const CHUNK_FREQUENCY: u32 = 2;
+const CHUNK_LOOP: u32 = 3;
+const CHUNK_VORBIS: u32 = 11;
+
+// FMOD always encodes Vorbis with 256 and 2048 sample blocks
+const VORBIS_BLOCKSIZES: (u8, u8) = (8, 11);
+
+/// FMOD_SOUND_FORMAT of a bank
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    PcmFloat,
+    GcAdpcm,
+    ImaAdpcm,
+    Vag,
+    HeVag,
+    Xma,
+    Mpeg,
+    Celt,
+    At9,
+    XWma,
+    Vorbis,
+    FAdpcm,
+    Opus,
+    Unknown(u32),
+}
+
+impl Codec {
+    fn new(mode: u32) -> Codec {
+        use Codec::*;
+        [None, Pcm8, Pcm16, Pcm24, Pcm32, PcmFloat, GcAdpcm, ImaAdpcm, Vag, HeVag, Xma, Mpeg, Celt, At9, XWma, Vorbis, FAdpcm, Opus]
+            .get(mode as usize)
+            .copied()
+            .unwrap_or(Unknown(mode))
+    }
+}
+
+/// one sample (subsound) of a bank
+#[derive(Serialize, Clone, Debug)]
+pub struct Sample {
+    pub name: Option<String>,
+    pub frequency: u32,
+    pub channels: u16,
+    pub samples: u64,                   // length in sample frames
+    pub loop_range: Option<(u32, u32)>, // loop start and end frames
+    pub vorbis_crc: Option<u32>,        // CRC32 of the Vorbis setup header FMOD left out
+    pub offset: usize,                  // offset in the sample data
+    pub size: usize,
+}
+
+/// FMOD sound bank version 5
+#[derive(Serialize, Clone, Debug)]
+pub struct Fsb5 {
+    pub version: u32,
+    pub codec: Codec,
+    pub samples: Vec<Sample>,
+    #[serde(skip)]
+    data: Vec<u8>,                      // sample data of every sample
+}
+
+impl Fsb5 {
+    pub fn parse(d: &[u8]) -> Result<Fsb5> {
+        let u32_at = |o: usize| -> Result<u32> {
+            binary_reader::u32_at(d, o).ok_or_else(|| "truncated FSB5 bank".into())
+        };
+        if d.len() < 0x3c || &d[0..4] != b"FSB5" {
+            return Err("not an FSB5 bank".into());
+        }
+        let version = u32_at(4)?;
+        let count = u32_at(8)? as usize;
+        let headers_size = u32_at(12)? as usize;
+        let names_size = u32_at(16)? as usize;
+        let data_size = u32_at(20)? as usize;
+        let codec = Codec::new(u32_at(24)?);
+        // version 0 has one more reserved word
+        let header_size = match version {
+            0 => 0x40,
+            _ => 0x3c,
+        };
+
+        let mut samples = Vec::with_capacity(count.min(0x10000));
+        let mut pos = header_size;
+        for _ in 0..count {
+            let lo = u32_at(pos)? as u64;
+            let hi = u32_at(pos + 4)? as u64;
+            let mode = lo | hi << 32;
+            pos += 8;
+            let mut sample = Sample{
+                name: None,
+                frequency: FREQUENCIES.get((mode >> 1 & 0xf) as usize).copied().unwrap_or(44100),
+                channels: [1, 2, 6, 8][(mode >> 5 & 3) as usize],
+                samples: mode >> 34,
+                loop_range: None,
+                vorbis_crc: None,
+                offset: ((mode >> 7 & 0x7ff_ffff) << 5) as usize,
+                size: 0,
+            };
+            let mut more = mode & 1 != 0;
+            while more {
+                let chunk = u32_at(pos)?;
+                more = chunk & 1 != 0;
+                let size = (chunk >> 1 & 0xff_ffff) as usize;
+                let body = pos + 4;
+                match chunk >> 25 {
+                    CHUNK_CHANNELS => sample.channels = *d.get(body).ok_or("truncated FSB5 chunk")? as u16,
+                    CHUNK_FREQUENCY => sample.frequency = u32_at(body)?,
+                    CHUNK_LOOP => sample.loop_range = Some((u32_at(body)?, u32_at(body + 4)?)),
+                    CHUNK_VORBIS => sample.vorbis_crc = Some(u32_at(body)?),
+                    _ => {},
+                }
+                pos = body + size;
+            }
+            samples.push(sample);
+        }
+
+        let names = header_size + headers_size;
+        if names_size > 0 {
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let start = names + u32_at(names + i * 4)? as usize;
+                let end = d[start.min(d.len())..].iter().position(|b| *b == 0).map(|p| start + p);
+                sample.name = end.map(|end| String::from_utf8_lossy(&d[start..end]).into_owned());
+            }
+        }
+
+        let data_start = names + names_size;
+        let data = d.get(data_start .. data_start + data_size)
+            .ok_or_else(|| format!("FSB5 sample data {}..{} out of range", data_start, data_start + data_size))?;
+        let offsets: Vec<usize> = samples.iter().map(|s| s.offset).collect();
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let end = offsets.get(i + 1).copied().unwrap_or(data_size);
+            if end < sample.offset || end > data_size {
+                return Err(format!("FSB5 sample {} out of range", i).into());
+            }
+            sample.size = end - sample.offset;
+        }
+        Ok(Fsb5{ version, codec, samples, data: data.to_vec() })
+    }
+
+    /// encoded bytes of sample `index`
+    pub fn sample_data(&self, index: usize) -> Option<&[u8]> {
+        let s = self.samples.get(index)?;
+        Some(&self.data[s.offset .. s.offset + s.size])
+    }
+
+    /// RIFF WAVE of a PCM sample
+    pub fn to_wav(&self, index: usize) -> Result<Vec<u8>> {
+        let sample = self.samples.get(index).ok_or_else(|| format!("no sample {}", index))?;
+        let data = self.sample_data(index).unwrap();
+        let (bits, tag) = match self.codec {
+            Codec::Pcm8 => (8, 1),
+            Codec::Pcm16 => (16, 1),
+            Codec::Pcm24 => (24, 1),
+            Codec::Pcm32 => (32, 1),
+            Codec::PcmFloat => (32, 3),
+            c => return Err(format!("{:?} samples cannot be written as WAV", c).into()),
+        };
+        let block_align = sample.channels as u32 * bits / 8;
+        let frames = (data.len() as u32 / block_align.max(1)).min(sample.samples as u32);
+        let data = &data[..(frames * block_align) as usize];
+        let byte_rate = sample.frequency.checked_mul(block_align)
+            .ok_or_else(|| format!("{} Hz sample rate too high for WAV", sample.frequency))?;
+
+        let mut out = Vec::with_capacity(44 + data.len());
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&(tag as u16).to_le_bytes());
+        out.extend_from_slice(&sample.channels.to_le_bytes());
+        out.extend_from_slice(&sample.frequency.to_le_bytes());
+        out.extend_from_slice(&byte_rate.to_le_bytes());
+        out.extend_from_slice(&(block_align as u16).to_le_bytes());
+        out.extend_from_slice(&(bits as u16).to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        match self.codec {
+            // FMOD's 8 bit PCM is signed, WAV's unsigned
+            Codec::Pcm8 => out.extend(data.iter().map(|b| b ^ 0x80)),
+            _ => out.extend_from_slice(data),
+        }
+        Ok(out)
+    }
+
+    /// Ogg Vorbis of a Vorbis sample
+    ///
+    /// FMOD strips the identification, comment and setup headers. The first two
+    /// are rebuilt from the sample header, the setup header is read from
+    /// `setup_dir` as `<crc32>.bin`, the CRC written as 8 hex digits or in decimal.
+    pub fn to_ogg(&self, index: usize, setup_dir: Option<&Path>) -> Result<Vec<u8>> {
+        let sample = self.samples.get(index).ok_or_else(|| format!("no sample {}", index))?;
+        if self.codec != Codec::Vorbis {
+            return Err(format!("{:?} samples cannot be written as Ogg Vorbis", self.codec).into());
+        }
+        let crc = sample.vorbis_crc.ok_or("Vorbis sample without setup header CRC")?;
+        let setup = vorbis_setup(crc, setup_dir)?;
+
+        let ident = vorbis_ident(sample.channels as u8, sample.frequency);
+        let ident_header = lewton::header::read_header_ident(&ident)?;
+        let setup_header = lewton::header::read_header_setup(&setup, sample.channels as u8, VORBIS_BLOCKSIZES)?;
+
+        let mut ogg = OggWriter::new(crc);
+        ogg.packet(&ident, 0, true);
+        ogg.packet(&vorbis_comment(), 0, false);
+        ogg.packet(&setup, 0, true);
+
+        let packets = vorbis_packets(self.sample_data(index).unwrap())?;
+        let mut granule = 0u64;
+        for (i, packet) in packets.iter().enumerate() {
+            // the first packet only primes the decoder
+            if i > 0 {
+                granule += lewton::audio::get_decoded_sample_count(&ident_header, &setup_header, packet)? as u64;
+            }
+            let granule = match i + 1 == packets.len() {
+                true => sample.samples,
+                false => granule.min(sample.samples),
+            };
+            ogg.packet(packet, granule as i64, false);
+        }
+        Ok(ogg.finish())
+    }
+}
+
+/// setup header packet of `crc`, with its "\x05vorbis" prefix
+fn vorbis_setup(crc: u32, setup_dir: Option<&Path>) -> Result<Vec<u8>> {
+    let dir = setup_dir.ok_or_else(|| format!("Vorbis setup header {:08x} needed, pass --vorbis-setup with a directory of <crc32>.bin setup headers", crc))?;
+    let path = [format!("{:08x}.bin", crc), format!("{}.bin", crc)].iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("Vorbis setup header {:08x}.bin not found in the --vorbis-setup directory {:?}", crc, dir))?;
+    let mut setup = fs::read(&path)?;
+    if !setup.starts_with(b"\x05vorbis") {
+        setup.splice(0..0, b"\x05vorbis".iter().copied());
+    }
+    if crc32fast::hash(&setup) != crc && crc32fast::hash(&setup[7..]) != crc {
+        warn!("{:?} does not match CRC {:08x}", path, crc);
+    }
+    Ok(setup)
+}
+
+/// identification header packet
+fn vorbis_ident(channels: u8, frequency: u32) -> Vec<u8> {
+    let mut p = b"\x01vorbis".to_vec();
+    p.extend_from_slice(&0u32.to_le_bytes());
+    p.push(channels);
+    p.extend_from_slice(&frequency.to_le_bytes());
+    p.extend_from_slice(&[0; 12]);      // maximum, nominal and minimum bitrate
+    p.push(VORBIS_BLOCKSIZES.0 | VORBIS_BLOCKSIZES.1 << 4);
+    p.push(1);                          // framing
+    p
+}
+
+/// comment header packet with a vendor string and no comments
+fn vorbis_comment() -> Vec<u8> {
+    let vendor = b"uabo";
+    let mut p = b"\x03vorbis".to_vec();
+    p.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    p.extend_from_slice(vendor);
+    p.extend_from_slice(&0u32.to_le_bytes());
+    p.push(1);
+    p
+}
+
+/// audio packets of a sample, each stored after its u16 length
+fn vorbis_packets(mut d: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut packets = Vec::new();
+    while d.len() >= 2 {
+        let size = u16::from_le_bytes([d[0], d[1]]) as usize;
+        if size == 0 {
+            break;
+        }
+        let packet = d.get(2 .. 2 + size).ok_or("truncated Vorbis packet")?;
+        packets.push(packet);
+        d = &d[2 + size..];
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// bank of `codec` with `count` samples described by `headers`
+    fn bank(codec: u32, count: u32, headers: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut d = b"FSB5".to_vec();
+        for v in &[1, count, headers.len() as u32, 0, data.len() as u32, codec] {
+            d.extend_from_slice(&v.to_le_bytes());
+        }
+        d.resize(0x3c, 0);
+        d.extend_from_slice(headers);
+        d.extend_from_slice(data);
+        d
+    }
+
+    fn mode(chunks: bool, frequency: u64, channels: u64, offset: u64, samples: u64) -> [u8; 8] {
+        (chunks as u64 | frequency << 1 | channels << 5 | (offset >> 5) << 7 | samples << 34).to_le_bytes()
+    }
+
+    fn chunk(kind: u32, more: bool, body: &[u8]) -> Vec<u8> {
+        let mut c = (more as u32 | (body.len() as u32) << 1 | kind << 25).to_le_bytes().to_vec();
+        c.extend_from_slice(body);
+        c
+    }
+
+    #[test]
+    fn mode_bits() {
+        let mut headers = mode(false, 8, 1, 0, 4).to_vec();
+        headers.extend_from_slice(&mode(true, 3, 0, 32, 3));
+        headers.extend(chunk(CHUNK_FREQUENCY, true, &32000u32.to_le_bytes()));
+        headers.extend(chunk(CHUNK_LOOP, true, &[1, 0, 0, 0, 2, 0, 0, 0]));
+        headers.extend(chunk(CHUNK_VORBIS, false, &0xdead_beefu32.to_le_bytes()));
+        let d = bank(2, 2, &headers, &[0; 38]);
+
+        let fsb = Fsb5::parse(&d).unwrap();
+        assert_eq!(fsb.codec, Codec::Pcm16);
+        let first = &fsb.samples[0];
+        assert_eq!((first.frequency, first.channels, first.samples, first.offset, first.size), (44100, 2, 4, 0, 32));
+        let second = &fsb.samples[1];
+        assert_eq!((second.frequency, second.channels, second.samples, second.offset, second.size), (32000, 1, 3, 32, 6));
+        assert_eq!(second.loop_range, Some((1, 2)));
+        assert_eq!(second.vorbis_crc, Some(0xdead_beef));
+    }
+
+    #[test]
+    fn pcm8_wav_is_unsigned() {
+        let d = bank(1, 1, &mode(false, 8, 0, 0, 2), &[0x00, 0x80]);
+        let wav = Fsb5::parse(&d).unwrap().to_wav(0).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[44..], &[0x80, 0x00]);
+    }
+
+    #[test]
+    fn wav_byte_rate_overflow() {
+        let mut headers = mode(true, 8, 1, 0, 1).to_vec();
+        headers.extend(chunk(CHUNK_FREQUENCY, false, &u32::MAX.to_le_bytes()));
+        let d = bank(4, 1, &headers, &[0; 32]);
+        assert!(Fsb5::parse(&d).unwrap().to_wav(0).is_err());
+    }
+
+    #[test]
+    fn sample_out_of_range() {
+        let d = bank(2, 1, &mode(false, 8, 0, 64, 1), &[0; 8]);
+        assert!(Fsb5::parse(&d).is_err());
+    }
+}
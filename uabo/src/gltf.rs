@@ -0,0 +1,263 @@
+use serde_json::{json, Value};
+
+use crate::mesh::{Mesh, Topology};
+
+// accessor component types
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+// buffer view targets
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// glTF 2.0 document with a single binary buffer, written as GLB
+///
+/// Unity's left handed data is mirrored on x and uvs are flipped to a top-left origin.
+#[derive(Default)]
+pub struct Gltf {
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    textures: Vec<Value>,
+    images: Vec<Value>,
+    skins: Vec<Value>,
+    animations: Vec<Value>,
+    accessors: Vec<Value>,
+    buffer_views: Vec<Value>,
+    roots: Vec<usize>,
+    bin: Vec<u8>,
+}
+
+impl Gltf {
+    pub fn new() -> Gltf {
+        Gltf::default()
+    }
+
+    /// appends `data` to the buffer on a 4 byte boundary
+    fn buffer_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        self.bin.resize(self.bin.len().div_ceil(4) * 4, 0);
+        let mut view = json!({ "buffer": 0, "byteOffset": self.bin.len(), "byteLength": data.len() });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(data);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    /// float accessor of `width` components per item; positions need their bounds
+    pub fn floats(&mut self, items: &[f32], width: usize, bounds: bool, target: Option<u32>) -> usize {
+        let bytes: Vec<u8> = items.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let view = self.buffer_view(&bytes, target);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": items.len() / width,
+            "type": match width {
+                1 => "SCALAR",
+                2 => "VEC2",
+                3 => "VEC3",
+                4 => "VEC4",
+                _ => "MAT4",
+            },
+        });
+        if bounds {
+            let column = |c: usize| items.iter().skip(c).step_by(width);
+            accessor["min"] = json!((0..width).map(|c| column(c).fold(f32::MAX, |a, b| a.min(*b))).collect::<Vec<f32>>());
+            accessor["max"] = json!((0..width).map(|c| column(c).fold(f32::MIN, |a, b| a.max(*b))).collect::<Vec<f32>>());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.buffer_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn joints(&mut self, joints: &[[u32; 4]]) -> usize {
+        let bytes: Vec<u8> = joints.iter().flatten().flat_map(|j| (*j as u16).to_le_bytes()).collect();
+        let view = self.buffer_view(&bytes, Some(ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_SHORT,
+            "count": joints.len(),
+            "type": "VEC4",
+        }));
+        self.accessors.len() - 1
+    }
+
+    /// adds `mesh` with one primitive per submesh, using `materials[i]` for submesh i;
+    /// `skinned` adds joints and weights for a skin
+    pub fn add_mesh(&mut self, mesh: &Mesh, materials: &[Option<usize>], skinned: bool) -> usize {
+        let mirror3 = |v: &[[f32; 3]]| -> Vec<f32> { v.iter().flat_map(|p| [-p[0], p[1], p[2]]).collect() };
+        let mut attributes = serde_json::Map::new();
+        let positions = mirror3(&mesh.positions);
+        attributes.insert("POSITION".into(), json!(self.floats(&positions, 3, true, Some(ARRAY_BUFFER))));
+        if mesh.normals.len() == mesh.vertex_count && mesh.vertex_count > 0 {
+            let normals = mirror3(&mesh.normals);
+            attributes.insert("NORMAL".into(), json!(self.floats(&normals, 3, false, Some(ARRAY_BUFFER))));
+        }
+        if mesh.tangents.len() == mesh.vertex_count && mesh.vertex_count > 0 {
+            // mirroring x and flipping v both flip the bitangent, keeping w
+            let tangents: Vec<f32> = mesh.tangents.iter().flat_map(|t| [-t[0], t[1], t[2], t[3]]).collect();
+            attributes.insert("TANGENT".into(), json!(self.floats(&tangents, 4, false, Some(ARRAY_BUFFER))));
+        }
+        for (name, uvs) in [("TEXCOORD_0", &mesh.uv0), ("TEXCOORD_1", &mesh.uv1)] {
+            if uvs.len() == mesh.vertex_count && mesh.vertex_count > 0 {
+                let uvs: Vec<f32> = uvs.iter().flat_map(|uv| [uv[0], 1.0 - uv[1]]).collect();
+                attributes.insert(name.into(), json!(self.floats(&uvs, 2, false, Some(ARRAY_BUFFER))));
+            }
+        }
+        if mesh.colors.len() == mesh.vertex_count && mesh.vertex_count > 0 {
+            let colors: Vec<f32> = mesh.colors.iter().flatten().copied().collect();
+            attributes.insert("COLOR_0".into(), json!(self.floats(&colors, 4, false, Some(ARRAY_BUFFER))));
+        }
+        if skinned && mesh.joints.len() == mesh.vertex_count && mesh.vertex_count > 0 {
+            attributes.insert("JOINTS_0".into(), json!(self.joints(&mesh.joints)));
+            let weights: Vec<f32> = mesh.weights.iter().flatten().copied().collect();
+            attributes.insert("WEIGHTS_0".into(), json!(self.floats(&weights, 4, false, Some(ARRAY_BUFFER))));
+        }
+
+        // morph targets take the full weight frame of each blend shape
+        let targets: Vec<Value> = mesh.blend_shapes.iter()
+            .filter_map(|shape| shape.frames.last())
+            .map(|frame| {
+                let mut target = json!({ "POSITION": self.floats(&mirror3(&frame.positions), 3, true, Some(ARRAY_BUFFER)) });
+                if let (Some(normals), true) = (&frame.normals, attributes.contains_key("NORMAL")) {
+                    target["NORMAL"] = json!(self.floats(&mirror3(normals), 3, false, Some(ARRAY_BUFFER)));
+                }
+                target
+            })
+            .collect();
+
+        let primitives: Vec<Value> = mesh.submeshes.iter().enumerate()
+            .filter(|(_, sub)| !sub.indices.is_empty())
+            .map(|(i, sub)| {
+                let (mode, indices) = match sub.topology {
+                    // the mirror flips the winding
+                    Topology::Triangles => (4, sub.indices.chunks_exact(3).flat_map(|t| [t[0], t[2], t[1]]).collect()),
+                    Topology::Lines => (1, sub.indices.clone()),
+                    Topology::LineStrip => (3, sub.indices.clone()),
+                    Topology::Points => (0, sub.indices.clone()),
+                };
+                let mut primitive = json!({ "attributes": attributes, "indices": self.indices(&indices), "mode": mode });
+                if let Some(Some(material)) = materials.get(i) {
+                    primitive["material"] = json!(material);
+                }
+                if !targets.is_empty() {
+                    primitive["targets"] = json!(targets);
+                }
+                primitive
+            })
+            .collect();
+
+        let mut value = json!({ "name": mesh.name, "primitives": primitives });
+        if !targets.is_empty() {
+            value["weights"] = json!(vec![0.0; targets.len()]);
+            value["extras"] = json!({ "targetNames": mesh.blend_shapes.iter()
+                .filter(|s| !s.frames.is_empty())
+                .map(|s| s.name.as_str())
+                .collect::<Vec<&str>>() });
+        }
+        self.meshes.push(value);
+        self.meshes.len() - 1
+    }
+
+    /// embeds a PNG image and returns its texture index
+    pub fn add_texture(&mut self, name: &str, png: &[u8]) -> usize {
+        let view = self.buffer_view(png, None);
+        self.images.push(json!({ "name": name, "bufferView": view, "mimeType": "image/png" }));
+        self.textures.push(json!({ "source": self.images.len() - 1 }));
+        self.textures.len() - 1
+    }
+
+    pub fn add_material(&mut self, material: Value) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// skin of `joints` nodes with Unity's row major bind poses
+    pub fn add_skin(&mut self, joints: &[usize], bind_poses: &[[f32; 16]]) -> usize {
+        // mirror on x on both sides: S * M * S with S = diag(-1, 1, 1, 1), written column major
+        let matrices: Vec<f32> = bind_poses.iter()
+            .flat_map(|m| (0..16).map(move |i| {
+                let (column, row) = (i / 4, i % 4);
+                match (row == 0) != (column == 0) {
+                    true => -m[row * 4 + column],
+                    false => m[row * 4 + column],
+                }
+            }))
+            .collect();
+        let inverse_bind_matrices = self.floats(&matrices, 16, false, None);
+        self.skins.push(json!({ "joints": joints, "inverseBindMatrices": inverse_bind_matrices }));
+        self.skins.len() - 1
+    }
+
+    pub fn add_animation(&mut self, animation: Value) -> usize {
+        self.animations.push(animation);
+        self.animations.len() - 1
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut Value {
+        &mut self.nodes[index]
+    }
+
+    pub fn add_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// marks node `index` as a root of the scene
+    pub fn add_root(&mut self, index: usize) {
+        self.roots.push(index);
+    }
+
+    /// whole document as binary glTF
+    pub fn to_glb(&self) -> Vec<u8> {
+        let mut root = json!({
+            "asset": { "version": "2.0", "generator": "uabo" },
+            "scene": 0,
+            "scenes": [{ "nodes": self.roots }],
+            "nodes": self.nodes,
+            "buffers": [{ "byteLength": self.bin.len() }],
+        });
+        for (name, items) in [
+            ("meshes", &self.meshes),
+            ("materials", &self.materials),
+            ("textures", &self.textures),
+            ("images", &self.images),
+            ("skins", &self.skins),
+            ("animations", &self.animations),
+            ("accessors", &self.accessors),
+            ("bufferViews", &self.buffer_views),
+        ] {
+            if !items.is_empty() {
+                root[name] = json!(items);
+            }
+        }
+        let mut json = serde_json::to_vec(&root).unwrap();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let mut bin = self.bin.clone();
+        bin.resize(bin.len().div_ceil(4) * 4, 0);
+        let mut out = Vec::with_capacity(28 + json.len() + bin.len());
+        out.extend_from_slice(b"glTF");
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&(28 + json.len() as u32 + bin.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"JSON");
+        out.extend_from_slice(&json);
+        out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"BIN\0");
+        out.extend_from_slice(&bin);
+        out
+    }
+}
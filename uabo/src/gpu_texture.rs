@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::object_info::ObjectInfo;
+use crate::object_value::ObjectValue;
+use crate::streaming_info::StreamingInfo;
+use crate::texture::{dds, ktx2, TextureFormat};
+use crate::texture_2d::Texture2D;
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+const CUBEMAP_CLASS_ID: i32 = 89;
+const TEXTURE3D_CLASS_ID: i32 = 117;
+const TEXTURE2D_ARRAY_CLASS_ID: i32 = 187;
+
+/// GPU container written by `export`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Dds,
+    Ktx2,
+}
+
+impl Container {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Dds => "dds",
+            Container::Ktx2 => "ktx2",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dimension {
+    Tex2D,
+    Cube,
+    Tex2DArray,
+    Tex3D,
+}
+
+/// texture payload exactly as uploaded by Unity
+///
+/// `data` keeps Unity's layout: image after image with its whole mip chain
+/// for 2D, cube and array textures, level after level with all depth slices
+/// for 3D textures. Rows run bottom to top.
+#[derive(Serialize, Clone, Debug)]
+pub struct GpuTexture {
+    pub name: String,
+    pub dimension: Dimension,
+    pub format: TextureFormat,
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,     // depth of 3D textures, 1 otherwise
+    pub images: u32,    // faces of cubemaps, slices of arrays, 1 otherwise
+    pub levels: u32,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+impl GpuTexture {
+    /// loads a Texture2D, Cubemap, Texture2DArray or Texture3D; None for other classes
+    pub fn load(bundle: &AssetBundle, asset: &Asset, obj: &ObjectInfo) -> Option<Result<GpuTexture>> {
+        let class_id = asset.class_id(obj)?;
+        let dimension = match class_id {
+            Texture2D::CLASS_ID => Dimension::Tex2D,
+            CUBEMAP_CLASS_ID => Dimension::Cube,
+            TEXTURE2D_ARRAY_CLASS_ID => Dimension::Tex2DArray,
+            TEXTURE3D_CLASS_ID => Dimension::Tex3D,
+            _ => return None,
+        };
+        let version = asset.unity_version();
+        Some(asset.object_value(obj).and_then(|v| match dimension {
+            Dimension::Tex2D | Dimension::Cube => GpuTexture::from_texture_2d(bundle, &v, dimension, version),
+            _ => GpuTexture::from_volume(bundle, &v, dimension, version),
+        }))
+    }
+
+    /// Texture2D and Cubemap share the Texture2D layout
+    fn from_texture_2d(bundle: &AssetBundle, v: &ObjectValue, dimension: Dimension, version: &UnityVersion) -> Result<GpuTexture> {
+        let t = Texture2D::from_value(v)?;
+        let images = match dimension {
+            Dimension::Cube => 6,
+            _ => 1,
+        };
+        if dimension == Dimension::Cube && t.image_count != 6 {
+            return Err(format!("cubemap with {} faces", t.image_count).into());
+        }
+        let (format, data) = t.blocks(bundle, version)?;
+        GpuTexture{
+            name: t.name.clone(),
+            dimension,
+            format,
+            srgb: t.srgb,
+            width: t.width,
+            height: t.height,
+            depth: 1,
+            images,
+            levels: t.levels(),
+            data: Vec::new(),
+        }.with_data(&data)
+    }
+
+    /// Texture2DArray and Texture3D
+    fn from_volume(bundle: &AssetBundle, v: &ObjectValue, dimension: Dimension, version: &UnityVersion) -> Result<GpuTexture> {
+        let id = v.int("m_Format")? as i32;
+        let (format, srgb) = match version.release() >= (2019, 1) {
+            true => TextureFormat::from_graphics_format(id)
+                .ok_or_else(|| format!("unsupported graphics format {}", id))?,
+            false => (TextureFormat::new(id), v.get("m_ColorSpace").and_then(|c| c.as_i64()) == Some(1)),
+        };
+        let (width, height) = (v.int("m_Width")? as u32, v.int("m_Height")? as u32);
+        let depth = v.int("m_Depth")? as u32;
+        let levels = match v.get("m_MipCount").and_then(|m| m.as_u64()) {
+            Some(m) => m as u32,
+            None => match v.get("m_MipMap").and_then(|m| m.as_bool()) {
+                Some(true) => 32 - width.max(height).max(depth).max(1).leading_zeros(),
+                _ => 1,
+            },
+        };
+        let inline = v.get("image data").and_then(|d| d.as_bytes()).unwrap_or_default();
+        let data = match (v.get("m_StreamData").and_then(StreamingInfo::from_value), inline.is_empty()) {
+            (Some(stream), true) => stream.load(bundle)?,
+            _ => inline,
+        };
+        let (depth, images) = match dimension {
+            Dimension::Tex3D => (depth, 1),
+            _ => (1, depth),
+        };
+        GpuTexture{
+            name: v.string("m_Name")?.to_string(),
+            dimension,
+            format,
+            srgb,
+            width,
+            height,
+            depth,
+            images,
+            levels: levels.max(1),
+            data: Vec::new(),
+        }.with_data(data)
+    }
+
+    /// takes the bytes covering every image and level, failing when they fall short
+    fn with_data(mut self, data: &[u8]) -> Result<GpuTexture> {
+        let size = (0..self.levels)
+            .map(|l| self.level_size(l).map(|s| s * self.images as usize))
+            .sum::<Result<usize>>()?;
+        if data.len() < size {
+            return Err(format!("{:?} {}x{}x{} with {} images and {} mips needs {} bytes, got {}",
+                self.format, self.width, self.height, self.depth, self.images, self.levels, size, data.len()).into());
+        }
+        self.data = data[..size].to_vec();
+        Ok(self)
+    }
+
+    /// (width, height, depth) of mip `level`
+    pub fn level_extent(&self, level: u32) -> (u32, u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1), (self.depth >> level).max(1))
+    }
+
+    /// bytes of mip `level` of one image, every depth slice included
+    pub fn level_size(&self, level: u32) -> Result<usize> {
+        let (w, h, d) = self.level_extent(level);
+        let size = self.format.level_size(w, h)
+            .ok_or_else(|| format!("unsupported texture format {:?}", self.format))?;
+        Ok(size * d as usize)
+    }
+
+    /// bytes of mip `level` of `image`
+    pub fn surface(&self, image: u32, level: u32) -> Result<&[u8]> {
+        let sizes = (0..self.levels).map(|l| self.level_size(l)).collect::<Result<Vec<usize>>>()?;
+        let offset = match self.dimension {
+            // 3D textures only have one image, stored level by level
+            Dimension::Tex3D => sizes[..level as usize].iter().sum::<usize>(),
+            _ => image as usize * sizes.iter().sum::<usize>() + sizes[..level as usize].iter().sum::<usize>(),
+        };
+        Ok(&self.data[offset .. offset + sizes[level as usize]])
+    }
+}
+
+/// writes every texture, cubemap, texture array and 3D texture of the bundle into `dst`
+/// as DDS or KTX2, keeping the GPU payload byte for byte
+pub fn export(bundle: &AssetBundle, dst: &Path, container: Container) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            let texture = match GpuTexture::load(bundle, asset, obj) {
+                None => continue,
+                Some(Ok(t)) => t,
+                Some(Err(e)) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let written = match container {
+                Container::Dds => dds::write(&texture),
+                Container::Ktx2 => ktx2::write(&texture),
+            };
+            match written {
+                Ok(bytes) => {
+                    let path = export::unique_path(dst, &texture.name, container.extension());
+                    fs::write(&path, bytes)?;
+                    info!("{:?} {:?} {:?}", texture.dimension, texture.format, path);
+                    count += 1;
+                },
+                Err(e) => warn!("{} ({:?}): {}", texture.name, texture.format, e),
+            }
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::Result;
+
+/// RGBA texels, 8 bit for LDR sources and 32 bit float for HDR ones
+#[derive(Clone, Debug)]
+pub enum Pixels {
+    Rgba8(Vec<u8>),
+    RgbaF32(Vec<f32>),
+}
+
+/// decoded image, rows stored top to bottom unless stated otherwise
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Pixels,
+    pub srgb: bool,     // tagged as sRGB when saved
+}
+
+impl Image {
+    pub fn rgba8(width: u32, height: u32, data: Vec<u8>) -> Image {
+        Image{ width, height, pixels: Pixels::Rgba8(data), srgb: false }
+    }
+
+    pub fn rgba_f32(width: u32, height: u32, data: Vec<f32>) -> Image {
+        Image{ width, height, pixels: Pixels::RgbaF32(data), srgb: false }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.pixels, Pixels::RgbaF32(_))
+    }
+
+    /// file extension used by `save`
+    pub fn extension(&self) -> &'static str {
+        match self.is_hdr() {
+            true => "exr",
+            false => "png",
+        }
+    }
+
+    /// mirrors rows, turning Unity's bottom-left origin into top-left
+    pub fn flip_vertical(&mut self) {
+        let row = self.width as usize * 4;
+        let height = self.height as usize;
+        match &mut self.pixels {
+            Pixels::Rgba8(p) => flip_rows(p, row, height),
+            Pixels::RgbaF32(p) => flip_rows(p, row, height),
+        }
+    }
+
+    /// mirrors columns
+    pub fn flip_horizontal(&mut self) {
+        let width = self.width as usize;
+        *self = self.transform(self.width, self.height, |x, y| y * width + width - 1 - x);
+    }
+
+    /// `width` x `height` texels starting at column `x`, row `y`
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        let (x, y, row) = (x as usize, y as usize, self.width as usize);
+        self.transform(width, height, |dx, dy| (y + dy) * row + x + dx)
+    }
+
+    /// rotates a quarter turn counterclockwise, taking rows as stored
+    pub fn rotate_ccw(&self) -> Image {
+        let width = self.width as usize;
+        self.transform(self.height, self.width, |x, y| x * width + width - 1 - y)
+    }
+
+    /// clears texels for which `keep(x, y)` is false
+    pub fn mask(&mut self, keep: impl Fn(u32, u32) -> bool) {
+        let width = self.width as usize;
+        let cleared = |i: usize| !keep((i % width) as u32, (i / width) as u32);
+        match &mut self.pixels {
+            Pixels::Rgba8(p) => p.chunks_exact_mut(4).enumerate()
+                .filter(|(i, _)| cleared(*i))
+                .for_each(|(_, t)| t.fill(0)),
+            Pixels::RgbaF32(p) => p.chunks_exact_mut(4).enumerate()
+                .filter(|(i, _)| cleared(*i))
+                .for_each(|(_, t)| t.fill(0.0)),
+        }
+    }
+
+    /// replaces alpha with the red channel of `alpha`, an image of the same size
+    pub fn apply_alpha(&mut self, alpha: &Image) {
+        let source: Vec<f32> = match &alpha.pixels {
+            Pixels::Rgba8(p) => p.iter().step_by(4).map(|r| *r as f32 / 255.0).collect(),
+            Pixels::RgbaF32(p) => p.iter().step_by(4).copied().collect(),
+        };
+        match &mut self.pixels {
+            Pixels::Rgba8(p) => p.chunks_exact_mut(4).zip(source)
+                .for_each(|(t, a)| t[3] = (a.clamp(0.0, 1.0) * 255.0).round() as u8),
+            Pixels::RgbaF32(p) => p.chunks_exact_mut(4).zip(source)
+                .for_each(|(t, a)| t[3] = a),
+        }
+    }
+
+    /// `width` x `height` image whose texel (x, y) is texel `source(x, y)` of this one
+    fn transform(&self, width: u32, height: u32, source: impl Fn(usize, usize) -> usize) -> Image {
+        fn remap<T: Copy>(data: &[T], width: usize, height: usize, source: impl Fn(usize, usize) -> usize) -> Vec<T> {
+            let mut out = Vec::with_capacity(width * height * 4);
+            for y in 0..height {
+                for x in 0..width {
+                    let i = source(x, y) * 4;
+                    out.extend_from_slice(&data[i .. i + 4]);
+                }
+            }
+            out
+        }
+        let (w, h) = (width as usize, height as usize);
+        let pixels = match &self.pixels {
+            Pixels::Rgba8(p) => Pixels::Rgba8(remap(p, w, h, source)),
+            Pixels::RgbaF32(p) => Pixels::RgbaF32(remap(p, w, h, source)),
+        };
+        Image{ width, height, pixels, srgb: self.srgb }
+    }
+
+    /// writes a PNG, or an EXR for HDR images
+    pub fn save(&self, path: &Path) -> Result<()> {
+        match &self.pixels {
+            Pixels::Rgba8(p) => self.write_png(path, p),
+            Pixels::RgbaF32(p) => self.write_exr(path, p),
+        }
+    }
+
+    fn write_png(&self, path: &Path, data: &[u8]) -> Result<()> {
+        self.encode_png(BufWriter::new(File::create(path)?), data)
+    }
+
+    /// PNG bytes; HDR images are clamped to 8 bits
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match &self.pixels {
+            Pixels::Rgba8(p) => self.encode_png(&mut out, p)?,
+            Pixels::RgbaF32(p) => {
+                let p: Vec<u8> = p.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+                self.encode_png(&mut out, &p)?
+            },
+        }
+        Ok(out)
+    }
+
+    fn encode_png<W: Write>(&self, w: W, data: &[u8]) -> Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        if self.srgb {
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        }
+        encoder.write_header()?.write_image_data(data)?;
+        Ok(())
+    }
+
+    /// uncompressed scanline OpenEXR with 32 bit float RGBA channels
+    fn write_exr(&self, path: &Path, data: &[f32]) -> Result<()> {
+        let mut header: Vec<u8> = Vec::new();
+        header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let mut channels: Vec<u8> = Vec::new();
+        for name in &["A", "B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+            channels.extend_from_slice(&[0, 0, 0, 0]);       // pLinear, reserved
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        let window: Vec<u8> = [0i32, 0, self.width as i32 - 1, self.height as i32 - 1].iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        let attributes: Vec<(&str, &str, Vec<u8>)> = vec![
+            ("channels", "chlist", channels),
+            ("compression", "compression", vec![0]),
+            ("dataWindow", "box2i", window.clone()),
+            ("displayWindow", "box2i", window),
+            ("lineOrder", "lineOrder", vec![0]),
+            ("pixelAspectRatio", "float", 1f32.to_le_bytes().to_vec()),
+            ("screenWindowCenter", "v2f", [0f32.to_le_bytes(), 0f32.to_le_bytes()].concat()),
+            ("screenWindowWidth", "float", 1f32.to_le_bytes().to_vec()),
+        ];
+        for (name, kind, value) in attributes {
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(kind.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            header.extend_from_slice(&value);
+        }
+        header.push(0);
+
+        let width = self.width as usize;
+        let line_size = width * 4 * 4;
+        let table_size = self.height as u64 * 8;
+        let mut f = BufWriter::new(File::create(path)?);
+        f.write_all(&header)?;
+        for y in 0..self.height as u64 {
+            let offset = header.len() as u64 + table_size + y * (8 + line_size as u64);
+            f.write_all(&offset.to_le_bytes())?;
+        }
+        for y in 0..self.height as usize {
+            f.write_all(&(y as i32).to_le_bytes())?;
+            f.write_all(&(line_size as i32).to_le_bytes())?;
+            let row = &data[y * width * 4 .. (y + 1) * width * 4];
+            for channel in &[3, 2, 1, 0] {
+                for x in 0..width {
+                    f.write_all(&row[x * 4 + channel].to_le_bytes())?;
+                }
+            }
+        }
+        f.flush()?;
+        Ok(())
+    }
+}
+
+fn flip_rows<T>(data: &mut [T], row: usize, height: usize) {
+    for y in 0..height / 2 {
+        let (top, bottom) = data.split_at_mut((height - 1 - y) * row);
+        top[y * row .. (y + 1) * row].swap_with_slice(&mut bottom[..row]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+
+use crate::asset_bundle::AssetBundle;
+use crate::constants;
+use crate::mono_script::MonoScript;
+use crate::Result;
+
+const CORE_MODULE: &str = "UnityEngine.CoreModule";
+
+/// engine classes outside the core module or outside the `UnityEngine` namespace, with their
+/// module and managed name when not `UnityEngine.<class>`
+const MODULES: &[(&str, &str, Option<&str>)] = &[
+    ("SpriteAtlas", CORE_MODULE, Some("UnityEngine.U2D.SpriteAtlas")),
+    ("SortingGroup", CORE_MODULE, Some("UnityEngine.Rendering.SortingGroup")),
+    ("GraphicsSettings", CORE_MODULE, Some("UnityEngine.Rendering.GraphicsSettings")),
+    ("RayTracingShader", CORE_MODULE, Some("UnityEngine.Experimental.Rendering.RayTracingShader")),
+    ("AimConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.AimConstraint")),
+    ("LookAtConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.LookAtConstraint")),
+    ("ParentConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.ParentConstraint")),
+    ("PositionConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.PositionConstraint")),
+    ("RotationConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.RotationConstraint")),
+    ("ScaleConstraint", "UnityEngine.AnimationModule", Some("UnityEngine.Animations.ScaleConstraint")),
+    ("Animation", "UnityEngine.AnimationModule", None),
+    ("AnimationClip", "UnityEngine.AnimationModule", None),
+    ("Animator", "UnityEngine.AnimationModule", None),
+    ("AnimatorController", "UnityEngine.AnimationModule", Some("UnityEngine.RuntimeAnimatorController")),
+    ("AnimatorOverrideController", "UnityEngine.AnimationModule", None),
+    ("RuntimeAnimatorController", "UnityEngine.AnimationModule", None),
+    ("Avatar", "UnityEngine.AnimationModule", None),
+    ("AvatarMask", "UnityEngine.AnimationModule", None),
+    ("Motion", "UnityEngine.AnimationModule", None),
+    ("AssetBundle", "UnityEngine.AssetBundleModule", None),
+    ("AssetBundleManifest", "UnityEngine.AssetBundleModule", None),
+    ("AudioClip", "UnityEngine.AudioModule", None),
+    ("AudioSource", "UnityEngine.AudioModule", None),
+    ("AudioListener", "UnityEngine.AudioModule", None),
+    ("AudioReverbZone", "UnityEngine.AudioModule", None),
+    ("AudioChorusFilter", "UnityEngine.AudioModule", None),
+    ("AudioDistortionFilter", "UnityEngine.AudioModule", None),
+    ("AudioEchoFilter", "UnityEngine.AudioModule", None),
+    ("AudioHighPassFilter", "UnityEngine.AudioModule", None),
+    ("AudioLowPassFilter", "UnityEngine.AudioModule", None),
+    ("AudioReverbFilter", "UnityEngine.AudioModule", None),
+    ("AudioMixer", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixer")),
+    ("AudioMixerGroup", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixerGroup")),
+    ("AudioMixerSnapshot", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixerSnapshot")),
+    ("AudioMixerController", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixer")),
+    ("AudioMixerGroupController", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixerGroup")),
+    ("AudioMixerSnapshotController", "UnityEngine.AudioModule", Some("UnityEngine.Audio.AudioMixerSnapshot")),
+    ("Cloth", "UnityEngine.ClothModule", None),
+    ("PlayableDirector", "UnityEngine.DirectorModule", Some("UnityEngine.Playables.PlayableDirector")),
+    ("Grid", "UnityEngine.GridModule", None),
+    ("GridLayout", "UnityEngine.GridModule", None),
+    ("NavMeshAgent", "UnityEngine.AIModule", Some("UnityEngine.AI.NavMeshAgent")),
+    ("NavMeshObstacle", "UnityEngine.AIModule", Some("UnityEngine.AI.NavMeshObstacle")),
+    ("NavMeshData", "UnityEngine.AIModule", Some("UnityEngine.AI.NavMeshData")),
+    ("OffMeshLink", "UnityEngine.AIModule", Some("UnityEngine.AI.OffMeshLink")),
+    ("ParticleSystem", "UnityEngine.ParticleSystemModule", None),
+    ("ParticleSystemRenderer", "UnityEngine.ParticleSystemModule", None),
+    ("ParticleSystemForceField", "UnityEngine.ParticleSystemModule", None),
+    ("Rigidbody", "UnityEngine.PhysicsModule", None),
+    ("Collider", "UnityEngine.PhysicsModule", None),
+    ("BoxCollider", "UnityEngine.PhysicsModule", None),
+    ("SphereCollider", "UnityEngine.PhysicsModule", None),
+    ("CapsuleCollider", "UnityEngine.PhysicsModule", None),
+    ("MeshCollider", "UnityEngine.PhysicsModule", None),
+    ("CharacterController", "UnityEngine.PhysicsModule", None),
+    ("PhysicMaterial", "UnityEngine.PhysicsModule", None),
+    ("ConstantForce", "UnityEngine.PhysicsModule", None),
+    ("HingeJoint", "UnityEngine.PhysicsModule", None),
+    ("FixedJoint", "UnityEngine.PhysicsModule", None),
+    ("SpringJoint", "UnityEngine.PhysicsModule", None),
+    ("CharacterJoint", "UnityEngine.PhysicsModule", None),
+    ("ConfigurableJoint", "UnityEngine.PhysicsModule", None),
+    ("ArticulationBody", "UnityEngine.PhysicsModule", None),
+    ("Rigidbody2D", "UnityEngine.Physics2DModule", None),
+    ("Collider2D", "UnityEngine.Physics2DModule", None),
+    ("BoxCollider2D", "UnityEngine.Physics2DModule", None),
+    ("CircleCollider2D", "UnityEngine.Physics2DModule", None),
+    ("CapsuleCollider2D", "UnityEngine.Physics2DModule", None),
+    ("PolygonCollider2D", "UnityEngine.Physics2DModule", None),
+    ("EdgeCollider2D", "UnityEngine.Physics2DModule", None),
+    ("CompositeCollider2D", "UnityEngine.Physics2DModule", None),
+    ("PhysicsMaterial2D", "UnityEngine.Physics2DModule", None),
+    ("DistanceJoint2D", "UnityEngine.Physics2DModule", None),
+    ("FixedJoint2D", "UnityEngine.Physics2DModule", None),
+    ("FrictionJoint2D", "UnityEngine.Physics2DModule", None),
+    ("HingeJoint2D", "UnityEngine.Physics2DModule", None),
+    ("RelativeJoint2D", "UnityEngine.Physics2DModule", None),
+    ("SliderJoint2D", "UnityEngine.Physics2DModule", None),
+    ("SpringJoint2D", "UnityEngine.Physics2DModule", None),
+    ("TargetJoint2D", "UnityEngine.Physics2DModule", None),
+    ("WheelJoint2D", "UnityEngine.Physics2DModule", None),
+    ("AreaEffector2D", "UnityEngine.Physics2DModule", None),
+    ("BuoyancyEffector2D", "UnityEngine.Physics2DModule", None),
+    ("PlatformEffector2D", "UnityEngine.Physics2DModule", None),
+    ("PointEffector2D", "UnityEngine.Physics2DModule", None),
+    ("SurfaceEffector2D", "UnityEngine.Physics2DModule", None),
+    ("SpriteMask", "UnityEngine.SpriteMaskModule", None),
+    ("SpriteShapeRenderer", "UnityEngine.SpriteShapeModule", Some("UnityEngine.U2D.SpriteShapeRenderer")),
+    ("Terrain", "UnityEngine.TerrainModule", None),
+    ("TerrainData", "UnityEngine.TerrainModule", None),
+    ("TerrainLayer", "UnityEngine.TerrainModule", None),
+    ("TerrainCollider", "UnityEngine.TerrainPhysicsModule", None),
+    ("Font", "UnityEngine.TextRenderingModule", None),
+    ("TextMesh", "UnityEngine.TextRenderingModule", None),
+    ("Tilemap", "UnityEngine.TilemapModule", Some("UnityEngine.Tilemaps.Tilemap")),
+    ("TilemapRenderer", "UnityEngine.TilemapModule", Some("UnityEngine.Tilemaps.TilemapRenderer")),
+    ("TilemapCollider2D", "UnityEngine.TilemapModule", Some("UnityEngine.Tilemaps.TilemapCollider2D")),
+    ("Canvas", "UnityEngine.UIModule", None),
+    ("CanvasGroup", "UnityEngine.UIModule", None),
+    ("CanvasRenderer", "UnityEngine.UIModule", None),
+    ("VideoClip", "UnityEngine.VideoModule", Some("UnityEngine.Video.VideoClip")),
+    ("VideoPlayer", "UnityEngine.VideoModule", Some("UnityEngine.Video.VideoPlayer")),
+    ("VisualEffect", "UnityEngine.VFXModule", Some("UnityEngine.VFX.VisualEffect")),
+    ("VisualEffectAsset", "UnityEngine.VFXModule", Some("UnityEngine.VFX.VisualEffectAsset")),
+    ("WheelCollider", "UnityEngine.VehiclesModule", None),
+    ("WindZone", "UnityEngine.WindModule", None),
+];
+
+/// serialized classes without a managed counterpart in the player
+const NATIVE_ONLY: &[&str] = &[
+    "PreloadData", "NavMeshSettings", "OcclusionCullingSettings", "MonoScript", "EditorExtension", "NamedObject",
+    "GameManager", "LevelGameManager", "GlobalGameManager", "Prefab", "PrefabInstance",
+    "AnimatorStateMachine", "AnimatorState", "AnimatorStateTransition", "AnimatorTransition", "BlendTree",
+    "CachedSpriteAtlasRuntimeData", "SpeedTreeWindAsset", "VFXRenderer", "SceneRoots", "OcclusionCullingData",
+    "LowerResBlitTexture", "BuiltAssetBundleInfoSet", "AudioBuildInfo", "PluginBuildInfo",
+    "VisualEffectResource", "VisualEffectObject",
+];
+
+/// assembly and managed type preserving the engine class `name`, None for native only classes
+fn engine_type(name: &str) -> Option<(&'static str, String)> {
+    if NATIVE_ONLY.contains(&name) {
+        return None;
+    }
+    match MODULES.iter().find(|m| m.0 == name) {
+        Some((_, module, Some(managed))) => Some((module, managed.to_string())),
+        Some((_, module, None)) => Some((module, format!("UnityEngine.{}", name))),
+        None => Some((CORE_MODULE, format!("UnityEngine.{}", name))),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// managed types to preserve by assembly: the engine classes serialized in `bundles` and the
+/// runtime MonoScripts they hold
+///
+/// Engine classes are listed by their 2017.2+ module; assemblies are marked
+/// `ignoreIfMissing` so players without a module still link.
+pub fn preserved_types(bundles: &[AssetBundle]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut assemblies: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for asset in bundles.iter().flat_map(|b| b.assets()) {
+        for class in asset.classes() {
+            let name = match constants::class_name(class.class_id, asset.unity_version()) {
+                Some(n) => n,
+                None => {
+                    warn!("{}: unknown class {}", asset.name(), class.class_id);
+                    continue;
+                },
+            };
+            if let Some((module, managed)) = engine_type(name) {
+                assemblies.entry(module.to_string()).or_default().insert(managed);
+            }
+        }
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(MonoScript::CLASS_ID) {
+                continue;
+            }
+            let script = match asset.object_value(obj).and_then(|v| MonoScript::from_value(&v)) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            if script.editor_script || script.assembly.is_empty() {
+                continue;
+            }
+            let assembly = script.assembly.strip_suffix(".dll").unwrap_or(&script.assembly).to_string();
+            assemblies.entry(assembly).or_default().insert(script.full_name());
+        }
+    }
+    assemblies
+}
+
+/// link.xml preserving `assemblies`
+pub fn to_xml(assemblies: &BTreeMap<String, BTreeSet<String>>) -> String {
+    let mut xml = String::from("<linker>\n");
+    for (assembly, types) in assemblies {
+        xml += &format!("  <assembly fullname=\"{}\" ignoreIfMissing=\"1\">\n", escape(assembly));
+        for t in types {
+            xml += &format!("    <type fullname=\"{}\" preserve=\"all\"/>\n", escape(t));
+        }
+        xml += "  </assembly>\n";
+    }
+    xml += "</linker>\n";
+    xml
+}
+
+/// writes `dst/link.xml` preserving what `bundles` need, returning the number of types
+pub fn export(bundles: &[AssetBundle], dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let assemblies = preserved_types(bundles);
+    let count = assemblies.values().map(|t| t.len()).sum();
+    info!("{} types in {} assemblies", count, assemblies.len());
+    fs::write(dst.join("link.xml"), to_xml(&assemblies))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, GAME_OBJECT_CLASS_ID};
+
+    #[test]
+    fn engine_types() {
+        assert_eq!(engine_type("SpriteAtlas"), Some((CORE_MODULE, "UnityEngine.U2D.SpriteAtlas".to_string())));
+        assert_eq!(engine_type("Animator"), Some(("UnityEngine.AnimationModule", "UnityEngine.Animator".to_string())));
+        assert_eq!(engine_type("Transform"), Some((CORE_MODULE, "UnityEngine.Transform".to_string())));
+        assert_eq!(engine_type("MonoScript"), None);
+    }
+
+    #[test]
+    fn preserve_serialized_classes() {
+        let classes = [(GAME_OBJECT_CLASS_ID, fixture::game_object_type()), (4, fixture::transform_type())];
+        let bundles = [fixture::bundle(vec![("level0", fixture::serialized_file(&classes, &[]))])];
+        let mut assemblies = preserved_types(&bundles);
+        assemblies.entry("Game<Tools>".to_string()).or_default().insert("A&B".to_string());
+        assert_eq!(to_xml(&assemblies), concat!(
+            "<linker>\n",
+            "  <assembly fullname=\"Game&lt;Tools&gt;\" ignoreIfMissing=\"1\">\n",
+            "    <type fullname=\"A&amp;B\" preserve=\"all\"/>\n",
+            "  </assembly>\n",
+            "  <assembly fullname=\"UnityEngine.CoreModule\" ignoreIfMissing=\"1\">\n",
+            "    <type fullname=\"UnityEngine.GameObject\" preserve=\"all\"/>\n",
+            "    <type fullname=\"UnityEngine.Transform\" preserve=\"all\"/>\n",
+            "  </assembly>\n",
+            "</linker>\n",
+        ));
+    }
+}
@@ -2,16 +2,16 @@ use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LocalObjectEntry {
-    file_id: i32,
-    local_id: i64,
+    pub file_id: i32,   // file id as in a PPtr, 0 is this file
+    pub local_id: i64,  // path id in that file
 }
 
 impl LocalObjectEntry {
     pub fn new(file_id: i32, local_id: i64) -> LocalObjectEntry {
         LocalObjectEntry
         {
-            file_id: file_id,
-            local_id: local_id
+            file_id,
+            local_id
         }
     }
 }
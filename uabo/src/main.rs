@@ -21,6 +21,43 @@ mod type_info;
 mod reference;
 mod local_object_entry;
 mod container_info;
+mod unity_version;
+mod object_value;
+mod resource_file;
+mod streaming_info;
+mod export;
+mod image;
+mod texture;
+mod texture_2d;
+mod gpu_texture;
+mod pptr;
+mod vertex_data;
+mod sprite;
+mod ogg;
+mod fsb5;
+mod audio_clip;
+mod packed_bit_vector;
+mod mesh;
+mod gltf;
+mod scene;
+mod animation_clip;
+mod animator_controller;
+mod shader;
+mod shader_program;
+mod material;
+mod text_asset;
+mod video_clip;
+mod mono_script;
+mod link_xml;
+mod scene_bundle;
+mod player_data;
+mod zip_archive;
+mod window;
+mod pack_file;
+mod web_data;
+mod caching;
+#[cfg(test)]
+mod fixture;
 
 use args::Args;
 
@@ -39,6 +76,27 @@ fn try_main(args: Args) -> Result<()> {
 
     let matched = match args.command()? {
         Files => files(&args),
+        Textures => textures(&args),
+        Containers(container) => containers(&args, container),
+        Sprites => sprites(&args),
+        Audio => audio(&args),
+        Meshes(format) => meshes(&args, format),
+        Scenes => scenes(&args),
+        Animations(gltf) => animations(&args, gltf),
+        Animators => animators(&args),
+        Shaders => shaders(&args),
+        ShaderPrograms => shader_programs(&args),
+        Materials => materials(&args),
+        TextAssets => text_assets(&args),
+        Videos => videos(&args),
+        Scripts => scripts(&args),
+        LinkXml => link_xml(&args),
+        SceneBundles => scene_bundles(&args),
+        PlayerData => player_data(&args),
+        Archive => archive(&args),
+        Scan => scan(&args),
+        Web => web(&args),
+        Cache => cache(&args),
     }?;
 
     if matched {
@@ -56,9 +114,163 @@ fn files(args: &Args) -> Result<bool> {
         Ok(eval) => {
             let serialized = serde_json::to_string_pretty(&eval).unwrap();
             let mut f = BufWriter::new(fs::File::create(args.dest()).unwrap());
-            f.write(&serialized.as_bytes()).unwrap();
+            f.write_all(serialized.as_bytes()).unwrap();
             //println!("{}", serialized);
         }
     }
    Ok(true)
 }
+
+fn textures(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = texture_2d::export(&bundle, args.dest_dir(), args.mips())?;
+    info!("{} textures exported", count);
+    Ok(count > 0)
+}
+
+fn containers(args: &Args, container: gpu_texture::Container) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = gpu_texture::export(&bundle, args.dest_dir(), container)?;
+    info!("{} textures written as {}", count, container.extension());
+    Ok(count > 0)
+}
+
+fn sprites(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = sprite::export(&bundles, args.dest_dir(), args.mesh_mask())?;
+    info!("{} sprites exported", count);
+    Ok(count > 0)
+}
+
+fn audio(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = audio_clip::export(&bundle, args.dest_dir(), args.vorbis_setup())?;
+    info!("{} audio clips exported", count);
+    Ok(count > 0)
+}
+
+fn meshes(args: &Args, format: mesh::MeshFormat) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = mesh::export(&bundle, args.dest_dir(), format)?;
+    info!("{} meshes written as {}", count, format.extension());
+    Ok(count > 0)
+}
+
+fn scenes(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = scene::export(&bundles, args.dest_dir(), args.root())?;
+    info!("{} scenes exported", count);
+    Ok(count > 0)
+}
+
+fn animations(args: &Args, gltf: bool) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = animation_clip::export(&bundles, args.dest_dir(), gltf)?;
+    info!("{} animation clips exported", count);
+    Ok(count > 0)
+}
+
+fn animators(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = animator_controller::export(&bundles, args.dest_dir())?;
+    info!("{} animator controllers exported", count);
+    Ok(count > 0)
+}
+
+fn shaders(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = shader::export(&bundle, args.dest_dir())?;
+    info!("{} shaders exported", count);
+    Ok(count > 0)
+}
+
+fn shader_programs(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = shader_program::export(&bundle, args.dest_dir())?;
+    info!("programs of {} shaders exported", count);
+    Ok(count > 0)
+}
+
+fn materials(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = material::export(&bundles, args.dest_dir())?;
+    info!("{} materials audited", count);
+    Ok(count > 0)
+}
+
+fn text_assets(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = text_asset::export(&bundle, args.dest_dir())?;
+    info!("{} text assets and fonts exported", count);
+    Ok(count > 0)
+}
+
+fn videos(args: &Args) -> Result<bool> {
+    let bundle = args.evaluates()?;
+    let count = video_clip::export(&bundle, args.dest_dir())?;
+    info!("{} video clips exported", count);
+    Ok(count > 0)
+}
+
+fn scripts(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let player = match args.player_types() {
+        Some(path) => Some(mono_script::PlayerTypes::load(path)?),
+        None => None,
+    };
+    let report = mono_script::export(&bundles, player.as_ref(), args.dest_dir())?;
+    Ok(!report.scripts.is_empty())
+}
+
+fn link_xml(args: &Args) -> Result<bool> {
+    let mut bundles = args.sources()?;
+    bundles.extend(args.dependencies()?);
+    let count = link_xml::export(&bundles, args.dest_dir())?;
+    info!("{} types preserved from {} bundles", count, bundles.len());
+    Ok(count > 0)
+}
+
+fn scene_bundles(args: &Args) -> Result<bool> {
+    let mut bundles = vec![args.evaluates()?];
+    bundles.extend(args.dependencies()?);
+    let count = scene_bundle::export(&bundles, args.dest_dir())?;
+    info!("{} scenes listed", count);
+    Ok(count > 0)
+}
+
+fn player_data(args: &Args) -> Result<bool> {
+    let data = args.evaluates()?;
+    let inventory = player_data::export(&data, args.dest_dir())?;
+    Ok(!inventory.files.is_empty())
+}
+
+fn archive(args: &Args) -> Result<bool> {
+    let archive = args.archive()?;
+    let count = zip_archive::export(&archive, args.dest_dir(), args.unity_version())?;
+    info!("{} bundles and serialized files in {} entries", count, archive.entries().len());
+    Ok(count > 0)
+}
+
+fn scan(args: &Args) -> Result<bool> {
+    let count = pack_file::export(args.source(), args.dest_dir(), args.unity_version())?;
+    info!("{} bundles parsed", count);
+    Ok(count > 0)
+}
+
+fn web(args: &Args) -> Result<bool> {
+    let web = args.web_data()?;
+    let count = web_data::export(&web, args.dest_dir(), args.unity_version())?;
+    info!("{} bundles and serialized files in {} package files", count, web.files().len());
+    Ok(count > 0)
+}
+
+fn cache(args: &Args) -> Result<bool> {
+    let report = caching::export(args.source(), args.dest_dir(), args.unity_version())?;
+    Ok(!report.bundles.is_empty())
+}
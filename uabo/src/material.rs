@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::constants;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, PPtr};
+use crate::shader::Shader;
+use crate::Result;
+
+const MATERIAL_CLASS_ID: i32 = 21;
+
+/// texture property of a material
+#[derive(Serialize, Clone, Debug)]
+pub struct TextureSlot {
+    pub name: String,
+    pub texture: Option<String>,    // name of the referenced texture
+    pub class: Option<String>,      // Texture2D, Cubemap, RenderTexture...
+    pub path_id: i64,               // 0 when the slot is empty
+    pub missing: bool,              // set but not found in the loaded bundles
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+}
+
+/// audit of one Material
+#[derive(Serialize, Clone, Debug)]
+pub struct MaterialReport {
+    pub name: String,
+    pub path_id: i64,
+    pub shader: Option<String>,
+    pub render_queue: i64,          // -1 uses the shader queue
+    pub keywords: Vec<String>,      // enabled
+    pub invalid_keywords: Vec<String>,  // kept by the editor as invalid, 2021.2+
+    pub undefined_keywords: Vec<String>,    // enabled but not compiled into any shader variant
+    pub textures: Vec<TextureSlot>,
+    pub floats: BTreeMap<String, f32>,
+    pub ints: BTreeMap<String, i64>,
+    pub colors: BTreeMap<String, [f32; 4]>,
+    pub issues: Vec<String>,
+}
+
+/// shader name and the keywords of its variants
+type ShaderInfo = Option<(String, BTreeSet<String>)>;
+
+/// reads Materials, resolving shaders and textures through `bundles`
+pub struct MaterialAudit<'a> {
+    bundles: &'a [AssetBundle],
+    shaders: HashMap<(String, i64), ShaderInfo>,
+}
+
+impl<'a> MaterialAudit<'a> {
+    pub fn new(bundles: &'a [AssetBundle]) -> MaterialAudit<'a> {
+        MaterialAudit{ bundles, shaders: HashMap::new() }
+    }
+
+    pub fn report(&mut self, asset: &Asset, path_id: i64, v: &ObjectValue) -> Result<MaterialReport> {
+        let mut issues = Vec::new();
+        let shader = match v.get("m_Shader").and_then(PPtr::from_value) {
+            Some(p) if !p.is_null() => match pptr::resolve(self.bundles, asset, &p) {
+                Some(s) => {
+                    let key = (s.asset.name().to_string(), s.object.path_id);
+                    if !self.shaders.contains_key(&key) {
+                        let info = s.asset.object_value(s.object)
+                            .and_then(|v| Shader::from_value(&v, s.asset.unity_version()))
+                            .map_err(|e| warn!("shader {}: {}", s.object.path_id, e))
+                            .ok()
+                            .map(|shader| {
+                                let keywords = shader.subshaders.iter()
+                                    .flat_map(|s| &s.passes)
+                                    .flat_map(|p| p.keywords.iter().cloned())
+                                    .collect();
+                                (shader.name, keywords)
+                            });
+                        self.shaders.insert(key.clone(), info);
+                    }
+                    match &self.shaders[&key] {
+                        Some(info) => Some(info.clone()),
+                        None => {
+                            issues.push(format!("shader {} could not be read", p.path_id));
+                            None
+                        },
+                    }
+                },
+                None => {
+                    issues.push(format!("shader ({}, {}) not found in the loaded bundles", p.file_id, p.path_id));
+                    None
+                },
+            },
+            _ => {
+                issues.push("no shader".to_string());
+                None
+            },
+        };
+
+        // a space separated string before 2021.2, valid and invalid lists since
+        let mut keywords: Vec<String> = match v.get("m_ValidKeywords") {
+            Some(ObjectValue::Array(k)) => k.iter().filter_map(|k| k.as_str()).map(String::from).collect(),
+            _ => v.get("m_ShaderKeywords").and_then(|k| k.as_str()).unwrap_or_default()
+                .split_whitespace().map(String::from).collect(),
+        };
+        keywords.sort();
+        keywords.dedup();
+        let invalid_keywords: Vec<String> = match v.get("m_InvalidKeywords") {
+            Some(ObjectValue::Array(k)) => k.iter().filter_map(|k| k.as_str()).map(String::from).collect(),
+            _ => Vec::new(),
+        };
+        let undefined_keywords: Vec<String> = match &shader {
+            Some((_, defined)) => keywords.iter().filter(|k| !defined.contains(*k)).cloned().collect(),
+            None => Vec::new(),
+        };
+        for k in invalid_keywords.iter().chain(&undefined_keywords) {
+            issues.push(format!("keyword {} is not defined by the shader", k));
+        }
+
+        let properties = v.get("m_SavedProperties");
+        let list = |name: &str| -> Vec<(String, &ObjectValue)> {
+            match properties.and_then(|p| p.get(name)) {
+                Some(ObjectValue::Array(entries)) => entries.iter()
+                    .filter_map(|e| Some((e.get("first")?.as_str()?.to_string(), e.get("second")?)))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        let mut textures = Vec::new();
+        for (name, env) in list("m_TexEnvs") {
+            let vec2 = |field: &str, default: f32| -> [f32; 2] {
+                let v = env.get(field);
+                let c = |n: &str| v.and_then(|v| v.get(n)).and_then(|c| c.as_f64()).unwrap_or(default as f64) as f32;
+                [c("x"), c("y")]
+            };
+            let ptr = env.get("m_Texture").and_then(PPtr::from_value).filter(|p| !p.is_null());
+            let texture = ptr.as_ref().and_then(|p| pptr::resolve(self.bundles, asset, p));
+            let missing = ptr.is_some() && texture.is_none();
+            if missing {
+                issues.push(format!("texture of {} not found in the loaded bundles", name));
+            }
+            textures.push(TextureSlot{
+                texture: texture.and_then(|t| t.asset.object_value(t.object).ok())
+                    .and_then(|t| t.get("m_Name").and_then(|n| n.as_str()).map(String::from)),
+                class: texture.and_then(|t| t.asset.class_id(t.object))
+                    .map(|c| constants::class_name(c, asset.unity_version()).map(String::from).unwrap_or_else(|| format!("Class{}", c))),
+                path_id: ptr.map(|p| p.path_id).unwrap_or(0),
+                missing,
+                scale: vec2("m_Scale", 1.0),
+                offset: vec2("m_Offset", 0.0),
+                name,
+            });
+        }
+        let colors = list("m_Colors").into_iter()
+            .map(|(name, c)| {
+                let c = |n: &str| c.get(n).and_then(|c| c.as_f64()).unwrap_or(0.0) as f32;
+                (name, [c("r"), c("g"), c("b"), c("a")])
+            })
+            .collect();
+
+        Ok(MaterialReport{
+            name: v.string("m_Name")?.to_string(),
+            path_id,
+            shader: shader.map(|s| s.0),
+            render_queue: v.get("m_CustomRenderQueue").and_then(|q| q.as_i64()).unwrap_or(-1),
+            keywords,
+            invalid_keywords,
+            undefined_keywords,
+            textures,
+            floats: list("m_Floats").into_iter().filter_map(|(n, f)| Some((n, f.as_f64()? as f32))).collect(),
+            ints: list("m_Ints").into_iter().filter_map(|(n, i)| Some((n, i.as_i64()?))).collect(),
+            colors,
+            issues,
+        })
+    }
+}
+
+/// writes a report of every Material of `bundles[0]` into `dst/materials.json`
+pub fn export(bundles: &[AssetBundle], dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let mut audit = MaterialAudit::new(bundles);
+    let mut reports = Vec::new();
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(MATERIAL_CLASS_ID) {
+                continue;
+            }
+            match asset.object_value(obj).and_then(|v| audit.report(asset, obj.path_id, &v)) {
+                Ok(r) => reports.push(r),
+                Err(e) => warn!("{} {}: {}", asset.name(), obj.path_id, e),
+            }
+        }
+    }
+    let flagged = reports.iter().filter(|r| !r.issues.is_empty()).count();
+    info!("{} of {} materials flagged", flagged, reports.len());
+    fs::write(dst.join("materials.json"), serde_json::to_string_pretty(&reports)?)?;
+    Ok(reports.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    fn value(fields: Vec<(&str, ObjectValue)>) -> ObjectValue {
+        ObjectValue::Struct(fields.into_iter().map(|(n, v)| (n.to_string(), v)).collect())
+    }
+
+    fn pptr(path_id: i64) -> ObjectValue {
+        value(vec![("m_FileID", ObjectValue::Int(0)), ("m_PathID", ObjectValue::Int(path_id))])
+    }
+
+    fn entry(name: &str, second: ObjectValue) -> ObjectValue {
+        value(vec![("first", ObjectValue::String(name.to_string())), ("second", second)])
+    }
+
+    #[test]
+    fn keywords_and_missing_references() {
+        let bundles = [fixture::bundle(vec![("level0", fixture::serialized_file(&[], &[]))])];
+        let asset = &bundles[0].assets()[0];
+        let properties = value(vec![
+            ("m_TexEnvs", ObjectValue::Array(vec![entry("_MainTex", value(vec![
+                ("m_Texture", pptr(99)),
+                ("m_Scale", value(vec![("x", ObjectValue::Float(2.0)), ("y", ObjectValue::Float(2.0))])),
+                ("m_Offset", value(vec![("x", ObjectValue::Float(0.0)), ("y", ObjectValue::Float(0.5))])),
+            ]))])),
+            ("m_Floats", ObjectValue::Array(vec![entry("_Glossiness", ObjectValue::Float(0.5))])),
+        ]);
+        let material = value(vec![
+            ("m_Name", ObjectValue::String("metal".to_string())),
+            ("m_Shader", pptr(0)),
+            ("m_ShaderKeywords", ObjectValue::String("_NORMALMAP _EMISSION _NORMALMAP".to_string())),
+            ("m_InvalidKeywords", ObjectValue::Array(vec![ObjectValue::String("_OLD".to_string())])),
+            ("m_SavedProperties", properties),
+        ]);
+
+        let report = MaterialAudit::new(&bundles).report(asset, 5, &material).unwrap();
+        assert_eq!(report.keywords, vec!["_EMISSION", "_NORMALMAP"]);
+        assert_eq!(report.invalid_keywords, vec!["_OLD"]);
+        assert!(report.undefined_keywords.is_empty());
+        assert_eq!(report.render_queue, -1);
+        let slot = &report.textures[0];
+        assert_eq!((slot.path_id, slot.missing, slot.scale, slot.offset), (99, true, [2.0, 2.0], [0.0, 0.5]));
+        assert_eq!(report.floats["_Glossiness"], 0.5);
+        assert_eq!(report.issues, vec![
+            "no shader",
+            "keyword _OLD is not defined by the shader",
+            "texture of _MainTex not found in the loaded bundles",
+        ]);
+    }
+}
@@ -0,0 +1,646 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::gltf::Gltf;
+use crate::object_value::ObjectValue;
+use crate::packed_bit_vector::PackedBitVector;
+use crate::streaming_info::StreamingInfo;
+use crate::unity_version::UnityVersion;
+use crate::vertex_data::{VertexData, CHANNEL_POSITION};
+use crate::Result;
+
+/// file format written by `export`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    Glb,
+}
+
+impl MeshFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            MeshFormat::Obj => "obj",
+            MeshFormat::Glb => "glb",
+        }
+    }
+}
+
+/// primitive type of a submesh; quads and strips are turned into triangles when loading
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Triangles,
+    Lines,
+    LineStrip,
+    Points,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SubMesh {
+    pub topology: Topology,
+    #[serde(skip)]
+    pub indices: Vec<u32>,      // base vertex already added
+}
+
+/// one frame of a blend shape, deltas for every vertex
+#[derive(Serialize, Clone, Debug)]
+pub struct BlendShapeFrame {
+    pub weight: f32,
+    #[serde(skip)]
+    pub positions: Vec<[f32; 3]>,
+    #[serde(skip)]
+    pub normals: Option<Vec<[f32; 3]>>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BlendShape {
+    pub name: String,
+    pub frames: Vec<BlendShapeFrame>,
+}
+
+/// vertex channel indices, renumbered in 2018.1
+struct ChannelLayout {
+    normal: usize,
+    tangent: usize,
+    color: usize,
+    uv0: usize,
+    uv1: usize,
+    skin: Option<(usize, usize)>,   // blend weights and indices, in m_Skin before 2018.1
+}
+
+impl ChannelLayout {
+    fn new(version: &UnityVersion) -> ChannelLayout {
+        match version.release() >= (2018, 1) {
+            true => ChannelLayout{ normal: 1, tangent: 2, color: 3, uv0: 4, uv1: 5, skin: Some((12, 13)) },
+            false => ChannelLayout{ normal: 1, tangent: 7, color: 2, uv0: 3, uv1: 4, skin: None },
+        }
+    }
+}
+
+/// Mesh object in Unity's left handed space, uv origin bottom-left
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Mesh {
+    pub name: String,
+    pub compression: u64,           // m_MeshCompression, 0 off to 3 high
+    pub streamed: bool,             // vertices stored in a .resS
+    pub index_bits: u32,
+    pub vertex_count: usize,
+    pub submeshes: Vec<SubMesh>,
+    pub blend_shapes: Vec<BlendShape>,
+    #[serde(skip)]
+    pub positions: Vec<[f32; 3]>,
+    #[serde(skip)]
+    pub normals: Vec<[f32; 3]>,
+    #[serde(skip)]
+    pub tangents: Vec<[f32; 4]>,
+    #[serde(skip)]
+    pub colors: Vec<[f32; 4]>,
+    #[serde(skip)]
+    pub uv0: Vec<[f32; 2]>,
+    #[serde(skip)]
+    pub uv1: Vec<[f32; 2]>,
+    #[serde(skip)]
+    pub joints: Vec<[u32; 4]>,      // bone indices into bind_poses
+    #[serde(skip)]
+    pub weights: Vec<[f32; 4]>,
+    #[serde(skip)]
+    pub bind_poses: Vec<[f32; 16]>, // row major
+    pub bone_name_hashes: Vec<u32>,
+    pub root_bone_name_hash: u32,
+}
+
+impl Mesh {
+    pub const CLASS_ID: i32 = 43;
+
+    /// reads a Mesh of 5.0 or later; streamed vertex data is looked up in `bundle`
+    pub fn from_value(bundle: &AssetBundle, v: &ObjectValue, version: &UnityVersion) -> Result<Mesh> {
+        let index_bits = match v.get("m_IndexFormat").and_then(|f| f.as_u64()) {
+            Some(1) => 32,
+            Some(_) => 16,
+            None => match v.get("m_Use16BitIndices").and_then(|u| u.as_u64()) {
+                Some(0) => 32,
+                _ => 16,
+            },
+        };
+        let mut mesh = Mesh{
+            name: v.string("m_Name")?.to_string(),
+            compression: v.get("m_MeshCompression").and_then(|c| c.as_u64()).unwrap_or(0),
+            streamed: false,
+            index_bits,
+            vertex_count: 0,
+            submeshes: Vec::new(),
+            blend_shapes: Vec::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            uv0: Vec::new(),
+            uv1: Vec::new(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            bind_poses: match v.get("m_BindPose") {
+                Some(ObjectValue::Array(poses)) => poses.iter().map(matrix).collect::<Result<Vec<[f32; 16]>>>()?,
+                _ => Vec::new(),
+            },
+            bone_name_hashes: match v.get("m_BoneNameHashes") {
+                Some(ObjectValue::Array(h)) => h.iter().filter_map(|h| h.as_u64()).map(|h| h as u32).collect(),
+                _ => Vec::new(),
+            },
+            root_bone_name_hash: v.get("m_RootBoneNameHash").and_then(|h| h.as_u64()).unwrap_or(0) as u32,
+        };
+
+        let compressed = match v.get("m_CompressedMesh") {
+            Some(c) => Some(PackedBitVector::from_value(c.field("m_Vertices")?)?)
+                .filter(|p| !p.is_empty())
+                .map(|_| c),
+            None => None,
+        };
+        let indices = match compressed {
+            Some(c) => mesh.read_compressed(c)?,
+            None => {
+                mesh.read_vertex_data(bundle, v, version)?;
+                let ib = v.get("m_IndexBuffer").and_then(|b| b.as_bytes()).unwrap_or_default();
+                match index_bits {
+                    32 => ib.chunks_exact(4).map(|i| u32::from_le_bytes([i[0], i[1], i[2], i[3]])).collect(),
+                    _ => ib.chunks_exact(2).map(|i| u16::from_le_bytes([i[0], i[1]]) as u32).collect(),
+                }
+            },
+        };
+        if mesh.joints.is_empty() {
+            mesh.read_skin(v)?;
+        }
+        mesh.vertex_count = mesh.positions.len();
+        mesh.submeshes = match v.field("m_SubMeshes")? {
+            ObjectValue::Array(subs) => subs.iter()
+                .map(|s| submesh(s, &indices, index_bits, mesh.vertex_count))
+                .collect::<Result<Vec<SubMesh>>>()?,
+            _ => return Err("m_SubMeshes is not an array".into()),
+        };
+        if let Some(shapes) = v.get("m_Shapes") {
+            mesh.blend_shapes = blend_shapes(shapes, mesh.vertex_count)?;
+        }
+        Ok(mesh)
+    }
+
+    /// channels of m_VertexData, or of the .resS it was streamed into
+    fn read_vertex_data(&mut self, bundle: &AssetBundle, v: &ObjectValue, version: &UnityVersion) -> Result<()> {
+        let mut vd = VertexData::from_value(v.field("m_VertexData")?, version)?;
+        if vd.data.is_empty() {
+            if let Some(stream) = v.get("m_StreamData").and_then(StreamingInfo::from_value) {
+                vd.data = stream.load(bundle)?.to_vec();
+                self.streamed = true;
+            }
+        }
+        self.read_channels(&vd, version)
+    }
+
+    /// positions, normals, colors, uvs and skin of the channels of `vd`
+    fn read_channels(&mut self, vd: &VertexData, version: &UnityVersion) -> Result<()> {
+        let layout = ChannelLayout::new(version);
+        let channel = |index: usize| -> Result<Vec<Vec<f32>>> {
+            vd.channel(index).transpose().map(|c| c.unwrap_or_default())
+        };
+        self.positions = channel(CHANNEL_POSITION)?.iter().map(|p| vec3(p)).collect();
+        self.normals = channel(layout.normal)?.iter().map(|n| vec3(n)).collect();
+        self.tangents = channel(layout.tangent)?.iter().map(|t| vec4(t, 1.0)).collect();
+        self.colors = channel(layout.color)?.iter().map(|c| vec4(c, 1.0)).collect();
+        self.uv0 = channel(layout.uv0)?.iter().map(|uv| vec2(uv)).collect();
+        self.uv1 = channel(layout.uv1)?.iter().map(|uv| vec2(uv)).collect();
+        if let Some((weights, indices)) = layout.skin {
+            let weights = channel(weights)?;
+            let indices = channel(indices)?;
+            if !indices.is_empty() {
+                self.joints = indices.iter().map(|i| {
+                    let i = vec4(i, 0.0);
+                    [i[0] as u32, i[1] as u32, i[2] as u32, i[3] as u32]
+                }).collect();
+                // a single bone per vertex leaves the weight channel out
+                self.weights = match weights.is_empty() {
+                    true => vec![[1.0, 0.0, 0.0, 0.0]; indices.len()],
+                    false => weights.iter().map(|w| vec4(w, 0.0)).collect(),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// m_Skin of meshes before 2018.1
+    fn read_skin(&mut self, v: &ObjectValue) -> Result<()> {
+        if let Some(ObjectValue::Array(skin)) = v.get("m_Skin") {
+            for bw in skin {
+                let mut joint = [0; 4];
+                let mut weight = [0.0; 4];
+                for i in 0..4 {
+                    weight[i] = bw.float(&format!("weight[{}]", i))? as f32;
+                    joint[i] = bw.int(&format!("boneIndex[{}]", i))? as u32;
+                }
+                self.joints.push(joint);
+                self.weights.push(weight);
+            }
+        }
+        Ok(())
+    }
+
+    /// vertices of m_CompressedMesh; returns the index buffer
+    fn read_compressed(&mut self, c: &ObjectValue) -> Result<Vec<u32>> {
+        let packed = |name: &str| -> Result<PackedBitVector> {
+            match c.get(name) {
+                Some(p) => PackedBitVector::from_value(p),
+                None => Ok(PackedBitVector::default()),
+            }
+        };
+        let vertices = packed("m_Vertices")?;
+        let count = vertices.count / 3;
+        self.positions = vertices.floats()?.chunks_exact(3).map(vec3).collect();
+
+        let uv = packed("m_UV")?;
+        if !uv.is_empty() {
+            let uv_info = c.get("m_UVInfo").and_then(|i| i.as_u64()).unwrap_or(0) as u32;
+            let mut channels = Vec::new();
+            match uv_info {
+                // before 5.0 only two channels of two components
+                0 => {
+                    channels.push(uv.floats_from(0, count * 2)?.chunks_exact(2).map(vec2).collect());
+                    if uv.count >= count * 4 {
+                        channels.push(uv.floats_from(count * 2, count * 2)?.chunks_exact(2).map(vec2).collect());
+                    }
+                },
+                _ => {
+                    let mut offset = 0;
+                    for channel in 0..8 {
+                        let info = uv_info >> (channel * 4) & 0xf;
+                        if info & 4 == 0 {
+                            continue;
+                        }
+                        let dimension = (info & 3) as usize + 1;
+                        let values = uv.floats_from(offset, count * dimension)?;
+                        offset += count * dimension;
+                        if channel < 2 {
+                            channels.resize(channel, Vec::new());
+                            channels.push(values.chunks_exact(dimension).map(vec2).collect());
+                        }
+                    }
+                },
+            }
+            let mut channels = channels.into_iter();
+            self.uv0 = channels.next().unwrap_or_default();
+            self.uv1 = channels.next().unwrap_or_default();
+        }
+
+        let normals = packed("m_Normals")?;
+        if !normals.is_empty() {
+            let signs = packed("m_NormalSigns")?.ints()?;
+            self.normals = normals.floats()?.chunks_exact(2).zip(signs)
+                .map(|(xy, sign)| {
+                    let n = unpack_unit(xy[0], xy[1], sign);
+                    [n[0], n[1], n[2]]
+                })
+                .collect();
+        }
+
+        let tangents = packed("m_Tangents")?;
+        if !tangents.is_empty() {
+            let signs = packed("m_TangentSigns")?.ints()?;
+            self.tangents = tangents.floats()?.chunks_exact(2).zip(signs.chunks_exact(2))
+                .map(|(xy, sign)| {
+                    let t = unpack_unit(xy[0], xy[1], sign[0]);
+                    [t[0], t[1], t[2], match sign[1] { 0 => -1.0, _ => 1.0 }]
+                })
+                .collect();
+        }
+
+        let colors = packed("m_FloatColors")?;
+        if !colors.is_empty() {
+            self.colors = colors.floats()?.chunks_exact(4).map(|c| vec4(c, 1.0)).collect();
+        }
+
+        let weights = packed("m_Weights")?;
+        if !weights.is_empty() {
+            let bones = packed("m_BoneIndices")?.ints()?;
+            let mut bones = bones.into_iter();
+            let mut next_bone = || bones.next().ok_or("compressed mesh is missing bone indices");
+            let (mut joint, mut weight, mut sum, mut j) = ([0; 4], [0.0; 4], 0, 0);
+            // weights are quantized to 31 steps; the last one of a vertex is implied
+            for w in weights.ints()? {
+                weight[j] = w as f32 / 31.0;
+                joint[j] = next_bone()?;
+                j += 1;
+                sum += w;
+                if sum >= 31 || j == 3 {
+                    if sum < 31 {
+                        weight[3] = (31 - sum) as f32 / 31.0;
+                        joint[3] = next_bone()?;
+                    }
+                    self.joints.push(joint);
+                    self.weights.push(weight);
+                    joint = [0; 4];
+                    weight = [0.0; 4];
+                    sum = 0;
+                    j = 0;
+                }
+            }
+        }
+
+        packed("m_Triangles")?.ints()
+    }
+
+    /// Wavefront OBJ, mirrored on x into a right handed space
+    pub fn to_obj(&self) -> String {
+        let mut s = String::new();
+        let _ = writeln!(s, "# {} vertices, {} submeshes", self.vertex_count, self.submeshes.len());
+        let _ = writeln!(s, "o {}", self.name);
+        for p in &self.positions {
+            let _ = writeln!(s, "v {} {} {}", -p[0], p[1], p[2]);
+        }
+        for uv in &self.uv0 {
+            let _ = writeln!(s, "vt {} {}", uv[0], uv[1]);
+        }
+        for n in &self.normals {
+            let _ = writeln!(s, "vn {} {} {}", -n[0], n[1], n[2]);
+        }
+        let vertex = |i: u32| {
+            let i = i + 1;
+            match (self.uv0.is_empty(), self.normals.is_empty()) {
+                (true, true) => format!("{}", i),
+                (false, true) => format!("{}/{}", i, i),
+                (true, false) => format!("{}//{}", i, i),
+                (false, false) => format!("{}/{}/{}", i, i, i),
+            }
+        };
+        for (n, sub) in self.submeshes.iter().enumerate() {
+            let _ = writeln!(s, "g {}_{}", self.name, n);
+            match sub.topology {
+                // the mirror flips the winding
+                Topology::Triangles => for t in sub.indices.chunks_exact(3) {
+                    let _ = writeln!(s, "f {} {} {}", vertex(t[0]), vertex(t[2]), vertex(t[1]));
+                },
+                Topology::Lines => for l in sub.indices.chunks_exact(2) {
+                    let _ = writeln!(s, "l {} {}", l[0] + 1, l[1] + 1);
+                },
+                Topology::LineStrip => {
+                    let strip: Vec<String> = sub.indices.iter().map(|i| (i + 1).to_string()).collect();
+                    let _ = writeln!(s, "l {}", strip.join(" "));
+                },
+                Topology::Points => for p in &sub.indices {
+                    let _ = writeln!(s, "p {}", p + 1);
+                },
+            }
+        }
+        s
+    }
+}
+
+/// indices of one m_SubMeshes entry, triangulated
+fn submesh(v: &ObjectValue, indices: &[u32], index_bits: u32, vertex_count: usize) -> Result<SubMesh> {
+    let first = v.int("firstByte")? as usize / (index_bits as usize / 8);
+    let count = v.int("indexCount")? as usize;
+    let base = v.get("baseVertex").and_then(|b| b.as_u64()).unwrap_or(0) as u32;
+    let range = first.checked_add(count).and_then(|end| indices.get(first .. end))
+        .ok_or_else(|| format!("submesh indices {}..{} out of {}", first, first.saturating_add(count), indices.len()))?;
+    let range: Vec<u32> = range.iter()
+        .map(|i| i.checked_add(base).filter(|i| (*i as usize) < vertex_count))
+        .collect::<Option<Vec<u32>>>()
+        .ok_or_else(|| format!("submesh index out of {} vertices", vertex_count))?;
+    let (topology, indices) = match v.get("topology").and_then(|t| t.as_u64()).unwrap_or(0) {
+        0 => (Topology::Triangles, range),
+        // strips alternate their winding and use repeated indices as restarts
+        1 => (Topology::Triangles, range.windows(3).enumerate()
+            .filter(|(_, t)| t[0] != t[1] && t[1] != t[2] && t[0] != t[2])
+            .flat_map(|(i, t)| match i % 2 {
+                0 => [t[0], t[1], t[2]],
+                _ => [t[1], t[0], t[2]],
+            })
+            .collect()),
+        2 => (Topology::Triangles, range.chunks_exact(4)
+            .flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]])
+            .collect()),
+        3 => (Topology::Lines, range),
+        4 => (Topology::LineStrip, range),
+        5 => (Topology::Points, range),
+        t => return Err(format!("unknown topology {}", t).into()),
+    };
+    Ok(SubMesh{ topology, indices })
+}
+
+/// m_Shapes: channels of frames, each frame a sparse list of vertex deltas
+fn blend_shapes(v: &ObjectValue, vertex_count: usize) -> Result<Vec<BlendShape>> {
+    let array = |name: &str| -> Result<&[ObjectValue]> {
+        match v.field(name)? {
+            ObjectValue::Array(a) => Ok(a),
+            _ => Err(format!("{} is not an array", name).into()),
+        }
+    };
+    let vertices = array("vertices")?;
+    let shapes = array("shapes")?;
+    let weights = array("fullWeights")?;
+    array("channels")?.iter()
+        .map(|channel| {
+            let first = channel.int("frameIndex")? as usize;
+            let last = first.checked_add(channel.int("frameCount")? as usize).ok_or("blend shape frames out of range")?;
+            let frames = (first .. last)
+                .map(|f| {
+                    let shape = shapes.get(f).ok_or("blend shape frame out of range")?;
+                    let start = shape.int("firstVertex")? as usize;
+                    let end = start.checked_add(shape.int("vertexCount")? as usize).ok_or("blend shape vertices out of range")?;
+                    let has_normals = shape.get("hasNormals").and_then(|n| n.as_bool()).unwrap_or(false);
+                    let mut positions = vec![[0.0; 3]; vertex_count];
+                    let mut normals = vec![[0.0; 3]; vertex_count];
+                    for bv in vertices.get(start..end).ok_or("blend shape vertices out of range")? {
+                        let index = bv.int("index")? as usize;
+                        if index >= vertex_count {
+                            return Err(format!("blend shape vertex {} out of range", index).into());
+                        }
+                        positions[index] = vector3(bv.field("vertex")?)?;
+                        normals[index] = vector3(bv.field("normal")?)?;
+                    }
+                    Ok(BlendShapeFrame{
+                        weight: weights.get(f).and_then(|w| w.as_f64()).unwrap_or(100.0) as f32,
+                        positions,
+                        normals: Some(normals).filter(|_| has_normals),
+                    })
+                })
+                .collect::<Result<Vec<BlendShapeFrame>>>()?;
+            Ok(BlendShape{ name: channel.string("name")?.to_string(), frames })
+        })
+        .collect()
+}
+
+/// unit vector from two components and the sign of the third
+fn unpack_unit(x: f32, y: f32, sign: u32) -> [f32; 3] {
+    let zz = 1.0 - x * x - y * y;
+    let v = match zz >= 0.0 {
+        true => [x, y, zz.sqrt()],
+        false => {
+            let l = (x * x + y * y).sqrt();
+            [x / l, y / l, 0.0]
+        },
+    };
+    match sign {
+        0 => [v[0], v[1], -v[2]],
+        _ => v,
+    }
+}
+
+fn vec2(v: &[f32]) -> [f32; 2] {
+    [v.first().copied().unwrap_or(0.0), v.get(1).copied().unwrap_or(0.0)]
+}
+
+fn vec3(v: &[f32]) -> [f32; 3] {
+    [v.first().copied().unwrap_or(0.0), v.get(1).copied().unwrap_or(0.0), v.get(2).copied().unwrap_or(0.0)]
+}
+
+/// four components, a missing w set to `w`
+fn vec4(v: &[f32], w: f32) -> [f32; 4] {
+    let xyz = vec3(v);
+    [xyz[0], xyz[1], xyz[2], v.get(3).copied().unwrap_or(w)]
+}
+
+pub fn vector3(v: &ObjectValue) -> Result<[f32; 3]> {
+    Ok([v.float("x")? as f32, v.float("y")? as f32, v.float("z")? as f32])
+}
+
+/// Matrix4x4f, row major
+pub fn matrix(v: &ObjectValue) -> Result<[f32; 16]> {
+    let mut m = [0.0; 16];
+    for (i, e) in m.iter_mut().enumerate() {
+        *e = v.float(&format!("e{}{}", i / 4, i % 4))? as f32;
+    }
+    Ok(m)
+}
+
+/// one exported mesh, listed in meshes.json
+#[derive(Serialize, Clone, Debug)]
+struct MeshSummary {
+    #[serde(flatten)]
+    mesh: Mesh,
+    skinned: bool,
+    bones: usize,
+    file: String,
+}
+
+/// writes every mesh of the bundle into `dst` as OBJ or binary glTF
+/// and lists them with their compression settings in `dst/meshes.json`
+pub fn export(bundle: &AssetBundle, dst: &Path, format: MeshFormat) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut summaries = Vec::new();
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(Mesh::CLASS_ID) {
+                continue;
+            }
+            let mesh = match asset.object_value(obj).and_then(|v| Mesh::from_value(bundle, &v, asset.unity_version())) {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let bytes = match format {
+                MeshFormat::Obj => mesh.to_obj().into_bytes(),
+                MeshFormat::Glb => {
+                    let mut gltf = Gltf::new();
+                    let index = gltf.add_mesh(&mesh, &[], false);
+                    let node = gltf.add_node(serde_json::json!({ "name": mesh.name, "mesh": index }));
+                    gltf.add_root(node);
+                    gltf.to_glb()
+                },
+            };
+            let path = export::unique_path(dst, &mesh.name, format.extension());
+            fs::write(&path, bytes)?;
+            info!("{} vertices {:?}", mesh.vertex_count, path);
+            summaries.push(MeshSummary{
+                skinned: !mesh.joints.is_empty(),
+                bones: mesh.bind_poses.len(),
+                file: path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default(),
+                mesh,
+            });
+        }
+    }
+    fs::write(dst.join("meshes.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(summaries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(stream: u64, offset: u64, format: u64, dimension: u64) -> ObjectValue {
+        ObjectValue::Struct(vec![
+            ("stream".to_string(), ObjectValue::UInt(stream)),
+            ("offset".to_string(), ObjectValue::UInt(offset)),
+            ("format".to_string(), ObjectValue::UInt(format)),
+            ("dimension".to_string(), ObjectValue::UInt(dimension)),
+        ])
+    }
+
+    #[test]
+    fn read_2018_normalized_channels() {
+        // VertexFormat2017: 0 Float, 5 UNorm16, 6 SNorm16
+        let mut channels = vec![channel(0, 0, 0, 0); 14];
+        channels[0] = channel(0, 0, 0, 3);
+        channels[1] = channel(0, 12, 6, 3);
+        channels[4] = channel(0, 18, 5, 2);
+        let mut data = Vec::new();
+        for (position, normal, uv) in [
+            ([1.0f32, 2.0, 3.0], [32767i16, -32767, 0], [65535u16, 0]),
+            ([0.0, -1.0, 0.5], [0, 0, 32767], [0, 32768]),
+        ] {
+            position.iter().for_each(|p| data.extend_from_slice(&p.to_le_bytes()));
+            normal.iter().for_each(|n| data.extend_from_slice(&n.to_le_bytes()));
+            uv.iter().for_each(|u| data.extend_from_slice(&u.to_le_bytes()));
+        }
+        let v = ObjectValue::Struct(vec![
+            ("m_VertexCount".to_string(), ObjectValue::UInt(2)),
+            ("m_Channels".to_string(), ObjectValue::Array(channels)),
+            ("m_DataSize".to_string(), ObjectValue::Bytes(data)),
+        ]);
+        let version = UnityVersion::parse("2018.4.2f1").unwrap();
+        let vd = VertexData::from_value(&v, &version).unwrap();
+        let mut mesh = Mesh::default();
+        mesh.read_channels(&vd, &version).unwrap();
+
+        assert_eq!(mesh.positions, vec![[1.0, 2.0, 3.0], [0.0, -1.0, 0.5]]);
+        assert_eq!(mesh.normals, vec![[1.0, -1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(mesh.uv0[0], [1.0, 0.0]);
+        assert!((mesh.uv0[1][1] - 32768.0 / 65535.0).abs() < 1e-6);
+        assert!(mesh.tangents.is_empty() && mesh.colors.is_empty() && mesh.joints.is_empty());
+    }
+
+    fn value(fields: &[(&str, ObjectValue)]) -> ObjectValue {
+        ObjectValue::Struct(fields.iter().map(|(n, v)| (n.to_string(), v.clone())).collect())
+    }
+
+    #[test]
+    fn overflowing_submeshes_and_shapes() {
+        let indices = [0, 1, 2, 2, 1, 3];
+        let sub = |first: u64, count: u64, base: u64| value(&[
+            ("firstByte", ObjectValue::UInt(first)),
+            ("indexCount", ObjectValue::UInt(count)),
+            ("baseVertex", ObjectValue::UInt(base)),
+        ]);
+        assert_eq!(submesh(&sub(6, 3, 1), &indices, 16, 5).unwrap().indices, vec![3, 2, 4]);
+        assert!(submesh(&sub(6, u64::MAX, 0), &indices, 16, 5).is_err());
+        assert!(submesh(&sub(0, 3, u32::MAX as u64), &indices, 16, 5).is_err());
+
+        let shapes = |frame_index: i64, frame_count: i64, first_vertex: i64, vertex_count: i64| value(&[
+            ("vertices", ObjectValue::Array(Vec::new())),
+            ("shapes", ObjectValue::Array(vec![value(&[
+                ("firstVertex", ObjectValue::Int(first_vertex)),
+                ("vertexCount", ObjectValue::Int(vertex_count)),
+            ])])),
+            ("fullWeights", ObjectValue::Array(Vec::new())),
+            ("channels", ObjectValue::Array(vec![value(&[
+                ("name", ObjectValue::String("smile".to_string())),
+                ("frameIndex", ObjectValue::Int(frame_index)),
+                ("frameCount", ObjectValue::Int(frame_count)),
+            ])])),
+        ]);
+        assert_eq!(blend_shapes(&shapes(0, 1, 0, 0), 4).unwrap()[0].frames.len(), 1);
+        assert!(blend_shapes(&shapes(1, -1, 0, 0), 4).is_err());
+        assert!(blend_shapes(&shapes(0, 1, 1, -1), 4).is_err());
+    }
+}
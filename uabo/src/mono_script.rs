@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, PPtr};
+use crate::Result;
+
+const MONO_BEHAVIOUR_CLASS_ID: i32 = 114;
+
+/// MonoScript, the managed class a MonoBehaviour runs
+#[derive(Serialize, Clone, Debug)]
+pub struct MonoScript {
+    pub name: String,
+    pub class_name: String,
+    pub namespace: String,
+    pub assembly: String,           // assembly file name, e.g. Assembly-CSharp.dll
+    pub properties_hash: String,    // hex; changes with the serialized fields
+    pub execution_order: i64,
+    pub editor_script: bool,
+}
+
+impl MonoScript {
+    pub const CLASS_ID: i32 = 115;
+
+    pub fn from_value(v: &ObjectValue) -> Result<MonoScript> {
+        let text = |name: &str| v.get(name).and_then(|s| s.as_str()).unwrap_or_default().to_string();
+        // Hash128 since 5.0, a UInt32 before
+        let properties_hash = match v.get("m_PropertiesHash") {
+            Some(ObjectValue::Struct(bytes)) => bytes.iter()
+                .filter_map(|(_, b)| b.as_u64())
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            Some(h) => h.as_u64().map(|h| format!("{:08x}", h)).unwrap_or_default(),
+            None => String::new(),
+        };
+        Ok(MonoScript{
+            name: v.string("m_Name")?.to_string(),
+            class_name: text("m_ClassName"),
+            namespace: text("m_Namespace"),
+            assembly: text("m_AssemblyName"),
+            properties_hash,
+            execution_order: v.get("m_ExecutionOrder").and_then(|o| o.as_i64()).unwrap_or(0),
+            editor_script: v.flag("m_IsEditorScript"),
+        })
+    }
+
+    /// class name qualified by its namespace
+    pub fn full_name(&self) -> String {
+        match self.namespace.is_empty() {
+            true => self.class_name.clone(),
+            false => format!("{}.{}", self.namespace, self.class_name),
+        }
+    }
+}
+
+/// assembly name without directory and .dll, compared case insensitively
+fn assembly_key(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name).to_lowercase();
+    match name.strip_suffix(".dll") {
+        Some(n) => n.to_string(),
+        None => name,
+    }
+}
+
+/// assemblies and types shipped with the player
+///
+/// A text list holds one entry per line: `Foo.dll` for a whole assembly,
+/// `Foo.dll:Namespace.Class` or `Namespace.Class` for a single type; `#` starts a comment.
+/// A directory, such as a player's Managed folder, contributes the assembly of every .dll inside.
+#[derive(Default, Debug)]
+pub struct PlayerTypes {
+    assemblies: HashSet<String>,
+    typed_assemblies: HashSet<String>,  // assemblies whose types are listed one by one
+    types: HashSet<String>,             // full names, also keyed by `assembly:full name`
+}
+
+impl PlayerTypes {
+    pub fn load(path: &Path) -> Result<PlayerTypes> {
+        let mut types = PlayerTypes::default();
+        match path.is_dir() {
+            true => {
+                for entry in fs::read_dir(path)? {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    if name.to_lowercase().ends_with(".dll") {
+                        types.assemblies.insert(assembly_key(&name));
+                    }
+                }
+            },
+            false => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("{:?}: {}", path, e))?;
+                for line in text.lines() {
+                    let line = line.split('#').next().unwrap_or_default().trim();
+                    match line.split_once(':') {
+                        _ if line.is_empty() => {},
+                        Some((assembly, name)) => {
+                            let assembly = assembly_key(assembly.trim());
+                            types.types.insert(format!("{}:{}", assembly, name.trim()));
+                            types.assemblies.insert(assembly.clone());
+                            types.typed_assemblies.insert(assembly);
+                        },
+                        None if line.to_lowercase().ends_with(".dll") => {
+                            types.assemblies.insert(assembly_key(line));
+                        },
+                        None => {
+                            types.types.insert(line.to_string());
+                        },
+                    }
+                }
+            },
+        }
+        Ok(types)
+    }
+
+    /// whether the player can instantiate `script`
+    pub fn contains(&self, script: &MonoScript) -> bool {
+        let assembly = assembly_key(&script.assembly);
+        let name = script.full_name();
+        self.types.contains(&name)
+            || self.types.contains(&format!("{}:{}", assembly, name))
+            || self.assemblies.contains(&assembly) && !self.typed_assemblies.contains(&assembly)
+    }
+}
+
+/// MonoScript of scripts.json with what links to it
+#[derive(Serialize, Clone, Debug)]
+pub struct ScriptEntry {
+    #[serde(flatten)]
+    pub script: MonoScript,
+    pub asset: String,
+    pub path_id: i64,
+    pub behaviours: usize,          // MonoBehaviours whose m_Script points here
+    pub type_entries: usize,        // type tree entries whose script_id points here
+    pub in_player: Option<bool>,    // unknown without a player type list
+}
+
+/// MonoBehaviour whose script can not be resolved
+#[derive(Serialize, Clone, Debug)]
+pub struct MissingScript {
+    pub asset: String,
+    pub path_id: i64,
+    pub name: String,
+    pub script: Option<PPtr>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ScriptReport {
+    pub scripts: Vec<ScriptEntry>,
+    pub missing_scripts: Vec<MissingScript>,
+    pub absent_from_player: Vec<String>,    // `assembly: full name` of scripts the player lacks
+}
+
+/// MonoScripts of `bundles` by file and path id, read once
+struct Scripts<'a> {
+    bundles: &'a [AssetBundle],
+    entries: BTreeMap<(String, i64), Option<ScriptEntry>>,
+}
+
+impl<'a> Scripts<'a> {
+    /// entry of the MonoScript `ptr` of `from` points to, or why there is none
+    fn resolve(&mut self, from: &Asset, ptr: &PPtr) -> std::result::Result<&mut ScriptEntry, String> {
+        let target = pptr::resolve(self.bundles, from, ptr)
+            .ok_or_else(|| format!("script ({}, {}) not found in the loaded bundles", ptr.file_id, ptr.path_id))?;
+        if target.asset.class_id(target.object) != Some(MonoScript::CLASS_ID) {
+            return Err(format!("script {} is not a MonoScript", ptr.path_id));
+        }
+        let key = (target.asset.name().to_string(), target.object.path_id);
+        let entry = self.entries.entry(key).or_insert_with(|| {
+            target.asset.object_value(target.object)
+                .and_then(|v| MonoScript::from_value(&v))
+                .map_err(|e| warn!("script {}: {}", target.object.path_id, e))
+                .ok()
+                .map(|script| ScriptEntry{
+                    script,
+                    asset: target.asset.name().to_string(),
+                    path_id: target.object.path_id,
+                    behaviours: 0,
+                    type_entries: 0,
+                    in_player: None,
+                })
+        });
+        entry.as_mut().ok_or_else(|| format!("script {} could not be read", ptr.path_id))
+    }
+}
+
+/// links the MonoBehaviours and type entries of `bundles[0]` to their MonoScripts, looked up
+/// through `bundles`, and checks the scripts against `player` when given
+pub fn report(bundles: &[AssetBundle], player: Option<&PlayerTypes>) -> Result<ScriptReport> {
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let mut scripts = Scripts{ bundles, entries: BTreeMap::new() };
+    let mut missing_scripts = Vec::new();
+    for asset in bundle.assets() {
+        // every MonoScript of the bundle, used or not
+        for obj in asset.objects() {
+            if asset.class_id(obj) == Some(MonoScript::CLASS_ID) {
+                let _ = scripts.resolve(asset, &PPtr{ file_id: 0, path_id: obj.path_id });
+            }
+        }
+        for class in asset.classes() {
+            let entry = class.script_id
+                .filter(|id| *id >= 0)
+                .and_then(|id| asset.script_types().get(id as usize));
+            if let Some(entry) = entry {
+                match scripts.resolve(asset, &PPtr{ file_id: entry.file_id, path_id: entry.local_id }) {
+                    Ok(s) => s.type_entries += 1,
+                    Err(e) => info!("type entry of script {}: {}", entry.local_id, e),
+                }
+            }
+        }
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(MONO_BEHAVIOUR_CLASS_ID) {
+                continue;
+            }
+            // without a type tree the script is still known from the class entry
+            let (name, ptr) = match asset.object_value(obj) {
+                Ok(v) => (
+                    v.get("m_Name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+                    v.get("m_Script").and_then(PPtr::from_value),
+                ),
+                Err(_) => (String::new(), asset.class_of(obj)
+                    .and_then(|c| c.script_id)
+                    .filter(|id| *id >= 0)
+                    .and_then(|id| asset.script_types().get(id as usize))
+                    .map(|e| PPtr{ file_id: e.file_id, path_id: e.local_id })),
+            };
+            let result = match ptr {
+                Some(p) if !p.is_null() => scripts.resolve(asset, &p).map(|s| s.behaviours += 1),
+                _ => Err("no script".to_string()),
+            };
+            if let Err(reason) = result {
+                missing_scripts.push(MissingScript{ asset: asset.name().to_string(), path_id: obj.path_id, name, script: ptr, reason });
+            }
+        }
+    }
+
+    let mut report = ScriptReport{ missing_scripts, ..Default::default() };
+    for mut entry in scripts.entries.into_values().flatten() {
+        if let Some(player) = player {
+            let present = player.contains(&entry.script);
+            if !present && !entry.script.editor_script {
+                report.absent_from_player.push(format!("{}: {}", entry.script.assembly, entry.script.full_name()));
+            }
+            entry.in_player = Some(present);
+        }
+        report.scripts.push(entry);
+    }
+    report.absent_from_player.sort();
+    report.absent_from_player.dedup();
+    Ok(report)
+}
+
+/// writes the script report of `bundles[0]` into `dst/scripts.json`
+pub fn export(bundles: &[AssetBundle], player: Option<&PlayerTypes>, dst: &Path) -> Result<ScriptReport> {
+    fs::create_dir_all(dst)?;
+    let report = report(bundles, player)?;
+    info!("{} scripts, {} missing, {} absent from the player",
+        report.scripts.len(), report.missing_scripts.len(), report.absent_from_player.len());
+    fs::write(dst.join("scripts.json"), serde_json::to_string_pretty(&report)?)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, Node, Writer, ALIGN};
+
+    fn string(name: &'static str) -> Vec<Node> {
+        vec![(1, "string", name, 0), (2, "Array", "Array", ALIGN), (3, "int", "size", 0), (3, "char", "data", 0)]
+    }
+
+    fn mono_script_type() -> Vec<Node> {
+        let mut nodes = vec![(0, "MonoScript", "Base", 0)];
+        for name in ["m_Name", "m_ClassName", "m_Namespace", "m_AssemblyName"] {
+            nodes.extend(string(name));
+        }
+        nodes
+    }
+
+    fn mono_behaviour_type() -> Vec<Node> {
+        let mut nodes = vec![(0, "MonoBehaviour", "Base", 0)];
+        nodes.extend(fixture::pptr(1, "PPtr<GameObject>", "m_GameObject"));
+        nodes.extend(fixture::pptr(1, "PPtr<MonoScript>", "m_Script"));
+        nodes.extend(string("m_Name"));
+        nodes
+    }
+
+    fn behaviour(script: i64, name: &str) -> Vec<u8> {
+        Writer::default().pptr(0).pptr(script).string(name).0
+    }
+
+    fn player_types(name: &str, text: &str) -> PlayerTypes {
+        let path = std::env::temp_dir().join(format!("uabo-{}-{}.txt", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let types = PlayerTypes::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        types
+    }
+
+    #[test]
+    fn link_behaviours_to_scripts() {
+        let classes = [(MonoScript::CLASS_ID, mono_script_type()), (MONO_BEHAVIOUR_CLASS_ID, mono_behaviour_type())];
+        let objects = [
+            (1, 0, Writer::default().string("Foo").string("Foo").string("Game").string("Assembly-CSharp.dll").0),
+            (2, 1, behaviour(1, "first")),
+            (3, 1, behaviour(9, "dangling")),
+            (4, 1, behaviour(0, "empty")),
+            (5, 1, behaviour(1, "second")),
+        ];
+        let bundles = [fixture::bundle(vec![("level0", fixture::serialized_file(&classes, &objects))])];
+
+        let player = player_types("types", "# shipped\nUnityEngine.UI.dll\nOther.dll:Game.Foo\n");
+        let report = report(&bundles, Some(&player)).unwrap();
+        assert_eq!(report.scripts.len(), 1);
+        assert_eq!((report.scripts[0].behaviours, report.scripts[0].in_player), (2, Some(false)));
+        assert_eq!(report.absent_from_player, vec!["Assembly-CSharp.dll: Game.Foo"]);
+        let missing: Vec<(&str, &str)> = report.missing_scripts.iter().map(|m| (m.name.as_str(), m.reason.as_str())).collect();
+        assert_eq!(missing, vec![("dangling", "script (0, 9) not found in the loaded bundles"), ("empty", "no script")]);
+    }
+
+    #[test]
+    fn player_type_lists() {
+        let script = |namespace: &str, assembly: &str| MonoScript{
+            name: "Foo".to_string(), class_name: "Foo".to_string(), namespace: namespace.to_string(),
+            assembly: assembly.to_string(), properties_hash: String::new(), execution_order: 0, editor_script: false,
+        };
+        let player = player_types("list", "Managed/Assembly-CSharp.DLL\nPlugins.dll:Vendor.Foo\nFoo\n");
+        assert!(player.contains(&script("Game", "Assembly-CSharp.dll")));
+        assert!(player.contains(&script("Vendor", "Plugins.dll")));
+        // assemblies listed type by type hold only those types
+        assert!(!player.contains(&script("Other", "Plugins.dll")));
+        assert!(player.contains(&script("", "Missing.dll")));
+    }
+}
@@ -0,0 +1,254 @@
+use std::io::{Read, Seek};
+use serde::ser::{Serialize, Serializer, SerializeMap};
+
+use crate::binary_reader::BinaryReader;
+use crate::type_info::TypeInfo;
+use crate::Result;
+
+/// type tree flag asking for 4 byte alignment after the field
+const ALIGN_FLAG: u32 = 0x4000;
+
+/// object field deserialized along its class type tree
+#[derive(Clone, Debug)]
+pub enum ObjectValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),                             // byte arrays, TypelessData and strings that are not UTF-8
+    Array(Vec<ObjectValue>),
+    Struct(Vec<(String, ObjectValue)>),         // fields in declaration order
+}
+
+impl ObjectValue {
+    /// reads a value laid out as described by `node`
+    pub fn read<T: Read + Seek>(reader: &mut BinaryReader<T>, node: &TypeInfo) -> Result<ObjectValue> {
+        let value = match &*node.type_str {
+            "TypelessData" => {
+                let size = ObjectValue::length(reader, 1)?;
+                ObjectValue::Bytes(reader.read(size)?)
+            },
+            _ if node.is_array => ObjectValue::read_array(reader, node)?,
+            "bool" => ObjectValue::Bool(reader.boolean()?),
+            "SInt8" => ObjectValue::Int(reader.int8()? as i64),
+            "UInt8" | "char" => ObjectValue::UInt(reader.uint8()? as u64),
+            "SInt16" | "short" => ObjectValue::Int(reader.int16()? as i64),
+            "UInt16" | "unsigned short" => ObjectValue::UInt(reader.uint16()? as u64),
+            "SInt32" | "int" => ObjectValue::Int(reader.int32()? as i64),
+            "UInt32" | "unsigned int" | "Type*" => ObjectValue::UInt(reader.uint32()? as u64),
+            "SInt64" | "long long" => ObjectValue::Int(reader.int64()?),
+            "UInt64" | "unsigned long long" | "FileSize" => ObjectValue::UInt(reader.uint64()?),
+            "float" => ObjectValue::Float(reader.float32()? as f64),
+            "double" => ObjectValue::Float(reader.float64()?),
+            "string" => {
+                let size = ObjectValue::length(reader, 1)?;
+                let bytes = reader.read(size)?;
+                if node.children.first().is_some_and(|c| c.flags & ALIGN_FLAG != 0) {
+                    reader.align(4);
+                }
+                // TextAsset scripts hold binary data as strings
+                match String::from_utf8(bytes) {
+                    Ok(s) => ObjectValue::String(s),
+                    Err(e) => ObjectValue::Bytes(e.into_bytes()),
+                }
+            },
+            _ => match node.children.first() {
+                // vector, set, map and friends wrap a single Array node
+                Some(array) if array.is_array => {
+                    let v = ObjectValue::read_array(reader, array)?;
+                    if array.flags & ALIGN_FLAG != 0 {
+                        reader.align(4);
+                    }
+                    v
+                },
+                _ => {
+                    let mut fields = Vec::with_capacity(node.children.len());
+                    for child in &node.children {
+                        fields.push((child.name_str.clone(), ObjectValue::read(reader, child)?));
+                    }
+                    ObjectValue::Struct(fields)
+                },
+            },
+        };
+        if node.flags & ALIGN_FLAG != 0 {
+            reader.align(4);
+        }
+        Ok(value)
+    }
+
+    fn read_array<T: Read + Seek>(reader: &mut BinaryReader<T>, node: &TypeInfo) -> Result<ObjectValue> {
+        let element = node.children.get(1)
+            .ok_or_else(|| format!("array {} without element type", node.name_str))?;
+        let is_byte = element.children.is_empty()
+            && matches!(&*element.type_str, "UInt8" | "SInt8" | "char");
+        // every element takes a byte at least
+        let count = ObjectValue::length(reader, 1)?;
+        match is_byte {
+            true => Ok(ObjectValue::Bytes(reader.read(count)?)),
+            false => {
+                let mut items = Vec::with_capacity(count.min(0x10000));
+                for _ in 0..count {
+                    items.push(ObjectValue::read(reader, element)?);
+                }
+                Ok(ObjectValue::Array(items))
+            },
+        }
+    }
+
+    /// reads a length prefix and checks that `unit` sized items fit in the stream
+    fn length<T: Read + Seek>(reader: &mut BinaryReader<T>, unit: u64) -> Result<usize> {
+        let len = reader.int32()?;
+        match len >= 0 && len as u64 * unit <= reader.remaining() {
+            true => Ok(len as usize),
+            false => Err(format!("invalid length {} at {}", len, reader.pos()).into()),
+        }
+    }
+
+    /// struct field by name
+    pub fn get(&self, name: &str) -> Option<&ObjectValue> {
+        match self {
+            ObjectValue::Struct(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// elements of an array field, none when it is missing or not an array
+    pub fn array(&self, name: &str) -> &[ObjectValue] {
+        match self.get(name) {
+            Some(ObjectValue::Array(a)) => a,
+            _ => &[],
+        }
+    }
+
+    /// boolean field, false when it is missing
+    pub fn flag(&self, name: &str) -> bool {
+        self.get(name).and_then(|f| f.as_bool()).unwrap_or(false)
+    }
+
+    /// target of an OffsetPtr, or the value itself
+    pub fn offset_ptr(&self) -> &ObjectValue {
+        self.get("data").unwrap_or(self)
+    }
+
+    /// struct field by name, failing when it is missing
+    pub fn field(&self, name: &str) -> Result<&ObjectValue> {
+        self.get(name).ok_or_else(|| format!("missing field {}", name).into())
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ObjectValue::Bool(b) => Some(*b),
+            ObjectValue::Int(i) => Some(*i != 0),
+            ObjectValue::UInt(u) => Some(*u != 0),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ObjectValue::Bool(b) => Some(*b as i64),
+            ObjectValue::Int(i) => Some(*i),
+            ObjectValue::UInt(u) => Some(*u as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().map(|i| i as u64)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ObjectValue::Float(f) => Some(*f),
+            ObjectValue::Int(i) => Some(*i as f64),
+            ObjectValue::UInt(u) => Some(*u as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ObjectValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// byte arrays and the bytes of strings
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ObjectValue::Bytes(b) => Some(b),
+            ObjectValue::String(s) => Some(s.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// integer field
+    pub fn int(&self, name: &str) -> Result<i64> {
+        self.field(name)?.as_i64().ok_or_else(|| format!("{} is not an integer", name).into())
+    }
+
+    /// numeric field as float
+    pub fn float(&self, name: &str) -> Result<f64> {
+        self.field(name)?.as_f64().ok_or_else(|| format!("{} is not a number", name).into())
+    }
+
+    /// string field
+    pub fn string(&self, name: &str) -> Result<&str> {
+        self.field(name)?.as_str().ok_or_else(|| format!("{} is not a string", name).into())
+    }
+}
+
+impl Serialize for ObjectValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            ObjectValue::Bool(b) => serializer.serialize_bool(*b),
+            ObjectValue::Int(i) => serializer.serialize_i64(*i),
+            ObjectValue::UInt(u) => serializer.serialize_u64(*u),
+            ObjectValue::Float(f) => serializer.serialize_f64(*f),
+            ObjectValue::String(s) => serializer.serialize_str(s),
+            ObjectValue::Bytes(b) => b.serialize(serializer),
+            ObjectValue::Array(a) => a.serialize(serializer),
+            ObjectValue::Struct(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::endian::Endian;
+
+    fn node(type_str: &str, name: &str, children: Vec<TypeInfo>) -> TypeInfo {
+        TypeInfo{ version: 1, level: 0, is_array: type_str == "Array", type_id: 0, type_str: type_str.to_string(),
+            name_id: 0, name_str: name.to_string(), size: -1, index: 0, flags: 0, v18meta: None, children }
+    }
+
+    fn read(node: &TypeInfo, data: &[u8]) -> Result<ObjectValue> {
+        ObjectValue::read(&mut BinaryReader::new(Cursor::new(data), Endian::Little), node)
+    }
+
+    #[test]
+    fn array_lengths() {
+        let floats = node("vector", "m_Values", vec![
+            node("Array", "Array", vec![node("int", "size", Vec::new()), node("float", "data", Vec::new())]),
+        ]);
+        let mut data = 2i32.to_le_bytes().to_vec();
+        data.extend(1.5f32.to_le_bytes());
+        data.extend((-2.0f32).to_le_bytes());
+        match read(&floats, &data).unwrap() {
+            ObjectValue::Array(a) => assert_eq!(a.iter().filter_map(|f| f.as_f64()).collect::<Vec<f64>>(), vec![1.5, -2.0]),
+            v => panic!("{:?}", v),
+        }
+        // more elements than bytes left, and a negative length
+        assert!(read(&floats, &0x4000_0000i32.to_le_bytes()).is_err());
+        assert!(read(&floats, &(-1i32).to_le_bytes()).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+lazy_static! {
+    /// CRC-32 of Ogg pages: polynomial 0x04c11db7, not reflected, no final xor
+    static ref CRC_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut r = (i as u32) << 24;
+            for _ in 0..8 {
+                r = match r & 0x8000_0000 != 0 {
+                    true => r << 1 ^ 0x04c1_1db7,
+                    false => r << 1,
+                };
+            }
+            *entry = r;
+        }
+        table
+    };
+}
+
+fn page_crc(page: &[u8]) -> u32 {
+    page.iter().fold(0u32, |crc, b| crc << 8 ^ CRC_TABLE[((crc >> 24) as u8 ^ b) as usize])
+}
+
+const CONTINUED: u8 = 1;
+const BEGIN_OF_STREAM: u8 = 2;
+const END_OF_STREAM: u8 = 4;
+
+/// writes packets of one logical stream as Ogg pages
+pub struct OggWriter {
+    serial: u32,
+    sequence: u32,
+    out: Vec<u8>,
+    segments: Vec<u8>,      // lacing values of the pending page
+    body: Vec<u8>,
+    granule: i64,           // granule of the last packet ending on the pending page, -1 if none
+    continued: bool,        // pending page starts inside a packet
+}
+
+impl OggWriter {
+    pub fn new(serial: u32) -> OggWriter {
+        OggWriter{
+            serial,
+            sequence: 0,
+            out: Vec::new(),
+            segments: Vec::new(),
+            body: Vec::new(),
+            granule: -1,
+            continued: false,
+        }
+    }
+
+    /// appends a packet ending at `granule`; `flush` puts it on a page of its own
+    pub fn packet(&mut self, data: &[u8], granule: i64, flush: bool) {
+        let mut rest = data;
+        loop {
+            let n = rest.len().min(255);
+            self.segments.push(n as u8);
+            self.body.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            // a packet ends with a lacing value below 255
+            if n < 255 {
+                break;
+            }
+            if self.segments.len() == 255 {
+                self.page(false);
+                self.continued = true;
+            }
+        }
+        self.granule = granule;
+        if flush || self.body.len() >= 4096 || self.segments.len() == 255 {
+            self.page(false);
+        }
+    }
+
+    /// the whole stream, the last page flagged end of stream
+    pub fn finish(mut self) -> Vec<u8> {
+        self.page(true);
+        self.out
+    }
+
+    fn page(&mut self, last: bool) {
+        if self.segments.is_empty() && !last {
+            return;
+        }
+        let mut flags = 0;
+        if self.continued {
+            flags |= CONTINUED;
+        }
+        if self.sequence == 0 {
+            flags |= BEGIN_OF_STREAM;
+        }
+        if last {
+            flags |= END_OF_STREAM;
+        }
+        let start = self.out.len();
+        self.out.extend_from_slice(b"OggS");
+        self.out.push(0);
+        self.out.push(flags);
+        self.out.extend_from_slice(&self.granule.to_le_bytes());
+        self.out.extend_from_slice(&self.serial.to_le_bytes());
+        self.out.extend_from_slice(&self.sequence.to_le_bytes());
+        self.out.extend_from_slice(&[0; 4]);
+        self.out.push(self.segments.len() as u8);
+        self.out.append(&mut self.segments);
+        self.out.append(&mut self.body);
+        let crc = page_crc(&self.out[start..]);
+        self.out[start + 22 .. start + 26].copy_from_slice(&crc.to_le_bytes());
+        self.sequence += 1;
+        self.granule = -1;
+        self.continued = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use super::*;
+
+    #[test]
+    fn crc_check_value() {
+        // CRC-32/POSIX checks 765e7680 with its final xor, which Ogg leaves out
+        assert_eq!(page_crc(b"123456789"), !0x765e_7680);
+    }
+
+    #[test]
+    fn pages_carry_their_crc() {
+        let mut ogg = OggWriter::new(7);
+        ogg.packet(&[1; 600], 0, true);
+        ogg.packet(&[2; 10], 960, false);
+        let out = ogg.finish();
+
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < out.len() {
+            assert_eq!(&out[pos..pos + 4], b"OggS");
+            let segments = out[pos + 26] as usize;
+            let lacing = &out[pos + 27..pos + 27 + segments];
+            let len = 27 + segments + lacing.iter().map(|l| *l as usize).sum::<usize>();
+            let mut page = out[pos..pos + len].to_vec();
+            let crc = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].copy_from_slice(&[0; 4]);
+            assert_eq!(page_crc(&page), crc);
+            pages.push((page[5], i64::from_le_bytes(page[6..14].try_into().unwrap()), lacing.to_vec()));
+            pos += len;
+        }
+        assert_eq!(pages, vec![
+            (BEGIN_OF_STREAM, 0, vec![255, 255, 90]),
+            (END_OF_STREAM, 960, vec![10]),
+        ]);
+    }
+}
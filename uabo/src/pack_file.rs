@@ -0,0 +1,177 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use log::info;
+use serde::Serialize;
+
+use crate::asset_bundle::{self, Analysis, BundleHeader, HEADER_PEEK};
+use crate::unity_version::UnityVersion;
+use crate::window::Window;
+use crate::Result;
+
+const SIGNATURE: &[u8] = b"UnityFS\0";
+const CHUNK: usize = 1 << 20;
+
+/// bundle found inside a larger file
+#[derive(Serialize, Clone, Debug)]
+pub struct LocatedBundle {
+    pub offset: u64,
+    #[serde(flatten)]
+    pub header: BundleHeader,
+}
+
+/// signature not starting a bundle
+#[derive(Serialize, Clone, Debug)]
+pub struct RejectedSignature {
+    pub offset: u64,
+    pub reason: String,
+}
+
+/// row of bundles.json
+#[derive(Serialize)]
+struct BundleSummary {
+    #[serde(flatten)]
+    located: LocatedBundle,
+    #[serde(flatten)]
+    analysis: Analysis,
+}
+
+/// bundles.json
+#[derive(Serialize)]
+struct ScanReport {
+    file_size: u64,
+    bundles: Vec<BundleSummary>,
+    rejected: Vec<RejectedSignature>,
+}
+
+fn read_full<R: Read>(file: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match file.read(&mut buf[n..])? {
+            0 => break,
+            r => n += r,
+        }
+    }
+    Ok(n)
+}
+
+/// header of the bundle at `offset`, which must end within `file_len`
+fn header_at<R: Read + Seek>(file: &mut R, offset: u64, file_len: u64) -> Result<std::result::Result<BundleHeader, String>> {
+    let mut head = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.by_ref().take(HEADER_PEEK).read_to_end(&mut head)?;
+    let header = match BundleHeader::parse(&head) {
+        Some(h) => h,
+        None => return Ok(Err("malformed header".to_string())),
+    };
+    Ok(match header.total_file_size {
+        0 => Err("no total file size".to_string()),
+        n if offset.checked_add(n).map(|end| end > file_len).unwrap_or(true) =>
+            Err(format!("total file size {} runs past the end of the file", n)),
+        _ => Ok(header),
+    })
+}
+
+/// every `UnityFS` header of the file at `path` whose bundle fits in the file, with the
+/// signatures rejected on the way
+///
+/// The file is streamed; signatures inside an accepted bundle are not considered.
+pub fn locate(path: &Path) -> Result<(Vec<LocatedBundle>, Vec<RejectedSignature>)> {
+    let mut file = File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    let file_len = file.metadata()?.len();
+    scan(&mut file, file_len, CHUNK)
+}
+
+/// `locate` over `file_len` bytes of `file`, read `chunk` bytes at a time
+fn scan<R: Read + Seek>(file: &mut R, file_len: u64, chunk: usize) -> Result<(Vec<LocatedBundle>, Vec<RejectedSignature>)> {
+    let mut bundles = Vec::new();
+    let mut rejected = Vec::new();
+    // chunks overlap by a signature less one byte so none is split between two
+    let mut buf = vec![0; chunk + SIGNATURE.len() - 1];
+    let mut start = 0;      // file offset of buf[0]
+    let mut next = 0;       // end of the last bundle found
+    loop {
+        file.seek(SeekFrom::Start(start))?;
+        let n = read_full(file, &mut buf)?;
+        let hits: Vec<u64> = buf[..n].windows(SIGNATURE.len())
+            .enumerate()
+            .filter(|(_, w)| *w == SIGNATURE)
+            .map(|(i, _)| start + i as u64)
+            .collect();
+        for offset in hits {
+            if offset < next {
+                continue;
+            }
+            match header_at(file, offset, file_len)? {
+                Ok(header) => {
+                    next = offset + header.total_file_size;
+                    bundles.push(LocatedBundle{ offset, header });
+                },
+                Err(reason) => {
+                    info!("signature at {}: {}", offset, reason);
+                    rejected.push(RejectedSignature{ offset, reason });
+                },
+            }
+        }
+        if n < buf.len() {
+            break;
+        }
+        start = (start + chunk as u64).max(next);
+    }
+    Ok((bundles, rejected))
+}
+
+/// locates the bundles of the file at `src`, parses each in place and lists them in
+/// `dst/bundles.json`, returning the number parsed
+pub fn export(src: &PathBuf, dst: &Path, unity_version: Option<&UnityVersion>) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let file_size = fs::metadata(src)?.len();
+    let (located, rejected) = locate(src)?;
+    let mut bundles = Vec::new();
+    for located in located {
+        let open = || Ok(Window::new(BufReader::new(File::open(src)?), located.offset, located.header.total_file_size)?);
+        let analysis = asset_bundle::analyse("bundle", &format!("bundle at {}", located.offset), open, unity_version);
+        info!("bundle at {}, {} bytes, {:?} objects", located.offset, located.header.total_file_size, analysis.objects);
+        bundles.push(BundleSummary{ located, analysis });
+    }
+    let count = bundles.iter().filter(|b| b.analysis.error.is_none()).count();
+    fs::write(dst.join("bundles.json"), serde_json::to_string_pretty(&ScanReport{ file_size, bundles, rejected })?)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    /// UnityFS header of a bundle of `total` bytes without blocks
+    fn header(total: u64) -> Vec<u8> {
+        let mut h = SIGNATURE.to_vec();
+        h.extend(6u32.to_be_bytes());
+        h.extend(b"5.x.x\0");
+        h.extend(b"2019.4.0f1\0");
+        h.extend(total.to_be_bytes());
+        h.extend([0; 12]);
+        h
+    }
+
+    #[test]
+    fn locate_across_chunks() {
+        // 64 byte chunks: the bundles at 60 and 200 straddle the chunk ends at 64 and 204
+        let mut file = vec![b'x'; 320];
+        let mut put = |offset: usize, bytes: &[u8]| file[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(60, &header(80));
+        put(120, SIGNATURE);            // inside the first bundle
+        put(150, SIGNATURE);
+        put(200, &header(60));
+        put(262, &header(1000));
+        let (bundles, rejected) = scan(&mut Cursor::new(&file), file.len() as u64, 64).unwrap();
+
+        assert_eq!(bundles.iter().map(|b| (b.offset, b.header.total_file_size)).collect::<Vec<_>>(), vec![(60, 80), (200, 60)]);
+        assert_eq!(bundles[0].header.upper_player_version, "2019.4.0f1");
+        assert_eq!(rejected.iter().map(|r| (r.offset, r.reason.as_str())).collect::<Vec<_>>(), vec![
+            (150, "malformed header"),
+            (262, "total file size 1000 runs past the end of the file"),
+        ]);
+    }
+}
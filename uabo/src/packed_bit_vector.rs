@@ -0,0 +1,130 @@
+use crate::object_value::ObjectValue;
+use crate::Result;
+
+/// PackedBitVector of compressed meshes and clips: `count` items of `bit_size` bits,
+/// floats quantized over [start, start + range]
+#[derive(Clone, Debug, Default)]
+pub struct PackedBitVector {
+    pub count: usize,
+    pub range: f32,
+    pub start: f32,
+    pub bit_size: u32,
+    pub data: Vec<u8>,
+}
+
+impl PackedBitVector {
+    /// reads a PackedBitVector; float vectors have m_Range and m_Start, int vectors only the bits
+    pub fn from_value(v: &ObjectValue) -> Result<PackedBitVector> {
+        Ok(PackedBitVector{
+            count: v.int("m_NumItems")? as usize,
+            range: v.get("m_Range").and_then(|r| r.as_f64()).unwrap_or(0.0) as f32,
+            start: v.get("m_Start").and_then(|s| s.as_f64()).unwrap_or(0.0) as f32,
+            bit_size: v.get("m_BitSize").and_then(|b| b.as_u64()).unwrap_or(0) as u32,
+            data: v.get("m_Data").and_then(|d| d.as_bytes()).unwrap_or_default().to_vec(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// `count` raw items starting at item `start`, read least significant bit first
+    pub fn ints_from(&self, start: usize, count: usize) -> Result<Vec<u32>> {
+        let bits = self.bit_size as usize;
+        let end = start.checked_add(count).and_then(|n| n.checked_mul(bits));
+        if bits > 32 || end.is_none_or(|end| end > self.data.len() * 8) {
+            return Err(format!("packed vector of {} bytes too small for {} items of {} bits",
+                self.data.len(), start.saturating_add(count), bits).into());
+        }
+        Ok((start .. start + count)
+            .map(|i| (0..bits).fold(0u32, |x, b| {
+                let bit = i * bits + b;
+                x | ((self.data[bit / 8] >> (bit % 8) & 1) as u32) << b
+            }))
+            .collect())
+    }
+
+    pub fn ints(&self) -> Result<Vec<u32>> {
+        self.ints_from(0, self.count)
+    }
+
+    /// `count` dequantized items starting at item `start`
+    pub fn floats_from(&self, start: usize, count: usize) -> Result<Vec<f32>> {
+        let ints = self.ints_from(start, count)?;
+        let max = ((1u64 << self.bit_size) - 1) as f32;
+        Ok(ints.into_iter()
+            .map(|x| match self.bit_size {
+                0 => self.start,
+                _ => self.start + x as f32 * self.range / max,
+            })
+            .collect())
+    }
+
+    pub fn floats(&self) -> Result<Vec<f32>> {
+        self.floats_from(0, self.count)
+    }
+
+    /// quaternions of a PackedQuatVector: 3 bits naming the largest component and its sign,
+    /// then the other three in 9 or 10 bits
+    pub fn quats(&self) -> Result<Vec<[f32; 4]>> {
+        let mut bit = 0;
+        let mut read = |bits: usize| -> Result<u32> {
+            if bit + bits > self.data.len() * 8 {
+                return Err("packed quaternions truncated".into());
+            }
+            let x = (0..bits).fold(0u32, |x, b| x | ((self.data[(bit + b) / 8] >> ((bit + b) % 8) & 1) as u32) << b);
+            bit += bits;
+            Ok(x)
+        };
+        let mut quats = Vec::with_capacity(self.count.min(0x10000));
+        for _ in 0..self.count {
+            let flags = read(3)? as usize;
+            let largest = flags & 3;
+            let mut q = [0.0f32; 4];
+            let mut sum = 0.0;
+            for (j, c) in q.iter_mut().enumerate() {
+                if j == largest {
+                    continue;
+                }
+                let bits = match (largest + 1) % 4 == j {
+                    true => 9,
+                    false => 10,
+                };
+                let max = ((1u32 << bits) - 1) as f32;
+                *c = read(bits)? as f32 / (0.5 * max) - 1.0;
+                sum += *c * *c;
+            }
+            q[largest] = (1.0 - sum).max(0.0).sqrt();
+            if flags & 4 != 0 {
+                q[largest] = -q[largest];
+            }
+            quats.push(q);
+        }
+        Ok(quats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(bit_size: u32, data: Vec<u8>) -> PackedBitVector {
+        PackedBitVector{ count: 4, range: 2.0, start: -1.0, bit_size, data }
+    }
+
+    #[test]
+    fn floats() {
+        // 4 bit items 0, 15, 5, 10 over [-1, 1]
+        let v = vector(4, vec![0xf0, 0xa5]);
+        assert_eq!(v.ints().unwrap(), vec![0, 15, 5, 10]);
+        assert_eq!(v.floats().unwrap(), vec![-1.0, 1.0, -1.0 + 10.0 / 15.0, -1.0 + 20.0 / 15.0]);
+    }
+
+    #[test]
+    fn corrupt_sizes() {
+        assert!(vector(64, vec![0; 64]).floats().is_err());
+        assert!(vector(200, vec![0; 64]).floats().is_err());
+        assert!(vector(4, vec![0xf0]).floats().is_err());
+        assert!(vector(8, vec![0; 4]).ints_from(usize::MAX, 2).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use std::slice;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::constants;
+use crate::endian::Endian;
+use crate::object_info::ObjectInfo;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, PPtr};
+use crate::Result;
+
+const GAME_OBJECT_CLASS_ID: i32 = 1;
+const TRANSFORM_CLASS_ID: i32 = 4;
+const PLAYER_SETTINGS_CLASS_ID: i32 = 129;
+const BUILD_SETTINGS_CLASS_ID: i32 = 141;
+const RESOURCE_MANAGER_CLASS_ID: i32 = 147;
+const RECT_TRANSFORM_CLASS_ID: i32 = 224;
+
+/// bounds checked reader of object data, for players whose files carry no type trees
+///
+/// Only layouts unchanged since 5.5 are read this way.
+struct Raw<'d> {
+    data: &'d [u8],
+    pos: usize,
+    big: bool,
+}
+
+impl<'d> Raw<'d> {
+    fn new(asset: &'d Asset, obj: &ObjectInfo) -> Raw<'d> {
+        Raw{ data: asset.object_data(obj), pos: 0, big: matches!(asset.endian(), Endian::Big) }
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'d [u8]> {
+        let b = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(b)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b: [u8; 4] = self.bytes(4)?.try_into().ok()?;
+        Some(match self.big {
+            true => u32::from_be_bytes(b),
+            false => u32::from_le_bytes(b),
+        })
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        let b: [u8; 8] = self.bytes(8)?.try_into().ok()?;
+        Some(match self.big {
+            true => i64::from_be_bytes(b),
+            false => i64::from_le_bytes(b),
+        })
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let s = std::str::from_utf8(self.bytes(len)?).ok()?.to_string();
+        self.pos = self.pos.div_ceil(4) * 4;
+        Some(s)
+    }
+
+    fn pptr(&mut self) -> Option<PPtr> {
+        Some(PPtr{ file_id: self.u32()? as i32, path_id: self.i64()? })
+    }
+
+    fn strings(&mut self) -> Option<Vec<String>> {
+        (0..self.u32()?).map(|_| self.string()).collect()
+    }
+}
+
+/// serialized file of the player
+#[derive(Serialize, Clone, Debug)]
+pub struct FileSummary {
+    pub name: String,
+    pub unity_version: String,
+    pub objects: usize,
+    pub type_trees: bool,
+    pub classes: BTreeMap<String, usize>,   // object count by class name
+}
+
+/// root GameObject of a scene
+#[derive(Serialize, Clone, Debug)]
+pub struct RootObject {
+    pub name: String,
+    pub path_id: i64,
+    pub children: usize,
+}
+
+/// scene of the build, stored in level<index>
+#[derive(Serialize, Clone, Debug)]
+pub struct PlayerScene {
+    pub index: usize,
+    pub path: Option<String>,       // from BuildSettings
+    pub file: Option<String>,
+    pub objects: usize,
+    pub roots: Vec<RootObject>,
+}
+
+/// object loadable through Resources.Load
+#[derive(Serialize, Clone, Debug)]
+pub struct ResourceEntry {
+    pub path: String,
+    pub name: Option<String>,
+    pub class: Option<String>,
+    pub file: Option<String>,
+    pub path_id: i64,
+    pub found: bool,
+}
+
+/// top level scalar fields of a settings object, read along its type tree
+#[derive(Serialize, Clone, Debug)]
+pub struct Settings {
+    pub path_id: i64,
+    pub fields: BTreeMap<String, ObjectValue>,
+}
+
+/// player.json
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PlayerInventory {
+    pub unity_version: String,
+    pub files: Vec<FileSummary>,
+    pub resource_files: BTreeMap<String, u64>,  // .resS and .resource sizes
+    pub scenes: Vec<PlayerScene>,
+    pub resources: Vec<ResourceEntry>,
+    pub build_settings: Option<Settings>,
+    pub player_settings: Option<Settings>,
+    pub issues: Vec<String>,
+}
+
+fn class_name(asset: &Asset, obj: &ObjectInfo) -> Option<String> {
+    asset.class_id(obj)
+        .map(|c| constants::class_name(c, asset.unity_version()).map(String::from).unwrap_or_else(|| format!("Class{}", c)))
+}
+
+fn scalars(path_id: i64, v: &ObjectValue) -> Settings {
+    let fields = match v {
+        ObjectValue::Struct(fields) => fields.iter()
+            .filter(|(_, f)| !matches!(f, ObjectValue::Array(_) | ObjectValue::Struct(_) | ObjectValue::Bytes(_)))
+            .cloned()
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    Settings{ path_id, fields }
+}
+
+/// name of a GameObject, or of a named object whose m_Name comes first
+fn object_name(asset: &Asset, obj: &ObjectInfo) -> Option<String> {
+    if let Ok(v) = asset.object_value(obj) {
+        return v.get("m_Name").and_then(|n| n.as_str()).map(String::from);
+    }
+    let mut raw = Raw::new(asset, obj);
+    if asset.class_id(obj) == Some(GAME_OBJECT_CLASS_ID) {
+        // ComponentPair since 5.5, pair of class id and pointer before
+        let pair = match asset.unity_version().release() >= (5, 5) {
+            true => 12,
+            false => 16,
+        };
+        let count = raw.u32()? as usize;
+        raw.bytes(count.checked_mul(pair)?)?;
+        raw.u32()?;
+    }
+    raw.string()
+}
+
+/// parent and children count of a Transform or RectTransform
+fn transform_links(asset: &Asset, obj: &ObjectInfo) -> Option<(PPtr, PPtr, usize)> {
+    if let Ok(v) = asset.object_value(obj) {
+        let children = match v.get("m_Children") {
+            Some(ObjectValue::Array(c)) => c.len(),
+            _ => 0,
+        };
+        return Some((v.get("m_GameObject").and_then(PPtr::from_value)?, v.get("m_Father").and_then(PPtr::from_value)?, children));
+    }
+    let mut raw = Raw::new(asset, obj);
+    let game_object = raw.pptr()?;
+    raw.bytes(40)?;     // local rotation, position and scale
+    let children = raw.u32()? as usize;
+    raw.bytes(children.checked_mul(12)?)?;
+    Some((game_object, raw.pptr()?, children))
+}
+
+/// root GameObjects of the scene file `asset`
+fn roots(asset: &Asset) -> Vec<RootObject> {
+    let mut roots = Vec::new();
+    for obj in asset.objects() {
+        if !matches!(asset.class_id(obj), Some(TRANSFORM_CLASS_ID) | Some(RECT_TRANSFORM_CLASS_ID)) {
+            continue;
+        }
+        let (game_object, father, children) = match transform_links(asset, obj) {
+            Some(l) => l,
+            None => {
+                warn!("{} {}: unreadable transform", asset.name(), obj.path_id);
+                continue;
+            },
+        };
+        if !father.is_null() {
+            continue;
+        }
+        let name = asset.object(game_object.path_id).and_then(|go| object_name(asset, go));
+        roots.push(RootObject{ name: name.unwrap_or_default(), path_id: game_object.path_id, children });
+    }
+    roots
+}
+
+/// m_Container of the ResourceManager: Resources folder path and object
+fn resource_container(asset: &Asset, obj: &ObjectInfo) -> Option<Vec<(String, PPtr)>> {
+    if let Ok(v) = asset.object_value(obj) {
+        return match v.get("m_Container") {
+            Some(ObjectValue::Array(entries)) => Some(entries.iter()
+                .filter_map(|e| Some((e.get("first")?.as_str()?.to_string(), PPtr::from_value(e.get("second")?)?)))
+                .collect()),
+            _ => None,
+        };
+    }
+    let mut raw = Raw::new(asset, obj);
+    (0..raw.u32()?).map(|_| Some((raw.string()?, raw.pptr()?))).collect()
+}
+
+/// inventory of a player's Data folder loaded by `AssetBundle::load_player_data`
+pub fn inventory(data: &AssetBundle) -> PlayerInventory {
+    let mut inventory = PlayerInventory{
+        unity_version: data.assets().first().map(|a| a.unity_version().to_string()).unwrap_or_default(),
+        resource_files: data.resources().iter().map(|r| (r.name.clone(), r.size)).collect(),
+        issues: data.issues().to_vec(),
+        ..Default::default()
+    };
+    let mut scene_paths = Vec::new();
+    for asset in data.assets() {
+        let mut classes = BTreeMap::new();
+        for obj in asset.objects() {
+            *classes.entry(class_name(asset, obj).unwrap_or_default()).or_insert(0) += 1;
+            match asset.class_id(obj) {
+                Some(BUILD_SETTINGS_CLASS_ID) => {
+                    let v = asset.object_value(obj);
+                    // the scene list comes first since 5.0
+                    scene_paths = match &v {
+                        Ok(v) => v.get("scenes").or_else(|| v.get("levels"))
+                            .and_then(|s| match s {
+                                ObjectValue::Array(s) => Some(s.iter().filter_map(|s| s.as_str()).map(String::from).collect()),
+                                _ => None,
+                            }),
+                        Err(_) => Raw::new(asset, obj).strings(),
+                    }.unwrap_or_default();
+                    match v {
+                        Ok(v) => inventory.build_settings = Some(scalars(obj.path_id, &v)),
+                        Err(e) => inventory.issues.push(format!("BuildSettings fields need a type tree: {}", e)),
+                    }
+                },
+                Some(PLAYER_SETTINGS_CLASS_ID) => match asset.object_value(obj) {
+                    Ok(v) => inventory.player_settings = Some(scalars(obj.path_id, &v)),
+                    Err(e) => inventory.issues.push(format!("PlayerSettings fields need a type tree: {}", e)),
+                },
+                Some(RESOURCE_MANAGER_CLASS_ID) => match resource_container(asset, obj) {
+                    Some(entries) => {
+                        for (path, ptr) in entries {
+                            let target = pptr::resolve(slice::from_ref(data), asset, &ptr);
+                            inventory.resources.push(ResourceEntry{
+                                path,
+                                name: target.and_then(|t| object_name(t.asset, t.object)),
+                                class: target.and_then(|t| class_name(t.asset, t.object)),
+                                file: target.map(|t| t.asset.name().to_string()),
+                                path_id: ptr.path_id,
+                                found: target.is_some(),
+                            });
+                        }
+                    },
+                    None => inventory.issues.push("ResourceManager container could not be read".to_string()),
+                },
+                _ => {},
+            }
+        }
+        inventory.files.push(FileSummary{
+            name: asset.name().to_string(),
+            unity_version: asset.unity_version().to_string(),
+            objects: asset.objects().len(),
+            type_trees: asset.classes().iter().any(|c| c.types.is_some()),
+            classes,
+        });
+    }
+
+    let level_count = data.assets().iter()
+        .filter_map(|a| a.name().strip_prefix("level")?.parse::<usize>().ok())
+        .map(|i| i + 1)
+        .max()
+        .unwrap_or(0);
+    for index in 0..scene_paths.len().max(level_count) {
+        let file = format!("level{}", index);
+        let asset = data.assets().iter().find(|a| a.name() == file);
+        if asset.is_none() {
+            inventory.issues.push(format!("scene {} has no {}", index, file));
+        }
+        inventory.scenes.push(PlayerScene{
+            index,
+            path: scene_paths.get(index).cloned(),
+            file: asset.map(|a| a.name().to_string()),
+            objects: asset.map(|a| a.objects().len()).unwrap_or(0),
+            roots: asset.map(roots).unwrap_or_default(),
+        });
+    }
+    for r in inventory.resources.iter().filter(|r| !r.found) {
+        inventory.issues.push(format!("resource {} not found", r.path));
+    }
+    inventory
+}
+
+/// writes the inventory of the player data `data` into `dst/player.json`
+pub fn export(data: &AssetBundle, dst: &Path) -> Result<PlayerInventory> {
+    fs::create_dir_all(dst)?;
+    let inventory = inventory(data);
+    info!("{} files, {} scenes, {} resources", inventory.files.len(), inventory.scenes.len(), inventory.resources.len());
+    fs::write(dst.join("player.json"), serde_json::to_string_pretty(&inventory)?)?;
+    Ok(inventory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    /// root 2 with child 4, and root 6, each transform following its GameObject
+    fn scene_objects() -> Vec<(i64, u32, Vec<u8>)> {
+        vec![
+            (1, 0, fixture::game_object("world", &[2])),
+            (2, 1, fixture::transform(1, [0.0, 0.0, 0.0], &[4], 0)),
+            (3, 0, fixture::game_object("tree", &[4])),
+            (4, 1, fixture::transform(3, [1.0, 0.0, 0.0], &[], 2)),
+            (5, 0, fixture::game_object("camera", &[6])),
+            (6, 1, fixture::transform(5, [0.0, 1.0, 0.0], &[], 0)),
+        ]
+    }
+
+    fn summary(roots: &[RootObject]) -> Vec<(&str, i64, usize)> {
+        roots.iter().map(|r| (r.name.as_str(), r.path_id, r.children)).collect()
+    }
+
+    #[test]
+    fn raw_scene_roots() {
+        let file = fixture::stripped_file(&[GAME_OBJECT_CLASS_ID, TRANSFORM_CLASS_ID], &scene_objects());
+        let bundle = fixture::bundle(vec![("level0", file)]);
+        let asset = &bundle.assets()[0];
+        assert!(asset.object_value(asset.object(1).unwrap()).is_err());
+        assert_eq!(summary(&roots(asset)), vec![("world", 1, 1), ("camera", 5, 0)]);
+
+        let (game_object, father, children) = transform_links(asset, asset.object(4).unwrap()).unwrap();
+        assert_eq!((game_object.path_id, father.path_id, children), (3, 2, 0));
+    }
+
+    #[test]
+    fn typed_scene_roots() {
+        let classes = [(GAME_OBJECT_CLASS_ID, fixture::game_object_type()), (TRANSFORM_CLASS_ID, fixture::transform_type())];
+        let bundle = fixture::bundle(vec![("level0", fixture::serialized_file(&classes, &scene_objects()))]);
+        assert_eq!(summary(&roots(&bundle.assets()[0])), vec![("world", 1, 1), ("camera", 5, 0)]);
+    }
+
+    #[test]
+    fn truncated_raw_transform() {
+        let mut transform = fixture::transform(1, [0.0, 0.0, 0.0], &[], 0);
+        transform.truncate(50);
+        let file = fixture::stripped_file(&[GAME_OBJECT_CLASS_ID, TRANSFORM_CLASS_ID], &[
+            (1, 0, fixture::game_object("world", &[2])),
+            (2, 1, transform),
+        ]);
+        let bundle = fixture::bundle(vec![("level0", file)]);
+        let asset = &bundle.assets()[0];
+        assert!(transform_links(asset, asset.object(2).unwrap()).is_none());
+        assert!(roots(asset).is_empty());
+        assert_eq!(object_name(asset, asset.object(1).unwrap()).as_deref(), Some("world"));
+    }
+}
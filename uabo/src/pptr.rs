@@ -0,0 +1,52 @@
+use serde::{Serialize, Deserialize};
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::object_info::ObjectInfo;
+use crate::object_value::ObjectValue;
+
+/// reference to an object of this file (file id 0) or of an external
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PPtr {
+    pub file_id: i32,   // m_FileID, index into the externals plus one
+    pub path_id: i64,   // m_PathID
+}
+
+impl PPtr {
+    pub fn from_value(v: &ObjectValue) -> Option<PPtr> {
+        Some(PPtr{
+            file_id: v.get("m_FileID")?.as_i64()? as i32,
+            path_id: v.get("m_PathID")?.as_i64()?,
+        })
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.path_id == 0
+    }
+}
+
+/// object found by resolving a PPtr, with the bundle holding its resources
+#[derive(Clone, Copy)]
+pub struct ObjectRef<'a> {
+    pub bundle: &'a AssetBundle,
+    pub asset: &'a Asset,
+    pub object: &'a ObjectInfo,
+}
+
+/// looks up the target of `pptr`, read in `from`, among every serialized file of `bundles`
+///
+/// Externals are matched by file name, so a reference into another bundle
+/// resolves once that bundle is loaded too.
+pub fn resolve<'a>(bundles: &'a [AssetBundle], from: &Asset, pptr: &PPtr) -> Option<ObjectRef<'a>> {
+    if pptr.is_null() {
+        return None;
+    }
+    let file_name = match pptr.file_id {
+        0 => from.name(),
+        id => from.reference(id)?.file_name(),
+    };
+    bundles.iter()
+        .flat_map(|bundle| bundle.assets().iter().map(move |asset| (bundle, asset)))
+        .filter(|(_, asset)| asset.name().eq_ignore_ascii_case(file_name))
+        .find_map(|(bundle, asset)| asset.object(pptr.path_id).map(|object| ObjectRef{ bundle, asset, object }))
+}
@@ -11,10 +11,15 @@ pub struct Reference {
 impl Reference {
     pub fn new(path: String, guid: Option<Vec<u8>>, type_: Option<i32>, file_path: String) -> Reference {
         Reference{
-            path: path,
-            guid: guid,
-            type_: type_,
-            file_path: file_path,
+            path,
+            guid,
+            type_,
+            file_path,
         }
     }
-}
\ No newline at end of file
+
+    /// file name of the external, "CAB-xxxx" for "archive:/CAB-xxxx/CAB-xxxx"
+    pub fn file_name(&self) -> &str {
+        self.file_path.rsplit('/').next().unwrap_or(&self.file_path)
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use serde::{Serialize, Deserialize};
+
+use crate::Result;
+
+/// non serialized node of an asset bundle (.resS, .resource)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResourceFile {
+    pub name: String,       // node name
+    pub size: u64,          // data size
+    #[serde(skip)]
+    data: OnceLock<Arc<Vec<u8>>>, // buffer holding the payload, read on first use for files on disk
+    #[serde(skip)]
+    start: usize,           // payload start in `data`
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ResourceFile {
+    pub fn new(name: String, data: Vec<u8>) -> ResourceFile {
+        let len = data.len();
+        ResourceFile::shared(name, &Arc::new(data), 0..len)
+    }
+
+    /// node at `node` of a bundle's decompressed `buffer`, without copying it
+    pub fn shared(name: String, buffer: &Arc<Vec<u8>>, node: Range<usize>) -> ResourceFile {
+        ResourceFile{
+            name,
+            size: node.len() as u64,
+            data: OnceLock::from(Arc::clone(buffer)),
+            start: node.start,
+            path: None,
+        }
+    }
+
+    /// resource file of `size` bytes at `path`, such as the .resS of a player's Data folder
+    pub fn on_disk(name: String, path: PathBuf, size: u64) -> ResourceFile {
+        ResourceFile{ name, size, data: OnceLock::new(), start: 0, path: Some(path) }
+    }
+
+    /// node payload, read in full the first time a resource on disk is needed
+    pub fn data(&self) -> Result<&[u8]> {
+        let buffer = match self.data.get() {
+            Some(buffer) => buffer,
+            None => {
+                let path = self.path.as_ref().ok_or_else(|| format!("resource {} has no data", self.name))?;
+                let data = fs::read(path).map_err(|e| format!("{:?}: {}", path, e))?;
+                self.data.get_or_init(|| Arc::new(data))
+            },
+        };
+        buffer.get(self.start..self.start + self.size as usize)
+            .ok_or_else(|| format!("resource {} is shorter than {} bytes", self.name, self.size).into())
+    }
+}
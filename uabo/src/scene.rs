@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::asset_bundle::AssetBundle;
+use crate::constants;
+use crate::export;
+use crate::gltf::Gltf;
+use crate::mesh::{self, Mesh};
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, ObjectRef, PPtr};
+use crate::texture_2d::Texture2D;
+use crate::Result;
+
+const TRANSFORM_CLASS_ID: i32 = 4;
+const MESH_RENDERER_CLASS_ID: i32 = 23;
+const MESH_FILTER_CLASS_ID: i32 = 33;
+const SKINNED_MESH_RENDERER_CLASS_ID: i32 = 137;
+const RECT_TRANSFORM_CLASS_ID: i32 = 224;
+
+/// material properties holding the base texture and color, built-in and SRP shaders
+const BASE_TEXTURES: [&str; 3] = ["_MainTex", "_BaseMap", "_BaseColorMap"];
+const BASE_COLORS: [&str; 2] = ["_Color", "_BaseColor"];
+
+/// GameObject with its Transform, components and children
+#[derive(Serialize, Clone)]
+pub struct SceneNode<'a> {
+    pub name: String,
+    pub path_id: i64,               // of the GameObject
+    pub active: bool,
+    pub layer: u32,
+    pub tag: Option<String>,
+    pub position: [f32; 3],         // local, left handed
+    pub rotation: [f32; 4],         // x, y, z, w
+    pub scale: [f32; 3],
+    pub components: Vec<String>,    // class names, Transform first
+    pub children: Vec<SceneNode<'a>>,
+    #[serde(skip)]
+    transform: ObjectRef<'a>,
+    #[serde(skip)]
+    component_refs: Vec<ObjectRef<'a>>,
+}
+
+impl<'a> SceneNode<'a> {
+    /// node of `transform`, a Transform or RectTransform, and everything below it
+    pub fn load(bundles: &'a [AssetBundle], transform: ObjectRef<'a>) -> Result<SceneNode<'a>> {
+        SceneNode::load_unvisited(bundles, transform, &mut HashSet::new())
+    }
+
+    /// loads `transform` unless it is already in `visited`, so that cyclic children are skipped
+    fn load_unvisited(bundles: &'a [AssetBundle], transform: ObjectRef<'a>, visited: &mut HashSet<Key>) -> Result<SceneNode<'a>> {
+        visited.insert(key(&transform));
+        let t = transform.asset.object_value(transform.object)?;
+        let go_ptr = PPtr::from_value(t.field("m_GameObject")?).ok_or("invalid m_GameObject")?;
+        let go_ref = pptr::resolve(bundles, transform.asset, &go_ptr)
+            .ok_or_else(|| format!("GameObject {} of transform {} not found", go_ptr.path_id, transform.object.path_id))?;
+        let go = go_ref.asset.object_value(go_ref.object)?;
+
+        let version = go_ref.asset.unity_version();
+        let mut components = Vec::new();
+        let mut component_refs = Vec::new();
+        if let Some(ObjectValue::Array(entries)) = go.get("m_Component") {
+            for entry in entries {
+                // ComponentPair since 5.5, pair of class id and pointer before
+                let ptr = match entry.get("component").or_else(|| entry.get("second")).and_then(PPtr::from_value) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                match pptr::resolve(bundles, go_ref.asset, &ptr) {
+                    Some(c) => {
+                        let class_id = c.asset.class_id(c.object).unwrap_or(0);
+                        components.push(constants::class_name(class_id, version)
+                            .map(String::from)
+                            .unwrap_or_else(|| format!("Class{}", class_id)));
+                        component_refs.push(c);
+                    },
+                    None => components.push(format!("Missing({}, {})", ptr.file_id, ptr.path_id)),
+                }
+            }
+        }
+
+        let mut children = Vec::new();
+        if let Some(ObjectValue::Array(ptrs)) = t.get("m_Children") {
+            for ptr in ptrs.iter().filter_map(PPtr::from_value) {
+                match pptr::resolve(bundles, transform.asset, &ptr) {
+                    Some(child) if visited.contains(&key(&child)) =>
+                        warn!("child transform {} of {} is already in the hierarchy", ptr.path_id, transform.object.path_id),
+                    Some(child) => children.push(SceneNode::load_unvisited(bundles, child, visited)?),
+                    None => warn!("child transform {} of {} not found", ptr.path_id, transform.object.path_id),
+                }
+            }
+        }
+
+        let q = t.field("m_LocalRotation")?;
+        Ok(SceneNode{
+            name: go.string("m_Name")?.to_string(),
+            path_id: go_ref.object.path_id,
+            active: go.get("m_IsActive").and_then(|a| a.as_bool()).unwrap_or(true),
+            layer: go.get("m_Layer").and_then(|l| l.as_u64()).unwrap_or(0) as u32,
+            tag: go.get("m_TagString").and_then(|t| t.as_str()).map(String::from),
+            position: mesh::vector3(t.field("m_LocalPosition")?)?,
+            rotation: [q.float("x")? as f32, q.float("y")? as f32, q.float("z")? as f32, q.float("w")? as f32],
+            scale: mesh::vector3(t.field("m_LocalScale")?)?,
+            components,
+            children,
+            transform,
+            component_refs,
+        })
+    }
+
+    fn component(&self, class_id: i32) -> Option<&ObjectRef<'a>> {
+        self.component_refs.iter().find(|c| c.asset.class_id(c.object) == Some(class_id))
+    }
+}
+
+/// every root transform of `bundle`, loaded with their hierarchies
+pub fn roots<'a>(bundles: &'a [AssetBundle], bundle: &'a AssetBundle) -> Vec<SceneNode<'a>> {
+    let mut roots = Vec::new();
+    for asset in bundle.assets() {
+        for object in asset.objects() {
+            if !matches!(asset.class_id(object), Some(TRANSFORM_CLASS_ID) | Some(RECT_TRANSFORM_CLASS_ID)) {
+                continue;
+            }
+            let father = asset.object_value(object).ok()
+                .and_then(|t| t.get("m_Father").and_then(PPtr::from_value));
+            if father.map(|f| !f.is_null()).unwrap_or(false) {
+                continue;
+            }
+            match SceneNode::load(bundles, ObjectRef{ bundle, asset, object }) {
+                Ok(node) => roots.push(node),
+                Err(e) => warn!("{} {}: {}", asset.name(), object.path_id, e),
+            }
+        }
+    }
+    roots
+}
+
+type Key = (String, i64);
+
+fn key(r: &ObjectRef) -> Key {
+    (r.asset.name().to_string(), r.object.path_id)
+}
+
+/// builds the glTF scene of one root
+struct SceneBuilder<'a> {
+    bundles: &'a [AssetBundle],
+    gltf: Gltf,
+    nodes: HashMap<Key, usize>,             // transform to node
+    meshes: HashMap<(Key, bool), usize>,    // mesh and skinned flag to glTF mesh
+    materials: HashMap<Key, Option<usize>>,
+    textures: HashMap<Key, Option<usize>>,
+}
+
+impl<'a> SceneBuilder<'a> {
+    /// adds `node` and its children, returning the node index
+    fn add_node(&mut self, node: &SceneNode<'a>) -> usize {
+        let q = node.rotation;
+        let index = self.gltf.add_node(json!({
+            "name": node.name,
+            "translation": [-node.position[0], node.position[1], node.position[2]],
+            "rotation": [q[0], -q[1], -q[2], q[3]],
+            "scale": node.scale,
+        }));
+        self.nodes.insert(key(&node.transform), index);
+        let children: Vec<usize> = node.children.iter().map(|c| self.add_node(c)).collect();
+        if !children.is_empty() {
+            self.gltf.node_mut(index)["children"] = json!(children);
+        }
+        index
+    }
+
+    /// attaches meshes of MeshFilter + MeshRenderer and SkinnedMeshRenderer components
+    fn add_renderers(&mut self, node: &SceneNode<'a>) {
+        let renderer = node.component(SKINNED_MESH_RENDERER_CLASS_ID)
+            .or_else(|| node.component(MESH_RENDERER_CLASS_ID))
+            .copied();
+        if let Some(renderer) = renderer {
+            if let Err(e) = self.add_renderer(node, renderer) {
+                warn!("{}: {}", node.name, e);
+            }
+        }
+        for child in &node.children {
+            self.add_renderers(child);
+        }
+    }
+
+    fn add_renderer(&mut self, node: &SceneNode<'a>, renderer: ObjectRef<'a>) -> Result<()> {
+        let r = renderer.asset.object_value(renderer.object)?;
+        let skinned = renderer.asset.class_id(renderer.object) == Some(SKINNED_MESH_RENDERER_CLASS_ID);
+        let mesh_ptr = match skinned {
+            true => r.get("m_Mesh").and_then(PPtr::from_value),
+            false => match node.component(MESH_FILTER_CLASS_ID) {
+                Some(filter) => filter.asset.object_value(filter.object)?.get("m_Mesh").and_then(PPtr::from_value),
+                None => None,
+            },
+        };
+        let mesh_ref = match mesh_ptr.and_then(|p| pptr::resolve(self.bundles, renderer.asset, &p)) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let mesh = Mesh::from_value(mesh_ref.bundle, &mesh_ref.asset.object_value(mesh_ref.object)?, mesh_ref.asset.unity_version())?;
+
+        let bundles = self.bundles;
+        let materials: Vec<Option<usize>> = match r.get("m_Materials") {
+            Some(ObjectValue::Array(ptrs)) => ptrs.iter()
+                .map(|p| PPtr::from_value(p).and_then(|p| pptr::resolve(bundles, renderer.asset, &p)))
+                .map(|m| m.and_then(|m| self.material(m)))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        // bones outside of the exported hierarchy leave the mesh unskinned
+        let joints = match skinned {
+            true => match r.get("m_Bones") {
+                Some(ObjectValue::Array(ptrs)) => ptrs.iter()
+                    .map(|p| PPtr::from_value(p)
+                        .and_then(|p| pptr::resolve(self.bundles, renderer.asset, &p))
+                        .and_then(|b| self.nodes.get(&key(&b)).copied()))
+                    .collect::<Option<Vec<usize>>>()
+                    .filter(|j| !j.is_empty() && j.len() == mesh.bind_poses.len() && mesh.joints.len() == mesh.vertex_count),
+                _ => None,
+            },
+            false => None,
+        };
+        if skinned && joints.is_none() {
+            warn!("{}: {} has no bone weights or bones outside of the hierarchy, exported without skin", node.name, mesh.name);
+        }
+
+        let mesh_key = (key(&mesh_ref), joints.is_some());
+        let mesh_index = match self.meshes.get(&mesh_key) {
+            Some(i) => *i,
+            None => {
+                let i = self.gltf.add_mesh(&mesh, &materials, joints.is_some());
+                self.meshes.insert(mesh_key, i);
+                i
+            },
+        };
+        let index = self.nodes[&key(&node.transform)];
+        self.gltf.node_mut(index)["mesh"] = json!(mesh_index);
+        if let Some(joints) = joints {
+            let skin = self.gltf.add_skin(&joints, &mesh.bind_poses);
+            self.gltf.node_mut(index)["skin"] = json!(skin);
+        }
+        Ok(())
+    }
+
+    /// glTF material with the base color and texture of a Material
+    fn material(&mut self, material: ObjectRef<'a>) -> Option<usize> {
+        let k = key(&material);
+        if let Some(m) = self.materials.get(&k) {
+            return *m;
+        }
+        let index = match material.asset.object_value(material.object) {
+            Ok(v) => Some(self.add_material(material, &v)),
+            Err(e) => {
+                warn!("material {}: {}", material.object.path_id, e);
+                None
+            },
+        };
+        self.materials.insert(k, index);
+        index
+    }
+
+    fn add_material(&mut self, material: ObjectRef<'a>, v: &ObjectValue) -> usize {
+        let name = v.get("m_Name").and_then(|n| n.as_str()).unwrap_or_default().to_string();
+        let properties = v.get("m_SavedProperties");
+        let mut pbr = json!({ "metallicFactor": 0.0, "roughnessFactor": 1.0 });
+        if let Some(color) = properties.and_then(|p| saved_property(p, "m_Colors", &BASE_COLORS)) {
+            let c = |n: &str| color.get(n).and_then(|c| c.as_f64()).unwrap_or(1.0) as f32;
+            // Unity keeps material colors in gamma space
+            pbr["baseColorFactor"] = json!([srgb_to_linear(c("r")), srgb_to_linear(c("g")), srgb_to_linear(c("b")), c("a")]);
+        }
+        let texture = properties
+            .and_then(|p| saved_property(p, "m_TexEnvs", &BASE_TEXTURES))
+            .and_then(|env| env.get("m_Texture").and_then(PPtr::from_value))
+            .and_then(|p| pptr::resolve(self.bundles, material.asset, &p))
+            .and_then(|t| self.texture(t));
+        if let Some(texture) = texture {
+            pbr["baseColorTexture"] = json!({ "index": texture });
+        }
+        self.gltf.add_material(json!({ "name": name, "pbrMetallicRoughness": pbr }))
+    }
+
+    /// Texture2D embedded as PNG
+    fn texture(&mut self, texture: ObjectRef<'a>) -> Option<usize> {
+        let k = key(&texture);
+        if let Some(t) = self.textures.get(&k) {
+            return *t;
+        }
+        let png = texture.asset.object_value(texture.object)
+            .and_then(|v| Texture2D::from_value(&v))
+            .and_then(|t| {
+                let png = t.decode_level(texture.bundle, texture.asset.unity_version(), 0)?.to_png()?;
+                Ok((t.name, png))
+            });
+        let index = match png {
+            Ok((name, png)) => Some(self.gltf.add_texture(&name, &png)),
+            Err(e) => {
+                warn!("texture {}: {}", texture.object.path_id, e);
+                None
+            },
+        };
+        self.textures.insert(k, index);
+        index
+    }
+}
+
+/// value of the first of `names` in a list of saved material properties
+fn saved_property<'v>(properties: &'v ObjectValue, list: &str, names: &[&str]) -> Option<&'v ObjectValue> {
+    let entries = match properties.get(list)? {
+        ObjectValue::Array(entries) => entries,
+        _ => return None,
+    };
+    names.iter().find_map(|name| entries.iter()
+        .find(|e| e.get("first").and_then(|f| f.as_str()) == Some(name))
+        .and_then(|e| e.get("second")))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// `root` with its meshes, materials and skins as binary glTF
+pub fn to_glb(bundles: &[AssetBundle], root: &SceneNode) -> Vec<u8> {
+    let mut builder = SceneBuilder{
+        bundles,
+        gltf: Gltf::new(),
+        nodes: HashMap::new(),
+        meshes: HashMap::new(),
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+    };
+    let index = builder.add_node(root);
+    builder.gltf.add_root(index);
+    builder.add_renderers(root);
+    builder.gltf.to_glb()
+}
+
+/// writes the hierarchy of every root GameObject of `bundles[0]` into `dst/hierarchy.json`
+/// and each root matching `select` (name or GameObject path id, every root when None) as glTF
+pub fn export(bundles: &[AssetBundle], dst: &Path, select: Option<&str>) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let roots = roots(bundles, bundle);
+    fs::write(dst.join("hierarchy.json"), serde_json::to_string_pretty(&roots)?)?;
+    let mut count = 0;
+    for root in &roots {
+        let selected = match select {
+            None => true,
+            Some(s) => root.name == s || root.path_id.to_string() == s,
+        };
+        if selected {
+            let path = export::unique_path(dst, &root.name, "glb");
+            fs::write(&path, to_glb(bundles, root))?;
+            info!("{} {:?}", root.name, path);
+            count += 1;
+        }
+    }
+    if let (Some(s), 0) = (select, count) {
+        return Err(format!("no root GameObject named {}", s).into());
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, GAME_OBJECT_CLASS_ID};
+
+    /// root 2 with children 4 and 6, each transform following its GameObject; 6 lists `extra` too
+    fn hierarchy(extra: &[i64]) -> AssetBundle {
+        let objects = [
+            (1, 0, fixture::game_object("root", &[2])),
+            (2, 1, fixture::transform(1, [1.0, 0.0, 0.0], &[4, 6], 0)),
+            (3, 0, fixture::game_object("left", &[4])),
+            (4, 1, fixture::transform(3, [0.0, 1.0, 0.0], &[], 2)),
+            (5, 0, fixture::game_object("right", &[6])),
+            (6, 1, fixture::transform(5, [0.0, 0.0, 1.0], extra, 2)),
+        ];
+        let classes = [(GAME_OBJECT_CLASS_ID, fixture::game_object_type()), (TRANSFORM_CLASS_ID, fixture::transform_type())];
+        fixture::bundle(vec![("level0", fixture::serialized_file(&classes, &objects))])
+    }
+
+    fn names(node: &SceneNode) -> Vec<String> {
+        std::iter::once(node.name.clone()).chain(node.children.iter().flat_map(names)).collect()
+    }
+
+    #[test]
+    fn load_hierarchy() {
+        let bundles = [hierarchy(&[])];
+        let roots = roots(&bundles, &bundles[0]);
+        assert_eq!(roots.len(), 1);
+        assert_eq!(names(&roots[0]), vec!["root", "left", "right"]);
+        assert_eq!(roots[0].children[1].position, [0.0, 0.0, 1.0]);
+        assert_eq!(roots[0].children[0].components, vec!["Transform"]);
+    }
+
+    #[test]
+    fn skip_cyclic_children() {
+        // right lists the root and itself as children
+        let bundles = [hierarchy(&[2, 6])];
+        let roots = roots(&bundles, &bundles[0]);
+        assert_eq!(names(&roots[0]), vec!["root", "left", "right"]);
+    }
+
+    #[test]
+    fn glb_hierarchy() {
+        let bundles = [hierarchy(&[])];
+        let roots = roots(&bundles, &bundles[0]);
+        let glb = to_glb(&bundles, &roots[0]);
+        assert_eq!(&glb[..4], b"glTF");
+        let len = u32::from_le_bytes([glb[12], glb[13], glb[14], glb[15]]) as usize;
+        let doc: serde_json::Value = serde_json::from_slice(&glb[20 .. 20 + len]).unwrap();
+        assert_eq!(doc["scenes"][0]["nodes"], json!([0]));
+        assert_eq!(doc["nodes"][0]["children"], json!([1, 2]));
+        assert_eq!(doc["nodes"][2]["name"], "right");
+        assert!(doc["nodes"][1].get("children").is_none());
+        // left-handed Unity space mirrored along x
+        assert_eq!(doc["nodes"][0]["translation"], json!([-1.0, 0.0, 0.0]));
+        assert!(doc.get("meshes").is_none());
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::constants;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, ObjectRef, PPtr};
+use crate::scene::SceneNode;
+use crate::Result;
+
+const ASSET_BUNDLE_CLASS_ID: i32 = 142;
+const TRANSFORM_CLASS_ID: i32 = 4;
+const RECT_TRANSFORM_CLASS_ID: i32 = 224;
+const RENDER_SETTINGS_CLASS_ID: i32 = 104;
+const LIGHTMAP_SETTINGS_CLASS_ID: i32 = 157;
+const MESH_RENDERER_CLASS_ID: i32 = 23;
+const SKINNED_MESH_RENDERER_CLASS_ID: i32 = 137;
+const TERRAIN_CLASS_ID: i32 = 218;
+
+/// serialized files of a scene bundle are named after the scene
+const SCENE_PREFIX: &str = "BuildPlayer-";
+const SHARED_ASSETS_SUFFIX: &str = ".sharedAssets";
+
+/// object referenced from a scene setting
+#[derive(Serialize, Clone, Debug)]
+pub struct SceneReference {
+    pub name: Option<String>,
+    pub class: Option<String>,
+    pub file_id: i32,
+    pub path_id: i64,
+    pub found: bool,                // resolved in the loaded bundles
+}
+
+impl SceneReference {
+    /// reference of the PPtr `v`, None when null
+    fn read(bundles: &[AssetBundle], asset: &Asset, v: Option<&ObjectValue>) -> Option<SceneReference> {
+        let ptr = v.and_then(PPtr::from_value).filter(|p| !p.is_null())?;
+        let target = pptr::resolve(bundles, asset, &ptr);
+        Some(SceneReference{
+            name: target.and_then(|t| t.asset.object_value(t.object).ok())
+                .and_then(|t| t.get("m_Name").and_then(|n| n.as_str()).map(String::from)),
+            class: target.and_then(|t| t.asset.class_id(t.object))
+                .map(|c| constants::class_name(c, asset.unity_version()).map(String::from).unwrap_or_else(|| format!("Class{}", c))),
+            file_id: ptr.file_id,
+            path_id: ptr.path_id,
+            found: target.is_some(),
+        })
+    }
+}
+
+/// RenderSettings of a scene
+#[derive(Serialize, Clone, Debug)]
+pub struct RenderSettings {
+    pub path_id: i64,
+    pub fog: bool,
+    pub fog_mode: i64,              // 1 linear, 2 exponential, 3 exponential squared
+    pub fog_color: [f32; 4],
+    pub fog_density: f32,
+    pub ambient_mode: i64,          // 0 skybox, 1 trilight, 3 flat
+    pub ambient_sky_color: [f32; 4],
+    pub ambient_equator_color: [f32; 4],
+    pub ambient_ground_color: [f32; 4],
+    pub ambient_intensity: f32,
+    pub skybox: Option<SceneReference>,
+    pub sun: Option<SceneReference>,
+    pub custom_reflection: Option<SceneReference>,
+}
+
+impl RenderSettings {
+    fn read(bundles: &[AssetBundle], asset: &Asset, path_id: i64, v: &ObjectValue) -> RenderSettings {
+        let float = |name: &str| v.get(name).and_then(|f| f.as_f64()).unwrap_or(0.0) as f32;
+        let color = |name: &str| {
+            let c = |n: &str| v.get(name).and_then(|c| c.get(n)).and_then(|c| c.as_f64()).unwrap_or(0.0) as f32;
+            [c("r"), c("g"), c("b"), c("a")]
+        };
+        RenderSettings{
+            path_id,
+            fog: v.flag("m_Fog"),
+            fog_mode: v.get("m_FogMode").and_then(|m| m.as_i64()).unwrap_or(0),
+            fog_color: color("m_FogColor"),
+            fog_density: float("m_FogDensity"),
+            ambient_mode: v.get("m_AmbientMode").and_then(|m| m.as_i64()).unwrap_or(0),
+            ambient_sky_color: color("m_AmbientSkyColor"),
+            ambient_equator_color: color("m_AmbientEquatorColor"),
+            ambient_ground_color: color("m_AmbientGroundColor"),
+            ambient_intensity: float("m_AmbientIntensity"),
+            skybox: SceneReference::read(bundles, asset, v.get("m_SkyboxMaterial")),
+            sun: SceneReference::read(bundles, asset, v.get("m_Sun")),
+            custom_reflection: SceneReference::read(bundles, asset, v.get("m_CustomReflection")),
+        }
+    }
+}
+
+/// baked lightmap of a scene with the renderers sampling it
+#[derive(Serialize, Clone, Debug)]
+pub struct Lightmap {
+    pub index: usize,
+    pub color: Option<SceneReference>,
+    pub direction: Option<SceneReference>,
+    pub shadow_mask: Option<SceneReference>,
+    pub renderers: usize,           // renderers and terrains whose m_LightmapIndex is `index`
+}
+
+/// LightmapSettings of a scene
+#[derive(Serialize, Clone, Debug)]
+pub struct LightmapSettings {
+    pub path_id: i64,
+    pub mode: i64,                  // 0 non directional, 1 combined directional
+    pub lightmaps: Vec<Lightmap>,
+    pub light_probes: Option<SceneReference>,
+}
+
+impl LightmapSettings {
+    fn read(bundles: &[AssetBundle], asset: &Asset, path_id: i64, v: &ObjectValue) -> LightmapSettings {
+        let lightmaps = match v.get("m_Lightmaps") {
+            Some(ObjectValue::Array(l)) => l.iter().enumerate()
+                .map(|(index, data)| Lightmap{
+                    index,
+                    color: SceneReference::read(bundles, asset, data.get("m_Lightmap")),
+                    // m_IndirectLightmap before 5.6
+                    direction: SceneReference::read(bundles, asset, data.get("m_DirLightmap").or_else(|| data.get("m_IndirectLightmap"))),
+                    shadow_mask: SceneReference::read(bundles, asset, data.get("m_ShadowMask")),
+                    renderers: 0,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        LightmapSettings{
+            path_id,
+            mode: v.get("m_LightmapsMode").and_then(|m| m.as_i64()).unwrap_or(0),
+            lightmaps,
+            light_probes: SceneReference::read(bundles, asset, v.get("m_LightProbes")),
+        }
+    }
+}
+
+/// scene of a streamed scene bundle
+#[derive(Serialize, Clone)]
+pub struct Scene<'a> {
+    pub name: String,
+    pub path: Option<String>,           // scene path in the project, from m_Container
+    pub file: String,                   // serialized file holding the scene objects
+    pub shared_assets: Option<String>,  // serialized file holding the assets only this scene uses
+    pub objects: usize,
+    pub render_settings: Option<RenderSettings>,
+    pub lightmap_settings: Option<LightmapSettings>,
+    pub roots: Vec<SceneNode<'a>>,
+}
+
+/// scenes.json
+#[derive(Serialize, Clone)]
+pub struct SceneBundle<'a> {
+    pub streamed: bool,             // m_IsStreamedSceneAssetBundle
+    pub scenes: Vec<Scene<'a>>,
+    pub other_files: Vec<String>,   // serialized files not belonging to a scene
+}
+
+/// scene name of a serialized file name, with whether it holds the shared assets
+pub fn scene_name(file: &str) -> Option<(&str, bool)> {
+    let name = file.strip_prefix(SCENE_PREFIX)?;
+    match name.strip_suffix(SHARED_ASSETS_SUFFIX) {
+        Some(scene) => Some((scene, true)),
+        None => Some((name, false)),
+    }
+}
+
+/// streamed flag and container paths of the AssetBundle object of `bundle`
+fn bundle_info(bundle: &AssetBundle) -> (bool, Vec<String>) {
+    for asset in bundle.assets() {
+        for obj in asset.objects().iter().filter(|o| asset.class_id(o) == Some(ASSET_BUNDLE_CLASS_ID)) {
+            let v = match asset.object_value(obj) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let paths = match v.get("m_Container") {
+                Some(ObjectValue::Array(entries)) => entries.iter()
+                    .filter_map(|e| e.get("first").and_then(|p| p.as_str()).map(String::from))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            return (v.flag("m_IsStreamedSceneAssetBundle"), paths);
+        }
+    }
+    (false, Vec::new())
+}
+
+/// scene of the serialized file `asset`
+fn scene<'a>(bundles: &'a [AssetBundle], bundle: &'a AssetBundle, asset: &'a Asset, name: &str, containers: &[String]) -> Scene<'a> {
+    let shared_assets = format!("{}{}{}", SCENE_PREFIX, name, SHARED_ASSETS_SUFFIX);
+    let path = containers.iter()
+        .find(|p| {
+            let stem = p.rsplit('/').next().unwrap_or(p);
+            stem.strip_suffix(".unity").unwrap_or(stem).eq_ignore_ascii_case(name)
+        })
+        .cloned();
+    let mut scene = Scene{
+        name: name.to_string(),
+        path,
+        file: asset.name().to_string(),
+        shared_assets: bundle.assets().iter().find(|a| a.name() == shared_assets).map(|_| shared_assets),
+        objects: asset.objects().len(),
+        render_settings: None,
+        lightmap_settings: None,
+        roots: Vec::new(),
+    };
+    let mut lightmap_users: BTreeMap<i64, usize> = BTreeMap::new();
+    for object in asset.objects() {
+        let class_id = asset.class_id(object);
+        let v = match class_id {
+            Some(RENDER_SETTINGS_CLASS_ID) | Some(LIGHTMAP_SETTINGS_CLASS_ID) | Some(TRANSFORM_CLASS_ID) | Some(RECT_TRANSFORM_CLASS_ID)
+                | Some(MESH_RENDERER_CLASS_ID) | Some(SKINNED_MESH_RENDERER_CLASS_ID) | Some(TERRAIN_CLASS_ID) => match asset.object_value(object) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), object.path_id, e);
+                    continue;
+                },
+            },
+            _ => continue,
+        };
+        match class_id {
+            Some(RENDER_SETTINGS_CLASS_ID) => scene.render_settings = Some(RenderSettings::read(bundles, asset, object.path_id, &v)),
+            Some(LIGHTMAP_SETTINGS_CLASS_ID) => scene.lightmap_settings = Some(LightmapSettings::read(bundles, asset, object.path_id, &v)),
+            Some(TRANSFORM_CLASS_ID) | Some(RECT_TRANSFORM_CLASS_ID) => {
+                let father = v.get("m_Father").and_then(PPtr::from_value);
+                if father.map(|f| !f.is_null()).unwrap_or(false) {
+                    continue;
+                }
+                match SceneNode::load(bundles, ObjectRef{ bundle, asset, object }) {
+                    Ok(node) => scene.roots.push(node),
+                    Err(e) => warn!("{} {}: {}", asset.name(), object.path_id, e),
+                }
+            },
+            _ => {
+                // 0xffff (-1 before 5.0) when not lightmapped
+                if let Some(index) = v.get("m_LightmapIndex").and_then(|i| i.as_i64()) {
+                    *lightmap_users.entry(index).or_default() += 1;
+                }
+            },
+        }
+    }
+    if let Some(settings) = scene.lightmap_settings.as_mut() {
+        for lightmap in settings.lightmaps.iter_mut() {
+            lightmap.renderers = lightmap_users.get(&(lightmap.index as i64)).copied().unwrap_or(0);
+        }
+    }
+    scene
+}
+
+/// scenes of `bundles[0]`, their shared assets and settings, resolving references through `bundles`
+pub fn report<'a>(bundles: &'a [AssetBundle]) -> Result<SceneBundle<'a>> {
+    let bundle = bundles.first().ok_or("no bundle")?;
+    let (streamed, containers) = bundle_info(bundle);
+    let mut scenes = Vec::new();
+    let mut other_files = Vec::new();
+    for asset in bundle.assets() {
+        match scene_name(asset.name()) {
+            Some((name, false)) => scenes.push(scene(bundles, bundle, asset, name, &containers)),
+            Some((name, true)) if bundle.assets().iter().any(|a| scene_name(a.name()) == Some((name, false))) => {},
+            _ => other_files.push(asset.name().to_string()),
+        }
+    }
+    if !streamed && scenes.is_empty() {
+        warn!("not a scene bundle");
+    }
+    Ok(SceneBundle{ streamed, scenes, other_files })
+}
+
+/// writes the scenes of `bundles[0]` into `dst/scenes.json`
+pub fn export(bundles: &[AssetBundle], dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let report = report(bundles)?;
+    for scene in &report.scenes {
+        info!("{}: {} roots, shared assets {:?}", scene.name, scene.roots.len(), scene.shared_assets);
+    }
+    fs::write(dst.join("scenes.json"), serde_json::to_string_pretty(&report)?)?;
+    Ok(report.scenes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, Node, Writer, ALIGN, GAME_OBJECT_CLASS_ID};
+
+    fn asset_bundle_type() -> Vec<Node> {
+        let mut nodes = vec![
+            (0, "AssetBundle", "Base", 0),
+            (1, "map", "m_Container", 0),
+            (2, "Array", "Array", 0),
+            (3, "int", "size", 0),
+            (3, "pair", "data", 0),
+            (4, "string", "first", 0),
+            (5, "Array", "Array", ALIGN),
+            (6, "int", "size", 0),
+            (6, "char", "data", 0),
+            (4, "AssetInfo", "second", 0),
+            (5, "int", "preloadIndex", 0),
+            (5, "int", "preloadSize", 0),
+        ];
+        nodes.extend(fixture::pptr(5, "PPtr<Object>", "asset"));
+        nodes.push((1, "bool", "m_IsStreamedSceneAssetBundle", ALIGN));
+        nodes
+    }
+
+    #[test]
+    fn pair_scenes_with_shared_assets() {
+        assert_eq!(scene_name("BuildPlayer-Main.sharedAssets"), Some(("Main", true)));
+        assert_eq!(scene_name("CAB-0123"), None);
+
+        let hierarchy = [
+            (1, 0, fixture::game_object("ground", &[2])),
+            (2, 1, fixture::transform(1, [0.0, 0.0, 0.0], &[], 0)),
+        ];
+        let classes = [(GAME_OBJECT_CLASS_ID, fixture::game_object_type()), (TRANSFORM_CLASS_ID, fixture::transform_type())];
+        let container = Writer::default().int(1).string("Assets/Scenes/main.unity").int(0).int(0).pptr(0);
+        let bundle_object = [(1, 0, container.boolean(true).align().0)];
+        let empty = fixture::serialized_file(&[], &[]);
+        let bundles = [fixture::bundle(vec![
+            ("BuildPlayer-Main", fixture::serialized_file(&classes, &hierarchy)),
+            ("BuildPlayer-Main.sharedAssets", fixture::serialized_file(&[(ASSET_BUNDLE_CLASS_ID, asset_bundle_type())], &bundle_object)),
+            ("BuildPlayer-Orphan.sharedAssets", empty.clone()),
+            ("extra", empty),
+        ])];
+
+        let report = report(&bundles).unwrap();
+        assert!(report.streamed);
+        assert_eq!(report.scenes.len(), 1);
+        let scene = &report.scenes[0];
+        assert_eq!(scene.path.as_deref(), Some("Assets/Scenes/main.unity"));
+        assert_eq!(scene.shared_assets.as_deref(), Some("BuildPlayer-Main.sharedAssets"));
+        assert_eq!((scene.objects, scene.roots.len()), (2, 1));
+        assert_eq!(scene.roots[0].name, "ground");
+        assert_eq!(report.other_files, vec!["BuildPlayer-Orphan.sharedAssets", "extra"]);
+    }
+}
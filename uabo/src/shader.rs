@@ -0,0 +1,445 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::object_value::ObjectValue;
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+/// ShaderCompilerPlatform names by id
+const PLATFORMS: [&str; 25] = [
+    "OpenGL", "D3D9", "Xbox360", "PS3", "D3D11", "GLES20", "NaCl", "Flash", "D3D11_9x", "GLES3Plus",
+    "PSP2", "PS4", "XboxOne", "PSM", "Metal", "OpenGLCore", "N3DS", "WiiU", "Vulkan", "Switch",
+    "XboxOneD3D12", "GameCoreXboxOne", "GameCoreScarlett", "PS5", "PS5NGGC",
+];
+
+/// program fields of a SerializedPass and their stage names
+pub const STAGES: [(&str, &str); 6] = [
+    ("progVertex", "vertex"),
+    ("progFragment", "fragment"),
+    ("progGeometry", "geometry"),
+    ("progHull", "hull"),
+    ("progDomain", "domain"),
+    ("progRayTracing", "raytracing"),
+];
+
+/// ShaderCompilerPlatform of each ShaderGpuProgramType; the console types are shared by
+/// every console and grouped as "Console"
+const GPU_PROGRAM_PLATFORMS: [&str; 33] = [
+    "Unknown", "OpenGL", "GLES3Plus", "GLES3Plus", "GLES3Plus", "GLES20", "OpenGLCore", "OpenGLCore", "OpenGLCore",
+    "D3D9", "D3D9", "D3D9", "D3D9", "D3D11_9x", "D3D11_9x",
+    "D3D11", "D3D11", "D3D11", "D3D11", "D3D11", "D3D11", "D3D11", "D3D11",
+    "Metal", "Metal", "Vulkan", "Console", "Console", "Console", "Console", "Console", "RayTracing", "PS5NGGC",
+];
+
+pub fn platform_name(id: u32) -> String {
+    PLATFORMS.get(id as usize).map(|p| p.to_string()).unwrap_or_else(|| format!("Platform{}", id))
+}
+
+/// platform a sub program is compiled for, from its m_GpuProgramType
+pub fn gpu_program_platform(sub: &ObjectValue) -> &'static str {
+    sub.get("m_GpuProgramType").and_then(|t| t.as_u64())
+        .and_then(|t| GPU_PROGRAM_PLATFORMS.get(t as usize))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Property {
+    pub name: String,
+    pub description: String,
+    pub kind: &'static str,         // Color, Vector, Float, Range, Texture or Int
+    pub attributes: Vec<String>,
+    pub flags: u32,
+    pub default: Value,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Pass {
+    pub name: String,
+    pub kind: &'static str,         // Normal, Use or Grab
+    pub use_name: Option<String>,
+    pub lod: i64,
+    pub tags: BTreeMap<String, String>,
+    pub programs: BTreeMap<&'static str, usize>,    // sub programs by stage, every platform and tier
+    pub keywords: Vec<String>,
+    pub keyword_sets: Vec<Vec<String>>,
+    pub variants: usize,            // distinct keyword sets of every platform together
+    pub platform_variants: BTreeMap<&'static str, usize>,  // distinct keyword sets compiled per platform
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SubShader {
+    pub lod: i64,
+    pub tags: BTreeMap<String, String>,
+    pub passes: Vec<Pass>,
+}
+
+/// compiled programs of one platform in the compressed blob
+#[derive(Serialize, Clone, Debug)]
+pub struct Platform {
+    pub name: String,
+    pub compressed_size: usize,
+    pub decompressed_size: usize,
+    pub programs: usize,            // sub programs in the blob, every variant, stage and tier
+    pub variants: Option<usize>,    // keyword sets compiled for the platform, summed over passes
+    pub error: Option<String>,
+}
+
+/// Shader with its parsed form (5.5+) and per platform blob statistics
+#[derive(Serialize, Clone, Debug)]
+pub struct Shader {
+    pub name: String,
+    pub fallback: Option<String>,
+    pub custom_editor: Option<String>,
+    pub properties: Vec<Property>,
+    pub subshaders: Vec<SubShader>,
+    pub variants: usize,            // sum of the pass variants
+    pub platform_variants: BTreeMap<&'static str, usize>,  // sum of the pass variants per platform
+    pub platforms: Vec<Platform>,
+}
+
+impl Shader {
+    pub const CLASS_ID: i32 = 48;
+
+    pub fn from_value(v: &ObjectValue, version: &UnityVersion) -> Result<Shader> {
+        let parsed = v.field("m_ParsedForm")?;
+        let keyword_names = strings(parsed.get("m_KeywordNames"));
+        let properties = parsed.field("m_PropInfo")?.array("m_Props").iter()
+            .map(property)
+            .collect::<Result<_>>()?;
+        let subshaders: Vec<SubShader> = parsed.array("m_SubShaders").iter()
+            .map(|s| Ok(SubShader{
+                lod: s.get("m_LOD").and_then(|l| l.as_i64()).unwrap_or(0),
+                tags: tags(s.get("m_Tags")),
+                passes: s.array("m_Passes").iter().map(|p| pass(p, &keyword_names)).collect::<Result<_>>()?,
+            }))
+            .collect::<Result<_>>()?;
+        let mut platform_variants = BTreeMap::new();
+        for pass in subshaders.iter().flat_map(|s| &s.passes) {
+            for (platform, variants) in &pass.platform_variants {
+                *platform_variants.entry(*platform).or_insert(0) += variants;
+            }
+        }
+        let platforms = blobs(v, version)?.into_iter()
+            .map(|blob| {
+                let segmented = blob.segmented;
+                let decompressed_size = blob.segments.as_ref().map(|s| s.iter().map(Vec::len).sum()).unwrap_or(0);
+                let (programs, error) = match blob.segments.and_then(|s| entries(&s, segmented)) {
+                    Ok(e) => (e.len(), None),
+                    Err(e) => (0, Some(e.to_string())),
+                };
+                let name = platform_name(blob.platform);
+                // console blobs hold the shared console program types
+                let variants = platform_variants.get(name.as_str()).copied()
+                    .or_else(|| match GPU_PROGRAM_PLATFORMS.contains(&name.as_str()) {
+                        true => None,
+                        false => platform_variants.get("Console").copied(),
+                    });
+                Platform{ name, compressed_size: blob.compressed_size, decompressed_size, programs, variants, error }
+            })
+            .collect();
+        let optional = |name: &str| parsed.get(name).and_then(|n| n.as_str()).filter(|n| !n.is_empty()).map(String::from);
+        Ok(Shader{
+            name: optional("m_Name").or_else(|| v.get("m_Name").and_then(|n| n.as_str()).map(String::from)).unwrap_or_default(),
+            fallback: optional("m_FallbackName"),
+            custom_editor: optional("m_CustomEditorName"),
+            properties,
+            variants: subshaders.iter().flat_map(|s| &s.passes).map(|p| p.variants).sum(),
+            platform_variants,
+            subshaders,
+            platforms,
+        })
+    }
+}
+
+fn property(p: &ObjectValue) -> Result<Property> {
+    let value = |i: usize| p.get(&format!("m_DefValue[{}]", i)).and_then(|d| d.as_f64()).unwrap_or(0.0);
+    let kind = match p.int("m_Type")? {
+        0 => "Color",
+        1 => "Vector",
+        2 => "Float",
+        3 => "Range",
+        4 => "Texture",
+        5 => "Int",
+        _ => "Unknown",
+    };
+    let default = match kind {
+        "Color" | "Vector" => json!([value(0), value(1), value(2), value(3)]),
+        "Range" => json!({ "value": value(0), "min": value(1), "max": value(2) }),
+        "Texture" => {
+            let t = p.field("m_DefTexture")?;
+            json!({ "name": t.string("m_DefaultName")?, "dimension": t.int("m_TexDim")? })
+        },
+        _ => json!(value(0)),
+    };
+    Ok(Property{
+        name: p.string("m_Name")?.to_string(),
+        description: p.string("m_Description")?.to_string(),
+        kind,
+        attributes: strings(p.get("m_Attributes")),
+        flags: p.get("m_Flags").and_then(|f| f.as_u64()).unwrap_or(0) as u32,
+        default,
+    })
+}
+
+/// keyword indices of a sub program; 2019.1 to 2020.1 split them into global and local ones
+pub fn keyword_indices(sub: &ObjectValue) -> Vec<u64> {
+    ["m_KeywordIndices", "m_GlobalKeywordIndices", "m_LocalKeywordIndices"].iter()
+        .filter_map(|f| match sub.get(f) {
+            Some(ObjectValue::Array(a)) => Some(a.iter().filter_map(|i| i.as_u64())),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// keyword names of a pass by index, from the shader wide table (2021.2+) or m_NameIndices
+pub fn keyword_table(pass: &ObjectValue, shader_keywords: &[String]) -> HashMap<u64, String> {
+    let mut table: HashMap<u64, String> = shader_keywords.iter().cloned().enumerate().map(|(i, k)| (i as u64, k)).collect();
+    if table.is_empty() {
+        for pair in pass.array("m_NameIndices") {
+            if let (Some(name), Some(index)) = (pair.get("first").and_then(|f| f.as_str()), pair.get("second").and_then(|s| s.as_u64())) {
+                table.insert(index, name.to_string());
+            }
+        }
+    }
+    table
+}
+
+/// sub programs of a SerializedProgram, including the player sub programs of 2022+
+pub fn sub_programs(program: &ObjectValue) -> Vec<&ObjectValue> {
+    let mut subs: Vec<&ObjectValue> = program.array("m_SubPrograms").iter().collect();
+    for tier in program.array("m_PlayerSubPrograms") {
+        if let ObjectValue::Array(t) = tier {
+            subs.extend(t.iter());
+        }
+    }
+    subs
+}
+
+fn pass(p: &ObjectValue, shader_keywords: &[String]) -> Result<Pass> {
+    let table = keyword_table(p, shader_keywords);
+    let name_of = |i: u64| table.get(&i).cloned().unwrap_or_else(|| format!("keyword{}", i));
+    let mut programs = BTreeMap::new();
+    let mut sets = BTreeSet::new();
+    let mut platform_sets: BTreeMap<&'static str, BTreeSet<Vec<String>>> = BTreeMap::new();
+    for (field, stage) in STAGES.iter() {
+        let subs = match p.get(field) {
+            Some(program) => sub_programs(program),
+            None => continue,
+        };
+        if subs.is_empty() {
+            continue;
+        }
+        programs.insert(*stage, subs.len());
+        for sub in subs {
+            let mut set: Vec<String> = keyword_indices(sub).into_iter().map(name_of).collect();
+            set.sort();
+            set.dedup();
+            platform_sets.entry(gpu_program_platform(sub)).or_default().insert(set.clone());
+            sets.insert(set);
+        }
+    }
+    let keywords: BTreeSet<&String> = sets.iter().flatten().collect();
+    let state = p.get("m_State");
+    let name = state.and_then(|s| s.get("m_Name")).or_else(|| p.get("m_Name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or_default();
+    Ok(Pass{
+        name: name.to_string(),
+        kind: match p.get("m_Type").and_then(|t| t.as_i64()) {
+            Some(1) => "Use",
+            Some(2) => "Grab",
+            _ => "Normal",
+        },
+        use_name: p.get("m_UseName").and_then(|n| n.as_str()).filter(|n| !n.is_empty()).map(String::from),
+        lod: state.and_then(|s| s.get("m_LOD")).and_then(|l| l.as_i64()).unwrap_or(0),
+        tags: tags(state.and_then(|s| s.get("m_Tags"))),
+        programs,
+        keywords: keywords.into_iter().cloned().collect(),
+        variants: sets.len(),
+        platform_variants: platform_sets.iter().map(|(p, s)| (*p, s.len())).collect(),
+        keyword_sets: sets.into_iter().collect(),
+    })
+}
+
+/// compressed programs of one platform
+pub struct Blob {
+    pub platform: u32,
+    pub compressed_size: usize,
+    pub segmented: bool,                    // 2019.3+, entries name their segment
+    pub segments: Result<Vec<Vec<u8>>>,     // decompressed
+}
+
+/// program blob of every platform
+///
+/// Since 2019.3 entries name their segment; since 2021.3 a platform may be split into several
+/// segments, each with its own offset and lengths.
+pub fn blobs(v: &ObjectValue, version: &UnityVersion) -> Result<Vec<Blob>> {
+    let platforms: Vec<u32> = match v.get("platforms") {
+        Some(ObjectValue::Array(p)) => p.iter().filter_map(|p| p.as_u64()).map(|p| p as u32).collect(),
+        _ => return Ok(Vec::new()),
+    };
+    let blob = v.get("compressedBlob").and_then(|b| b.as_bytes()).unwrap_or_default();
+    let segments = |name: &str| -> Vec<Vec<u64>> {
+        v.array(name).iter()
+            .map(|x| match x {
+                ObjectValue::Array(a) => a.iter().filter_map(|i| i.as_u64()).collect(),
+                x => x.as_u64().into_iter().collect(),
+            })
+            .collect()
+    };
+    let (offsets, compressed, decompressed) = (segments("offsets"), segments("compressedLengths"), segments("decompressedLengths"));
+    let segmented = version.release() >= (2019, 3);
+    Ok(platforms.into_iter().enumerate()
+        .map(|(i, id)| {
+            let (o, c, d) = (offsets.get(i).cloned().unwrap_or_default(), compressed.get(i).cloned().unwrap_or_default(),
+                decompressed.get(i).cloned().unwrap_or_default());
+            let segments = (0..o.len())
+                .map(|s| {
+                    let (offset, length, size) = (o[s] as usize, *c.get(s).ok_or("missing compressed length")? as usize,
+                        *d.get(s).ok_or("missing decompressed length")? as usize);
+                    let src = blob.get(offset..offset + length)
+                        .ok_or_else(|| format!("segment {}..{} outside of a {} byte blob", offset, offset + length, blob.len()))?;
+                    Ok(lz4::block::decompress(src, Some(size as i32))?)
+                })
+                .collect();
+            Blob{ platform: id, compressed_size: c.iter().sum::<u64>() as usize, segmented, segments }
+        })
+        .collect())
+}
+
+/// sub program entries of a platform blob: segment, offset and length
+///
+/// The first segment starts with the entry count.
+pub fn entries(segments: &[Vec<u8>], segmented: bool) -> Result<Vec<(usize, usize, usize)>> {
+    let head = segments.first().ok_or("empty shader blob")?;
+    let read = |pos: usize| -> Result<usize> {
+        head.get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| "shader blob header truncated".into())
+    };
+    let count = read(0)?;
+    let width = match segmented {
+        true => 12,
+        false => 8,
+    };
+    (0..count)
+        .map(|i| {
+            let pos = 4 + i * width;
+            let segment = match segmented {
+                true => read(pos + 8)?,
+                false => 0,
+            };
+            let (offset, length) = (read(pos)?, read(pos + 4)?);
+            match segments.get(segment).map(|s| offset + length <= s.len()) {
+                Some(true) => Ok((segment, offset, length)),
+                _ => Err(format!("sub program {} outside of segment {}", i, segment).into()),
+            }
+        })
+        .collect()
+}
+
+fn strings(v: Option<&ObjectValue>) -> Vec<String> {
+    match v {
+        Some(ObjectValue::Array(a)) => a.iter().filter_map(|s| s.as_str()).map(String::from).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// SerializedTagMap as a map
+fn tags(v: Option<&ObjectValue>) -> BTreeMap<String, String> {
+    let pairs = match v.and_then(|t| t.get("tags")) {
+        Some(ObjectValue::Array(a)) => a,
+        _ => return BTreeMap::new(),
+    };
+    pairs.iter()
+        .filter_map(|p| Some((p.get("first")?.as_str()?.to_string(), p.get("second")?.as_str()?.to_string())))
+        .collect()
+}
+
+/// per shader row of shaders.json
+#[derive(Serialize)]
+struct ShaderSummary {
+    name: String,
+    variants: usize,
+    platform_variants: BTreeMap<&'static str, usize>,
+    programs: BTreeMap<String, usize>,
+    compressed_size: usize,
+    file: String,
+}
+
+/// writes every Shader of `bundle` into `dst` as `<name>.json`, with `shaders.json` listing
+/// variant and program counts per platform
+pub fn export(bundle: &AssetBundle, dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut summaries = Vec::new();
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(Shader::CLASS_ID) {
+                continue;
+            }
+            let shader = match asset.object_value(obj).and_then(|v| Shader::from_value(&v, asset.unity_version())) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let path = export::unique_path(dst, &shader.name, "json");
+            fs::write(&path, serde_json::to_string_pretty(&shader)?)?;
+            info!("{} variants {:?}", shader.variants, path);
+            summaries.push(ShaderSummary{
+                name: shader.name.clone(),
+                variants: shader.variants,
+                platform_variants: shader.platform_variants.clone(),
+                programs: shader.platforms.iter().map(|p| (p.name.clone(), p.programs)).collect(),
+                compressed_size: shader.platforms.iter().map(|p| p.compressed_size).sum(),
+                file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            });
+        }
+    }
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.compressed_size));
+    fs::write(dst.join("shaders.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(summaries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_of_unnested_2020_blob() {
+        // two 12 byte entries (offset, length, segment) in a single segment
+        let mut head = 2u32.to_le_bytes().to_vec();
+        for (offset, length) in [(28u32, 4u32), (32, 2)] {
+            head.extend_from_slice(&offset.to_le_bytes());
+            head.extend_from_slice(&length.to_le_bytes());
+            head.extend_from_slice(&0u32.to_le_bytes());
+        }
+        head.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let compressed = lz4::block::compress(&head, None, false).unwrap();
+        let lengths = |n: usize| ObjectValue::Array(vec![ObjectValue::UInt(n as u64)]);
+        let v = ObjectValue::Struct(vec![
+            ("platforms".to_string(), ObjectValue::Array(vec![ObjectValue::UInt(15)])),
+            ("offsets".to_string(), lengths(0)),
+            ("compressedLengths".to_string(), lengths(compressed.len())),
+            ("decompressedLengths".to_string(), lengths(head.len())),
+            ("compressedBlob".to_string(), ObjectValue::Bytes(compressed)),
+        ]);
+        let blob = blobs(&v, &UnityVersion::parse("2020.3.30f1").unwrap()).unwrap().remove(0);
+        assert!(blob.segmented);
+        let segments = blob.segments.unwrap();
+        assert_eq!(entries(&segments, blob.segmented).unwrap(), vec![(0, 28, 4), (0, 32, 2)]);
+
+        let blob = blobs(&v, &UnityVersion::parse("2019.2.0f1").unwrap()).unwrap().remove(0);
+        assert!(!blob.segmented);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::object_value::ObjectValue;
+use crate::shader::{self, Shader};
+use crate::Result;
+
+/// ShaderGpuProgramType names by id, 5.5+
+const PROGRAM_TYPES: [&str; 33] = [
+    "Unknown", "GLLegacy", "GLES31AEP", "GLES31", "GLES3", "GLES", "GLCore32", "GLCore41", "GLCore43",
+    "DX9VertexSM20", "DX9VertexSM30", "DX9PixelSM20", "DX9PixelSM30",
+    "DX10Level9Vertex", "DX10Level9Pixel", "DX11VertexSM40", "DX11VertexSM50", "DX11PixelSM40", "DX11PixelSM50",
+    "DX11GeometrySM40", "DX11GeometrySM50", "DX11HullSM50", "DX11DomainSM50",
+    "MetalVS", "MetalFS", "SPIRV", "ConsoleVS", "ConsoleFS", "ConsoleHS", "ConsoleDS", "ConsoleGS", "RayTracing", "PS5NGGC",
+];
+
+// sub program versions adding fields
+const VERSION_5_3: i32 = 201509030;
+const VERSION_STATS: i32 = 201608170;
+const VERSION_LOCAL_KEYWORDS: i32 = 201806140;
+const VERSION_MERGED_KEYWORDS: i32 = 202012090;
+
+/// Metal programs with this magic start with the offset of the entry point name and source
+const METAL_MAGIC: u32 = 0xf00d_cafe;
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// ShaderSubProgram of a decompressed blob
+pub struct SubProgram {
+    pub version: i32,
+    pub program_type: i32,
+    pub keywords: Vec<String>,
+    pub local_keywords: Vec<String>,
+    pub code: Vec<u8>,
+}
+
+impl SubProgram {
+    pub fn parse(d: &[u8]) -> Result<SubProgram> {
+        let mut pos = 0;
+        let int = |pos: &mut usize| -> Result<i32> {
+            let v = d.get(*pos..*pos + 4).ok_or("sub program truncated")?;
+            *pos += 4;
+            Ok(i32::from_le_bytes(v.try_into().unwrap()))
+        };
+        let version = int(&mut pos)?;
+        let program_type = int(&mut pos)?;
+        pos += match version >= VERSION_STATS {
+            true => 16,
+            false => 12,
+        };
+        let bytes = |pos: &mut usize| -> Result<Vec<u8>> {
+            let len = int(pos)?;
+            if len < 0 {
+                return Err("negative sub program string length".into());
+            }
+            let len = len as usize;
+            let end = pos.checked_add(len).filter(|end| *end <= d.len()).ok_or("sub program string truncated")?;
+            let v = d[*pos..end].to_vec();
+            *pos = end.div_ceil(4) * 4;
+            Ok(v)
+        };
+        let strings = |pos: &mut usize| -> Result<Vec<String>> {
+            (0..int(pos)?).map(|_| Ok(String::from_utf8_lossy(&bytes(pos)?).into_owned())).collect()
+        };
+        let keywords = strings(&mut pos)?;
+        let local_keywords = match (VERSION_LOCAL_KEYWORDS..VERSION_MERGED_KEYWORDS).contains(&version) {
+            true => strings(&mut pos)?,
+            false => Vec::new(),
+        };
+        let code = bytes(&mut pos)?;
+        Ok(SubProgram{ version, program_type, keywords, local_keywords, code })
+    }
+
+    pub fn type_name(&self) -> String {
+        PROGRAM_TYPES.get(self.program_type as usize).map(|t| t.to_string())
+            .unwrap_or_else(|| format!("Type{}", self.program_type))
+    }
+
+    /// program code as written to disk: source text for GL and Metal, bytecode for the rest,
+    /// with the extension and an optional suffix per part
+    pub fn files(&self) -> Result<Vec<(String, &'static str, Vec<u8>)>> {
+        let code = &self.code;
+        let word = |pos: usize| code.get(pos..pos + 4).map(|w| u32::from_le_bytes(w.try_into().unwrap()));
+        Ok(match self.program_type {
+            1..=8 => vec![(String::new(), "glsl", code.clone())],
+            9..=12 => vec![(String::new(), "dx9", code.clone())],
+            13..=22 => {
+                // DXBC follows Unity's header, 38 bytes long since 5.3 and 6 before
+                let start = match self.version >= VERSION_5_3 {
+                    true => 38,
+                    false => 6,
+                };
+                let start = match code.get(start..start + 4) {
+                    Some(b"DXBC") => start,
+                    _ => code.windows(4).position(|w| w == b"DXBC").ok_or("no DXBC container in program")?,
+                };
+                vec![(String::new(), "dxbc", code[start..].to_vec())]
+            },
+            23 | 24 => {
+                let start = match word(0) {
+                    Some(METAL_MAGIC) => word(4).map(|o| o as usize).unwrap_or(0),
+                    _ => 0,
+                };
+                // the source follows the null terminated entry point name
+                let source = code.get(start..).unwrap_or_default();
+                let name_end = source.iter().position(|b| *b == 0).map(|p| p + 1).unwrap_or(0);
+                vec![(String::new(), "metal", source[name_end..].to_vec())]
+            },
+            25 => {
+                // a requirements word, then (offset, size) of up to 5 stage blobs, each SPIR-V or SMOL-V
+                let mut parts = Vec::new();
+                let mut first = code.len();
+                for stage in 0..5 {
+                    let pos = 4 + stage * 8;
+                    if pos + 8 > first {
+                        break;
+                    }
+                    let (offset, size) = match (word(pos), word(pos + 4)) {
+                        (Some(o), Some(s)) => (o as usize, s as usize),
+                        _ => break,
+                    };
+                    if size == 0 {
+                        continue;
+                    }
+                    let part = match code.get(offset..offset + size) {
+                        Some(p) => p,
+                        None => break,
+                    };
+                    first = first.min(offset);
+                    let ext = match part.get(0..4).map(|m| u32::from_le_bytes(m.try_into().unwrap())) {
+                        Some(SPIRV_MAGIC) => "spv",
+                        _ => "smolv",
+                    };
+                    parts.push((format!("_{}", stage), ext, part.to_vec()));
+                }
+                match parts.is_empty() {
+                    true => vec![(String::new(), "spirv.bin", code.clone())],
+                    false => parts,
+                }
+            },
+            _ => vec![(String::new(), "bin", code.clone())],
+        })
+    }
+}
+
+/// one written program, row of programs.json
+#[derive(Serialize)]
+struct ProgramSummary {
+    file: String,
+    platform: String,
+    subshader: Option<usize>,
+    pass: Option<usize>,
+    pass_name: Option<String>,
+    stage: Option<&'static str>,
+    tier: Option<i64>,
+    program_type: String,
+    version: i32,
+    keywords: Vec<String>,
+    local_keywords: Vec<String>,
+    size: usize,
+}
+
+/// where the parsed form uses a blob entry: subshader, pass, pass name, stage, tier and program type
+type Usage = (usize, usize, String, &'static str, Option<i64>, Option<i64>);
+
+fn usages(parsed: &ObjectValue) -> HashMap<u64, Vec<Usage>> {
+    let mut usages: HashMap<u64, Vec<Usage>> = HashMap::new();
+    for (s, subshader) in parsed.array("m_SubShaders").iter().enumerate() {
+        for (p, pass) in subshader.array("m_Passes").iter().enumerate() {
+            let name = pass.get("m_State").and_then(|s| s.get("m_Name")).and_then(|n| n.as_str()).unwrap_or_default();
+            for (field, stage) in shader::STAGES.iter() {
+                for sub in pass.get(field).map(shader::sub_programs).unwrap_or_default() {
+                    if let Some(index) = sub.get("m_BlobIndex").and_then(|i| i.as_u64()) {
+                        usages.entry(index).or_default().push((s, p, name.to_string(), *stage,
+                            sub.get("m_ShaderHardwareTier").and_then(|t| t.as_i64()),
+                            sub.get("m_GpuProgramType").and_then(|t| t.as_i64())));
+                    }
+                }
+            }
+        }
+    }
+    usages
+}
+
+/// writes the compiled programs of every Shader of `bundle` into `dst/<shader>/<platform>/`,
+/// with `programs.json` per shader listing stage, keywords and type of each file
+pub fn export(bundle: &AssetBundle, dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(Shader::CLASS_ID) {
+                continue;
+            }
+            let v = match asset.object_value(obj) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let parsed = v.get("m_ParsedForm");
+            let name = parsed.and_then(|p| p.get("m_Name")).or_else(|| v.get("m_Name"))
+                .and_then(|n| n.as_str())
+                .unwrap_or_default();
+            let usages = parsed.map(usages).unwrap_or_default();
+            // same named shaders of other assets get their own directory
+            let dir = export::unique_path(dst, name, "");
+            fs::create_dir_all(&dir)?;
+            let mut summaries = Vec::new();
+            for blob in shader::blobs(&v, asset.unity_version())? {
+                let platform = shader::platform_name(blob.platform);
+                let segmented = blob.segmented;
+                let (segments, entries) = match blob.segments.and_then(|s| shader::entries(&s, segmented).map(|e| (s, e))) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        warn!("{} {}: {}", name, platform, e);
+                        continue;
+                    },
+                };
+                fs::create_dir_all(dir.join(&platform))?;
+                for (i, (segment, offset, length)) in entries.into_iter().enumerate() {
+                    let program = match SubProgram::parse(&segments[segment][offset..offset + length]) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            warn!("{} {} program {}: {}", name, platform, i, e);
+                            continue;
+                        },
+                    };
+                    // the parsed form lists every platform; prefer the use with this program type
+                    let usage = usages.get(&(i as u64)).and_then(|u| {
+                        u.iter().find(|u| u.5 == Some(program.program_type as i64)).or_else(|| u.first())
+                    });
+                    let stem = match usage {
+                        Some((s, p, _, stage, _, _)) => format!("{}_{}_{}_{}", s, p, stage, i),
+                        None => format!("program_{}", i),
+                    };
+                    let files = match program.files() {
+                        Ok(f) => f,
+                        Err(e) => {
+                            warn!("{} {} program {}: {}", name, platform, i, e);
+                            continue;
+                        },
+                    };
+                    for (suffix, ext, bytes) in files {
+                        let path = dir.join(&platform).join(format!("{}{}.{}", stem, suffix, ext));
+                        fs::write(&path, &bytes)?;
+                        summaries.push(ProgramSummary{
+                            file: format!("{}/{}", platform, path.file_name().unwrap_or_default().to_string_lossy()),
+                            platform: platform.clone(),
+                            subshader: usage.map(|u| u.0),
+                            pass: usage.map(|u| u.1),
+                            pass_name: usage.map(|u| u.2.clone()),
+                            stage: usage.map(|u| u.3),
+                            tier: usage.and_then(|u| u.4),
+                            program_type: program.type_name(),
+                            version: program.version,
+                            keywords: program.keywords.clone(),
+                            local_keywords: program.local_keywords.clone(),
+                            size: bytes.len(),
+                        });
+                    }
+                }
+            }
+            if summaries.is_empty() {
+                fs::remove_dir_all(&dir)?;
+                continue;
+            }
+            fs::write(dir.join("programs.json"), serde_json::to_string_pretty(&summaries)?)?;
+            info!("{} programs {:?}", summaries.len(), dir);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(version: i32, program_type: i32, code: Vec<u8>) -> SubProgram {
+        SubProgram{ version, program_type, keywords: Vec::new(), local_keywords: Vec::new(), code }
+    }
+
+    #[test]
+    fn dxbc_after_unity_header() {
+        for (version, header) in [(201509030, 38), (201502070, 6)] {
+            let mut code = vec![0xaa; header];
+            code.extend_from_slice(b"DXBC0123");
+            let files = program(version, 17, code).files().unwrap();
+            assert_eq!(files[0].2, b"DXBC0123");
+        }
+        assert!(program(201608170, 17, vec![0; 64]).files().is_err());
+    }
+
+    #[test]
+    fn spirv_stages_after_requirements() {
+        let spirv = SPIRV_MAGIC.to_le_bytes();
+        let mut code = vec![1, 0, 0, 0];
+        for (offset, size) in [(44u32, 4u32), (0, 0), (48, 2), (0, 0), (0, 0)] {
+            code.extend_from_slice(&offset.to_le_bytes());
+            code.extend_from_slice(&size.to_le_bytes());
+        }
+        code.extend_from_slice(&spirv);
+        code.extend_from_slice(&[7, 8]);
+        let files = program(201608170, 25, code).files().unwrap();
+        assert_eq!(files, vec![
+            ("_0".to_string(), "spv", spirv.to_vec()),
+            ("_2".to_string(), "smolv", vec![7, 8]),
+        ]);
+    }
+
+    #[test]
+    fn parse_string_lengths() {
+        // version, type, 12 bytes of stats, no keywords, then 3 bytes of code
+        let mut d = Vec::new();
+        for word in [201509030i32, 17, 0, 0, 0, 0, 3] {
+            d.extend_from_slice(&word.to_le_bytes());
+        }
+        d.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(SubProgram::parse(&d).unwrap().code, vec![1, 2, 3]);
+        d[24..28].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(SubProgram::parse(&d).is_err());
+        d[24..28].copy_from_slice(&4i32.to_le_bytes());
+        assert!(SubProgram::parse(&d).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::image::Image;
+use crate::object_value::ObjectValue;
+use crate::pptr::{self, ObjectRef, PPtr};
+use crate::texture_2d::Texture2D;
+use crate::vertex_data::{VertexData, CHANNEL_POSITION};
+use crate::Result;
+
+/// SpriteRenderData.settingsRaw: packed flag, packing mode and rotation
+const SETTINGS_PACKED: u32 = 0x1;
+const SETTINGS_RECTANGLE_PACKING: u32 = 0x2;
+
+/// SpritePackingRotation
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackingRotation {
+    None,
+    FlipHorizontal,
+    FlipVertical,
+    Rotate180,
+    Rotate90,
+}
+
+impl PackingRotation {
+    fn new(settings: u32) -> PackingRotation {
+        match settings >> 2 & 0xf {
+            1 => PackingRotation::FlipHorizontal,
+            2 => PackingRotation::FlipVertical,
+            3 => PackingRotation::Rotate180,
+            4 => PackingRotation::Rotate90,
+            _ => PackingRotation::None,
+        }
+    }
+}
+
+/// where a sprite's texels live: m_RD of the sprite or its SpriteAtlas entry
+#[derive(Serialize, Clone, Debug)]
+pub struct SpriteRenderData {
+    pub texture: PPtr,
+    pub alpha_texture: Option<PPtr>,    // separate alpha of ETC1 atlases
+    pub texture_rect: [f32; 4],         // x, y, width, height in texels, bottom-left origin
+    pub texture_rect_offset: [f32; 2],  // trimmed margin of the sprite rect
+    pub packed: bool,
+    pub tight: bool,                    // tight packing, texels outside the mesh belong to other sprites
+    pub rotation: PackingRotation,
+}
+
+impl SpriteRenderData {
+    fn from_value(v: &ObjectValue) -> Result<SpriteRenderData> {
+        let settings = v.int("settingsRaw")? as u32;
+        Ok(SpriteRenderData{
+            texture: PPtr::from_value(v.field("texture")?).ok_or("invalid texture pointer")?,
+            alpha_texture: v.get("alphaTexture").and_then(PPtr::from_value).filter(|p| !p.is_null()),
+            texture_rect: rect(v.field("textureRect")?)?,
+            texture_rect_offset: vector2(v.field("textureRectOffset")?)?,
+            packed: settings & SETTINGS_PACKED != 0,
+            tight: settings & SETTINGS_PACKED != 0 && settings & SETTINGS_RECTANGLE_PACKING == 0,
+            rotation: PackingRotation::new(settings),
+        })
+    }
+}
+
+/// Sprite object
+#[derive(Serialize, Clone, Debug)]
+pub struct Sprite {
+    pub name: String,
+    pub rect: [f32; 4],                 // sprite rect in the source texture
+    pub pivot: [f32; 2],                // normalized pivot
+    pub pixels_to_units: f32,
+    pub render_data_key: Option<(Vec<u64>, i64)>, // GUID and id of the entry in the atlas
+    pub atlas: Option<PPtr>,
+    pub render_data: SpriteRenderData,
+    #[serde(skip)]
+    pub vertices: Vec<[f32; 2]>,        // mesh positions in units, relative to the pivot
+    #[serde(skip)]
+    pub indices: Vec<u32>,              // triangle list
+}
+
+impl Sprite {
+    pub const CLASS_ID: i32 = 213;
+
+    pub fn from_value(v: &ObjectValue, asset: &Asset) -> Result<Sprite> {
+        let rd = v.field("m_RD")?;
+        let (vertices, indices) = Sprite::mesh(rd, asset)?;
+        Ok(Sprite{
+            name: v.string("m_Name")?.to_string(),
+            rect: rect(v.field("m_Rect")?)?,
+            pivot: v.get("m_Pivot").map(vector2).transpose()?.unwrap_or([0.5, 0.5]),
+            pixels_to_units: v.float("m_PixelsToUnits")? as f32,
+            render_data_key: v.get("m_RenderDataKey").and_then(render_data_key),
+            atlas: v.get("m_SpriteAtlas").and_then(PPtr::from_value).filter(|p| !p.is_null()),
+            render_data: SpriteRenderData::from_value(rd)?,
+            vertices,
+            indices,
+        })
+    }
+
+    /// triangles of m_RD: VertexData and a 16 bit index buffer since 5.6, plain arrays before
+    fn mesh(rd: &ObjectValue, asset: &Asset) -> Result<(Vec<[f32; 2]>, Vec<u32>)> {
+        let (positions, indices) = match (rd.get("m_VertexData"), rd.get("m_IndexBuffer")) {
+            (Some(vd), Some(ib)) => {
+                let vertex_data = VertexData::from_value(vd, asset.unity_version())?;
+                let positions = match vertex_data.channel(CHANNEL_POSITION) {
+                    Some(p) => p?,
+                    None => Vec::new(),
+                };
+                let indices = ib.as_bytes().unwrap_or_default()
+                    .chunks_exact(2)
+                    .map(|i| u16::from_le_bytes([i[0], i[1]]) as u32)
+                    .collect();
+                (positions, indices)
+            },
+            _ => {
+                let positions = match rd.get("vertices") {
+                    Some(ObjectValue::Array(vs)) => vs.iter()
+                        .map(|v| {
+                            let p = v.field("pos")?;
+                            Ok(vec![p.float("x")? as f32, p.float("y")? as f32])
+                        })
+                        .collect::<Result<Vec<Vec<f32>>>>()?,
+                    _ => Vec::new(),
+                };
+                let indices = match rd.get("indices") {
+                    Some(ObjectValue::Array(is)) => is.iter().filter_map(|i| i.as_u64()).map(|i| i as u32).collect(),
+                    Some(ObjectValue::Bytes(b)) => b.chunks_exact(2).map(|i| u16::from_le_bytes([i[0], i[1]]) as u32).collect(),
+                    _ => Vec::new(),
+                };
+                (positions, indices)
+            },
+        };
+        let vertices: Vec<[f32; 2]> = positions.iter()
+            .map(|p| [p.first().copied().unwrap_or(0.0), p.get(1).copied().unwrap_or(0.0)])
+            .collect();
+        match indices.iter().all(|i| (*i as usize) < vertices.len()) {
+            true => Ok((vertices, indices)),
+            false => Err("sprite index out of range".into()),
+        }
+    }
+
+    /// render data of the atlas the sprite was packed into, if that atlas is loaded
+    fn atlas_render_data<'a>(&self, bundles: &'a [AssetBundle], asset: &Asset) -> Option<(&'a Asset, SpriteRenderData)> {
+        let key = self.render_data_key.as_ref()?;
+        let atlas = pptr::resolve(bundles, asset, self.atlas.as_ref()?)?;
+        let value = atlas.asset.object_value(atlas.object).ok()?;
+        let entries = match value.get("m_RenderDataMap")? {
+            ObjectValue::Array(entries) => entries,
+            _ => return None,
+        };
+        entries.iter()
+            .filter(|e| e.get("first").and_then(render_data_key).as_ref() == Some(key))
+            .find_map(|e| SpriteRenderData::from_value(e.get("second")?).ok())
+            .map(|rd| (atlas.asset, rd))
+    }
+
+    /// true when texel centre (x, y) of the unrotated sprite falls inside its mesh
+    fn covers(&self, rd: &SpriteRenderData, x: u32, y: u32) -> bool {
+        let origin = [
+            self.rect[2] * self.pivot[0] - rd.texture_rect_offset[0],
+            self.rect[3] * self.pivot[1] - rd.texture_rect_offset[1],
+        ];
+        let p = [x as f32 + 0.5, y as f32 + 0.5];
+        let texel = |i: u32| {
+            let v = self.vertices[i as usize];
+            [v[0] * self.pixels_to_units + origin[0], v[1] * self.pixels_to_units + origin[1]]
+        };
+        self.indices.chunks_exact(3).any(|t| {
+            let (a, b, c) = (texel(t[0]), texel(t[1]), texel(t[2]));
+            let edge = |p0: [f32; 2], p1: [f32; 2]| (p1[0] - p0[0]) * (p[1] - p0[1]) - (p1[1] - p0[1]) * (p[0] - p0[0]);
+            let (e0, e1, e2) = (edge(a, b), edge(b, c), edge(c, a));
+            (e0 >= 0.0 && e1 >= 0.0 && e2 >= 0.0) || (e0 <= 0.0 && e1 <= 0.0 && e2 <= 0.0)
+        })
+    }
+}
+
+/// decoded textures shared by the sprites of an atlas, keyed by file name and path id
+type TextureCache = HashMap<(String, i64), Image>;
+
+/// level 0 of a Texture2D, bottom-left origin
+fn load_texture<'a>(cache: &'a mut TextureCache, texture: ObjectRef) -> Result<&'a Image> {
+    let key = (texture.asset.name().to_string(), texture.object.path_id);
+    if !cache.contains_key(&key) {
+        let t = Texture2D::from_value(&texture.asset.object_value(texture.object)?)?;
+        let image = t.decode_level_raw(texture.bundle, texture.asset.unity_version(), 0)?;
+        cache.insert(key.clone(), image);
+    }
+    Ok(&cache[&key])
+}
+
+/// crops the texels covering `r` from a bottom-left origin image
+fn crop(image: &Image, r: [f32; 4]) -> Result<Image> {
+    let x0 = (r[0].floor().max(0.0) as u32).min(image.width);
+    let y0 = (r[1].floor().max(0.0) as u32).min(image.height);
+    let x1 = ((r[0] + r[2]).ceil().max(0.0) as u32).min(image.width);
+    let y1 = ((r[1] + r[3]).ceil().max(0.0) as u32).min(image.height);
+    match x1 > x0 && y1 > y0 {
+        true => Ok(image.crop(x0, y0, x1 - x0, y1 - y0)),
+        false => Err(format!("sprite rect {:?} outside of the {}x{} texture", r, image.width, image.height).into()),
+    }
+}
+
+/// cuts a sprite out of its texture, top-left origin
+///
+/// The packing rotation is undone, and with `mask` texels outside the sprite
+/// mesh of tightly packed sprites are cleared so their neighbours do not bleed in.
+pub fn extract(bundles: &[AssetBundle], asset: &Asset, sprite: &Sprite, mask: bool, cache: &mut TextureCache) -> Result<Image> {
+    let atlas = sprite.atlas_render_data(bundles, asset);
+    let (from, rd) = match &atlas {
+        Some((atlas_asset, rd)) => (*atlas_asset, rd),
+        None => (asset, &sprite.render_data),
+    };
+    let texture = match (pptr::resolve(bundles, from, &rd.texture), sprite.atlas) {
+        (Some(t), _) => t,
+        (None, Some(a)) if atlas.is_none() => {
+            return Err(format!("sprite atlas {:?} not found, load the bundle holding it", a).into());
+        },
+        (None, _) => return Err(format!("texture {:?} not found, load the bundle holding it", rd.texture).into()),
+    };
+    let mut image = crop(load_texture(cache, texture)?, rd.texture_rect)?;
+    if let Some(alpha) = rd.alpha_texture.and_then(|p| pptr::resolve(bundles, from, &p)) {
+        image.apply_alpha(&crop(load_texture(cache, alpha)?, rd.texture_rect)?);
+    }
+    if rd.packed {
+        image = match rd.rotation {
+            PackingRotation::FlipHorizontal => {
+                image.flip_horizontal();
+                image
+            },
+            PackingRotation::FlipVertical => {
+                image.flip_vertical();
+                image
+            },
+            PackingRotation::Rotate180 => {
+                image.flip_horizontal();
+                image.flip_vertical();
+                image
+            },
+            PackingRotation::Rotate90 => image.rotate_ccw(),
+            PackingRotation::None => image,
+        };
+    }
+    if mask && rd.tight && !sprite.indices.is_empty() {
+        image.mask(|x, y| sprite.covers(rd, x, y));
+    }
+    image.flip_vertical();
+    Ok(image)
+}
+
+/// writes every Sprite of the first bundle into `dst` as PNG
+///
+/// The other bundles are only searched for the atlases and textures the
+/// sprites point to.
+pub fn export(bundles: &[AssetBundle], dst: &Path, mask: bool) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut cache = TextureCache::new();
+    let mut count = 0;
+    let bundle = bundles.first().ok_or("no bundle")?;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(Sprite::CLASS_ID) {
+                continue;
+            }
+            let sprite = match asset.object_value(obj).and_then(|v| Sprite::from_value(&v, asset)) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            match extract(bundles, asset, &sprite, mask, &mut cache) {
+                Ok(image) => {
+                    let path = export::unique_path(dst, &sprite.name, image.extension());
+                    image.save(&path)?;
+                    info!("{:?}", path);
+                    count += 1;
+                },
+                Err(e) => warn!("{}: {}", sprite.name, e),
+            }
+        }
+    }
+    Ok(count)
+}
+
+fn rect(v: &ObjectValue) -> Result<[f32; 4]> {
+    Ok([v.float("x")? as f32, v.float("y")? as f32, v.float("width")? as f32, v.float("height")? as f32])
+}
+
+fn vector2(v: &ObjectValue) -> Result<[f32; 2]> {
+    Ok([v.float("x")? as f32, v.float("y")? as f32])
+}
+
+/// m_RenderDataKey, a pair of GUID and local id
+fn render_data_key(v: &ObjectValue) -> Option<(Vec<u64>, i64)> {
+    let guid = match v.get("first")? {
+        ObjectValue::Struct(fields) => fields.iter().filter_map(|(_, d)| d.as_u64()).collect(),
+        _ => return None,
+    };
+    Some((guid, v.get("second")?.as_i64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Pixels;
+
+    fn texels(image: &Image) -> Vec<u8> {
+        match &image.pixels {
+            Pixels::Rgba8(p) => p.iter().step_by(4).copied().collect(),
+            Pixels::RgbaF32(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn packing_rotation() {
+        assert_eq!(PackingRotation::new(SETTINGS_PACKED | 4 << 2), PackingRotation::Rotate90);
+        assert_eq!(PackingRotation::new(3 << 2), PackingRotation::Rotate180);
+        assert_eq!(PackingRotation::new(9 << 2), PackingRotation::None);
+
+        // a 2x1 strip packed a quarter turn clockwise stands upright again
+        let image = Image::rgba8(2, 1, vec![1, 0, 0, 0, 2, 0, 0, 0]).rotate_ccw();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(texels(&image), [2, 1]);
+    }
+
+    #[test]
+    fn crop_to_texture() {
+        let image = Image::rgba8(4, 4, (0..16).flat_map(|i| [i, 0, 0, 0]).collect());
+        let cropped = crop(&image, [2.5, 3.0, 4.0, 2.0]).unwrap();
+        assert_eq!((cropped.width, cropped.height), (2, 1));
+        assert_eq!(texels(&cropped), [14, 15]);
+        assert!(crop(&image, [4.0, 0.0, 1.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn tight_mesh_mask() {
+        let rd = SpriteRenderData{
+            texture: PPtr{ file_id: 0, path_id: 1 },
+            alpha_texture: None,
+            texture_rect: [0.0, 0.0, 4.0, 4.0],
+            texture_rect_offset: [0.0, 0.0],
+            packed: true,
+            tight: true,
+            rotation: PackingRotation::None,
+        };
+        let sprite = Sprite{
+            name: String::from("s"),
+            rect: [0.0, 0.0, 4.0, 4.0],
+            pivot: [0.5, 0.5],
+            pixels_to_units: 1.0,
+            render_data_key: None,
+            atlas: None,
+            render_data: rd.clone(),
+            vertices: vec![[-2.0, -2.0], [2.0, -2.0], [-2.0, 2.0]],
+            indices: vec![0, 1, 2],
+        };
+        let mut image = Image::rgba8(4, 4, vec![255; 64]);
+        image.mask(|x, y| sprite.covers(&rd, x, y));
+        let kept: Vec<bool> = texels(&image).iter().map(|t| *t != 0).collect();
+        for (i, k) in kept.iter().enumerate() {
+            assert_eq!(*k, i % 4 + i / 4 <= 3, "texel {}", i);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Serialize, Deserialize};
+
+use crate::asset_bundle::AssetBundle;
+use crate::object_value::ObjectValue;
+use crate::Result;
+
+/// payload stored outside of the serialized file (m_StreamData, m_Resource)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StreamingInfo {
+    pub offset: u64,    // offset in the resource file
+    pub size: u64,      // payload size
+    pub path: String,   // "archive:/CAB-xxxx/CAB-xxxx.resS"
+}
+
+impl StreamingInfo {
+    /// reads a StreamingInfo or StreamedResource value; None when nothing is streamed
+    pub fn from_value(v: &ObjectValue) -> Option<StreamingInfo> {
+        let path = v.get("path").or_else(|| v.get("m_Source"))?.as_str()?;
+        let offset = v.get("offset").or_else(|| v.get("m_Offset"))?.as_u64()?;
+        let size = v.get("size").or_else(|| v.get("m_Size"))?.as_u64()?;
+        match path.is_empty() || size == 0 {
+            true => None,
+            false => Some(StreamingInfo{ offset, size, path: path.to_string() }),
+        }
+    }
+
+    /// resource node name ("CAB-xxxx.resS")
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// streamed bytes, looked up among the bundle's resource nodes
+    pub fn load<'a>(&self, bundle: &'a AssetBundle) -> Result<&'a [u8]> {
+        let data = bundle.resource(self.file_name())
+            .ok_or_else(|| format!("resource {} not found", self.path))?
+            .data()?;
+        self.offset.checked_add(self.size)
+            .filter(|end| *end <= data.len() as u64)
+            .map(|end| &data[self.offset as usize..end as usize])
+            .ok_or_else(|| format!("{} is too small for {}..+{}", self.path, self.offset, self.size).into())
+    }
+}
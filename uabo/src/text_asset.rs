@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::container_info;
+use crate::export;
+use crate::object_value::ObjectValue;
+use crate::Result;
+
+const TEXT_ASSET_CLASS_ID: i32 = 49;
+const FONT_CLASS_ID: i32 = 128;
+
+/// file extension of font data by its magic
+fn font_extension(data: &[u8]) -> &'static str {
+    match data.get(0..4) {
+        Some(b"OTTO") => "otf",
+        Some(b"ttcf") => "ttc",
+        Some(b"wOFF") => "woff",
+        Some(b"wOF2") => "woff2",
+        _ => "ttf",
+    }
+}
+
+/// row of text_assets.json
+#[derive(Serialize)]
+struct PayloadSummary {
+    name: String,
+    class: &'static str,
+    path_id: i64,
+    container: Option<String>,
+    size: usize,
+    file: String,
+}
+
+/// writes the bytes of every TextAsset (`m_Script`) and Font (`m_FontData`) of `bundle` into `dst`,
+/// at their container path when the bundle lists one, with `text_assets.json` listing them
+pub fn export(bundle: &AssetBundle, dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let containers = container_info::container_paths(bundle);
+    let mut summaries = Vec::new();
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            let (class, field) = match asset.class_id(obj) {
+                Some(TEXT_ASSET_CLASS_ID) => ("TextAsset", "m_Script"),
+                Some(FONT_CLASS_ID) => ("Font", "m_FontData"),
+                _ => continue,
+            };
+            let v = match asset.object_value(obj) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let name = v.get("m_Name").and_then(|n| n.as_str()).unwrap_or_default().to_string();
+            let data = v.get(field).and_then(ObjectValue::as_bytes).unwrap_or_default();
+            if class == "Font" && data.is_empty() {
+                // dynamic fonts referencing OS fonts carry no data
+                info!("font {} has no embedded data", name);
+                continue;
+            }
+            let container = containers.get(&(asset.name().to_string(), obj.path_id)).cloned();
+            let path = match &container {
+                Some(c) => export::container_path(dst, c),
+                None => {
+                    let ext = match class {
+                        "Font" => font_extension(data),
+                        _ => match std::str::from_utf8(data) {
+                            Ok(_) => "txt",
+                            Err(_) => "bytes",
+                        },
+                    };
+                    export::unique_path(dst, &name, ext)
+                },
+            };
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, data)?;
+            summaries.push(PayloadSummary{
+                name,
+                class,
+                path_id: obj.path_id,
+                container,
+                size: data.len(),
+                file: path.strip_prefix(dst).unwrap_or(&path).to_string_lossy().replace('\\', "/"),
+            });
+        }
+    }
+    fs::write(dst.join("text_assets.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(summaries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, Node, Writer, ALIGN};
+
+    /// class with a string m_Name and a byte payload `field`
+    fn payload_type(class: &'static str, field: &'static str, field_type: &'static str) -> Vec<Node> {
+        let mut nodes = vec![(0, class, "Base", 0)];
+        for (name, type_str) in [("m_Name", "string"), (field, field_type)] {
+            nodes.extend([(1, type_str, name, 0), (2, "Array", "Array", ALIGN), (3, "int", "size", 0), (3, "char", "data", 0)]);
+        }
+        nodes
+    }
+
+    fn payload(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut w = Writer::default().string(name).int(data.len() as i32);
+        w.0.extend(data);
+        w.align().0
+    }
+
+    #[test]
+    fn export_payloads() {
+        let classes = [
+            (TEXT_ASSET_CLASS_ID, payload_type("TextAsset", "m_Script", "string")),
+            (FONT_CLASS_ID, payload_type("Font", "m_FontData", "vector")),
+        ];
+        let objects = [
+            (1, 0, payload("notes", b"hello")),
+            (2, 0, payload("blob", &[0xff, 0xfe])),
+            (3, 1, payload("sans", b"OTTO\0\0")),
+            (4, 1, payload("system", &[])),
+        ];
+        let bundle = fixture::bundle(vec![("level0", fixture::serialized_file(&classes, &objects))]);
+        let dst = std::env::temp_dir().join(format!("uabo-text-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dst);
+
+        assert_eq!(export(&bundle, &dst).unwrap(), 3);
+        assert_eq!(fs::read(dst.join("notes.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(dst.join("blob.bytes")).unwrap(), [0xff, 0xfe]);
+        assert_eq!(fs::read(dst.join("sans.otf")).unwrap(), b"OTTO\0\0");
+        assert!(!dst.join("system.ttf").exists());
+        let summary = fs::read_to_string(dst.join("text_assets.json")).unwrap();
+        assert!(summary.contains("\"class\": \"Font\""));
+        fs::remove_dir_all(&dst).unwrap();
+    }
+}
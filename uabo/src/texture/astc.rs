@@ -0,0 +1,849 @@
+use std::convert::TryInto;
+
+use super::{decode_blocks, half_to_f32};
+use crate::image::Image;
+
+/// texel value used for blocks the decoder rejects
+const ERROR_COLOR: [u16; 4] = [0xffff, 0, 0xffff, 0xffff];
+
+/// ASTC with `block_width` x `block_height` footprint, LDR output
+pub fn decode_ldr(data: &[u8], width: u32, height: u32, block_width: u32, block_height: u32, srgb: bool) -> Image {
+    let layout = (block_width, block_height, 16);
+    Image::rgba8(width, height, decode_blocks(data, width, height, layout, |b, out| {
+        for (i, texel) in decode_block(b, block_width, block_height, srgb).iter().enumerate() {
+            match texel {
+                Texel::Ldr(c) => {
+                    for ch in 0..4 {
+                        out[i * 4 + ch] = (c[ch] >> 8) as u8;
+                    }
+                },
+                Texel::Hdr(..) => out[i * 4 .. i * 4 + 4].copy_from_slice(&[255, 0, 255, 255]),
+            }
+        }
+    }))
+}
+
+/// ASTC with HDR endpoints allowed, float output
+pub fn decode_hdr(data: &[u8], width: u32, height: u32, block_width: u32, block_height: u32) -> Image {
+    let layout = (block_width, block_height, 16);
+    Image::rgba_f32(width, height, decode_blocks(data, width, height, layout, |b, out| {
+        for (i, texel) in decode_block(b, block_width, block_height, false).iter().enumerate() {
+            let c = match texel {
+                Texel::Ldr(c) => c.map(|v| v as f32 / 65535.0),
+                Texel::Hdr(c, hdr) => {
+                    let mut f = [0.0; 4];
+                    for ch in 0..4 {
+                        f[ch] = match hdr[ch] {
+                            true => half_to_f32(c[ch]),
+                            false => c[ch] as f32 / 65535.0,
+                        };
+                    }
+                    f
+                },
+            };
+            out[i * 4 .. i * 4 + 4].copy_from_slice(&c);
+        }
+    }))
+}
+
+/// decoded texel: UNORM16 channels, or FP16 channels where flagged
+#[derive(Clone, Copy)]
+enum Texel {
+    Ldr([u16; 4]),
+    Hdr([u16; 4], [bool; 4]),
+}
+
+fn decode_block(b: &[u8], bw: u32, bh: u32, srgb: bool) -> Vec<Texel> {
+    let count = (bw * bh) as usize;
+    let bits = u128::from_le_bytes(b.try_into().unwrap());
+    match Block::new(bits, bw, bh).and_then(|block| block.texels(bits, srgb)) {
+        Some(texels) => texels,
+        None => vec![Texel::Ldr(ERROR_COLOR); count],
+    }
+}
+
+fn field(v: u128, start: u32, count: u32) -> u32 {
+    match count == 0 || start >= 128 {
+        true => 0,
+        false => (v >> start) as u32 & (u32::MAX >> (32 - count)),
+    }
+}
+
+/// (levels, trits, quints, bits) of the integer sequence encodings
+const ISE_RANGES: [(u32, u32, u32, u32); 21] = [
+    (2, 0, 0, 1), (3, 1, 0, 0), (4, 0, 0, 2), (5, 0, 1, 0), (6, 1, 0, 1), (8, 0, 0, 3),
+    (10, 0, 1, 1), (12, 1, 0, 2), (16, 0, 0, 4), (20, 0, 1, 2), (24, 1, 0, 3), (32, 0, 0, 5),
+    (40, 0, 1, 3), (48, 1, 0, 4), (64, 0, 0, 6), (80, 0, 1, 4), (96, 1, 0, 5), (128, 0, 0, 7),
+    (160, 0, 1, 5), (192, 1, 0, 6), (256, 0, 0, 8),
+];
+
+/// weight ranges selected by the block mode
+const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+
+fn ise_range(levels: u32) -> (u32, u32, u32) {
+    let (_, trits, quints, bits) = ISE_RANGES.iter().find(|r| r.0 == levels).unwrap();
+    (*trits, *quints, *bits)
+}
+
+fn ise_bit_count(count: u32, levels: u32) -> u32 {
+    let (trits, quints, bits) = ise_range(levels);
+    count * bits + match (trits, quints) {
+        (1, _) => (count * 8).div_ceil(5),
+        (_, 1) => (count * 7).div_ceil(3),
+        _ => 0,
+    }
+}
+
+/// integer sequence decoding; yields (low bits, trit or quint) pairs
+fn decode_ise(v: u128, start: u32, count: u32, levels: u32) -> Vec<(u32, u32)> {
+    let (trits, quints, bits) = ise_range(levels);
+    let mut out = Vec::with_capacity(count as usize + 4);
+    let mut pos = start;
+    let mut take = |n: u32| {
+        let f = field(v, pos, n);
+        pos += n;
+        f
+    };
+    if trits == 1 {
+        while (out.len() as u32) < count {
+            let mut m = [0u32; 5];
+            let mut t = 0;
+            for (i, tb) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].iter().enumerate() {
+                m[i] = take(bits);
+                t |= take(tb.1) << tb.0;
+            }
+            for (i, trit) in decode_trits(t).iter().enumerate() {
+                out.push((m[i], *trit));
+            }
+        }
+    } else if quints == 1 {
+        while (out.len() as u32) < count {
+            let mut m = [0u32; 3];
+            let mut q = 0;
+            for (i, qb) in [(0, 3), (3, 2), (5, 2)].iter().enumerate() {
+                m[i] = take(bits);
+                q |= take(qb.1) << qb.0;
+            }
+            for (i, quint) in decode_quints(q).iter().enumerate() {
+                out.push((m[i], *quint));
+            }
+        }
+    } else {
+        for _ in 0..count {
+            out.push((take(bits), 0));
+        }
+    }
+    out.truncate(count as usize);
+    out
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |v: u32, i: u32| v >> i & 1;
+    let (c, t3, t4) = match t >> 2 & 7 {
+        7 => ((t >> 5 & 7) << 2 | (t & 3), 2, 2),
+        _ => {
+            let c = t & 0x1f;
+            match t >> 5 & 3 {
+                3 => (c, bit(t, 7), 2),
+                _ => (c, t >> 5 & 3, bit(t, 7)),
+            }
+        },
+    };
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1), bit(c, 4), 2)
+    } else if c >> 2 & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1), c >> 2 & 3, bit(c, 4))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |v: u32, i: u32| v >> i & 1;
+    if q >> 1 & 3 == 3 && q >> 5 & 3 == 0 {
+        let q0 = bit(q, 0);
+        let q2 = q0 << 2 | (bit(q, 4) & !q0 & 1) << 1 | (bit(q, 3) & !q0 & 1);
+        return [4, 4, q2];
+    }
+    let (c, q2) = match q >> 1 & 3 {
+        3 => ((q >> 3 & 3) << 3 | (!(q >> 5) & 3) << 1 | bit(q, 0), 4),
+        _ => (q & 0x1f, q >> 5 & 3),
+    };
+    match c & 7 {
+        5 => [c >> 3 & 3, 4, q2],
+        _ => [c & 7, c >> 3 & 3, q2],
+    }
+}
+
+/// ISE color value to 0..255
+fn unquantize_color((m, d): (u32, u32), levels: u32) -> u32 {
+    let (trits, quints, bits) = ise_range(levels);
+    if trits == 0 && quints == 0 {
+        return replicate(m, bits, 8);
+    }
+    let a = (m & 1) * 0x1ff;
+    let (b, c) = match (trits, bits) {
+        (1, 1) => (0, 204),
+        (1, 2) => { let b = m >> 1 & 1; (b << 8 | b << 4 | b << 2 | b << 1, 93) },
+        (1, 3) => { let b = m >> 1 & 3; (b << 7 | b << 2 | b, 44) },
+        (1, 4) => { let b = m >> 1 & 7; (b << 6 | b, 22) },
+        (1, 5) => { let b = m >> 1 & 0xf; (b << 5 | b >> 2, 11) },
+        (1, _) => { let b = m >> 1 & 0x1f; (b << 4 | b >> 4, 5) },
+        (_, 1) => (0, 113),
+        (_, 2) => { let b = m >> 1 & 1; (b << 8 | b << 3 | b << 2, 54) },
+        (_, 3) => { let b = m >> 1 & 3; (b << 7 | b << 1 | b >> 1, 26) },
+        (_, 4) => { let b = m >> 1 & 7; (b << 6 | b >> 1, 13) },
+        _ => { let b = m >> 1 & 0xf; (b << 5 | b >> 3, 6) },
+    };
+    let t = (d * c + b) ^ a;
+    (a & 0x80) | (t >> 2)
+}
+
+/// ISE weight value to 0..64
+fn unquantize_weight((m, d): (u32, u32), levels: u32) -> u32 {
+    let (trits, quints, bits) = ise_range(levels);
+    let w = match (trits, quints, bits) {
+        (0, 0, _) => replicate(m, bits, 6),
+        // pure trit and quint ranges
+        (1, _, 0) => [0, 32, 63][d as usize],
+        (_, 1, 0) => [0, 16, 32, 47, 63][d as usize],
+        _ => {
+            let a = (m & 1) * 0x7f;
+            let (b, c) = match (trits, bits) {
+                (1, 1) => (0, 50),
+                (1, 2) => { let b = m >> 1 & 1; (b << 6 | b << 2 | b, 23) },
+                (1, _) => { let b = m >> 1 & 3; (b << 5 | b, 11) },
+                (_, 1) => (0, 28),
+                _ => { let b = m >> 1 & 1; (b << 6 | b << 1, 13) },
+            };
+            (a & 0x20) | ((d * c + b) ^ a) >> 2
+        },
+    };
+    match w > 32 {
+        true => w + 1,
+        false => w,
+    }
+}
+
+/// repeats the `bits` wide value to fill `to` bits
+fn replicate(v: u32, bits: u32, to: u32) -> u32 {
+    let mut out = 0;
+    let mut filled = 0;
+    while filled < to {
+        out = out << bits | v;
+        filled += bits;
+    }
+    out >> (filled - to)
+}
+
+/// block configuration from the header bits
+struct Block {
+    bw: u32,
+    bh: u32,
+    void_extent: bool,
+    grid_width: u32,
+    grid_height: u32,
+    dual_plane: bool,
+    weight_levels: u32,
+    partitions: u32,
+}
+
+impl Block {
+    fn new(v: u128, bw: u32, bh: u32) -> Option<Block> {
+        let mode = field(v, 0, 11);
+        if mode & 0x1ff == 0x1fc {
+            return Some(Block{ bw, bh, void_extent: true, grid_width: 0, grid_height: 0, dual_plane: false, weight_levels: 0, partitions: 0 });
+        }
+        let a = mode >> 5 & 3;
+        let (mut high, mut dual) = (mode >> 9 & 1, mode >> 10 & 1);
+        let (w, h, range) = match mode & 3 {
+            0 => {
+                let range = (mode >> 4 & 1) | (mode >> 2 & 3) << 1;
+                if mode >> 2 & 3 == 0 {
+                    return None;
+                }
+                let b = mode >> 9 & 3;
+                let (w, h) = match mode >> 7 & 3 {
+                    0 => (12, a + 2),
+                    1 => (a + 2, 12),
+                    2 => {
+                        high = 0;
+                        dual = 0;
+                        (a + 6, b + 6)
+                    },
+                    _ => match a {
+                        0 => (6, 10),
+                        1 => (10, 6),
+                        _ => return None,
+                    },
+                };
+                (w, h, range)
+            },
+            low => {
+                let range = (mode >> 4 & 1) | low << 1;
+                let b = mode >> 7 & 3;
+                let (w, h) = match mode >> 2 & 3 {
+                    0 => (b + 4, a + 2),
+                    1 => (b + 8, a + 2),
+                    2 => (a + 2, b + 8),
+                    _ => match mode >> 8 & 1 {
+                        1 => ((b & 1) + 2, a + 2),
+                        _ => (a + 2, (b & 1) + 6),
+                    },
+                };
+                (w, h, range)
+            },
+        };
+        let weight_levels = WEIGHT_LEVELS[(range - 2 + 6 * high) as usize];
+        let partitions = field(v, 11, 2) + 1;
+        let weight_count = w * h * (dual + 1);
+        let weight_bits = ise_bit_count(weight_count, weight_levels);
+        let valid = w <= bw && h <= bh
+            && weight_count <= 64
+            && (24..=96).contains(&weight_bits)
+            && !(dual == 1 && partitions == 4);
+        match valid {
+            true => Some(Block{
+                bw,
+                bh,
+                void_extent: false,
+                grid_width: w,
+                grid_height: h,
+                dual_plane: dual == 1,
+                weight_levels,
+                partitions,
+            }),
+            false => None,
+        }
+    }
+
+    fn texels(&self, v: u128, srgb: bool) -> Option<Vec<Texel>> {
+        let count = (self.bw * self.bh) as usize;
+        if self.void_extent {
+            let c = [0, 1, 2, 3].map(|i| field(v, 64 + i * 16, 16) as u16);
+            let texel = match v >> 9 & 1 {
+                1 => Texel::Hdr(c, [true; 4]),
+                _ => Texel::Ldr(c),
+            };
+            return Some(vec![texel; count]);
+        }
+
+        let weight_count = self.grid_width * self.grid_height * (self.dual_plane as u32 + 1);
+        let weight_bits = ise_bit_count(weight_count, self.weight_levels);
+        let mut below_weights = 128 - weight_bits;
+
+        // color endpoint modes
+        let mut modes = [0u32; 4];
+        let config_start = match self.partitions {
+            1 => {
+                modes[0] = field(v, 13, 4);
+                17
+            },
+            n => {
+                let selector = field(v, 23, 2);
+                match selector {
+                    0 => {
+                        let mode = field(v, 25, 4);
+                        modes.iter_mut().for_each(|m| *m = mode);
+                    },
+                    _ => {
+                        let extra = 3 * n - 4;
+                        below_weights -= extra;
+                        let mut encoded = field(v, 25, 4) | field(v, below_weights, extra) << 4;
+                        let mut classes = [0u32; 4];
+                        for c in classes.iter_mut().take(n as usize) {
+                            *c = encoded & 1;
+                            encoded >>= 1;
+                        }
+                        for (i, m) in modes.iter_mut().take(n as usize).enumerate() {
+                            *m = (selector - 1 + classes[i]) << 2 | (encoded & 3);
+                            encoded >>= 2;
+                        }
+                    },
+                }
+                29
+            },
+        };
+        let plane2_component = match self.dual_plane {
+            true => {
+                below_weights -= 2;
+                Some(field(v, below_weights, 2) as usize)
+            },
+            false => None,
+        };
+
+        // color endpoints
+        let partitions = self.partitions as usize;
+        let value_count: u32 = modes[..partitions].iter().map(|m| (m >> 2) * 2 + 2).sum();
+        if value_count > 18 || below_weights <= config_start {
+            return None;
+        }
+        let color_bits = below_weights - config_start;
+        let color_levels = ISE_RANGES.iter().rev()
+            .map(|r| r.0)
+            .take_while(|l| *l >= 6)
+            .find(|l| ise_bit_count(value_count, *l) <= color_bits)?;
+        let values: Vec<u32> = decode_ise(v, config_start, value_count, color_levels).into_iter()
+            .map(|c| unquantize_color(c, color_levels))
+            .collect();
+        let mut endpoints = Vec::with_capacity(partitions);
+        let mut offset = 0;
+        for mode in &modes[..partitions] {
+            let n = ((mode >> 2) * 2 + 2) as usize;
+            endpoints.push(Endpoints::new(*mode, &values[offset .. offset + n], srgb));
+            offset += n;
+        }
+
+        // weights, stored bit reversed from the top of the block
+        let weights: Vec<u32> = decode_ise(v.reverse_bits(), 0, weight_count, self.weight_levels).into_iter()
+            .map(|w| unquantize_weight(w, self.weight_levels))
+            .collect();
+        let planes = self.dual_plane as usize + 1;
+        let infilled: Vec<Vec<u32>> = (0..planes).map(|p| self.infill(&weights, planes, p)).collect();
+
+        let seed = field(v, 13, 10);
+        let small = count < 31;
+        let mut texels = Vec::with_capacity(count);
+        for y in 0..self.bh {
+            for x in 0..self.bw {
+                let i = (y * self.bw + x) as usize;
+                let part = match partitions {
+                    1 => 0,
+                    _ => select_partition(seed, x, y, partitions as u32, small),
+                };
+                let e = &endpoints[part];
+                let mut c = [0u16; 4];
+                for (ch, c) in c.iter_mut().enumerate() {
+                    let w = match plane2_component == Some(ch) {
+                        true => infilled[1][i],
+                        false => infilled[0][i],
+                    };
+                    let (c0, c1) = (e.low[ch] as u32, e.high[ch] as u32);
+                    *c = ((c0 * (64 - w) + c1 * w + 32) >> 6) as u16;
+                }
+                texels.push(match e.hdr.iter().any(|h| *h) {
+                    true => Texel::Hdr(c, e.hdr).lns_to_half(),
+                    false => Texel::Ldr(c),
+                });
+            }
+        }
+        Some(texels)
+    }
+
+    /// bilinear infill of the weight grid onto the block texels
+    fn infill(&self, weights: &[u32], planes: usize, plane: usize) -> Vec<u32> {
+        let (gw, gh) = (self.grid_width, self.grid_height);
+        let grid = |x: u32, y: u32| -> u32 {
+            match x < gw && y < gh {
+                true => weights[((y * gw + x) as usize) * planes + plane],
+                false => 0,
+            }
+        };
+        let ds = (1024 + self.bw / 2) / (self.bw - 1).max(1);
+        let dt = (1024 + self.bh / 2) / (self.bh - 1).max(1);
+        let mut out = Vec::with_capacity((self.bw * self.bh) as usize);
+        for t in 0..self.bh {
+            for s in 0..self.bw {
+                let gs = (ds * s * (gw - 1) + 32) >> 6;
+                let gt = (dt * t * (gh - 1) + 32) >> 6;
+                let (js, fs, jt, ft) = (gs >> 4, gs & 0xf, gt >> 4, gt & 0xf);
+                let w11 = (fs * ft + 8) >> 4;
+                let w10 = ft - w11;
+                let w01 = fs - w11;
+                let w00 = 16 + w11 - fs - ft;
+                let p = grid(js, jt) * w00 + grid(js + 1, jt) * w01
+                    + grid(js, jt + 1) * w10 + grid(js + 1, jt + 1) * w11;
+                out.push((p + 8) >> 4);
+            }
+        }
+        out
+    }
+}
+
+impl Texel {
+    /// converts the logarithmic HDR channels of an interpolated texel to FP16
+    fn lns_to_half(self) -> Texel {
+        match self {
+            Texel::Hdr(c, hdr) => {
+                let mut out = c;
+                for ch in 0..4 {
+                    if hdr[ch] {
+                        let (e, m) = (c[ch] as u32 >> 11, c[ch] as u32 & 0x7ff);
+                        let mt = match m {
+                            m if m < 512 => 3 * m,
+                            m if m >= 1536 => 5 * m - 2048,
+                            m => 4 * m - 512,
+                        };
+                        out[ch] = ((e << 10) + (mt >> 3)).min(0x7bff) as u16;
+                    }
+                }
+                Texel::Hdr(out, hdr)
+            },
+            ldr => ldr,
+        }
+    }
+}
+
+/// partition of texel (x, y) for the given partition seed
+fn select_partition(seed: u32, x: u32, y: u32, count: u32, small: bool) -> usize {
+    let (x, y) = match small {
+        true => (x << 1, y << 1),
+        false => (x, y),
+    };
+    let seed = seed + (count - 1) * 1024;
+    let rnum = hash52(seed);
+    let mut s = [
+        rnum & 0xf, rnum >> 4 & 0xf, rnum >> 8 & 0xf, rnum >> 12 & 0xf,
+        rnum >> 16 & 0xf, rnum >> 20 & 0xf, rnum >> 24 & 0xf, rnum >> 28 & 0xf,
+        rnum >> 18 & 0xf, rnum >> 22 & 0xf, rnum >> 26 & 0xf, rnum.rotate_left(2) & 0xf,
+    ];
+    for v in s.iter_mut() {
+        *v *= *v;
+    }
+    let (sh1, sh2) = match seed & 1 {
+        1 => (if seed & 2 != 0 { 4 } else { 5 }, if count == 3 { 6 } else { 5 }),
+        _ => (if count == 3 { 6 } else { 5 }, if seed & 2 != 0 { 4 } else { 5 }),
+    };
+    let sh3 = match seed & 0x10 {
+        0 => sh2,
+        _ => sh1,
+    };
+    for (i, v) in s.iter_mut().enumerate() {
+        *v >>= match i {
+            8..=11 => sh3,
+            i if i % 2 == 0 => sh1,
+            _ => sh2,
+        };
+    }
+    let a = (s[0].wrapping_mul(x).wrapping_add(s[1].wrapping_mul(y)).wrapping_add(rnum >> 14)) & 0x3f;
+    let b = (s[2].wrapping_mul(x).wrapping_add(s[3].wrapping_mul(y)).wrapping_add(rnum >> 10)) & 0x3f;
+    let c = match count < 3 {
+        true => 0,
+        false => (s[4].wrapping_mul(x).wrapping_add(s[5].wrapping_mul(y)).wrapping_add(rnum >> 6)) & 0x3f,
+    };
+    let d = match count < 4 {
+        true => 0,
+        false => (s[6].wrapping_mul(x).wrapping_add(s[7].wrapping_mul(y)).wrapping_add(rnum >> 2)) & 0x3f,
+    };
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(p: u32) -> u32 {
+    let mut p = p;
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// endpoint pair of one partition, 16 bit per channel
+struct Endpoints {
+    low: [u16; 4],
+    high: [u16; 4],
+    hdr: [bool; 4],   // channel holds a logarithmic HDR value
+}
+
+impl Endpoints {
+    fn new(mode: u32, v: &[u32], srgb: bool) -> Endpoints {
+        let v: Vec<i32> = v.iter().map(|x| *x as i32).collect();
+        let ldr = |e0: [i32; 4], e1: [i32; 4]| {
+            let expand = |c: i32| {
+                let c = c.clamp(0, 255) as u16;
+                match srgb {
+                    true => c << 8 | 0x80,
+                    false => c * 257,
+                }
+            };
+            Endpoints{ low: e0.map(expand), high: e1.map(expand), hdr: [false; 4] }
+        };
+        match mode {
+            0 => ldr([v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]),
+            1 => {
+                let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+                let l1 = (l0 + (v[1] & 0x3f)).min(255);
+                ldr([l0, l0, l0, 255], [l1, l1, l1, 255])
+            },
+            4 => ldr([v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]),
+            5 => {
+                let (b0, a0) = bit_transfer_signed(v[1], v[0]);
+                let (b2, a2) = bit_transfer_signed(v[3], v[2]);
+                ldr([a0, a0, a0, a2], [a0 + b0, a0 + b0, a0 + b0, a2 + b2])
+            },
+            6 => ldr(
+                [(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, 255],
+                [v[0], v[1], v[2], 255],
+            ),
+            8 | 12 => {
+                let (a0, a1) = match mode {
+                    12 => (v[6], v[7]),
+                    _ => (255, 255),
+                };
+                match v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                    true => ldr([v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]),
+                    false => ldr(blue_contract([v[1], v[3], v[5], a1]), blue_contract([v[0], v[2], v[4], a0])),
+                }
+            },
+            9 | 13 => {
+                let (b0, a0) = bit_transfer_signed(v[1], v[0]);
+                let (b1, a1) = bit_transfer_signed(v[3], v[2]);
+                let (b2, a2) = bit_transfer_signed(v[5], v[4]);
+                let (b3, a3) = match mode {
+                    13 => bit_transfer_signed(v[7], v[6]),
+                    _ => (0, 255),
+                };
+                match b0 + b1 + b2 >= 0 {
+                    true => ldr([a0, a1, a2, a3], [a0 + b0, a1 + b1, a2 + b2, a3 + b3]),
+                    false => ldr(blue_contract([a0 + b0, a1 + b1, a2 + b2, a3 + b3]), blue_contract([a0, a1, a2, a3])),
+                }
+            },
+            10 => ldr(
+                [(v[0] * v[3]) >> 8, (v[1] * v[3]) >> 8, (v[2] * v[3]) >> 8, v[4]],
+                [v[0], v[1], v[2], v[5]],
+            ),
+            2 | 3 => {
+                let (y0, y1) = match mode {
+                    2 => hdr_luminance_large(v[0], v[1]),
+                    _ => hdr_luminance_small(v[0], v[1]),
+                };
+                hdr([y0, y0, y0], [y1, y1, y1], None)
+            },
+            7 => {
+                let (e0, e1) = hdr_rgb_scale(&v);
+                hdr(e0, e1, None)
+            },
+            11 => {
+                let (e0, e1) = hdr_rgb(&v);
+                hdr(e0, e1, None)
+            },
+            14 => {
+                let (e0, e1) = hdr_rgb(&v);
+                let mut e = hdr(e0, e1, None);
+                e.low[3] = v[6] as u16 * 257;
+                e.high[3] = v[7] as u16 * 257;
+                e.hdr[3] = false;
+                e
+            },
+            _ => {
+                let (e0, e1) = hdr_rgb(&v);
+                hdr(e0, e1, Some(hdr_alpha(v[6], v[7])))
+            },
+        }
+    }
+}
+
+/// HDR endpoints from 12 bit values; alpha defaults to 1.0
+fn hdr(e0: [i32; 3], e1: [i32; 3], alpha: Option<(i32, i32)>) -> Endpoints {
+    let (a0, a1) = alpha.unwrap_or((0x780, 0x780));
+    let widen = |e: [i32; 3], a: i32| [e[0], e[1], e[2], a].map(|c| (c.clamp(0, 0xfff) << 4) as u16);
+    Endpoints{ low: widen(e0, a0), high: widen(e1, a1), hdr: [true; 4] }
+}
+
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let a = (a >> 1) & 0x3f;
+    let a = match a & 0x20 {
+        0 => a,
+        _ => a - 0x40,
+    };
+    (a, b)
+}
+
+fn blue_contract(c: [i32; 4]) -> [i32; 4] {
+    [(c[0] + c[2]) >> 1, (c[1] + c[2]) >> 1, c[2], c[3]]
+}
+
+fn hdr_luminance_large(v0: i32, v1: i32) -> (i32, i32) {
+    match v1 >= v0 {
+        true => (v0 << 4, v1 << 4),
+        false => ((v1 << 4) + 8, (v0 << 4) - 8),
+    }
+}
+
+fn hdr_luminance_small(v0: i32, v1: i32) -> (i32, i32) {
+    let (y0, d) = match v0 & 0x80 {
+        0 => ((v1 & 0xf0) << 4 | (v0 & 0x7f) << 1, (v1 & 0x0f) << 1),
+        _ => ((v1 & 0xe0) << 4 | (v0 & 0x7f) << 2, (v1 & 0x1f) << 2),
+    };
+    (y0, (y0 + d).min(0xfff))
+}
+
+fn swap_major(c: &mut [i32; 3], major: i32) {
+    match major {
+        1 => c.swap(0, 1),
+        2 => c.swap(0, 2),
+        _ => {},
+    }
+}
+
+/// endpoint mode 7, HDR RGB base and scale
+fn hdr_rgb_scale(v: &[i32]) -> ([i32; 3], [i32; 3]) {
+    let modeval = (v[0] & 0xc0) >> 6 | (v[1] & 0x80) >> 5 | (v[2] & 0x80) >> 4;
+    let (major, mode) = if modeval & 0xc != 0xc {
+        (modeval >> 2, modeval & 3)
+    } else if modeval != 0xf {
+        (modeval & 3, 4)
+    } else {
+        (0, 5)
+    };
+    let (mut red, mut green, mut blue, mut scale) = (v[0] & 0x3f, v[1] & 0x1f, v[2] & 0x1f, v[3] & 0x1f);
+    let x = [v[1] >> 6, v[1] >> 5, v[2] >> 6, v[2] >> 5, v[3] >> 7, v[3] >> 6, v[3] >> 5].map(|b| b & 1);
+    let oh = 1 << mode;
+    if oh & 0x30 != 0 { green |= x[0] << 6; }
+    if oh & 0x3a != 0 { green |= x[1] << 5; }
+    if oh & 0x30 != 0 { blue |= x[2] << 6; }
+    if oh & 0x3a != 0 { blue |= x[3] << 5; }
+    if oh & 0x3d != 0 { scale |= x[6] << 5; }
+    if oh & 0x2d != 0 { scale |= x[5] << 6; }
+    if oh & 0x04 != 0 { scale |= x[4] << 7; }
+    if oh & 0x3b != 0 { red |= x[4] << 6; }
+    if oh & 0x04 != 0 { red |= x[3] << 6; }
+    if oh & 0x10 != 0 { red |= x[5] << 7; }
+    if oh & 0x0f != 0 { red |= x[2] << 7; }
+    if oh & 0x05 != 0 { red |= x[1] << 8; }
+    if oh & 0x0a != 0 { red |= x[0] << 8; }
+    if oh & 0x05 != 0 { red |= x[0] << 9; }
+    if oh & 0x02 != 0 { red |= x[6] << 9; }
+    if oh & 0x01 != 0 { red |= x[3] << 10; }
+    if oh & 0x02 != 0 { red |= x[5] << 10; }
+    let shift = [1, 1, 2, 3, 4, 5][mode as usize];
+    red <<= shift;
+    green <<= shift;
+    blue <<= shift;
+    scale <<= shift;
+    if mode != 5 {
+        green = red - green;
+        blue = red - blue;
+    }
+    let mut e1 = [red, green, blue];
+    swap_major(&mut e1, major);
+    let e0 = e1.map(|c| c - scale);
+    (e0, e1)
+}
+
+/// endpoint mode 11, HDR RGB direct
+fn hdr_rgb(v: &[i32]) -> ([i32; 3], [i32; 3]) {
+    let major = (v[4] & 0x80) >> 7 | (v[5] & 0x80) >> 6;
+    if major == 3 {
+        // 8 bit values placed at the top of the 12 bits
+        return (
+            [v[0] << 4, v[2] << 4, (v[4] & 0x7f) << 5],
+            [v[1] << 4, v[3] << 4, (v[5] & 0x7f) << 5],
+        );
+    }
+    let mode = (v[1] & 0x80) >> 7 | (v[2] & 0x80) >> 6 | (v[3] & 0x80) >> 5;
+    let mut a = v[0] | (v[1] & 0x40) << 2;
+    let (mut b0, mut b1) = (v[2] & 0x3f, v[3] & 0x3f);
+    let mut c = v[1] & 0x3f;
+    let (mut d0, mut d1) = (v[4] & 0x1f, v[5] & 0x1f);
+    let dbits = [7, 6, 7, 6, 5, 6, 5, 6][mode as usize];
+    let x = [v[2] >> 6, v[3] >> 6, v[4] >> 6, v[5] >> 6, v[4] >> 5, v[5] >> 5].map(|b| b & 1);
+    let oh = 1 << mode;
+    if oh & 0xa4 != 0 { a |= x[0] << 9; }
+    if oh & 0x08 != 0 { a |= x[2] << 9; }
+    if oh & 0x50 != 0 { a |= x[4] << 9; }
+    if oh & 0x50 != 0 { a |= x[5] << 10; }
+    if oh & 0xa0 != 0 { a |= x[1] << 10; }
+    if oh & 0xc0 != 0 { a |= x[2] << 11; }
+    if oh & 0x04 != 0 { c |= x[1] << 6; }
+    if oh & 0xe8 != 0 { c |= x[3] << 6; }
+    if oh & 0x20 != 0 { c |= x[2] << 7; }
+    if oh & 0x5b != 0 { b0 |= x[0] << 6; b1 |= x[1] << 6; }
+    if oh & 0x12 != 0 { b0 |= x[2] << 7; b1 |= x[3] << 7; }
+    if oh & 0xaf != 0 { d0 |= x[4] << 5; d1 |= x[5] << 5; }
+    if oh & 0x05 != 0 { d0 |= x[2] << 6; d1 |= x[3] << 6; }
+    let sign_extend = |d: i32| (d << (32 - dbits)) >> (32 - dbits);
+    let (d0, d1) = (sign_extend(d0), sign_extend(d1));
+    let shift = (mode >> 1) ^ 3;
+    let (a, b0, b1, c, d0, d1) = (a << shift, b0 << shift, b1 << shift, c << shift, d0 << shift, d1 << shift);
+    let mut e1 = [a, a - b0, a - b1];
+    let mut e0 = [a - c, a - b0 - c - d0, a - b1 - c - d1];
+    swap_major(&mut e0, major);
+    swap_major(&mut e1, major);
+    (e0, e1)
+}
+
+/// endpoint mode 15 alpha, 12 bit HDR values
+fn hdr_alpha(v6: i32, v7: i32) -> (i32, i32) {
+    let selector = (v6 >> 7 & 1) | (v7 >> 6 & 2);
+    let (mut v6, mut v7) = (v6 & 0x7f, v7 & 0x7f);
+    if selector == 3 {
+        return (v6 << 5, v7 << 5);
+    }
+    v6 |= (v7 << (selector + 1)) & 0x780;
+    v7 &= 0x3f >> selector;
+    v7 ^= 32 >> selector;
+    v7 -= 32 >> selector;
+    v6 <<= 4 - selector;
+    v7 <<= 4 - selector;
+    v7 += v6;
+    (v6, v7.clamp(0, 0xfff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Pixels;
+
+    fn texels(image: Image) -> Vec<u8> {
+        match image.pixels {
+            Pixels::Rgba8(p) => p,
+            Pixels::RgbaF32(_) => panic!("expected 8 bit texels"),
+        }
+    }
+
+    #[test]
+    fn ldr_luminance_block() {
+        // 4x4 grid of 2 bit weights, one partition of luminance endpoints 0 and 255
+        let mut v: u128 = 0x42;             // block mode
+        v |= 255 << 25;                     // CEM 0 at 13, v0 at 17, v1 at 25
+        // weights 0, 1, 2 and 3 on the first row, stored bit reversed from the top
+        for (i, w) in [0u128, 1, 2, 3].iter().enumerate() {
+            v |= (w & 1) << (127 - 2 * i);
+            v |= (w >> 1) << (126 - 2 * i);
+        }
+        let t = texels(decode_ldr(&v.to_le_bytes(), 4, 4, 4, 4, false));
+        assert_eq!(t[..16], [0, 0, 0, 255, 84, 84, 84, 255, 171, 171, 171, 255, 255, 255, 255, 255]);
+        assert!(t[16..].chunks(4).all(|t| t == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn infill_of_smaller_grid() {
+        // the same 4x4 grid stretched over a 6x6 block, where both fractions may exceed 8
+        let mut v: u128 = 0x42;
+        v |= 255 << 25;
+        for (i, w) in [0u128, 1, 2, 3].iter().enumerate() {
+            v |= (w & 1) << (127 - 2 * i);
+            v |= (w >> 1) << (126 - 2 * i);
+        }
+        let t = texels(decode_ldr(&v.to_le_bytes(), 6, 6, 6, 6, false));
+        let luminance: Vec<u8> = t.chunks(4).map(|t| t[0]).collect();
+        assert_eq!(luminance[..12], [0, 52, 100, 155, 203, 255, 0, 20, 36, 60, 76, 96]);
+        assert!(luminance[12..].iter().all(|l| *l == 0));
+    }
+
+    #[test]
+    fn void_extent_and_error_blocks() {
+        // LDR void extent of (0xffff, 0, 0x8080, 0xffff), then a reserved block mode
+        let mut block = vec![0xfc, 0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0x80, 0x80, 0xff, 0xff];
+        block.extend([0u8; 16]);
+        let t = texels(decode_ldr(&block, 8, 4, 4, 4, false));
+        assert_eq!(t[..4], [255, 0, 128, 255]);
+        assert_eq!(t[16..20], [255, 0, 255, 255]);
+    }
+}
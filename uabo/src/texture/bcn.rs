@@ -0,0 +1,515 @@
+use super::decode_blocks;
+use super::raw::{expand5, expand6};
+use crate::image::Image;
+
+/// DXT1 / BC1
+pub fn decode_bc1(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        color_block(b, out, false);
+    }))
+}
+
+/// DXT5 / BC3
+pub fn decode_bc3(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 16), |b, out| {
+        color_block(&b[8..], out, true);
+        for (i, a) in alpha_block(b).iter().enumerate() {
+            out[i * 4 + 3] = *a;
+        }
+    }))
+}
+
+/// BC4, decoded to the red channel
+pub fn decode_bc4(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        for (i, r) in alpha_block(b).iter().enumerate() {
+            out[i * 4 .. i * 4 + 4].copy_from_slice(&[*r, 0, 0, 255]);
+        }
+    }))
+}
+
+/// BC5, decoded to the red and green channels
+pub fn decode_bc5(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 16), |b, out| {
+        let (r, g) = (alpha_block(b), alpha_block(&b[8..]));
+        for i in 0..16 {
+            out[i * 4 .. i * 4 + 4].copy_from_slice(&[r[i], g[i], 0, 255]);
+        }
+    }))
+}
+
+/// BC6H (unsigned), decoded to float texels
+pub fn decode_bc6h(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba_f32(width, height, decode_blocks(data, width, height, (4, 4, 16), bc6h_block))
+}
+
+/// BC7
+pub fn decode_bc7(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 16), bc7_block))
+}
+
+fn rgb565(c: u16) -> [u8; 4] {
+    [expand5((c >> 11) as u8), expand6((c >> 5) as u8 & 0x3f), expand5(c as u8 & 0x1f), 255]
+}
+
+/// BC1 color block; BC2/BC3 always use the four color palette
+pub fn color_block(b: &[u8], out: &mut [u8], four_colors: bool) {
+    let c0 = u16::from_le_bytes([b[0], b[1]]);
+    let c1 = u16::from_le_bytes([b[2], b[3]]);
+    let (p0, p1) = (rgb565(c0), rgb565(c1));
+    let mut palette = [p0, p1, [0u8; 4], [0u8; 4]];
+    let mix = |w0: u16, w1: u16, d: u16| {
+        let mut c = [0u8, 0, 0, 255];
+        for i in 0..3 {
+            c[i] = ((p0[i] as u16 * w0 + p1[i] as u16 * w1) / d) as u8;
+        }
+        c
+    };
+    match four_colors || c0 > c1 {
+        true => {
+            palette[2] = mix(2, 1, 3);
+            palette[3] = mix(1, 2, 3);
+        },
+        false => palette[2] = mix(1, 1, 2),
+    }
+    let indices = u32::from_le_bytes([b[4], b[5], b[6], b[7]]);
+    for i in 0..16 {
+        out[i * 4 .. i * 4 + 4].copy_from_slice(&palette[(indices >> (i * 2) & 3) as usize]);
+    }
+}
+
+/// BC3 alpha / BC4 channel block
+pub fn alpha_block(b: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (b[0] as u32, b[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    match a0 > a1 {
+        true => for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        },
+        false => for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        },
+    }
+    let bits = (0..6).fold(0u64, |acc, i| acc | (b[2 + i] as u64) << (8 * i));
+    let mut out = [0u8; 16];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = palette[(bits >> (i * 3) & 7) as usize] as u8;
+    }
+    out
+}
+
+/// little endian bit stream over one 128 bit block
+struct BlockBits {
+    bits: u128,
+    pos: u32,
+}
+
+impl BlockBits {
+    fn new(b: &[u8]) -> BlockBits {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&b[..16]);
+        BlockBits{ bits: u128::from_le_bytes(bytes), pos: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let v = (self.bits >> self.pos) as u32 & ((1u64 << count) - 1) as u32;
+        self.pos += count;
+        v
+    }
+}
+
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weight(index_bits: u32, index: u32) -> u32 {
+    match index_bits {
+        2 => WEIGHTS2[index as usize],
+        3 => WEIGHTS3[index as usize],
+        _ => WEIGHTS4[index as usize],
+    }
+}
+
+/// partitions of two subset blocks, one bit per texel
+pub const PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// partitions of three subset blocks, two bits per texel
+pub const PARTITIONS3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// anchor texel of the second subset in two subset partitions
+pub const ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15,  2,  8,  2,  2,  8,  8, 15,  2,  8,  2,  2,  8,  8,  2,  2,
+    15, 15,  6,  8,  2,  8, 15, 15,  2,  8,  2,  2,  2, 15, 15,  6,
+     6,  2,  6,  8, 15, 15,  2,  2, 15, 15, 15, 15, 15,  2,  2, 15,
+];
+
+/// anchor texel of the second subset in three subset partitions
+const ANCHORS3_2: [u8; 64] = [
+     3,  3, 15, 15,  8,  3, 15, 15,  8,  8,  6,  6,  6,  5,  3,  3,
+     3,  3,  8, 15,  3,  3,  6, 10,  5,  8,  8,  6,  8,  5, 15, 15,
+     8, 15,  3,  5,  6, 10,  8, 15, 15,  3, 15,  5, 15, 15, 15, 15,
+     3, 15,  5,  5,  5,  8,  5, 10,  5, 10,  8, 13, 15, 12,  3,  3,
+];
+
+/// anchor texel of the third subset in three subset partitions
+const ANCHORS3_3: [u8; 64] = [
+    15,  8,  8,  3, 15, 15,  3,  8, 15, 15, 15, 15, 15, 15, 15,  8,
+    15,  8, 15,  3, 15,  8, 15,  8,  3, 15,  6, 10, 15, 15, 10,  8,
+    15,  3, 15, 10, 10,  8,  9, 10,  6, 15,  8, 15,  3,  6,  6,  8,
+    15,  3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,  3, 15, 15,  8,
+];
+
+/// subset of texel `i` and whether it is its subset's anchor
+fn subset(subsets: u32, partition: usize, i: usize) -> (usize, bool) {
+    match subsets {
+        2 => {
+            let s = (PARTITIONS2[partition] >> i & 1) as usize;
+            (s, i == [0, ANCHORS2[partition] as usize][s])
+        },
+        3 => {
+            let s = (PARTITIONS3[partition] >> (i * 2) & 3) as usize;
+            (s, i == [0, ANCHORS3_2[partition] as usize, ANCHORS3_3[partition] as usize][s])
+        },
+        _ => (0, i == 0),
+    }
+}
+
+/// BC7 mode parameters
+struct Bc7Mode {
+    subsets: u32,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,   // one p-bit per endpoint
+    shared_pbits: bool,     // one p-bit per subset
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+const fn bc7_mode(params: [u32; 10]) -> Bc7Mode {
+    Bc7Mode{
+        subsets: params[0],
+        partition_bits: params[1],
+        rotation_bits: params[2],
+        index_selection_bits: params[3],
+        color_bits: params[4],
+        alpha_bits: params[5],
+        endpoint_pbits: params[6] != 0,
+        shared_pbits: params[7] != 0,
+        index_bits: params[8],
+        index2_bits: params[9],
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode([3, 4, 0, 0, 4, 0, 1, 0, 3, 0]),
+    bc7_mode([2, 6, 0, 0, 6, 0, 0, 1, 3, 0]),
+    bc7_mode([3, 6, 0, 0, 5, 0, 0, 0, 2, 0]),
+    bc7_mode([2, 6, 0, 0, 7, 0, 1, 0, 2, 0]),
+    bc7_mode([1, 0, 2, 1, 5, 6, 0, 0, 2, 3]),
+    bc7_mode([1, 0, 2, 0, 7, 8, 0, 0, 2, 2]),
+    bc7_mode([1, 0, 0, 0, 7, 7, 1, 0, 4, 0]),
+    bc7_mode([2, 6, 0, 0, 5, 5, 1, 0, 2, 0]),
+];
+
+fn bc7_block(b: &[u8], out: &mut [u8]) {
+    if b[0] == 0 {
+        // reserved mode
+        out.iter_mut().for_each(|v| *v = 0);
+        return;
+    }
+    let mode_index = b[0].trailing_zeros();
+    let mode = &BC7_MODES[mode_index as usize];
+    let mut bits = BlockBits::new(b);
+    bits.read(mode_index + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoints = (mode.subsets * 2) as usize;
+    let mut colors = [[0u32; 4]; 6];
+    for c in 0..3 {
+        for e in colors.iter_mut().take(endpoints) {
+            e[c] = bits.read(mode.color_bits);
+        }
+    }
+    for e in colors.iter_mut().take(endpoints) {
+        e[3] = bits.read(mode.alpha_bits);
+    }
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let pbits: Vec<u32> = match mode.endpoint_pbits {
+            true => (0..endpoints).map(|_| bits.read(1)).collect(),
+            false => (0..mode.subsets).flat_map(|_| { let p = bits.read(1); vec![p, p] }).collect(),
+        };
+        for (e, p) in colors.iter_mut().zip(pbits) {
+            for c in e.iter_mut() {
+                *c = *c << 1 | p;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    for e in colors.iter_mut().take(endpoints) {
+        for c in e.iter_mut().take(3) {
+            *c = expand_bits(*c, color_bits);
+        }
+        e[3] = match alpha_bits {
+            0 => 255,
+            n => expand_bits(e[3], n),
+        };
+    }
+
+    let mut indices = [0u32; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        let anchor = subset(mode.subsets, partition, i).1;
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+    let mut indices2 = [0u32; 16];
+    if mode.index2_bits > 0 {
+        for (i, index) in indices2.iter_mut().enumerate() {
+            *index = bits.read(mode.index2_bits - (i == 0) as u32);
+        }
+    }
+
+    for i in 0..16 {
+        let s = subset(mode.subsets, partition, i).0;
+        let (e0, e1) = (colors[s * 2], colors[s * 2 + 1]);
+        let (color_weight, alpha_weight) = match (mode.index2_bits, index_selection) {
+            (0, _) => {
+                let w = weight(mode.index_bits, indices[i]);
+                (w, w)
+            },
+            (_, 0) => (weight(mode.index_bits, indices[i]), weight(mode.index2_bits, indices2[i])),
+            _ => (weight(mode.index2_bits, indices2[i]), weight(mode.index_bits, indices[i])),
+        };
+        let mut texel = [0u8; 4];
+        for c in 0..4 {
+            let w = match c {
+                3 => alpha_weight,
+                _ => color_weight,
+            };
+            texel[c] = (((64 - w) * e0[c] + w * e1[c] + 32) >> 6) as u8;
+        }
+        match rotation {
+            1 => texel.swap(0, 3),
+            2 => texel.swap(1, 3),
+            3 => texel.swap(2, 3),
+            _ => {},
+        }
+        out[i * 4 .. i * 4 + 4].copy_from_slice(&texel);
+    }
+}
+
+/// widens an `n` bit value to 8 bits by replicating its high bits
+fn expand_bits(v: u32, n: u32) -> u32 {
+    let v = v << (8 - n);
+    v | v >> n
+}
+
+/// BC6H mode layouts in the notation of the D3D11 specification;
+/// `xx[a:b]` stores bits from b towards a.
+const BC6H_MODES: [(u32, u32, [u32; 3], bool, &str); 14] = [
+    (0x00, 10, [5, 5, 5], true, "m[1:0],gy[4],by[4],bz[4],rw[9:0],gw[9:0],bw[9:0],rx[4:0],gz[4],gy[3:0],gx[4:0],bz[0],gz[3:0],bx[4:0],bz[1],by[3:0],ry[4:0],bz[2],rz[4:0],bz[3],d[4:0]"),
+    (0x01, 7, [6, 6, 6], true, "m[1:0],gy[5],gz[4],gz[5],rw[6:0],bz[0],bz[1],by[4],gw[6:0],by[5],bz[2],gy[4],bw[6:0],bz[3],bz[5],bz[4],rx[5:0],gy[3:0],gx[5:0],gz[3:0],bx[5:0],by[3:0],ry[5:0],rz[5:0],d[4:0]"),
+    (0x02, 11, [5, 4, 4], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[4:0],rw[10],gy[3:0],gx[3:0],gw[10],bz[0],gz[3:0],bx[3:0],bw[10],bz[1],by[3:0],ry[4:0],bz[2],rz[4:0],bz[3],d[4:0]"),
+    (0x06, 11, [4, 5, 4], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[3:0],rw[10],gz[4],gy[3:0],gx[4:0],gw[10],gz[3:0],bx[3:0],bw[10],bz[1],by[3:0],ry[3:0],bz[0],bz[2],rz[3:0],gy[4],bz[3],d[4:0]"),
+    (0x0a, 11, [4, 4, 5], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[3:0],rw[10],by[4],gy[3:0],gx[3:0],gw[10],bz[0],gz[3:0],bx[4:0],bw[10],by[3:0],ry[3:0],bz[1],bz[2],rz[3:0],bz[4],bz[3],d[4:0]"),
+    (0x0e, 9, [5, 5, 5], true, "m[4:0],rw[8:0],by[4],gw[8:0],gy[4],bw[8:0],bz[4],rx[4:0],gz[4],gy[3:0],gx[4:0],bz[0],gz[3:0],bx[4:0],bz[1],by[3:0],ry[4:0],bz[2],rz[4:0],bz[3],d[4:0]"),
+    (0x12, 8, [6, 5, 5], true, "m[4:0],rw[7:0],gz[4],by[4],gw[7:0],bz[2],gy[4],bw[7:0],bz[3],bz[4],rx[5:0],gy[3:0],gx[4:0],bz[0],gz[3:0],bx[4:0],bz[1],by[3:0],ry[5:0],rz[5:0],d[4:0]"),
+    (0x16, 8, [5, 6, 5], true, "m[4:0],rw[7:0],bz[0],by[4],gw[7:0],gy[5],gy[4],bw[7:0],gz[5],bz[4],rx[4:0],gz[4],gy[3:0],gx[5:0],gz[3:0],bx[4:0],bz[1],by[3:0],ry[4:0],bz[2],rz[4:0],bz[3],d[4:0]"),
+    (0x1a, 8, [5, 5, 6], true, "m[4:0],rw[7:0],bz[1],by[4],gw[7:0],by[5],gy[4],bw[7:0],bz[5],bz[4],rx[4:0],gz[4],gy[3:0],gx[4:0],bz[0],gz[3:0],bx[5:0],by[3:0],ry[4:0],bz[2],rz[4:0],bz[3],d[4:0]"),
+    (0x1e, 6, [6, 6, 6], false, "m[4:0],rw[5:0],gz[4],bz[0],bz[1],by[4],gw[5:0],gy[5],by[5],bz[2],gy[4],bw[5:0],gz[5],bz[3],bz[5],bz[4],rx[5:0],gy[3:0],gx[5:0],gz[3:0],bx[5:0],by[3:0],ry[5:0],rz[5:0],d[4:0]"),
+    (0x03, 10, [10, 10, 10], false, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[9:0],gx[9:0],bx[9:0]"),
+    (0x07, 11, [9, 9, 9], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[8:0],rw[10],gx[8:0],gw[10],bx[8:0],bw[10]"),
+    (0x0b, 12, [8, 8, 8], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[7:0],rw[10:11],gx[7:0],gw[10:11],bx[7:0],bw[10:11]"),
+    (0x0f, 16, [4, 4, 4], true, "m[4:0],rw[9:0],gw[9:0],bw[9:0],rx[3:0],rw[10:15],gx[3:0],gw[10:15],bx[3:0],bw[10:15]"),
+];
+
+/// reads the fields of a BC6H layout; returns [w, x, y, z] per channel and the partition
+fn bc6h_fields(bits: &mut BlockBits, layout: &str) -> ([[u32; 4]; 3], u32) {
+    let mut values = [[0u32; 4]; 3];
+    let mut partition = 0;
+    for field in layout.split(',') {
+        let (name, range) = field.split_at(field.find('[').unwrap());
+        let range = &range[1 .. range.len() - 1];
+        let (hi, lo) = match range.find(':') {
+            Some(p) => (range[..p].parse::<i32>().unwrap(), range[p + 1..].parse::<i32>().unwrap()),
+            None => {
+                let b = range.parse::<i32>().unwrap();
+                (b, b)
+            },
+        };
+        let step = match hi >= lo { true => 1, false => -1 };
+        let mut bit = lo;
+        loop {
+            let v = bits.read(1);
+            let target = match name {
+                "m" => None,
+                "d" => Some(&mut partition),
+                _ => {
+                    let mut chars = name.chars();
+                    let channel = match chars.next() { Some('r') => 0, Some('g') => 1, _ => 2 };
+                    let slot = match chars.next() { Some('w') => 0, Some('x') => 1, Some('y') => 2, _ => 3 };
+                    Some(&mut values[channel][slot])
+                },
+            };
+            if let Some(t) = target {
+                *t |= v << bit;
+            }
+            if bit == hi {
+                break;
+            }
+            bit += step;
+        }
+    }
+    (values, partition)
+}
+
+fn sign_extend(v: u32, bits: u32) -> i32 {
+    ((v << (32 - bits)) as i32) >> (32 - bits)
+}
+
+fn bc6h_unquantize(v: u32, bits: u32) -> u32 {
+    match v {
+        _ if bits >= 15 => v,
+        0 => 0,
+        _ if v == (1 << bits) - 1 => 0xffff,
+        _ => ((v << 16) + 0x8000) >> bits,
+    }
+}
+
+fn bc6h_block(b: &[u8], out: &mut [f32]) {
+    let mode_bits = match b[0] & 3 < 2 {
+        true => (b[0] & 3) as u32,
+        false => (b[0] & 0x1f) as u32,
+    };
+    let mode = match BC6H_MODES.iter().find(|m| m.0 == mode_bits) {
+        Some(m) => m,
+        None => {
+            // reserved modes decode to black
+            for t in out.chunks_exact_mut(4) {
+                t.copy_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+            }
+            return;
+        },
+    };
+    let (endpoint_bits, delta_bits, transformed, layout) = (mode.1, mode.2, mode.3, mode.4);
+    let mut bits = BlockBits::new(b);
+    let (mut values, partition) = bc6h_fields(&mut bits, layout);
+    let two_regions = bits.pos == 82;
+    let slots = match two_regions { true => 4, false => 2 };
+
+    let mask = (1u32 << endpoint_bits) - 1;
+    for (c, channel) in values.iter_mut().enumerate() {
+        if transformed {
+            for slot in 1..slots {
+                let delta = sign_extend(channel[slot], delta_bits[c]);
+                channel[slot] = (channel[0] as i32 + delta) as u32 & mask;
+            }
+        }
+        for v in channel.iter_mut().take(slots) {
+            *v = bc6h_unquantize(*v, endpoint_bits);
+        }
+    }
+
+    let index_bits = match two_regions { true => 3, false => 4 };
+    for i in 0..16 {
+        let (s, anchor) = match two_regions {
+            true => subset(2, partition as usize, i),
+            false => (0, i == 0),
+        };
+        let index = bits.read(index_bits - anchor as u32);
+        let w = weight(index_bits, index);
+        for c in 0..3 {
+            let (e0, e1) = (values[c][s * 2], values[c][s * 2 + 1]);
+            let v = ((64 - w) * e0 + w * e1 + 32) >> 6;
+            out[i * 4 + c] = super::half_to_f32(((v * 31) >> 6) as u16);
+        }
+        out[i * 4 + 3] = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// packs (bit count, value) fields from the lowest bit up
+    fn block(fields: &[(u32, u128)]) -> [u8; 16] {
+        let mut v = 0u128;
+        let mut pos = 0;
+        for (count, value) in fields {
+            v |= value << pos;
+            pos += count;
+        }
+        v.to_le_bytes()
+    }
+
+    #[test]
+    fn bc1_four_and_three_colors() {
+        // red and blue endpoints, indices 0 1 2 3 on every row
+        let mut out = [0u8; 64];
+        color_block(&[0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4], &mut out, false);
+        assert_eq!(out[..16], [255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255, 85, 0, 170, 255]);
+
+        // c0 <= c1 switches to three colors and transparent black
+        color_block(&[0x1f, 0x00, 0x00, 0xf8, 0xe4, 0xe4, 0xe4, 0xe4], &mut out, false);
+        assert_eq!(out[..16], [0, 0, 255, 255, 255, 0, 0, 255, 127, 0, 127, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc3_alpha_ramp() {
+        // 8 alpha ramp between 255 and 0, index i at texel i for the first 8 texels
+        let indices = (0..8u64).fold(0u64, |acc, i| acc | i << (i * 3));
+        let mut b = vec![255, 0];
+        b.extend_from_slice(&indices.to_le_bytes()[..6]);
+        assert_eq!(alpha_block(&b)[..8], [255, 0, 218, 182, 145, 109, 72, 36]);
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        // endpoints 0 and 0x7f with p bits 0 and 1, indices 0, 15, 8 then 15
+        let mut fields = vec![(7, 0x40)];
+        fields.extend([(7, 0), (7, 0x7f)].repeat(4));
+        fields.extend([(1, 0), (1, 1), (3, 0), (4, 15), (4, 8)]);
+        fields.extend([(4, 15); 13]);
+        let mut out = [0u8; 64];
+        bc7_block(&block(&fields), &mut out);
+        assert_eq!(out[..12], [0, 0, 0, 0, 255, 255, 255, 255, 135, 135, 135, 135]);
+        assert!(out[12..].iter().all(|v| *v == 255));
+    }
+
+    #[test]
+    fn bc6h_mode11() {
+        // untransformed 10 bit endpoints (0, 0, 0) and (1023, 1023, 512), indices 0 then 15
+        let mut fields = vec![(5, 0x03), (10, 0), (10, 0), (10, 0), (10, 1023), (10, 1023), (10, 512), (3, 0)];
+        fields.extend([(4, 15); 15]);
+        let mut out = [0f32; 64];
+        bc6h_block(&block(&fields), &mut out);
+        assert_eq!(out[..8], [0.0, 0.0, 0.0, 1.0, 65504.0, 65504.0, 1.5146484, 1.0]);
+    }
+}
@@ -0,0 +1,812 @@
+use crate::Result;
+
+/// crn file signature "Hx"
+const SIGNATURE: u16 = 0x4878;
+
+/// crn_format values
+const FORMAT_DXT1: u8 = 0;
+const FORMAT_DXT5: u8 = 2;
+const FORMAT_ETC1: u8 = 10;
+const FORMAT_ETC2A: u8 = 12;
+
+/// blocks of a legacy 2x2 chunk using each endpoint tile, and the tile counts
+const CHUNK_TILES: [[usize; 4]; 8] = [
+    [0, 0, 0, 0], [0, 0, 1, 1], [0, 1, 0, 1], [0, 0, 1, 2],
+    [1, 2, 0, 0], [0, 1, 0, 2], [1, 0, 2, 0], [0, 1, 2, 3],
+];
+const CHUNK_TILE_COUNTS: [usize; 8] = [1, 2, 2, 3, 3, 3, 3, 4];
+
+/// linear selector order to DXT1 / DXT5 selector values
+const DXT1_FROM_LINEAR: [u32; 4] = [0, 2, 3, 1];
+const DXT5_FROM_LINEAR: [u64; 8] = [0, 2, 3, 4, 5, 6, 7, 1];
+
+/// Huffman code length alphabet, in the order the lengths are sent
+const CODE_LENGTH_ORDER: [usize; 21] = [17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16];
+
+/// transcodes a crn file into raw blocks
+///
+/// `unity` selects the bitstream revision of Unity's crunch fork (2017.3 and
+/// later) over the original crunch one. The result holds every mip level of
+/// every face, face after face, as DXT1, DXT5, ETC1 or ETC2 RGBA8 blocks.
+pub fn transcode(data: &[u8], unity: bool) -> Result<Vec<u8>> {
+    let header = Header::read(data)?;
+    let palettes = Palettes::read(data, &header, unity)?;
+    let block_bytes = match header.format {
+        FORMAT_DXT1 | FORMAT_ETC1 => 8,
+        _ => 16,
+    };
+    let mut faces = vec![Vec::new(); header.faces];
+    for level in 0..header.levels {
+        let start = header.level_offsets[level] as usize;
+        let end = match header.level_offsets.get(level + 1) {
+            Some(o) => *o as usize,
+            None => header.data_size.min(data.len()),
+        };
+        if start > end || end > data.len() {
+            return Err(format!("crunch level {} out of range", level).into());
+        }
+        let blocks_x = ((header.width >> level).max(1) as usize).div_ceil(4);
+        let blocks_y = ((header.height >> level).max(1) as usize).div_ceil(4);
+        let mut out: Vec<Vec<u8>> = vec![vec![0u8; blocks_x * blocks_y * block_bytes]; header.faces];
+        let mut level = Level{
+            codec: Codec::new(&data[start..end]),
+            palettes: &palettes,
+            blocks_x,
+            blocks_y,
+            block_bytes,
+        };
+        match (unity, header.format) {
+            (false, FORMAT_DXT1) | (false, FORMAT_DXT5) => level.unpack_chunks(&mut out, header.format == FORMAT_DXT5)?,
+            (true, FORMAT_DXT1) | (true, FORMAT_DXT5) => level.unpack_dxt(&mut out, header.format == FORMAT_DXT5)?,
+            (true, FORMAT_ETC1) | (true, FORMAT_ETC2A) => level.unpack_etc(&mut out, header.format == FORMAT_ETC2A)?,
+            (_, f) => return Err(format!("unsupported crunch format {}", f).into()),
+        }
+        for (face, blocks) in faces.iter_mut().zip(out) {
+            face.extend_from_slice(&blocks);
+        }
+    }
+    Ok(faces.concat())
+}
+
+/// (offset, size, count) of a palette
+type Palette = (usize, usize, usize);
+
+struct Header {
+    data_size: usize,
+    width: u32,
+    height: u32,
+    levels: usize,
+    faces: usize,
+    format: u8,
+    color_endpoints: Palette,
+    color_selectors: Palette,
+    alpha_endpoints: Palette,
+    alpha_selectors: Palette,
+    tables: (usize, usize),     // offset, size
+    level_offsets: Vec<u32>,
+}
+
+impl Header {
+    fn read(d: &[u8]) -> Result<Header> {
+        // every field is big endian and byte aligned
+        let be = |ofs: usize, len: usize| -> u32 {
+            d[ofs..ofs + len].iter().fold(0, |v, b| v << 8 | *b as u32)
+        };
+        if d.len() < 74 || be(0, 2) as u16 != SIGNATURE {
+            return Err("not a crunch file".into());
+        }
+        let palette = |ofs: usize| (be(ofs, 3) as usize, be(ofs + 3, 3) as usize, be(ofs + 6, 2) as usize);
+        let levels = be(16, 1) as usize;
+        if d.len() < 70 + levels * 4 {
+            return Err("truncated crunch header".into());
+        }
+        Ok(Header{
+            data_size: be(6, 4) as usize,
+            width: be(12, 2),
+            height: be(14, 2),
+            levels,
+            faces: be(17, 1).max(1) as usize,
+            format: be(18, 1) as u8,
+            color_endpoints: palette(33),
+            color_selectors: palette(41),
+            alpha_endpoints: palette(49),
+            alpha_selectors: palette(57),
+            tables: (be(67, 3) as usize, be(65, 2) as usize),
+            level_offsets: (0..levels).map(|i| be(70 + i * 4, 4)).collect(),
+        })
+    }
+}
+
+/// MSB first bit reader with the static Huffman models used by crunch
+struct Codec<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> Codec<'a> {
+    fn new(data: &'a [u8]) -> Codec<'a> {
+        Codec{ data, pos: 0, bit_buf: 0, bit_count: 0 }
+    }
+
+    fn section(data: &'a [u8], (offset, size): (usize, usize)) -> Result<Codec<'a>> {
+        match data.get(offset .. offset + size) {
+            Some(d) => Ok(Codec::new(d)),
+            None => Err(format!("crunch section {}..{} out of range", offset, offset + size).into()),
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        while self.bit_count < n {
+            // reading past the end yields zeros
+            let c = self.data.get(self.pos).copied().unwrap_or(0) as u32;
+            self.pos += 1;
+            self.bit_count += 8;
+            self.bit_buf |= c << (32 - self.bit_count);
+        }
+        let v = self.bit_buf >> (32 - n);
+        self.bit_buf = match n {
+            32 => 0,
+            _ => self.bit_buf << n,
+        };
+        self.bit_count -= n;
+        v
+    }
+
+    /// reads the code lengths of a model, themselves Huffman coded
+    fn model(&mut self) -> Result<Model> {
+        let total = self.bits(14) as usize;
+        if total == 0 {
+            return Ok(Model::new(&[]));
+        }
+        let sent = self.bits(5) as usize;
+        if sent == 0 || sent > CODE_LENGTH_ORDER.len() {
+            return Err("invalid crunch code length count".into());
+        }
+        let mut lengths_of_lengths = [0u8; 21];
+        for i in CODE_LENGTH_ORDER.iter().take(sent) {
+            lengths_of_lengths[*i] = self.bits(3) as u8;
+        }
+        let code_lengths = Model::new(&lengths_of_lengths);
+        let mut lengths = vec![0u8; total];
+        let mut i = 0;
+        while i < total {
+            let code = self.decode(&code_lengths)?;
+            let (run, repeat) = match code {
+                0..=16 => {
+                    lengths[i] = code as u8;
+                    i += 1;
+                    continue;
+                },
+                17 => (self.bits(3) + 3, false),
+                18 => (self.bits(7) + 11, false),
+                19 => (self.bits(2) + 3, true),
+                _ => (self.bits(6) + 7, true),
+            };
+            let run = run as usize;
+            if run > total - i || (repeat && (i == 0 || lengths[i - 1] == 0)) {
+                return Err("invalid crunch code length run".into());
+            }
+            if repeat {
+                let prev = lengths[i - 1];
+                lengths[i .. i + run].iter_mut().for_each(|l| *l = prev);
+            }
+            i += run;
+        }
+        Ok(Model::new(&lengths))
+    }
+
+    fn decode(&mut self, model: &Model) -> Result<u32> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for len in 1..=16 {
+            code = code << 1 | self.bits(1);
+            let count = model.counts[len];
+            if code - first < count {
+                return Ok(model.symbols[(index + code - first) as usize] as u32);
+            }
+            index += count;
+            first = (first + count) << 1;
+        }
+        Err("invalid crunch Huffman code".into())
+    }
+}
+
+/// canonical Huffman code: symbols sorted by code length
+struct Model {
+    counts: [u32; 17],
+    symbols: Vec<u16>,
+}
+
+impl Model {
+    fn new(lengths: &[u8]) -> Model {
+        let mut counts = [0u32; 17];
+        let mut symbols = Vec::new();
+        for len in 1..=16u8 {
+            for (s, l) in lengths.iter().enumerate() {
+                if *l == len {
+                    counts[len as usize] += 1;
+                    symbols.push(s as u16);
+                }
+            }
+        }
+        Model{ counts, symbols }
+    }
+}
+
+/// decoded endpoint and selector palettes
+struct Palettes {
+    color_endpoints: Vec<u32>,
+    color_selectors: Vec<u32>,          // DXT1 selector words
+    etc_selectors: Vec<[[u8; 4]; 2]>,   // ETC selector bytes, unflipped and flipped
+    alpha_endpoints: Vec<u16>,
+    alpha_selectors: Vec<[[u8; 6]; 2]>, // DXT5 / EAC selector bytes, unflipped and flipped
+    reference: Model,                   // chunk encodings, or Unity's endpoint references
+    endpoint_delta: [Model; 2],         // color, alpha
+    selector_delta: [Model; 2],
+}
+
+impl Palettes {
+    fn read(data: &[u8], h: &Header, unity: bool) -> Result<Palettes> {
+        let etc = matches!(h.format, FORMAT_ETC1 | FORMAT_ETC2A);
+        let mut tables = Codec::section(data, h.tables)?;
+        let reference = tables.model()?;
+        let mut endpoint_delta = [Model::new(&[]), Model::new(&[])];
+        let mut selector_delta = [Model::new(&[]), Model::new(&[])];
+        if h.color_endpoints.2 == 0 && h.alpha_endpoints.2 == 0 {
+            return Err("crunch file without endpoints".into());
+        }
+        if h.color_endpoints.2 > 0 {
+            endpoint_delta[0] = tables.model()?;
+            selector_delta[0] = tables.model()?;
+        }
+        if h.alpha_endpoints.2 > 0 {
+            endpoint_delta[1] = tables.model()?;
+            selector_delta[1] = tables.model()?;
+        }
+
+        let mut p = Palettes{
+            color_endpoints: Vec::new(),
+            color_selectors: Vec::new(),
+            etc_selectors: Vec::new(),
+            alpha_endpoints: Vec::new(),
+            alpha_selectors: Vec::new(),
+            reference,
+            endpoint_delta,
+            selector_delta,
+        };
+        if h.color_endpoints.2 > 0 {
+            p.read_color_endpoints(data, h.color_endpoints, etc)?;
+            match (unity, etc) {
+                (false, _) => p.read_legacy_color_selectors(data, h.color_selectors)?,
+                (true, false) => p.read_color_selectors(data, h.color_selectors)?,
+                (true, true) => p.read_etc_selectors(data, h.color_selectors)?,
+            }
+        }
+        if h.alpha_endpoints.2 > 0 {
+            p.read_alpha_endpoints(data, h.alpha_endpoints)?;
+            match (unity, etc) {
+                (false, _) => p.read_legacy_alpha_selectors(data, h.alpha_selectors)?,
+                (true, false) => p.read_alpha_selectors(data, h.alpha_selectors)?,
+                (true, true) => p.read_eac_selectors(data, h.alpha_selectors)?,
+            }
+        }
+        Ok(p)
+    }
+
+    fn read_color_endpoints(&mut self, data: &[u8], palette: Palette, etc: bool) -> Result<()> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm0 = codec.model()?;
+        let dm1 = match etc {
+            true => Model::new(&[]),
+            false => codec.model()?,
+        };
+        let mut v = [0u32; 6];
+        for _ in 0..palette.2 {
+            let endpoint = match etc {
+                // R, G, B and the intensity table, one byte each
+                true => {
+                    let mut a = *self.color_endpoints.last().unwrap_or(&0);
+                    for shift in [0, 8, 16, 24] {
+                        a = a.wrapping_add(codec.decode(&dm0)? << shift);
+                    }
+                    a & 0x1f1f1f1f
+                },
+                // two RGB565 colors as deltas from the previous entry
+                false => {
+                    for (i, (model, mask)) in [(&dm0, 31), (&dm1, 63), (&dm0, 31), (&dm0, 31), (&dm1, 63), (&dm0, 31)].iter().enumerate() {
+                        v[i] = (v[i] + codec.decode(model)?) & mask;
+                    }
+                    v[2] | v[1] << 5 | v[0] << 11 | v[5] << 16 | v[4] << 21 | v[3] << 27
+                },
+            };
+            self.color_endpoints.push(endpoint);
+        }
+        Ok(())
+    }
+
+    fn read_legacy_color_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm = codec.model()?;
+        let mut cur = [0i32; 16];
+        for _ in 0..palette.2 {
+            for j in 0..8 {
+                let sym = codec.decode(&dm)? as i32;
+                cur[j * 2] = (cur[j * 2] + sym % 7 - 3) & 3;
+                cur[j * 2 + 1] = (cur[j * 2 + 1] + sym / 7 - 3) & 3;
+            }
+            let selector = cur.iter().enumerate()
+                .fold(0, |s, (i, c)| s | DXT1_FROM_LINEAR[*c as usize] << (i * 2));
+            self.color_selectors.push(selector);
+        }
+        Ok(())
+    }
+
+    /// linear selectors of 16 texels, xor coded against the previous entry
+    fn read_linear_selectors(data: &[u8], palette: Palette) -> Result<Vec<u32>> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm = codec.model()?;
+        let mut s = 0u32;
+        let mut out = Vec::with_capacity(palette.2);
+        for _ in 0..palette.2 {
+            for shift in (0..32).step_by(4) {
+                s ^= codec.decode(&dm)? << shift;
+            }
+            out.push(s);
+        }
+        Ok(out)
+    }
+
+    fn read_color_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        self.color_selectors = Palettes::read_linear_selectors(data, palette)?.into_iter()
+            .map(|s| ((s ^ s << 1) & 0xaaaaaaaa) | (s >> 1 & 0x55555555))
+            .collect();
+        Ok(())
+    }
+
+    fn read_etc_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        for s in Palettes::read_linear_selectors(data, palette)? {
+            let mut bytes = [[0u8; 4]; 2];
+            for (flip, out) in bytes.iter_mut().enumerate() {
+                let (mut msb, mut lsb) = (0u16, 0u16);
+                for p in 0..16 {
+                    // linear order -b, -a, +a, +b to ETC pixel indices 3, 2, 0, 1
+                    let index = [3, 2, 0, 1][(s >> (p * 2) & 3) as usize];
+                    let i = etc_pixel(p, flip == 1);
+                    msb |= (index >> 1) << i;
+                    lsb |= (index & 1) << i;
+                }
+                out[..2].copy_from_slice(&msb.to_be_bytes());
+                out[2..].copy_from_slice(&lsb.to_be_bytes());
+            }
+            self.etc_selectors.push(bytes);
+        }
+        Ok(())
+    }
+
+    fn read_alpha_endpoints(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm = codec.model()?;
+        let (mut a, mut b) = (0u32, 0u32);
+        for _ in 0..palette.2 {
+            a = (a + codec.decode(&dm)?) & 0xff;
+            b = (b + codec.decode(&dm)?) & 0xff;
+            self.alpha_endpoints.push((a | b << 8) as u16);
+        }
+        Ok(())
+    }
+
+    fn read_legacy_alpha_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm = codec.model()?;
+        let mut cur = [0i32; 16];
+        for _ in 0..palette.2 {
+            for j in 0..8 {
+                let sym = codec.decode(&dm)? as i32;
+                cur[j * 2] = (cur[j * 2] + sym % 15 - 7) & 7;
+                cur[j * 2 + 1] = (cur[j * 2 + 1] + sym / 15 - 7) & 7;
+            }
+            let bits = cur.iter().enumerate()
+                .fold(0u64, |s, (i, c)| s | DXT5_FROM_LINEAR[*c as usize] << (i * 3));
+            self.alpha_selectors.push([dxt5_selector_bytes(bits); 2]);
+        }
+        Ok(())
+    }
+
+    /// linear 3 bit selectors of 16 texels, xor coded two at a time
+    fn read_linear_alpha_selectors(data: &[u8], palette: Palette) -> Result<Vec<[u8; 16]>> {
+        let mut codec = Codec::section(data, (palette.0, palette.1))?;
+        let dm = codec.model()?;
+        let mut groups = [0u32; 8];
+        let mut out = Vec::with_capacity(palette.2);
+        for _ in 0..palette.2 {
+            let mut linear = [0u8; 16];
+            for (g, group) in groups.iter_mut().enumerate() {
+                *group ^= codec.decode(&dm)?;
+                linear[g * 2] = (*group & 7) as u8;
+                linear[g * 2 + 1] = (*group >> 3 & 7) as u8;
+            }
+            out.push(linear);
+        }
+        Ok(out)
+    }
+
+    fn read_alpha_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        for linear in Palettes::read_linear_alpha_selectors(data, palette)? {
+            let bits = linear.iter().enumerate()
+                .fold(0u64, |s, (i, l)| s | DXT5_FROM_LINEAR[*l as usize] << (i * 3));
+            self.alpha_selectors.push([dxt5_selector_bytes(bits); 2]);
+        }
+        Ok(())
+    }
+
+    fn read_eac_selectors(&mut self, data: &[u8], palette: Palette) -> Result<()> {
+        for linear in Palettes::read_linear_alpha_selectors(data, palette)? {
+            let mut bytes = [[0u8; 6]; 2];
+            for (flip, out) in bytes.iter_mut().enumerate() {
+                let mut bits = 0u64;
+                for (p, l) in linear.iter().enumerate() {
+                    // linear order runs from the most negative modifier to the most positive one
+                    let index = match *l {
+                        l if l <= 3 => 3 - l,
+                        l => l,
+                    } as u64;
+                    bits |= index << (45 - 3 * etc_pixel(p, flip == 1));
+                }
+                out.copy_from_slice(&bits.to_be_bytes()[2..]);
+            }
+            self.alpha_selectors.push(bytes);
+        }
+        Ok(())
+    }
+}
+
+/// ETC pixel index (column major) of linear selector `p`
+///
+/// Unity's crunch stores ETC selectors with the two sub-blocks first and
+/// second; flipped blocks split into top and bottom halves, so their linear
+/// order is row major.
+fn etc_pixel(p: usize, flip: bool) -> usize {
+    match flip {
+        true => (p & 3) * 4 + (p >> 2),
+        false => p,
+    }
+}
+
+fn dxt5_selector_bytes(bits: u64) -> [u8; 6] {
+    let mut b = [0u8; 6];
+    b.copy_from_slice(&bits.to_le_bytes()[..6]);
+    b
+}
+
+/// endpoint indices a block shares with its neighbours (Unity bitstream)
+#[derive(Clone, Copy, Default)]
+struct BlockState {
+    reference: u32,
+    color: usize,
+    alpha: usize,
+}
+
+struct Level<'a, 'b> {
+    codec: Codec<'a>,
+    palettes: &'b Palettes,
+    blocks_x: usize,
+    blocks_y: usize,
+    block_bytes: usize,
+}
+
+impl Level<'_, '_> {
+    /// adds a delta coded palette index, wrapping at the palette size
+    fn next_index(&mut self, index: usize, model: usize, len: usize) -> Result<usize> {
+        let delta = self.codec.decode(&self.palettes.endpoint_delta[model])? as usize;
+        next(index, delta, len)
+    }
+
+    fn selector(&mut self, model: usize, len: usize) -> Result<usize> {
+        let index = self.codec.decode(&self.palettes.selector_delta[model])? as usize;
+        match index < len {
+            true => Ok(index),
+            false => Err(format!("crunch selector {} out of range", index).into()),
+        }
+    }
+
+    fn put(&self, out: &mut [u8], x: usize, y: usize, block: &[u8]) {
+        let i = (y * self.blocks_x + x) * self.block_bytes;
+        out[i .. i + self.block_bytes].copy_from_slice(block);
+    }
+
+    fn dxt_block(&self, color: usize, color_selector: usize, alpha: Option<(usize, usize)>) -> Vec<u8> {
+        let p = self.palettes;
+        let mut block = Vec::with_capacity(16);
+        if let Some((endpoint, selector)) = alpha {
+            block.extend_from_slice(&p.alpha_endpoints[endpoint].to_le_bytes());
+            block.extend_from_slice(&p.alpha_selectors[selector][0]);
+        }
+        block.extend_from_slice(&p.color_endpoints[color].to_le_bytes());
+        block.extend_from_slice(&p.color_selectors[color_selector].to_le_bytes());
+        block
+    }
+
+    /// original crunch: 2x2 block chunks sharing up to four endpoint tiles, serpentine order
+    fn unpack_chunks(&mut self, out: &mut [Vec<u8>], alpha: bool) -> Result<()> {
+        let p = self.palettes;
+        let (colors, alphas) = (p.color_endpoints.len(), p.alpha_endpoints.len());
+        let (color_selectors, alpha_selectors) = (p.color_selectors.len(), p.alpha_selectors.len());
+        let chunks_x = self.blocks_x.div_ceil(2);
+        let chunks_y = self.blocks_y.div_ceil(2);
+        let (mut color, mut alpha_endpoint, mut color_selector, mut alpha_selector) = (0, 0, 0, 0);
+        let mut encoding_bits = 1;
+        for face in out.iter_mut() {
+            for cy in 0..chunks_y {
+                for i in 0..chunks_x {
+                    let cx = match cy & 1 {
+                        0 => i,
+                        _ => chunks_x - 1 - i,
+                    };
+                    if encoding_bits == 1 {
+                        encoding_bits = self.codec.decode(&p.reference)? | 512;
+                    }
+                    let encoding = (encoding_bits & 7) as usize;
+                    encoding_bits >>= 3;
+                    let tiles = CHUNK_TILE_COUNTS[encoding];
+                    let mut tile_colors = [0; 4];
+                    let mut tile_alphas = [0; 4];
+                    for c in tile_colors.iter_mut().take(tiles) {
+                        color = self.next_index(color, 0, colors)?;
+                        *c = color;
+                    }
+                    if alpha {
+                        for a in tile_alphas.iter_mut().take(tiles) {
+                            alpha_endpoint = self.next_index(alpha_endpoint, 1, alphas)?;
+                            *a = alpha_endpoint;
+                        }
+                    }
+                    for (b, tile) in CHUNK_TILES[encoding].iter().enumerate() {
+                        let delta = self.codec.decode(&p.selector_delta[0])? as usize;
+                        color_selector = next(color_selector, delta, color_selectors)?;
+                        if alpha {
+                            let delta = self.codec.decode(&p.selector_delta[1])? as usize;
+                            alpha_selector = next(alpha_selector, delta, alpha_selectors)?;
+                        }
+                        let (x, y) = (cx * 2 + (b & 1), cy * 2 + (b >> 1));
+                        if x < self.blocks_x && y < self.blocks_y {
+                            let a = match alpha {
+                                true => Some((tile_alphas[*tile], alpha_selector)),
+                                false => None,
+                            };
+                            let block = self.dxt_block(tile_colors[*tile], color_selector, a);
+                            self.put(face, x, y, &block);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unity crunch DXT: each block reuses the left or upper endpoints or codes a new one
+    fn unpack_dxt(&mut self, out: &mut [Vec<u8>], alpha: bool) -> Result<()> {
+        let p = self.palettes;
+        let (colors, alphas) = (p.color_endpoints.len(), p.alpha_endpoints.len());
+        let width = self.blocks_x.div_ceil(2) * 2;
+        let height = self.blocks_y.div_ceil(2) * 2;
+        let mut above = vec![BlockState::default(); width];
+        let (mut color, mut alpha_endpoint) = (0, 0);
+        let mut group = 0;
+        for face in out.iter_mut() {
+            for y in 0..height {
+                for (x, column) in above.iter_mut().enumerate() {
+                    if y & 1 == 0 && x & 1 == 0 {
+                        group = self.codec.decode(&p.reference)?;
+                    }
+                    let reference = match y & 1 {
+                        1 => column.reference,
+                        _ => {
+                            let r = group & 3;
+                            column.reference = group >> 2 & 3;
+                            group >>= 4;
+                            r
+                        },
+                    };
+                    match reference {
+                        0 => {
+                            color = self.next_index(color, 0, colors)?;
+                            if alpha {
+                                alpha_endpoint = self.next_index(alpha_endpoint, 1, alphas)?;
+                            }
+                            column.color = color;
+                            column.alpha = alpha_endpoint;
+                        },
+                        1 => {
+                            column.color = color;
+                            column.alpha = alpha_endpoint;
+                        },
+                        _ => {
+                            color = column.color;
+                            alpha_endpoint = column.alpha;
+                        },
+                    }
+                    let color_selector = self.selector(0, p.color_selectors.len())?;
+                    let alpha_selector = match alpha {
+                        true => Some(self.selector(1, p.alpha_selectors.len())?),
+                        false => None,
+                    };
+                    if x < self.blocks_x && y < self.blocks_y {
+                        let block = self.dxt_block(color, color_selector, alpha_selector.map(|s| (alpha_endpoint, s)));
+                        self.put(face, x, y, &block);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unity crunch ETC1 / ETC2 RGBA8: sub-block endpoints referencing left, upper or diagonal neighbours
+    fn unpack_etc(&mut self, out: &mut [Vec<u8>], alpha: bool) -> Result<()> {
+        let p = self.palettes;
+        let (colors, alphas) = (p.color_endpoints.len(), p.alpha_endpoints.len());
+        let width = self.blocks_x.div_ceil(2) * 2;
+        let height = self.blocks_y.div_ceil(2) * 2;
+        // first and second sub-block state per column
+        let mut above = vec![[BlockState::default(); 2]; width];
+        let (mut color, mut alpha_endpoint) = (0, 0);
+        let (mut diagonal_color, mut diagonal_alpha) = (0, 0);
+        for face in out.iter_mut() {
+            for y in 0..height {
+                for (x, column) in above.iter_mut().enumerate() {
+                    let mut reference = match y & 1 {
+                        1 => column[0].reference,
+                        _ => {
+                            let group = self.codec.decode(&p.reference)?;
+                            column[0].reference = (group >> 2 & 3) | (group >> 4 & 12);
+                            (group & 3) | (group >> 2 & 12)
+                        },
+                    };
+                    match reference & 3 {
+                        0 => {
+                            color = self.next_index(color, 0, colors)?;
+                            if alpha {
+                                alpha_endpoint = self.next_index(alpha_endpoint, 1, alphas)?;
+                            }
+                        },
+                        1 => {},
+                        3 => {
+                            color = diagonal_color;
+                            alpha_endpoint = diagonal_alpha;
+                        },
+                        _ => {
+                            color = column[0].color;
+                            alpha_endpoint = column[0].alpha;
+                        },
+                    }
+                    column[0].color = color;
+                    column[0].alpha = alpha_endpoint;
+                    reference >>= 2;
+                    let e0 = p.color_endpoints[color].to_le_bytes();
+                    let color_selector = self.selector(0, p.etc_selectors.len())?;
+                    let alpha_selector = match alpha {
+                        true => self.selector(1, p.alpha_selectors.len())?,
+                        false => 0,
+                    };
+                    if reference != 0 {
+                        color = self.next_index(color, 0, colors)?;
+                    }
+                    diagonal_color = column[1].color;
+                    diagonal_alpha = column[1].alpha;
+                    column[1].color = color;
+                    column[1].alpha = alpha_endpoint;
+                    let e1 = p.color_endpoints[color].to_le_bytes();
+                    if x >= self.blocks_x || y >= self.blocks_y {
+                        continue;
+                    }
+
+                    let flip = (reference >> 1 ^ 1) as u8;
+                    let diff = (0..3).all(|c| e0[c] + 3 >= e1[c] && e1[c] + 4 >= e0[c]);
+                    let mut block = Vec::with_capacity(16);
+                    if alpha {
+                        block.extend_from_slice(&p.alpha_endpoints[alpha_endpoint].to_le_bytes());
+                        block.extend_from_slice(&p.alpha_selectors[alpha_selector][flip as usize]);
+                    }
+                    for c in 0..3 {
+                        block.push(match diff {
+                            true => e0[c] << 3 | (e1[c].wrapping_sub(e0[c]) & 7),
+                            false => (e0[c] << 3 & 0xf0) | e1[c] >> 1,
+                        });
+                    }
+                    block.push(e0[3] << 5 | e1[3] << 2 | (diff as u8) << 1 | flip);
+                    block.extend_from_slice(&p.etc_selectors[color_selector][flip as usize]);
+                    self.put(face, x, y, &block);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn next(index: usize, delta: usize, len: usize) -> Result<usize> {
+    let mut i = index + delta;
+    if i >= len {
+        i = i.checked_sub(len).filter(|i| *i < len)
+            .ok_or_else(|| format!("crunch palette index {} out of range", index + delta))?;
+    }
+    Ok(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// packs (bit count, value) fields MSB first
+    fn bits(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut filled = 0;
+        for (count, value) in fields {
+            for i in (0..*count).rev() {
+                if filled % 8 == 0 {
+                    out.push(0);
+                }
+                *out.last_mut().unwrap() |= ((value >> i & 1) as u8) << (7 - filled % 8);
+                filled += 1;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn huffman_model() {
+        // code lengths 1, 2, 2 sent through a code length model where "0" is 1 and "1" is 2;
+        // lengths 1 and 2 are the last two of the first 19 sent entries
+        let mut fields = vec![(14, 3), (5, 19)];
+        fields.extend((0..19).map(|i| (3, match i { 16 | 18 => 1, _ => 0 })));
+        fields.extend([(1, 0), (1, 1), (1, 1)]);
+        // symbols 0, 1, 2, 1 as "0", "10", "11", "10"
+        fields.extend([(1, 0), (2, 2), (2, 3), (2, 2)]);
+        let data = bits(&fields);
+        let mut codec = Codec::new(&data);
+        let model = codec.model().unwrap();
+        let symbols: Vec<u32> = (0..4).map(|_| codec.decode(&model).unwrap()).collect();
+        assert_eq!(symbols, vec![0, 1, 2, 1]);
+
+        // a repeat without a previous length is rejected
+        let mut fields = vec![(14, 3), (5, 4)];
+        fields.extend([(3, 0), (3, 0), (3, 1), (3, 0), (1, 0)]);
+        assert!(Codec::new(&bits(&fields)).model().is_err());
+    }
+
+    #[test]
+    fn read_header() {
+        let mut d = vec![0u8; 74];
+        d[0..2].copy_from_slice(&SIGNATURE.to_be_bytes());
+        d[6..10].copy_from_slice(&200u32.to_be_bytes());
+        d[12..14].copy_from_slice(&64u16.to_be_bytes());
+        d[14..16].copy_from_slice(&32u16.to_be_bytes());
+        d[16] = 1;
+        d[18] = FORMAT_DXT5;
+        d[33..41].copy_from_slice(&[0, 0, 80, 0, 0, 12, 0, 3]);
+        d[65..70].copy_from_slice(&[0, 9, 0, 0, 74]);
+        d[70..74].copy_from_slice(&120u32.to_be_bytes());
+        let h = Header::read(&d).unwrap();
+        assert_eq!((h.data_size, h.width, h.height, h.levels, h.faces, h.format), (200, 64, 32, 1, 1, FORMAT_DXT5));
+        assert_eq!(h.color_endpoints, (80, 12, 3));
+        assert_eq!(h.tables, (74, 9));
+        assert_eq!(h.level_offsets, vec![120]);
+
+        d[16] = 2;
+        assert!(Header::read(&d).is_err());
+        d[0] = 0;
+        assert!(Header::read(&d).is_err());
+    }
+
+    #[test]
+    fn palette_index_wraps_once() {
+        assert_eq!(next(3, 2, 8).unwrap(), 5);
+        assert_eq!(next(6, 5, 8).unwrap(), 3);
+        assert!(next(6, 12, 8).is_err());
+        assert_eq!((0..16).map(|p| etc_pixel(p, true)).take(5).collect::<Vec<_>>(), vec![0, 4, 8, 12, 1]);
+    }
+}
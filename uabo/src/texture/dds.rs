@@ -0,0 +1,202 @@
+use super::TextureFormat;
+use crate::gpu_texture::{Dimension, GpuTexture};
+use crate::Result;
+
+// DDS_HEADER.dwFlags
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDSD_DEPTH: u32 = 0x800000;
+
+// DDS_PIXELFORMAT.dwFlags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+// dwCaps, dwCaps2
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xfe00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// DDS_HEADER_DXT10
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// pixel format of the header
+enum PixelFormat {
+    Dxgi(u32),
+    Masks(u32, [u32; 4]),   // bits per pixel, RGBA masks
+}
+
+/// DXGI_FORMAT, or bit masks for the layouts DXGI lacks
+fn pixel_format(format: TextureFormat, srgb: bool) -> Option<PixelFormat> {
+    use TextureFormat::*;
+    use PixelFormat::*;
+    let s = |linear: u32, srgb_format: u32| match srgb {
+        true => Dxgi(srgb_format),
+        false => Dxgi(linear),
+    };
+    Some(match format {
+        Alpha8 => Dxgi(65),
+        R8 => Dxgi(61),
+        RG16 => Dxgi(49),
+        R16 => Dxgi(56),
+        RG32 => Dxgi(35),
+        RGBA64 => Dxgi(11),
+        RGBA32 => s(28, 29),
+        BGRA32 => s(87, 91),
+        RGB565 => Dxgi(85),
+        ARGB4444 => Dxgi(115),
+        RHalf => Dxgi(54),
+        RGHalf => Dxgi(34),
+        RGBAHalf => Dxgi(10),
+        RFloat => Dxgi(41),
+        RGFloat => Dxgi(16),
+        RGBAFloat => Dxgi(2),
+        RGB9e5Float => Dxgi(67),
+        YUY2 => Dxgi(107),
+        DXT1 => s(71, 72),
+        DXT5 => s(77, 78),
+        BC4 => Dxgi(80),
+        BC5 => Dxgi(83),
+        BC6H => Dxgi(95),
+        BC7 => s(98, 99),
+        RGB24 => Masks(24, [0xff, 0xff00, 0xff0000, 0]),
+        ARGB32 => Masks(32, [0xff00, 0xff0000, 0xff000000, 0xff]),
+        RGBA4444 => Masks(16, [0xf000, 0x0f00, 0x00f0, 0x000f]),
+        _ => return None,
+    })
+}
+
+/// DDS file of a texture
+///
+/// Formats with a DXGI equivalent get the DX10 extension header; the rest
+/// fall back to RGB bit masks, which cannot describe arrays. Rows keep
+/// Unity's bottom-up order.
+pub fn write(t: &GpuTexture) -> Result<Vec<u8>> {
+    let pf = pixel_format(t.format, t.srgb)
+        .ok_or_else(|| format!("{:?} has no DDS format, export as ktx2", t.format))?;
+    let (block_width, _, block_bytes) = t.format.block_layout()
+        .ok_or_else(|| format!("unsupported texture format {:?}", t.format))?;
+    let compressed = block_width > 1 && t.format != TextureFormat::YUY2;
+
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT;
+    let pitch = match compressed {
+        true => {
+            flags |= DDSD_LINEARSIZE;
+            t.level_size(0)? / t.depth as usize
+        },
+        false => {
+            flags |= DDSD_PITCH;
+            t.width.div_ceil(block_width) as usize * block_bytes as usize
+        },
+    };
+    let mut caps = DDSCAPS_TEXTURE;
+    if t.levels > 1 {
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+    let caps2 = match t.dimension {
+        Dimension::Cube => {
+            caps |= DDSCAPS_COMPLEX;
+            DDSCAPS2_CUBEMAP_ALLFACES
+        },
+        Dimension::Tex3D => {
+            flags |= DDSD_DEPTH;
+            caps |= DDSCAPS_COMPLEX;
+            DDSCAPS2_VOLUME
+        },
+        _ => 0,
+    };
+
+    let mut out = Vec::with_capacity(148 + t.data.len());
+    out.extend_from_slice(b"DDS ");
+    for v in [124, flags, t.height, t.width, pitch as u32, t.depth, t.levels] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(&[0u8; 44]);
+    let (pf_flags, four_cc, bits, masks) = match pf {
+        PixelFormat::Dxgi(_) => (DDPF_FOURCC, *b"DX10", 0, [0; 4]),
+        PixelFormat::Masks(bits, masks) => match masks[3] {
+            0 => (DDPF_RGB, [0; 4], bits, masks),
+            _ => (DDPF_RGB | DDPF_ALPHAPIXELS, [0; 4], bits, masks),
+        },
+    };
+    out.extend_from_slice(&32u32.to_le_bytes());
+    out.extend_from_slice(&pf_flags.to_le_bytes());
+    out.extend_from_slice(&four_cc);
+    for v in [bits, masks[0], masks[1], masks[2], masks[3], caps, caps2, 0, 0, 0] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    match pf {
+        PixelFormat::Dxgi(dxgi) => {
+            let (dimension, misc, array_size) = match t.dimension {
+                Dimension::Tex3D => (D3D10_RESOURCE_DIMENSION_TEXTURE3D, 0, 1),
+                Dimension::Cube => (D3D10_RESOURCE_DIMENSION_TEXTURE2D, D3D10_RESOURCE_MISC_TEXTURECUBE, t.images / 6),
+                _ => (D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, t.images),
+            };
+            for v in [dxgi, dimension, misc, array_size, 0] {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        },
+        PixelFormat::Masks(..) if t.dimension == Dimension::Tex2DArray => {
+            return Err(format!("{:?} arrays need a DXGI format, export as ktx2", t.format).into());
+        },
+        _ => {},
+    }
+    // DDS orders surfaces the way Unity does
+    out.extend_from_slice(&t.data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(format: TextureFormat, dimension: Dimension, images: u32, data: Vec<u8>) -> GpuTexture {
+        GpuTexture{
+            name: String::from("t"), dimension, format, srgb: true,
+            width: 4, height: 4, depth: 1, images, levels: 2, data,
+        }
+    }
+
+    fn field(out: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&out[offset .. offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn dx10_header() {
+        let data: Vec<u8> = (0..80).collect();
+        let out = write(&texture(TextureFormat::RGBA32, Dimension::Tex2D, 1, data.clone())).unwrap();
+        assert_eq!(&out[..4], b"DDS ");
+        assert_eq!(field(&out, 8), DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT | DDSD_PITCH);
+        assert_eq!((field(&out, 20), field(&out, 28)), (16, 2));
+        assert_eq!(&out[84..88], b"DX10");
+        assert_eq!(field(&out, 108), DDSCAPS_TEXTURE | DDSCAPS_COMPLEX | DDSCAPS_MIPMAP);
+        assert_eq!(field(&out, 128), 29);
+        assert_eq!(field(&out, 132), D3D10_RESOURCE_DIMENSION_TEXTURE2D);
+        assert_eq!(field(&out, 140), 1);
+        assert_eq!(&out[148..], &data[..]);
+    }
+
+    #[test]
+    fn mask_header() {
+        let out = write(&texture(TextureFormat::ARGB32, Dimension::Tex2D, 1, vec![0; 80])).unwrap();
+        assert_eq!(out.len(), 128 + 80);
+        assert_eq!(field(&out, 80), DDPF_RGB | DDPF_ALPHAPIXELS);
+        assert_eq!(field(&out, 88), 32);
+        assert_eq!(field(&out, 104), 0xff);
+
+        let array = texture(TextureFormat::ARGB32, Dimension::Tex2DArray, 2, vec![0; 160]);
+        assert!(write(&array).is_err());
+        assert!(write(&texture(TextureFormat::ETC_RGB4, Dimension::Tex2D, 1, vec![0; 16])).is_err());
+    }
+}
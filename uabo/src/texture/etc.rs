@@ -0,0 +1,343 @@
+use super::decode_blocks;
+use crate::image::Image;
+
+/// ETC1 intensity modifiers, indexed by table and pixel index (msb << 1 | lsb)
+const ETC1_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+/// ETC2 T and H mode distances
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// EAC modifiers, indexed by table and 3 bit pixel index
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// ETC_RGB4
+pub fn decode_etc1(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        etc1_block(block_bits(b), out);
+    }))
+}
+
+/// ETC2_RGB
+pub fn decode_etc2_rgb(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        etc2_block(block_bits(b), out, false);
+    }))
+}
+
+/// ETC2_RGBA1, colors with punch-through alpha
+pub fn decode_etc2_rgba1(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        etc2_block(block_bits(b), out, true);
+    }))
+}
+
+/// ETC2_RGBA8, an EAC alpha block followed by an ETC2 color block
+pub fn decode_etc2_rgba8(data: &[u8], width: u32, height: u32) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 16), |b, out| {
+        etc2_block(block_bits(&b[8..]), out, false);
+        for (i, a) in eac_alpha(block_bits(b)).iter().enumerate() {
+            out[i * 4 + 3] = *a;
+        }
+    }))
+}
+
+/// EAC_R and EAC_R_SIGNED, decoded to the red channel
+pub fn decode_eac_r(data: &[u8], width: u32, height: u32, signed: bool) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 8), |b, out| {
+        for (i, r) in eac_r11(block_bits(b), signed).iter().enumerate() {
+            out[i * 4 .. i * 4 + 4].copy_from_slice(&[*r, 0, 0, 255]);
+        }
+    }))
+}
+
+/// EAC_RG and EAC_RG_SIGNED, decoded to the red and green channels
+pub fn decode_eac_rg(data: &[u8], width: u32, height: u32, signed: bool) -> Image {
+    Image::rgba8(width, height, decode_blocks(data, width, height, (4, 4, 16), |b, out| {
+        let r = eac_r11(block_bits(b), signed);
+        let g = eac_r11(block_bits(&b[8..]), signed);
+        for i in 0..16 {
+            out[i * 4 .. i * 4 + 4].copy_from_slice(&[r[i], g[i], 0, 255]);
+        }
+    }))
+}
+
+/// ETC blocks are stored big endian
+fn block_bits(b: &[u8]) -> u64 {
+    u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+}
+
+fn bits(v: u64, hi: u32, lo: u32) -> i32 {
+    (v >> lo & ((1 << (hi - lo + 1)) - 1)) as i32
+}
+
+fn extend4(v: i32) -> i32 {
+    v << 4 | v
+}
+
+fn extend5(v: i32) -> i32 {
+    v << 3 | v >> 2
+}
+
+/// ETC pixel indices are stored column by column; returns (x, y, index) in that order
+fn pixel_indices(v: u64) -> impl Iterator<Item = (usize, usize, usize)> {
+    (0..16).map(move |i| {
+        let msb = (v >> (16 + i) & 1) as usize;
+        let lsb = (v >> i & 1) as usize;
+        (i / 4, i % 4, msb << 1 | lsb)
+    })
+}
+
+fn put(out: &mut [u8], x: usize, y: usize, rgb: [i32; 3], alpha: u8) {
+    let i = (y * 4 + x) * 4;
+    for c in 0..3 {
+        out[i + c] = rgb[c].clamp(0, 255) as u8;
+    }
+    out[i + 3] = alpha;
+}
+
+fn etc1_block(v: u64, out: &mut [u8]) {
+    let (c0, c1) = match v >> 33 & 1 {
+        0 => (
+            [extend4(bits(v, 63, 60)), extend4(bits(v, 55, 52)), extend4(bits(v, 47, 44))],
+            [extend4(bits(v, 59, 56)), extend4(bits(v, 51, 48)), extend4(bits(v, 43, 40))],
+        ),
+        _ => {
+            let (r, g, b) = (bits(v, 63, 59), bits(v, 55, 51), bits(v, 47, 43));
+            let (dr, dg, db) = (signed3(bits(v, 58, 56)), signed3(bits(v, 50, 48)), signed3(bits(v, 42, 40)));
+            (
+                [extend5(r), extend5(g), extend5(b)],
+                [extend5((r + dr) & 0x1f), extend5((g + dg) & 0x1f), extend5((b + db) & 0x1f)],
+            )
+        },
+    };
+    subblocks(v, out, [c0, c1], &ETC1_MODIFIERS, None);
+}
+
+/// decodes the two half blocks of an ETC1 style block
+///
+/// `transparent` asks for punch-through handling: pixel index 2 becomes
+/// transparent black and the modifier tables lose their small entries.
+fn subblocks(v: u64, out: &mut [u8], colors: [[i32; 3]; 2], modifiers: &[[i32; 4]; 8], transparent: Option<bool>) {
+    let tables = [bits(v, 39, 37) as usize, bits(v, 36, 34) as usize];
+    let flip = v >> 32 & 1 == 1;
+    for (x, y, index) in pixel_indices(v) {
+        let half = match flip {
+            true => (y >= 2) as usize,
+            false => (x >= 2) as usize,
+        };
+        let mut modifier = modifiers[tables[half]][index];
+        if transparent == Some(true) {
+            if index == 2 {
+                put(out, x, y, [0, 0, 0], 0);
+                continue;
+            }
+            if index == 0 {
+                modifier = 0;
+            }
+        }
+        let c = colors[half];
+        put(out, x, y, [c[0] + modifier, c[1] + modifier, c[2] + modifier], 255);
+    }
+}
+
+fn signed3(v: i32) -> i32 {
+    (v << 29) >> 29
+}
+
+/// ETC2 color block; `punch_through` reads the differential bit as an opacity flag
+fn etc2_block(v: u64, out: &mut [u8], punch_through: bool) {
+    let differential = punch_through || v >> 33 & 1 == 1;
+    if !differential {
+        etc1_block(v, out);
+        return;
+    }
+    let transparent = match punch_through {
+        true => Some(v >> 33 & 1 == 0),
+        false => None,
+    };
+    let (r, g, b) = (bits(v, 63, 59), bits(v, 55, 51), bits(v, 47, 43));
+    let (dr, dg, db) = (signed3(bits(v, 58, 56)), signed3(bits(v, 50, 48)), signed3(bits(v, 42, 40)));
+    if !(0..32).contains(&(r + dr)) {
+        etc2_t_block(v, out, transparent == Some(true));
+    } else if !(0..32).contains(&(g + dg)) {
+        etc2_h_block(v, out, transparent == Some(true));
+    } else if !(0..32).contains(&(b + db)) {
+        etc2_planar_block(v, out);
+    } else {
+        let colors = [
+            [extend5(r), extend5(g), extend5(b)],
+            [extend5(r + dr), extend5(g + dg), extend5(b + db)],
+        ];
+        subblocks(v, out, colors, &ETC1_MODIFIERS, transparent);
+    }
+}
+
+fn paint(v: u64, out: &mut [u8], paints: [[i32; 3]; 4], transparent: bool) {
+    for (x, y, index) in pixel_indices(v) {
+        match transparent && index == 2 {
+            true => put(out, x, y, [0, 0, 0], 0),
+            false => put(out, x, y, paints[index], 255),
+        }
+    }
+}
+
+fn etc2_t_block(v: u64, out: &mut [u8], transparent: bool) {
+    let c0 = [
+        extend4(bits(v, 60, 59) << 2 | bits(v, 57, 56)),
+        extend4(bits(v, 55, 52)),
+        extend4(bits(v, 51, 48)),
+    ];
+    let c1 = [extend4(bits(v, 47, 44)), extend4(bits(v, 43, 40)), extend4(bits(v, 39, 36))];
+    let d = ETC2_DISTANCES[(bits(v, 35, 34) << 1 | bits(v, 32, 32)) as usize];
+    let paints = [
+        c0,
+        [c1[0] + d, c1[1] + d, c1[2] + d],
+        c1,
+        [c1[0] - d, c1[1] - d, c1[2] - d],
+    ];
+    paint(v, out, paints, transparent);
+}
+
+fn etc2_h_block(v: u64, out: &mut [u8], transparent: bool) {
+    let r0 = bits(v, 62, 59);
+    let g0 = bits(v, 58, 56) << 1 | bits(v, 52, 52);
+    let b0 = bits(v, 51, 51) << 3 | bits(v, 49, 47);
+    let (r1, g1, b1) = (bits(v, 46, 43), bits(v, 42, 39), bits(v, 38, 35));
+    let order = ((r0 << 8 | g0 << 4 | b0) >= (r1 << 8 | g1 << 4 | b1)) as i32;
+    let d = ETC2_DISTANCES[(bits(v, 34, 34) << 2 | bits(v, 32, 32) << 1 | order) as usize];
+    let c0 = [extend4(r0), extend4(g0), extend4(b0)];
+    let c1 = [extend4(r1), extend4(g1), extend4(b1)];
+    let paints = [
+        [c0[0] + d, c0[1] + d, c0[2] + d],
+        [c0[0] - d, c0[1] - d, c0[2] - d],
+        [c1[0] + d, c1[1] + d, c1[2] + d],
+        [c1[0] - d, c1[1] - d, c1[2] - d],
+    ];
+    paint(v, out, paints, transparent);
+}
+
+fn etc2_planar_block(v: u64, out: &mut [u8]) {
+    let extend6 = |c: i32| c << 2 | c >> 4;
+    let extend7 = |c: i32| c << 1 | c >> 6;
+    let o = [
+        extend6(bits(v, 62, 57)),
+        extend7(bits(v, 56, 56) << 6 | bits(v, 54, 49)),
+        extend6(bits(v, 48, 48) << 5 | bits(v, 44, 43) << 3 | bits(v, 41, 39)),
+    ];
+    let h = [
+        extend6(bits(v, 38, 34) << 1 | bits(v, 32, 32)),
+        extend7(bits(v, 31, 25)),
+        extend6(bits(v, 24, 19)),
+    ];
+    let vv = [extend6(bits(v, 18, 13)), extend7(bits(v, 12, 6)), extend6(bits(v, 5, 0))];
+    for y in 0..4 {
+        for x in 0..4 {
+            let (xi, yi) = (x as i32, y as i32);
+            let c = [0, 1, 2].map(|i| (xi * (h[i] - o[i]) + yi * (vv[i] - o[i]) + 4 * o[i] + 2) >> 2);
+            put(out, x, y, c, 255);
+        }
+    }
+}
+
+/// EAC pixel values before scaling, in row order: (base, multiplier, modifier) per pixel
+fn eac_values(v: u64) -> [(i32, i32, i32); 16] {
+    let base = bits(v, 63, 56);
+    let multiplier = bits(v, 55, 52);
+    let table = &EAC_MODIFIERS[bits(v, 51, 48) as usize];
+    let mut values = [(0, 0, 0); 16];
+    for i in 0..16 {
+        let index = (v >> (45 - i * 3) & 7) as usize;
+        values[(i % 4) * 4 + i / 4] = (base, multiplier, table[index]);
+    }
+    values
+}
+
+/// ETC2 8 bit alpha block
+fn eac_alpha(v: u64) -> [u8; 16] {
+    eac_values(v).map(|(base, multiplier, modifier)| (base + modifier * multiplier).clamp(0, 255) as u8)
+}
+
+/// EAC 11 bit channel, rescaled to 8 bits
+fn eac_r11(v: u64, signed: bool) -> [u8; 16] {
+    eac_values(v).map(|(base, multiplier, modifier)| {
+        let scale = match multiplier {
+            0 => 1,
+            m => m * 8,
+        };
+        match signed {
+            true => {
+                let value = (base as i8 as i32 * 8 + modifier * scale).clamp(-1023, 1023);
+                ((value + 1023) * 255 / 2046) as u8
+            },
+            false => {
+                let value = (base * 8 + 4 + modifier * scale).clamp(0, 2047);
+                (value * 255 / 2047) as u8
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Pixels;
+
+    /// EAC: base 128, multiplier 1, table 0; index 7 (+14) at (0, 0) and 0 (-3) elsewhere
+    const EAC_BLOCK: [u8; 8] = [128, 0x10, 0xe0, 0, 0, 0, 0, 0];
+
+    fn texels(image: Image) -> Vec<u8> {
+        match image.pixels {
+            Pixels::Rgba8(p) => p,
+            Pixels::RgbaF32(_) => panic!("expected 8 bit texels"),
+        }
+    }
+
+    #[test]
+    fn etc2_rgba8_individual_block() {
+        // individual colors 0x88 and 0x00, table 0 (+-2, +-8), side by side;
+        // (0, 0) takes +8 and (3, 3) takes -8, everything else +2
+        let mut block = EAC_BLOCK.to_vec();
+        block.extend([0x80, 0x80, 0x80, 0x00, 0x80, 0x00, 0x80, 0x01]);
+        let t = texels(decode_etc2_rgba8(&block, 4, 4));
+        let texel = |x: usize, y: usize| &t[(y * 4 + x) * 4 .. (y * 4 + x) * 4 + 4];
+        assert_eq!(texel(0, 0), [144, 144, 144, 142]);
+        assert_eq!(texel(1, 0), [138, 138, 138, 125]);
+        assert_eq!(texel(1, 3), [138, 138, 138, 125]);
+        assert_eq!(texel(2, 0), [2, 2, 2, 125]);
+        assert_eq!(texel(3, 3), [0, 0, 0, 125]);
+    }
+
+    #[test]
+    fn eac_r11_block() {
+        // (128 * 8 + 4 + modifier * 8) scaled from 11 to 8 bits
+        let t = texels(decode_eac_r(&EAC_BLOCK, 4, 4, false));
+        assert_eq!(t[..8], [142, 0, 0, 255, 125, 0, 0, 255]);
+        assert!(t.chunks(4).skip(1).all(|t| t == [125, 0, 0, 255]));
+    }
+}
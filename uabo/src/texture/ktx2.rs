@@ -0,0 +1,313 @@
+use super::TextureFormat;
+use crate::gpu_texture::{Dimension, GpuTexture};
+use crate::Result;
+
+const IDENTIFIER: [u8; 12] = [0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+
+// Khronos data format descriptor: color models
+const MODEL_RGBSDA: u8 = 1;
+const MODEL_BC1A: u8 = 128;
+const MODEL_BC3: u8 = 130;
+const MODEL_BC4: u8 = 131;
+const MODEL_BC5: u8 = 132;
+const MODEL_BC6H: u8 = 133;
+const MODEL_BC7: u8 = 134;
+const MODEL_ETC1: u8 = 160;
+const MODEL_ETC2: u8 = 161;
+const MODEL_ASTC: u8 = 162;
+const MODEL_PVRTC: u8 = 164;
+
+// channel ids and qualifiers
+const R: u8 = 0;
+const G: u8 = 1;
+const B: u8 = 2;
+const A: u8 = 15;
+const ETC2_COLOR: u8 = 2;
+const LINEAR: u8 = 0x10;
+const EXPONENT: u8 = 0x20;
+const SIGNED: u8 = 0x40;
+const FLOAT: u8 = 0x80;
+
+const TRANSFER_LINEAR: u8 = 1;
+const TRANSFER_SRGB: u8 = 2;
+const PRIMARIES_BT709: u8 = 1;
+
+/// sample of a descriptor: channel and qualifiers, bit offset, bit length
+type Sample = (u8, u16, u8);
+
+/// VkFormat and type size of a texture format
+fn vk_format(format: TextureFormat, srgb: bool) -> Option<(u32, u32)> {
+    use TextureFormat::*;
+    let s = |linear: u32, srgb_format: u32| match srgb {
+        true => srgb_format,
+        false => linear,
+    };
+    let astc_hdr = |i: u32| 1000066000 + i;
+    Some(match format {
+        Alpha8 => (1000470001, 1),
+        R8 => (s(9, 15), 1),
+        RG16 => (s(16, 22), 1),
+        RGB24 => (s(23, 29), 1),
+        RGBA32 => (s(37, 43), 1),
+        BGRA32 => (s(44, 50), 1),
+        R16 => (70, 2),
+        RG32 => (77, 2),
+        RGB48 => (84, 2),
+        RGBA64 => (91, 2),
+        RHalf => (76, 2),
+        RGHalf => (83, 2),
+        RGBAHalf => (97, 2),
+        RFloat => (100, 4),
+        RGFloat => (103, 4),
+        RGBAFloat => (109, 4),
+        RGB565 => (4, 2),
+        RGBA4444 => (2, 2),
+        ARGB4444 => (1000340000, 2),
+        RGB9e5Float => (123, 4),
+        DXT1 => (s(131, 132), 1),
+        DXT5 => (s(137, 138), 1),
+        BC4 => (139, 1),
+        BC5 => (141, 1),
+        BC6H => (143, 1),
+        BC7 => (s(145, 146), 1),
+        ETC_RGB4 | ETC2_RGB => (s(147, 148), 1),
+        ETC2_RGBA1 => (s(149, 150), 1),
+        ETC2_RGBA8 => (s(151, 152), 1),
+        EAC_R => (153, 1),
+        EAC_R_SIGNED => (154, 1),
+        EAC_RG => (155, 1),
+        EAC_RG_SIGNED => (156, 1),
+        PVRTC_RGB2 | PVRTC_RGBA2 => (s(1000054000, 1000054004), 1),
+        PVRTC_RGB4 | PVRTC_RGBA4 => (s(1000054001, 1000054005), 1),
+        ASTC_4x4 | ASTC_RGBA_4x4 => (s(157, 158), 1),
+        ASTC_5x5 | ASTC_RGBA_5x5 => (s(161, 162), 1),
+        ASTC_6x6 | ASTC_RGBA_6x6 => (s(165, 166), 1),
+        ASTC_8x8 | ASTC_RGBA_8x8 => (s(171, 172), 1),
+        ASTC_10x10 | ASTC_RGBA_10x10 => (s(179, 180), 1),
+        ASTC_12x12 | ASTC_RGBA_12x12 => (s(183, 184), 1),
+        ASTC_HDR_4x4 => (astc_hdr(0), 1),
+        ASTC_HDR_5x5 => (astc_hdr(2), 1),
+        ASTC_HDR_6x6 => (astc_hdr(4), 1),
+        ASTC_HDR_8x8 => (astc_hdr(7), 1),
+        ASTC_HDR_10x10 => (astc_hdr(11), 1),
+        ASTC_HDR_12x12 => (astc_hdr(13), 1),
+        _ => return None,
+    })
+}
+
+/// color model and samples of the data format descriptor
+fn descriptor_samples(format: TextureFormat) -> Option<(u8, Vec<Sample>)> {
+    use TextureFormat::*;
+    let rgbsda = |samples: Vec<Sample>| Some((MODEL_RGBSDA, samples));
+    let channels = |channels: &[u8], bits: u8, qualifiers: u8| channels.iter().enumerate()
+        .map(|(i, c)| (c | qualifiers, i as u16 * bits as u16, bits))
+        .collect::<Vec<Sample>>();
+    match format {
+        Alpha8 => rgbsda(channels(&[A], 8, 0)),
+        R8 => rgbsda(channels(&[R], 8, 0)),
+        RG16 => rgbsda(channels(&[R, G], 8, 0)),
+        RGB24 => rgbsda(channels(&[R, G, B], 8, 0)),
+        RGBA32 => rgbsda(channels(&[R, G, B, A], 8, 0)),
+        BGRA32 => rgbsda(channels(&[B, G, R, A], 8, 0)),
+        R16 => rgbsda(channels(&[R], 16, 0)),
+        RG32 => rgbsda(channels(&[R, G], 16, 0)),
+        RGB48 => rgbsda(channels(&[R, G, B], 16, 0)),
+        RGBA64 => rgbsda(channels(&[R, G, B, A], 16, 0)),
+        RHalf => rgbsda(channels(&[R], 16, FLOAT | SIGNED)),
+        RGHalf => rgbsda(channels(&[R, G], 16, FLOAT | SIGNED)),
+        RGBAHalf => rgbsda(channels(&[R, G, B, A], 16, FLOAT | SIGNED)),
+        RFloat => rgbsda(channels(&[R], 32, FLOAT | SIGNED)),
+        RGFloat => rgbsda(channels(&[R, G], 32, FLOAT | SIGNED)),
+        RGBAFloat => rgbsda(channels(&[R, G, B, A], 32, FLOAT | SIGNED)),
+        RGB565 => rgbsda(vec![(B, 0, 5), (G, 5, 6), (R, 11, 5)]),
+        RGBA4444 => rgbsda(vec![(A, 0, 4), (B, 4, 4), (G, 8, 4), (R, 12, 4)]),
+        ARGB4444 => rgbsda(vec![(B, 0, 4), (G, 4, 4), (R, 8, 4), (A, 12, 4)]),
+        RGB9e5Float => rgbsda(vec![
+            (R, 0, 9), (R | EXPONENT, 27, 5),
+            (G, 9, 9), (G | EXPONENT, 27, 5),
+            (B, 18, 9), (B | EXPONENT, 27, 5),
+        ]),
+        DXT1 => Some((MODEL_BC1A, vec![(0, 0, 64)])),
+        DXT5 => Some((MODEL_BC3, vec![(A, 0, 64), (0, 64, 64)])),
+        BC4 => Some((MODEL_BC4, vec![(0, 0, 64)])),
+        BC5 => Some((MODEL_BC5, vec![(0, 0, 64), (1, 64, 64)])),
+        BC6H => Some((MODEL_BC6H, vec![(FLOAT, 0, 128)])),
+        BC7 => Some((MODEL_BC7, vec![(0, 0, 128)])),
+        ETC_RGB4 => Some((MODEL_ETC1, vec![(0, 0, 64)])),
+        ETC2_RGB => Some((MODEL_ETC2, vec![(ETC2_COLOR, 0, 64)])),
+        ETC2_RGBA1 => Some((MODEL_ETC2, vec![(ETC2_COLOR, 0, 64)])),
+        ETC2_RGBA8 => Some((MODEL_ETC2, vec![(A, 0, 64), (ETC2_COLOR, 64, 64)])),
+        EAC_R => Some((MODEL_ETC2, vec![(R, 0, 64)])),
+        EAC_R_SIGNED => Some((MODEL_ETC2, vec![(R | SIGNED, 0, 64)])),
+        EAC_RG => Some((MODEL_ETC2, vec![(R, 0, 64), (G, 64, 64)])),
+        EAC_RG_SIGNED => Some((MODEL_ETC2, vec![(R | SIGNED, 0, 64), (G | SIGNED, 64, 64)])),
+        PVRTC_RGB2 | PVRTC_RGBA2 | PVRTC_RGB4 | PVRTC_RGBA4 => Some((MODEL_PVRTC, vec![(0, 0, 64)])),
+        ASTC_HDR_4x4 | ASTC_HDR_5x5 | ASTC_HDR_6x6 | ASTC_HDR_8x8 | ASTC_HDR_10x10 | ASTC_HDR_12x12 => {
+            Some((MODEL_ASTC, vec![(FLOAT | SIGNED, 0, 128)]))
+        },
+        _ if format.astc_block().is_some() => Some((MODEL_ASTC, vec![(0, 0, 128)])),
+        _ => None,
+    }
+}
+
+/// basic data format descriptor block, preceded by the total size
+fn descriptor(format: TextureFormat, srgb: bool) -> Option<Vec<u8>> {
+    let (model, samples) = descriptor_samples(format)?;
+    let (bw, bh, bytes) = format.block_layout()?;
+    let block_size = 24 + 16 * samples.len();
+    let mut d = Vec::with_capacity(4 + block_size);
+    d.extend_from_slice(&(4 + block_size as u32).to_le_bytes());
+    d.extend_from_slice(&0u32.to_le_bytes());           // Khronos vendor, basic descriptor type
+    d.extend_from_slice(&2u16.to_le_bytes());           // version 1.3
+    d.extend_from_slice(&(block_size as u16).to_le_bytes());
+    let transfer = match srgb {
+        true => TRANSFER_SRGB,
+        false => TRANSFER_LINEAR,
+    };
+    d.extend_from_slice(&[model, PRIMARIES_BT709, transfer, 0]);
+    d.extend_from_slice(&[(bw - 1) as u8, (bh - 1) as u8, 0, 0]);
+    d.extend_from_slice(&[bytes as u8, 0, 0, 0, 0, 0, 0, 0]);
+    for (channel, offset, bits) in samples {
+        let float = channel & FLOAT != 0;
+        let signed = channel & SIGNED != 0;
+        let exponent = channel & EXPONENT != 0;
+        // alpha stays linear in sRGB formats
+        let channel = match srgb && channel & 0xf == A {
+            true => channel | LINEAR,
+            false => channel,
+        };
+        let (lower, upper) = match (float, signed, exponent) {
+            (true, _, _) => (0xbf800000u32, 0x3f800000u32),
+            (_, _, true) => (15, 31),
+            (_, true, _) => (0x80000000u32 >> (32 - bits.min(32) as u32), u32::MAX >> (33 - bits.min(32) as u32)),
+            _ if bits >= 32 => (0, u32::MAX),
+            _ => (0, (1u32 << bits) - 1),
+        };
+        // RGB9E5 mantissas reach 1.0 at 256
+        let upper = match format == TextureFormat::RGB9e5Float && !exponent {
+            true => 256,
+            false => upper,
+        };
+        d.extend_from_slice(&offset.to_le_bytes());
+        d.extend_from_slice(&[bits - 1, channel]);
+        d.extend_from_slice(&[0, 0, 0, 0]);
+        d.extend_from_slice(&lower.to_le_bytes());
+        d.extend_from_slice(&upper.to_le_bytes());
+    }
+    Some(d)
+}
+
+/// key/value data: orientation of Unity's bottom-up rows and the writer, sorted by key
+fn key_values() -> Vec<u8> {
+    let mut kvd = Vec::new();
+    for (key, value) in [("KTXorientation", "ru"), ("KTXwriter", "uabo")] {
+        let len = key.len() + value.len() + 2;
+        kvd.extend_from_slice(&(len as u32).to_le_bytes());
+        kvd.extend_from_slice(key.as_bytes());
+        kvd.push(0);
+        kvd.extend_from_slice(value.as_bytes());
+        kvd.push(0);
+        kvd.resize(kvd.len().div_ceil(4) * 4, 0);
+    }
+    kvd
+}
+
+fn pad(out: &mut Vec<u8>, alignment: usize) {
+    out.resize(out.len().div_ceil(alignment) * alignment, 0);
+}
+
+/// KTX2 file of a texture
+///
+/// Surfaces are regrouped level by level as KTX2 requires, smallest level
+/// first in the file; the bytes of each surface are copied unchanged and
+/// tagged with the bottom-up orientation Unity stores.
+pub fn write(t: &GpuTexture) -> Result<Vec<u8>> {
+    let unsupported = || format!("{:?} has no KTX2 format", t.format);
+    let (vk_format, type_size) = vk_format(t.format, t.srgb).ok_or_else(unsupported)?;
+    let dfd = descriptor(t.format, t.srgb).ok_or_else(unsupported)?;
+    let kvd = key_values();
+    let (layers, faces) = match t.dimension {
+        Dimension::Cube => (0, 6),
+        Dimension::Tex2DArray => (t.images, 1),
+        _ => (0, 1),
+    };
+    let depth = match t.dimension {
+        Dimension::Tex3D => t.depth,
+        _ => 0,
+    };
+
+    let header_size = 80 + 24 * t.levels as usize;
+    let mut out = Vec::with_capacity(header_size + dfd.len() + kvd.len() + t.data.len() + 64);
+    out.extend_from_slice(&IDENTIFIER);
+    for v in [vk_format, type_size, t.width, t.height, depth, layers, faces, t.levels, 0] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    let dfd_offset = header_size;
+    let kvd_offset = dfd_offset + dfd.len();
+    for v in [dfd_offset, dfd.len(), kvd_offset, kvd.len()] {
+        out.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&[0u8; 16]);     // no supercompression global data
+    let index = out.len();
+    out.resize(header_size, 0);
+    out.extend_from_slice(&dfd);
+    out.extend_from_slice(&kvd);
+
+    // levels start on multiples of the block size and of 4
+    let (_, _, block_bytes) = t.format.block_layout().ok_or_else(unsupported)?;
+    let alignment = match block_bytes % 4 {
+        0 => block_bytes as usize,
+        _ if block_bytes % 2 == 0 => block_bytes as usize * 2,
+        _ => block_bytes as usize * 4,
+    };
+    for level in (0..t.levels).rev() {
+        pad(&mut out, alignment);
+        let offset = out.len();
+        for image in 0..t.images {
+            out.extend_from_slice(t.surface(image, level)?);
+        }
+        let length = (out.len() - offset) as u64;
+        let entry = index + 24 * level as usize;
+        out[entry .. entry + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+        out[entry + 8 .. entry + 16].copy_from_slice(&length.to_le_bytes());
+        out[entry + 16 .. entry + 24].copy_from_slice(&length.to_le_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(out: &[u8], offset: usize) -> u64 {
+        let mut bytes = [0; 8];
+        let len = if offset >= 80 { 8 } else { 4 };
+        bytes[..len].copy_from_slice(&out[offset .. offset + len]);
+        u64::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn level_index() {
+        let mut data: Vec<u8> = (0..64).collect();
+        data.extend([200; 16]);
+        let t = GpuTexture{
+            name: String::from("t"), dimension: Dimension::Tex2D, format: TextureFormat::RGBA32, srgb: true,
+            width: 4, height: 4, depth: 1, images: 1, levels: 2, data,
+        };
+        let out = write(&t).unwrap();
+        assert_eq!(&out[..12], &IDENTIFIER);
+        assert_eq!((field(&out, 12), field(&out, 16)), (43, 1));
+        assert_eq!((field(&out, 20), field(&out, 24), field(&out, 36), field(&out, 40)), (4, 4, 1, 2));
+        assert_eq!(field(&out, 48), 80 + 2 * 24);
+
+        // smallest level first
+        let (offset0, length0) = (field(&out, 80) as usize, field(&out, 88) as usize);
+        let (offset1, length1) = (field(&out, 104) as usize, field(&out, 112) as usize);
+        assert_eq!((length0, length1), (64, 16));
+        assert!(offset1 < offset0);
+        assert_eq!(offset0 % 4, 0);
+        assert_eq!(&out[offset1 .. offset1 + 16], &[200; 16]);
+        assert_eq!(&out[offset0 .. offset0 + 64], &t.data[..64]);
+        assert_eq!(out.len(), offset0 + 64);
+    }
+}
@@ -0,0 +1,412 @@
+mod raw;
+mod bcn;
+mod etc;
+mod astc;
+mod pvrtc;
+mod crunch;
+pub mod dds;
+pub mod ktx2;
+
+use serde::{Serialize, Deserialize};
+
+use crate::image::Image;
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+/// UnityEngine.TextureFormat
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Alpha8,
+    ARGB4444,
+    RGB24,
+    RGBA32,
+    ARGB32,
+    RGB565,
+    R16,
+    DXT1,
+    DXT5,
+    RGBA4444,
+    BGRA32,
+    RHalf,
+    RGHalf,
+    RGBAHalf,
+    RFloat,
+    RGFloat,
+    RGBAFloat,
+    YUY2,
+    RGB9e5Float,
+    BC6H,
+    BC7,
+    BC4,
+    BC5,
+    DXT1Crunched,
+    DXT5Crunched,
+    PVRTC_RGB2,
+    PVRTC_RGBA2,
+    PVRTC_RGB4,
+    PVRTC_RGBA4,
+    ETC_RGB4,
+    EAC_R,
+    EAC_R_SIGNED,
+    EAC_RG,
+    EAC_RG_SIGNED,
+    ETC2_RGB,
+    ETC2_RGBA1,
+    ETC2_RGBA8,
+    ASTC_4x4,
+    ASTC_5x5,
+    ASTC_6x6,
+    ASTC_8x8,
+    ASTC_10x10,
+    ASTC_12x12,
+    ASTC_RGBA_4x4,
+    ASTC_RGBA_5x5,
+    ASTC_RGBA_6x6,
+    ASTC_RGBA_8x8,
+    ASTC_RGBA_10x10,
+    ASTC_RGBA_12x12,
+    ETC_RGB4_3DS,
+    ETC_RGBA8_3DS,
+    RG16,
+    R8,
+    ETC_RGB4Crunched,
+    ETC2_RGBA8Crunched,
+    ASTC_HDR_4x4,
+    ASTC_HDR_5x5,
+    ASTC_HDR_6x6,
+    ASTC_HDR_8x8,
+    ASTC_HDR_10x10,
+    ASTC_HDR_12x12,
+    RG32,
+    RGB48,
+    RGBA64,
+    Unknown(i32),
+}
+
+impl TextureFormat {
+    pub fn new(id: i32) -> TextureFormat {
+        use TextureFormat::*;
+        match id {
+            1 => Alpha8,
+            2 => ARGB4444,
+            3 => RGB24,
+            4 => RGBA32,
+            5 => ARGB32,
+            7 => RGB565,
+            9 => R16,
+            10 => DXT1,
+            12 => DXT5,
+            13 => RGBA4444,
+            14 => BGRA32,
+            15 => RHalf,
+            16 => RGHalf,
+            17 => RGBAHalf,
+            18 => RFloat,
+            19 => RGFloat,
+            20 => RGBAFloat,
+            21 => YUY2,
+            22 => RGB9e5Float,
+            24 => BC6H,
+            25 => BC7,
+            26 => BC4,
+            27 => BC5,
+            28 => DXT1Crunched,
+            29 => DXT5Crunched,
+            30 => PVRTC_RGB2,
+            31 => PVRTC_RGBA2,
+            32 => PVRTC_RGB4,
+            33 => PVRTC_RGBA4,
+            34 => ETC_RGB4,
+            41 => EAC_R,
+            42 => EAC_R_SIGNED,
+            43 => EAC_RG,
+            44 => EAC_RG_SIGNED,
+            45 => ETC2_RGB,
+            46 => ETC2_RGBA1,
+            47 => ETC2_RGBA8,
+            48 => ASTC_4x4,
+            49 => ASTC_5x5,
+            50 => ASTC_6x6,
+            51 => ASTC_8x8,
+            52 => ASTC_10x10,
+            53 => ASTC_12x12,
+            54 => ASTC_RGBA_4x4,
+            55 => ASTC_RGBA_5x5,
+            56 => ASTC_RGBA_6x6,
+            57 => ASTC_RGBA_8x8,
+            58 => ASTC_RGBA_10x10,
+            59 => ASTC_RGBA_12x12,
+            60 => ETC_RGB4_3DS,
+            61 => ETC_RGBA8_3DS,
+            62 => RG16,
+            63 => R8,
+            64 => ETC_RGB4Crunched,
+            65 => ETC2_RGBA8Crunched,
+            66 => ASTC_HDR_4x4,
+            67 => ASTC_HDR_5x5,
+            68 => ASTC_HDR_6x6,
+            69 => ASTC_HDR_8x8,
+            70 => ASTC_HDR_10x10,
+            71 => ASTC_HDR_12x12,
+            72 => RG32,
+            73 => RGB48,
+            74 => RGBA64,
+            _ => Unknown(id),
+        }
+    }
+
+    /// TextureFormat and sRGB flag of a UnityEngine.Experimental.Rendering.GraphicsFormat
+    ///
+    /// Texture2DArray and Texture3D store a GraphicsFormat since 2019.1.
+    pub fn from_graphics_format(id: i32) -> Option<(TextureFormat, bool)> {
+        use TextureFormat::*;
+        let format = match id {
+            1 | 5 => R8,
+            2 | 6 => RG16,
+            3 | 7 => RGB24,
+            4 | 8 => RGBA32,
+            21 => R16,
+            22 => RG32,
+            23 => RGB48,
+            24 => RGBA64,
+            45 => RHalf,
+            46 => RGHalf,
+            48 => RGBAHalf,
+            49 => RFloat,
+            50 => RGFloat,
+            52 => RGBAFloat,
+            57 | 59 => BGRA32,
+            66 => RGBA4444,
+            68 => RGB565,
+            73 => RGB9e5Float,
+            96 | 97 => DXT1,
+            100 | 101 => DXT5,
+            102 => BC4,
+            104 => BC5,
+            106 => BC6H,
+            108 | 109 => BC7,
+            110 | 111 => PVRTC_RGB2,
+            112 | 113 => PVRTC_RGB4,
+            114 | 115 => PVRTC_RGBA2,
+            116 | 117 => PVRTC_RGBA4,
+            118 => ETC_RGB4,
+            119 | 120 => ETC2_RGB,
+            121 | 122 => ETC2_RGBA1,
+            123 | 124 => ETC2_RGBA8,
+            125 => EAC_R,
+            126 => EAC_R_SIGNED,
+            127 => EAC_RG,
+            128 => EAC_RG_SIGNED,
+            129 | 130 => ASTC_RGBA_4x4,
+            131 | 132 => ASTC_RGBA_5x5,
+            133 | 134 => ASTC_RGBA_6x6,
+            135 | 136 => ASTC_RGBA_8x8,
+            137 | 138 => ASTC_RGBA_10x10,
+            139 | 140 => ASTC_RGBA_12x12,
+            145 => ASTC_HDR_4x4,
+            146 => ASTC_HDR_5x5,
+            147 => ASTC_HDR_6x6,
+            148 => ASTC_HDR_8x8,
+            149 => ASTC_HDR_10x10,
+            150 => ASTC_HDR_12x12,
+            _ => return None,
+        };
+        // compressed formats alternate sRGB and UNorm variants
+        let srgb = match id {
+            1..=4 | 57 => true,
+            96..=101 | 108..=117 => id % 2 == 0,
+            119..=124 | 129..=140 => id % 2 == 1,
+            _ => false,
+        };
+        Some((format, srgb))
+    }
+
+    /// (block width, block height, bytes per block); plain formats are 1x1 blocks
+    pub fn block_layout(self) -> Option<(u32, u32, u32)> {
+        use TextureFormat::*;
+        match self {
+            Alpha8 | R8 => Some((1, 1, 1)),
+            ARGB4444 | RGBA4444 | RGB565 | R16 | RG16 | RHalf => Some((1, 1, 2)),
+            RGB24 => Some((1, 1, 3)),
+            RGBA32 | ARGB32 | BGRA32 | RGHalf | RFloat | RGB9e5Float | RG32 => Some((1, 1, 4)),
+            RGB48 => Some((1, 1, 6)),
+            RGBAHalf | RGFloat | RGBA64 => Some((1, 1, 8)),
+            RGBAFloat => Some((1, 1, 16)),
+            YUY2 => Some((2, 1, 4)),
+            DXT1 | BC4 => Some((4, 4, 8)),
+            DXT5 | BC5 | BC6H | BC7 => Some((4, 4, 16)),
+            ETC_RGB4 | ETC2_RGB | ETC2_RGBA1 | EAC_R | EAC_R_SIGNED => Some((4, 4, 8)),
+            ETC2_RGBA8 | EAC_RG | EAC_RG_SIGNED => Some((4, 4, 16)),
+            PVRTC_RGB2 | PVRTC_RGBA2 => Some((8, 4, 8)),
+            PVRTC_RGB4 | PVRTC_RGBA4 => Some((4, 4, 8)),
+            _ => self.astc_block().map(|(w, h)| (w, h, 16)),
+        }
+    }
+
+    /// ASTC block footprint
+    pub fn astc_block(self) -> Option<(u32, u32)> {
+        use TextureFormat::*;
+        match self {
+            ASTC_4x4 | ASTC_RGBA_4x4 | ASTC_HDR_4x4 => Some((4, 4)),
+            ASTC_5x5 | ASTC_RGBA_5x5 | ASTC_HDR_5x5 => Some((5, 5)),
+            ASTC_6x6 | ASTC_RGBA_6x6 | ASTC_HDR_6x6 => Some((6, 6)),
+            ASTC_8x8 | ASTC_RGBA_8x8 | ASTC_HDR_8x8 => Some((8, 8)),
+            ASTC_10x10 | ASTC_RGBA_10x10 | ASTC_HDR_10x10 => Some((10, 10)),
+            ASTC_12x12 | ASTC_RGBA_12x12 | ASTC_HDR_12x12 => Some((12, 12)),
+            _ => None,
+        }
+    }
+
+    /// block format a crunched format transcodes to
+    pub fn crunch_base(self) -> Option<TextureFormat> {
+        use TextureFormat::*;
+        match self {
+            DXT1Crunched => Some(DXT1),
+            DXT5Crunched => Some(DXT5),
+            ETC_RGB4Crunched => Some(ETC_RGB4),
+            ETC2_RGBA8Crunched => Some(ETC2_RGBA8),
+            _ => None,
+        }
+    }
+
+    fn is_pvrtc(self) -> bool {
+        use TextureFormat::*;
+        matches!(self, PVRTC_RGB2 | PVRTC_RGBA2 | PVRTC_RGB4 | PVRTC_RGBA4)
+    }
+
+    /// byte size of one mip level
+    pub fn level_size(self, width: u32, height: u32) -> Option<usize> {
+        let (bw, bh, bytes) = self.block_layout()?;
+        let mut blocks_x = width.max(1).div_ceil(bw) as usize;
+        let mut blocks_y = height.max(1).div_ceil(bh) as usize;
+        if self.is_pvrtc() {
+            // PVRTC levels are padded to at least 2x2 blocks
+            blocks_x = blocks_x.max(2);
+            blocks_y = blocks_y.max(2);
+        }
+        Some(blocks_x * blocks_y * bytes as usize)
+    }
+}
+
+/// transcodes crunched texture data into the blocks of its base format
+///
+/// Unity switched to its own crunch bitstream in 2017.3; the ETC variants
+/// only ever existed in that revision.
+pub fn uncrunch(format: TextureFormat, data: &[u8], version: &UnityVersion) -> Result<(TextureFormat, Vec<u8>)> {
+    use TextureFormat::*;
+    let base = format.crunch_base()
+        .ok_or_else(|| format!("{:?} is not a crunched format", format))?;
+    let unity = match format {
+        ETC_RGB4Crunched | ETC2_RGBA8Crunched => true,
+        _ => version.release() >= (2017, 3),
+    };
+    Ok((base, crunch::transcode(data, unity)?))
+}
+
+/// decodes the top mip level of `data` into an image with bottom-left origin
+///
+/// `srgb` only changes the ASTC endpoint expansion; other formats decode the
+/// stored values as they are.
+pub fn decode(format: TextureFormat, data: &[u8], width: u32, height: u32, srgb: bool) -> Result<Image> {
+    use TextureFormat::*;
+    if let Some(size) = format.level_size(width, height) {
+        if data.len() < size {
+            return Err(format!("{:?} {}x{} needs {} bytes, got {}", format, width, height, size, data.len()).into());
+        }
+    }
+    match format {
+        DXT1 => Ok(bcn::decode_bc1(data, width, height)),
+        DXT5 => Ok(bcn::decode_bc3(data, width, height)),
+        BC4 => Ok(bcn::decode_bc4(data, width, height)),
+        BC5 => Ok(bcn::decode_bc5(data, width, height)),
+        BC6H => Ok(bcn::decode_bc6h(data, width, height)),
+        BC7 => Ok(bcn::decode_bc7(data, width, height)),
+        ETC_RGB4 => Ok(etc::decode_etc1(data, width, height)),
+        ETC2_RGB => Ok(etc::decode_etc2_rgb(data, width, height)),
+        ETC2_RGBA1 => Ok(etc::decode_etc2_rgba1(data, width, height)),
+        ETC2_RGBA8 => Ok(etc::decode_etc2_rgba8(data, width, height)),
+        EAC_R => Ok(etc::decode_eac_r(data, width, height, false)),
+        EAC_R_SIGNED => Ok(etc::decode_eac_r(data, width, height, true)),
+        EAC_RG => Ok(etc::decode_eac_rg(data, width, height, false)),
+        EAC_RG_SIGNED => Ok(etc::decode_eac_rg(data, width, height, true)),
+        PVRTC_RGB2 | PVRTC_RGBA2 => Ok(pvrtc::decode(data, width, height, true)),
+        PVRTC_RGB4 | PVRTC_RGBA4 => Ok(pvrtc::decode(data, width, height, false)),
+        ASTC_HDR_4x4 | ASTC_HDR_5x5 | ASTC_HDR_6x6 | ASTC_HDR_8x8 | ASTC_HDR_10x10 | ASTC_HDR_12x12 => {
+            let (bw, bh) = format.astc_block().unwrap();
+            Ok(astc::decode_hdr(data, width, height, bw, bh))
+        },
+        _ if format.astc_block().is_some() => {
+            let (bw, bh) = format.astc_block().unwrap();
+            Ok(astc::decode_ldr(data, width, height, bw, bh, srgb))
+        },
+        _ => raw::decode(format, data, width, height),
+    }
+}
+
+/// runs `decode_block` over every block of `data` and stitches the texels
+///
+/// `decode_block` fills `block_width * block_height` RGBA texels in row order;
+/// the result holds `width * height` RGBA texels.
+fn decode_blocks<T: Copy + Default>(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    (block_width, block_height, block_bytes): (u32, u32, u32),
+    mut decode_block: impl FnMut(&[u8], &mut [T]),
+) -> Vec<T> {
+    let (width, height) = (width as usize, height as usize);
+    let (bw, bh) = (block_width as usize, block_height as usize);
+    let blocks_x = width.div_ceil(bw);
+    let blocks_y = height.div_ceil(bh);
+    let mut out = vec![T::default(); width * height * 4];
+    let mut texels = vec![T::default(); bw * bh * 4];
+    for (i, block) in data.chunks_exact(block_bytes as usize).take(blocks_x * blocks_y).enumerate() {
+        decode_block(block, &mut texels);
+        let (x0, y0) = (i % blocks_x * bw, i / blocks_x * bh);
+        for y in 0..bh.min(height - y0) {
+            let w = bw.min(width - x0);
+            let dst = ((y0 + y) * width + x0) * 4;
+            out[dst .. dst + w * 4].copy_from_slice(&texels[y * bw * 4 .. (y * bw + w) * 4]);
+        }
+    }
+    out
+}
+
+/// IEEE 754 half precision to single precision
+pub fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h >> 15) as u32) << 31;
+    let exp = ((h >> 10) & 0x1f) as u32;
+    let mant = (h & 0x3ff) as u32;
+    let bits = match (exp, mant) {
+        (0, 0) => sign,
+        (0, _) => {
+            // subnormal
+            let shift = mant.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((mant << shift) & 0x3ff) << 13)
+        },
+        (0x1f, _) => sign | 0x7f800000 | (mant << 13),
+        _ => sign | ((exp + 112) << 23) | (mant << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphics_formats() {
+        use TextureFormat::*;
+        assert_eq!(TextureFormat::from_graphics_format(96), Some((DXT1, true)));
+        assert_eq!(TextureFormat::from_graphics_format(97), Some((DXT1, false)));
+        assert_eq!(TextureFormat::from_graphics_format(123), Some((ETC2_RGBA8, true)));
+        assert_eq!(TextureFormat::from_graphics_format(124), Some((ETC2_RGBA8, false)));
+        // the EAC formats have no sRGB variants
+        assert_eq!(TextureFormat::from_graphics_format(125), Some((EAC_R, false)));
+        assert_eq!(TextureFormat::from_graphics_format(127), Some((EAC_RG, false)));
+        assert_eq!(TextureFormat::from_graphics_format(129), Some((ASTC_RGBA_4x4, true)));
+        assert_eq!(TextureFormat::from_graphics_format(140), Some((ASTC_RGBA_12x12, false)));
+        assert_eq!(TextureFormat::from_graphics_format(0), None);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::image::Image;
+
+/// PVRTC 4bpp punch-through modulation; values above 10 mark transparent texels
+const PUNCH_THROUGH: u32 = 10;
+
+/// PVRTC1 at 2 or 4 bits per pixel
+///
+/// Blocks are stored in Morton order and every texel blends the colors of the
+/// four nearest blocks, so the whole level is decoded at once. Levels smaller
+/// than the minimum block grid are padded by the encoder and cropped here.
+pub fn decode(data: &[u8], width: u32, height: u32, two_bpp: bool) -> Image {
+    let block_width = match two_bpp {
+        true => 8,
+        false => 4,
+    };
+    let blocks_x = (width as usize).div_ceil(block_width).max(2);
+    let blocks_y = (height as usize).div_ceil(4).max(2);
+    let blocks: Vec<(u32, u32)> = data.chunks_exact(8)
+        .take(blocks_x * blocks_y)
+        .map(|b| (
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+        ))
+        .collect();
+    let block = |bx: usize, by: usize| -> (u32, u32) {
+        let i = twiddle(blocks_x, blocks_y, bx % blocks_x, by % blocks_y);
+        blocks.get(i).copied().unwrap_or((0, 0))
+    };
+
+    let full_width = blocks_x * block_width;
+    let full_height = blocks_y * 4;
+    let modulation = modulation_values(&block, blocks_x, blocks_y, block_width, two_bpp);
+    let modulation_at = |x: isize, y: isize| {
+        let x = x.rem_euclid(full_width as isize) as usize;
+        let y = y.rem_euclid(full_height as isize) as usize;
+        modulation[y * full_width + x]
+    };
+
+    let (width, height) = (width as usize, height as usize);
+    let mut out = vec![0u8; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            // blocks whose centers surround the texel
+            let fx = x as isize - block_width as isize / 2;
+            let fy = y as isize - 2;
+            let bx = fx.div_euclid(block_width as isize);
+            let by = fy.div_euclid(4);
+            let (u, v) = (fx.rem_euclid(block_width as isize) as i32, fy.rem_euclid(4) as i32);
+            let wrap = |b: isize, n: usize| b.rem_euclid(n as isize) as usize;
+            let (x0, x1) = (wrap(bx, blocks_x), wrap(bx + 1, blocks_x));
+            let (y0, y1) = (wrap(by, blocks_y), wrap(by + 1, blocks_y));
+            let corners = [block(x0, y0), block(x1, y0), block(x0, y1), block(x1, y1)];
+            let a = interpolate(corners.map(|b| color_a(b.1)), u, v, block_width as i32);
+            let b = interpolate(corners.map(|b| color_b(b.1)), u, v, block_width as i32);
+
+            let mut m = match modulation_at(x as isize, y as isize) {
+                Modulation::Value(m) => m,
+                Modulation::Average => {
+                    let sum = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+                        .map(|(dx, dy)| modulation_at(x as isize + dx, y as isize + dy).value())
+                        .sum::<u32>();
+                    (sum + 2) / 4
+                },
+                Modulation::Horizontal => {
+                    let (x, y) = (x as isize, y as isize);
+                    (modulation_at(x - 1, y).value() + modulation_at(x + 1, y).value()).div_ceil(2)
+                },
+                Modulation::Vertical => {
+                    let (x, y) = (x as isize, y as isize);
+                    (modulation_at(x, y - 1).value() + modulation_at(x, y + 1).value()).div_ceil(2)
+                },
+            };
+            let punch = m > PUNCH_THROUGH;
+            if punch {
+                m -= PUNCH_THROUGH;
+            }
+            let i = (y * width + x) * 4;
+            for c in 0..4 {
+                out[i + c] = ((a[c] * (8 - m) + b[c] * m) / 8) as u8;
+            }
+            if punch {
+                out[i + 3] = 0;
+            }
+        }
+    }
+    Image::rgba8(width as u32, height as u32, out)
+}
+
+#[derive(Clone, Copy)]
+enum Modulation {
+    Value(u32),     // weight of color B out of 8
+    Average,        // 2bpp texel interpolated from its four neighbours
+    Horizontal,     // 2bpp texel interpolated from left and right
+    Vertical,       // 2bpp texel interpolated from above and below
+}
+
+impl Modulation {
+    /// stored weight; interpolated texels never neighbour each other
+    fn value(self) -> u32 {
+        match self {
+            Modulation::Value(m) => m % PUNCH_THROUGH,
+            _ => 0,
+        }
+    }
+}
+
+/// per texel modulation of the whole padded level
+fn modulation_values(
+    block: &impl Fn(usize, usize) -> (u32, u32),
+    blocks_x: usize,
+    blocks_y: usize,
+    block_width: usize,
+    two_bpp: bool,
+) -> Vec<Modulation> {
+    let full_width = blocks_x * block_width;
+    let mut out = vec![Modulation::Value(0); full_width * blocks_y * 4];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let (mut bits, color) = block(bx, by);
+            let punch_through = color & 1 == 1;
+            for y in 0..4 {
+                for x in 0..block_width {
+                    let i = (by * 4 + y) * full_width + bx * block_width + x;
+                    out[i] = match (two_bpp, punch_through) {
+                        (false, false) => Modulation::Value([0, 3, 5, 8][(bits >> ((y * 4 + x) * 2) & 3) as usize]),
+                        (false, true) => Modulation::Value([0, 4, 4 + PUNCH_THROUGH, 8][(bits >> ((y * 4 + x) * 2) & 3) as usize]),
+                        (true, false) => Modulation::Value((bits >> (y * 8 + x) & 1) * 8),
+                        (true, true) => Modulation::Value(0),
+                    };
+                }
+            }
+            if two_bpp && punch_through {
+                // checkerboard of 2 bit values, the other texels are interpolated
+                let mode = match (bits & 1, bits >> 20 & 1) {
+                    (0, _) => Modulation::Average,
+                    (_, 0) => Modulation::Horizontal,
+                    _ => Modulation::Vertical,
+                };
+                if bits & 1 == 1 {
+                    match bits >> 21 & 1 {
+                        1 => bits |= 1 << 20,
+                        _ => bits &= !(1 << 20),
+                    }
+                }
+                for y in 0..4 {
+                    for x in 0..block_width {
+                        let i = (by * 4 + y) * full_width + bx * block_width + x;
+                        out[i] = match (x ^ y) & 1 {
+                            0 => {
+                                let m = [0, 3, 5, 8][(bits & 3) as usize];
+                                bits >>= 2;
+                                Modulation::Value(m)
+                            },
+                            _ => mode,
+                        };
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Morton index of a block, the shorter side is interleaved first
+fn twiddle(blocks_x: usize, blocks_y: usize, x: usize, y: usize) -> usize {
+    let (min, mut rest) = match blocks_y < blocks_x {
+        true => (blocks_y, x),
+        false => (blocks_x, y),
+    };
+    let mut index = 0;
+    let mut bit = 1;
+    let mut shift = 0;
+    while bit < min {
+        if y & bit != 0 {
+            index |= 1 << (shift * 2);
+        }
+        if x & bit != 0 {
+            index |= 1 << (shift * 2 + 1);
+        }
+        bit <<= 1;
+        shift += 1;
+    }
+    rest >>= shift;
+    index | rest << (shift * 2)
+}
+
+/// color A, low half of the color word: RGB554 or ARGB3443, as 5 bit RGB and 4 bit alpha
+fn color_a(c: u32) -> [i32; 4] {
+    let c = c as i32;
+    match c & 0x8000 {
+        0 => [
+            (c & 0xf00) >> 7 | (c & 0xf00) >> 11,
+            (c & 0xf0) >> 3 | (c & 0xf0) >> 7,
+            (c & 0xe) << 1 | (c & 0xe) >> 2,
+            (c & 0x7000) >> 11,
+        ],
+        _ => [(c & 0x7c00) >> 10, (c & 0x3e0) >> 5, (c & 0x1e) | (c & 0x1e) >> 4, 0xf],
+    }
+}
+
+/// color B, high half of the color word: RGB555 or ARGB3444
+fn color_b(c: u32) -> [i32; 4] {
+    match c & 0x8000_0000 {
+        0 => {
+            let c = c as i32;
+            [
+                (c & 0xf000000) >> 23 | (c & 0xf000000) >> 27,
+                (c & 0xf00000) >> 19 | (c & 0xf00000) >> 23,
+                (c & 0xf0000) >> 15 | (c & 0xf0000) >> 19,
+                (c & 0x70000000) >> 27,
+            ]
+        },
+        _ => [(c >> 26 & 0x1f) as i32, (c >> 21 & 0x1f) as i32, (c >> 16 & 0x1f) as i32, 0xf],
+    }
+}
+
+/// bilinear blend of the four block colors, expanded to 8 bits
+fn interpolate(corners: [[i32; 4]; 4], u: i32, v: i32, block_width: i32) -> [u32; 4] {
+    // the blend carries 5 bit colors and 4 bit alpha scaled by 4 * block width (2^k)
+    let k = match block_width {
+        8 => 5,
+        _ => 4,
+    };
+    let mut out = [0u32; 4];
+    for c in 0..4 {
+        let top = corners[0][c] * (block_width - u) + corners[1][c] * u;
+        let bottom = corners[2][c] * (block_width - u) + corners[3][c] * u;
+        let value = top * (4 - v) + bottom * v;
+        out[c] = match c {
+            3 => (value >> k) + (value >> (k - 4)),
+            _ => (value >> (k + 2)) + (value >> (k - 3)),
+        } as u32;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Pixels;
+
+    #[test]
+    fn four_bpp_opaque_blocks() {
+        // four equal blocks of opaque red A and green B; B on the diagonal, 3/8 B right of (0, 0)
+        let modulation: u32 = 3 | 1 << 2 | 3 << 10 | 3 << 20 | 3 << 30;
+        let colors: u32 = 0x83e0_fc00;
+        let block = [modulation.to_le_bytes(), colors.to_le_bytes()].concat();
+        let image = decode(&block.repeat(4), 8, 8, false);
+        let t = match image.pixels {
+            Pixels::Rgba8(p) => p,
+            Pixels::RgbaF32(_) => panic!("expected 8 bit texels"),
+        };
+        let texel = |x: usize, y: usize| &t[(y * 8 + x) * 4 .. (y * 8 + x) * 4 + 4];
+        for (x, y) in [(0, 0), (3, 3), (4, 4), (7, 7)] {
+            assert_eq!(texel(x, y), [0, 255, 0, 255]);
+        }
+        assert_eq!(texel(1, 0), [159, 95, 0, 255]);
+        assert_eq!(texel(5, 4), [159, 95, 0, 255]);
+        assert_eq!(texel(2, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(0, 7), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn twiddled_block_order() {
+        // 4x2 blocks: square 2x2 Morton tiles, then the rest of the longer side
+        let order: Vec<usize> = (0..2).flat_map(|y| (0..4).map(move |x| twiddle(4, 2, x, y))).collect();
+        assert_eq!(order, vec![0, 2, 4, 6, 1, 3, 5, 7]);
+    }
+}
@@ -0,0 +1,96 @@
+use super::{TextureFormat, decode_blocks, half_to_f32};
+use crate::image::Image;
+use crate::Result;
+
+/// decodes uncompressed formats
+pub fn decode(format: TextureFormat, data: &[u8], width: u32, height: u32) -> Result<Image> {
+    use TextureFormat::*;
+    let layout = format.block_layout()
+        .ok_or_else(|| format!("unsupported texture format {:?}", format))?;
+    let ldr = |texel: fn(&[u8], &mut [u8])| {
+        Image::rgba8(width, height, decode_blocks(data, width, height, layout, texel))
+    };
+    let hdr = |texel: fn(&[u8], &mut [f32])| {
+        Image::rgba_f32(width, height, decode_blocks(data, width, height, layout, texel))
+    };
+    let image = match format {
+        Alpha8 => ldr(|s, d| d.copy_from_slice(&[255, 255, 255, s[0]])),
+        R8 => ldr(|s, d| d.copy_from_slice(&[s[0], 0, 0, 255])),
+        RG16 => ldr(|s, d| d.copy_from_slice(&[s[0], s[1], 0, 255])),
+        RGB24 => ldr(|s, d| d.copy_from_slice(&[s[0], s[1], s[2], 255])),
+        RGBA32 => ldr(|s, d| d.copy_from_slice(s)),
+        ARGB32 => ldr(|s, d| d.copy_from_slice(&[s[1], s[2], s[3], s[0]])),
+        BGRA32 => ldr(|s, d| d.copy_from_slice(&[s[2], s[1], s[0], s[3]])),
+        R16 => ldr(|s, d| d.copy_from_slice(&[s[1], 0, 0, 255])),
+        RG32 => ldr(|s, d| d.copy_from_slice(&[s[1], s[3], 0, 255])),
+        RGB48 => ldr(|s, d| d.copy_from_slice(&[s[1], s[3], s[5], 255])),
+        RGBA64 => ldr(|s, d| d.copy_from_slice(&[s[1], s[3], s[5], s[7]])),
+        RGB565 => ldr(|s, d| {
+            let v = u16::from_le_bytes([s[0], s[1]]);
+            d.copy_from_slice(&[
+                expand5((v >> 11) as u8),
+                expand6((v >> 5) as u8 & 0x3f),
+                expand5(v as u8 & 0x1f),
+                255,
+            ]);
+        }),
+        ARGB4444 => ldr(|s, d| {
+            let v = u16::from_le_bytes([s[0], s[1]]);
+            d.copy_from_slice(&[nibble(v, 8), nibble(v, 4), nibble(v, 0), nibble(v, 12)]);
+        }),
+        RGBA4444 => ldr(|s, d| {
+            let v = u16::from_le_bytes([s[0], s[1]]);
+            d.copy_from_slice(&[nibble(v, 12), nibble(v, 8), nibble(v, 4), nibble(v, 0)]);
+        }),
+        YUY2 => ldr(|s, d| {
+            let (u, v) = (s[1] as i32 - 128, s[3] as i32 - 128);
+            for (i, y) in [s[0], s[2]].iter().enumerate() {
+                let c = 298 * (*y as i32 - 16);
+                d[i * 4 .. i * 4 + 4].copy_from_slice(&[
+                    ((c + 409 * v + 128) >> 8).clamp(0, 255) as u8,
+                    ((c - 100 * u - 208 * v + 128) >> 8).clamp(0, 255) as u8,
+                    ((c + 516 * u + 128) >> 8).clamp(0, 255) as u8,
+                    255,
+                ]);
+            }
+        }),
+        RHalf => hdr(|s, d| d.copy_from_slice(&[half(s, 0), 0.0, 0.0, 1.0])),
+        RGHalf => hdr(|s, d| d.copy_from_slice(&[half(s, 0), half(s, 1), 0.0, 1.0])),
+        RGBAHalf => hdr(|s, d| d.copy_from_slice(&[half(s, 0), half(s, 1), half(s, 2), half(s, 3)])),
+        RFloat => hdr(|s, d| d.copy_from_slice(&[float(s, 0), 0.0, 0.0, 1.0])),
+        RGFloat => hdr(|s, d| d.copy_from_slice(&[float(s, 0), float(s, 1), 0.0, 1.0])),
+        RGBAFloat => hdr(|s, d| d.copy_from_slice(&[float(s, 0), float(s, 1), float(s, 2), float(s, 3)])),
+        RGB9e5Float => hdr(|s, d| {
+            let v = u32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+            let scale = 2f32.powi((v >> 27) as i32 - 15 - 9);
+            d.copy_from_slice(&[
+                (v & 0x1ff) as f32 * scale,
+                (v >> 9 & 0x1ff) as f32 * scale,
+                (v >> 18 & 0x1ff) as f32 * scale,
+                1.0,
+            ]);
+        }),
+        _ => return Err(format!("unsupported texture format {:?}", format).into()),
+    };
+    Ok(image)
+}
+
+pub fn expand5(v: u8) -> u8 {
+    v << 3 | v >> 2
+}
+
+pub fn expand6(v: u8) -> u8 {
+    v << 2 | v >> 4
+}
+
+fn nibble(v: u16, shift: u16) -> u8 {
+    (v >> shift & 0xf) as u8 * 17
+}
+
+fn half(s: &[u8], i: usize) -> f32 {
+    half_to_f32(u16::from_le_bytes([s[i * 2], s[i * 2 + 1]]))
+}
+
+fn float(s: &[u8], i: usize) -> f32 {
+    f32::from_le_bytes([s[i * 4], s[i * 4 + 1], s[i * 4 + 2], s[i * 4 + 3]])
+}
@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::image::Image;
+use crate::object_value::ObjectValue;
+use crate::streaming_info::StreamingInfo;
+use crate::texture::{self, TextureFormat};
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+/// Texture2D object
+#[derive(Serialize, Clone, Debug)]
+pub struct Texture2D {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub mip_count: u32,
+    pub image_count: u32,
+    pub srgb: bool,             // m_ColorSpace, sampled as sRGB
+    pub stream_data: Option<StreamingInfo>,
+    #[serde(skip)]
+    pub image_data: Vec<u8>,    // inline pixel data, empty when streamed
+}
+
+impl Texture2D {
+    pub const CLASS_ID: i32 = 28;
+
+    pub fn from_value(v: &ObjectValue) -> Result<Texture2D> {
+        let mip_count = match v.get("m_MipCount") {
+            Some(m) => m.as_u64().unwrap_or(1) as u32,
+            // before 5.2 only a flag was stored
+            None => match v.get("m_MipMap").and_then(|m| m.as_bool()) {
+                Some(true) => 0,
+                _ => 1,
+            },
+        };
+        Ok(Texture2D{
+            name: v.string("m_Name")?.to_string(),
+            width: v.int("m_Width")? as u32,
+            height: v.int("m_Height")? as u32,
+            format: TextureFormat::new(v.int("m_TextureFormat")? as i32),
+            mip_count,
+            image_count: v.get("m_ImageCount").and_then(|c| c.as_u64()).unwrap_or(1) as u32,
+            srgb: v.get("m_ColorSpace").and_then(|c| c.as_i64()) == Some(1),
+            stream_data: v.get("m_StreamData").and_then(StreamingInfo::from_value),
+            image_data: v.get("image data")
+                .and_then(|d| d.as_bytes())
+                .map(|d| d.to_vec())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// pixel data of every mip level and image, inline or streamed from the bundle
+    pub fn data<'a>(&'a self, bundle: &'a AssetBundle) -> Result<&'a [u8]> {
+        match (&self.stream_data, self.image_data.is_empty()) {
+            (Some(stream), true) => stream.load(bundle),
+            _ => Ok(&self.image_data),
+        }
+    }
+
+    /// block format and data, transcoding crunched textures
+    pub fn blocks<'a>(&'a self, bundle: &'a AssetBundle, version: &UnityVersion) -> Result<(TextureFormat, Cow<'a, [u8]>)> {
+        let data = self.data(bundle)?;
+        match self.format.crunch_base() {
+            Some(_) => {
+                let (format, blocks) = texture::uncrunch(self.format, data, version)?;
+                Ok((format, Cow::Owned(blocks)))
+            },
+            None => Ok((self.format, Cow::Borrowed(data))),
+        }
+    }
+
+    /// number of mip levels stored, resolving the pre 5.2 mip flag
+    pub fn levels(&self) -> u32 {
+        match self.mip_count {
+            0 => 32 - self.width.max(self.height).max(1).leading_zeros(),
+            n => n,
+        }
+    }
+
+    /// bytes and size of mip `level` of the first image, stored as `format`
+    pub fn level<'a>(&self, format: TextureFormat, data: &'a [u8], level: u32) -> Result<(&'a [u8], u32, u32)> {
+        let mut offset = 0;
+        for l in 0..=level {
+            let (w, h) = ((self.width >> l).max(1), (self.height >> l).max(1));
+            let size = format.level_size(w, h)
+                .ok_or_else(|| format!("unsupported texture format {:?}", format))?;
+            if l == level {
+                return match offset + size <= data.len() {
+                    true => Ok((&data[offset .. offset + size], w, h)),
+                    false => Err(format!("mip {} needs {} bytes, got {}", l, offset + size, data.len()).into()),
+                };
+            }
+            offset += size;
+        }
+        unreachable!()
+    }
+
+    /// mip `level` of the first image, top-left origin
+    pub fn decode_level(&self, bundle: &AssetBundle, version: &UnityVersion, level: u32) -> Result<Image> {
+        let mut image = self.decode_level_raw(bundle, version, level)?;
+        image.flip_vertical();
+        Ok(image)
+    }
+
+    /// mip `level` of the first image with Unity's bottom-left origin
+    pub fn decode_level_raw(&self, bundle: &AssetBundle, version: &UnityVersion, level: u32) -> Result<Image> {
+        let (format, data) = self.blocks(bundle, version)?;
+        let mut image = match format.block_layout() {
+            Some(_) => {
+                let (data, width, height) = self.level(format, &data, level)?;
+                texture::decode(format, data, width, height, self.srgb)?
+            },
+            // let decode report the format
+            None => texture::decode(format, &data, self.width, self.height, self.srgb)?,
+        };
+        image.srgb = self.srgb;
+        Ok(image)
+    }
+}
+
+/// decodes every Texture2D of the bundle into `dst` as PNG (EXR for HDR formats)
+///
+/// with `mips` every mip level is written as `<name>_mip<level>`.
+pub fn export(bundle: &AssetBundle, dst: &Path, mips: bool) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(Texture2D::CLASS_ID) {
+                continue;
+            }
+            let texture = match asset.object_value(obj).and_then(|v| Texture2D::from_value(&v)) {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let levels = match mips {
+                true => texture.levels(),
+                false => 1,
+            };
+            for level in 0..levels {
+                let name = match mips {
+                    true => format!("{}_mip{}", texture.name, level),
+                    false => texture.name.clone(),
+                };
+                match texture.decode_level(bundle, asset.unity_version(), level) {
+                    Ok(image) => {
+                        let path = export::unique_path(dst, &name, image.extension());
+                        image.save(&path)?;
+                        info!("{:?} {:?}", texture.format, path);
+                        count += 1;
+                    },
+                    Err(e) => warn!("{} ({:?}): {}", name, texture.format, e),
+                }
+            }
+        }
+    }
+    Ok(count)
+}
@@ -3,71 +3,93 @@ use log::{info};
 use serde::{Serialize, Deserialize};
 use crate::endian::Endian;
 use crate::constants;
+use crate::unity_version::UnityVersion;
 use crate::binary_reader::BinaryReader;
+use crate::Result;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TypeInfo{
-    version: u16,
-    level: u8,
-    is_array: bool,
-    type_id: u32,
-    type_str: String,
-    name_id: u32,
-    name_str: String,
-    size: i32,
-    index: u32,
-    flags: u32,
-    v18meta: Option<u64>,
-    children: Vec<TypeInfo>,
+    pub version: u16,
+    pub level: u8,
+    pub is_array: bool,
+    pub type_id: u32,
+    pub type_str: String,
+    pub name_id: u32,
+    pub name_str: String,
+    pub size: i32,
+    pub index: u32,
+    pub flags: u32,
+    pub v18meta: Option<u64>,
+    pub children: Vec<TypeInfo>,
 }
 
 impl TypeInfo {
-    pub fn load<T: Read+Seek>(reader: &mut BinaryReader<T>, format: u32) -> Vec<TypeInfo> {
-        let node_count = reader.uint32();
-        let buf_size = reader.uint32();
+    pub fn load<T: Read+Seek>(reader: &mut BinaryReader<T>, format: u32, unity_version: &UnityVersion) -> Result<Vec<TypeInfo>> {
+        let node_size = match format >= 19 {
+            true => 32,
+            false => 24,
+        };
+        let node_count = reader.count(node_size)?;
+        let buf_size = reader.count(1)?;
+        if node_count as u64 * node_size + buf_size as u64 > reader.remaining() {
+            return Err(format!("type tree of {} nodes and {} string bytes is truncated", node_count, buf_size).into());
+        }
         let mut nodes: Vec<TypeInfo> = Vec::new();
         for _ in 0..node_count {
-            let node = TypeInfo::new(reader);
+            let node = TypeInfo::new(reader, format)?;
             nodes.push(node);
         }
-        let buf = reader.read(buf_size as usize);
-        let mut reader = BinaryReader::new(Cursor::new(buf), Endian::Big);
+        let buf = reader.read(buf_size as usize)?;
+        let mut buf_reader = BinaryReader::new(Cursor::new(buf), Endian::Big);
 
-        for mut node in &mut nodes {
-            node.type_str = constants::get_string_or_default(node.type_id, &mut reader);
-            node.name_str = constants::get_string_or_default(node.name_id, &mut reader);
+        for node in &mut nodes {
+            node.type_str = constants::get_string_or_default(node.type_id, &mut buf_reader, unity_version)?;
+            node.name_str = constants::get_string_or_default(node.name_id, &mut buf_reader, unity_version)?;
         }
         if format >= 21 {
-            reader.skip(4);
+            // type dependencies
+            let count = reader.count(4)?;
+            reader.skip(count as i64 * 4);
         }
-        let mut reverse: Vec<TypeInfo> = Vec::new();
-        while !nodes.is_empty() {
-            let n = nodes.pop().unwrap();
-            if n.level > 0 {
-                let idx = n.level - 1;
-                nodes[ idx as usize ].children.push(n);
-            } else {
-                reverse.push(n);
-            }
-        };
 
-        reverse
+        // rebuild the tree from the depth-first node list
+        let mut roots: Vec<TypeInfo> = Vec::new();
+        let mut parents: Vec<TypeInfo> = Vec::new();
+        for n in nodes {
+            TypeInfo::close_until(&mut parents, &mut roots, n.level);
+            parents.push(n);
+        }
+        TypeInfo::close_until(&mut parents, &mut roots, 0);
+
+        Ok(roots)
     }
-    fn new<T: Read + Seek>(reader: &mut BinaryReader<T>) -> TypeInfo {
-        let ver = reader.uint16();
-        let lv  = reader.uint8();
-        let is_arr = reader.boolean();
-        let ty = reader.uint32();
-        let name= reader.uint32();
-        let size = reader.int32();
-        let index = reader.uint32();
-        let flags = reader.uint32();
-        let v18meta = match ver >= 18 {
-            true => Some(reader.uint64()),
+
+    /// attaches open nodes at or below `level` to their parents
+    fn close_until(parents: &mut Vec<TypeInfo>, roots: &mut Vec<TypeInfo>, level: u8) {
+        while parents.last().is_some_and(|p| p.level >= level) {
+            let n = parents.pop().unwrap();
+            match parents.last_mut() {
+                Some(p) => p.children.push(n),
+                None => roots.push(n),
+            }
+        }
+    }
+
+    fn new<T: Read + Seek>(reader: &mut BinaryReader<T>, format: u32) -> Result<TypeInfo> {
+        let ver = reader.uint16()?;
+        let lv  = reader.uint8()?;
+        let is_arr = reader.boolean()?;
+        let ty = reader.uint32()?;
+        let name= reader.uint32()?;
+        let size = reader.int32()?;
+        let index = reader.uint32()?;
+        let flags = reader.uint32()?;
+        let v18meta = match format >= 19 {
+            true => Some(reader.uint64()?),
             false => None,
         };
         info!("version : {}, level : {}, type_id : {}, name_id : {}", ver, lv, ty, name);
-        TypeInfo{
+        Ok(TypeInfo{
             version: ver,
             level: lv,
             is_array: is_arr,
@@ -75,11 +97,11 @@ impl TypeInfo {
             type_str: String::from(""),
             name_id: name,
             name_str: String::from(""),
-            size: size,
-            index: index,
-            flags: flags,
-            v18meta: v18meta,
+            size,
+            index,
+            flags,
+            v18meta,
             children: Vec::new(),
-        }
+        })
     }
 }
@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+use crate::Result;
+
+/// Unity engine version such as "2018.4.2f1"
+///
+/// Versions order by release then by type (a < b < c < f < p < x) and
+/// build, so layouts can be gated with plain comparisons.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(into = "String", try_from = "String")]
+pub struct UnityVersion {
+    pub major: u32,             // 2018
+    pub minor: u32,             // 4
+    pub patch: u32,             // 2
+    pub release_type: char,     // a(lpha), b(eta), c(hina), f(inal), p(atch), x(experimental)
+    pub build: u32,             // 1
+}
+
+impl UnityVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> UnityVersion {
+        UnityVersion{
+            major,
+            minor,
+            patch,
+            release_type: 'f',
+            build: 1,
+        }
+    }
+
+    /// parses a version string; wildcard ("5.x.x") and missing parts read as 0
+    pub fn parse(s: &str) -> Result<UnityVersion> {
+        let s = s.trim();
+        let mut numbers = [0u32; 3];
+        let mut release_type = 'f';
+        let mut build = 0;
+        let mut rest = s;
+        for (i, number) in numbers.iter_mut().enumerate() {
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            rest = &rest[digits.len()..];
+            match digits.is_empty() {
+                true => {
+                    if rest.starts_with('x') {
+                        rest = &rest[1..];
+                    } else if i == 0 {
+                        return Err(format!("invalid unity version : {}", s).into());
+                    }
+                },
+                false => *number = digits.parse()?,
+            }
+            if i < 2 {
+                match rest.strip_prefix('.') {
+                    Some(r) => rest = r,
+                    None => break,
+                }
+            }
+        }
+        if let Some(c) = rest.chars().next().filter(|c| c.is_ascii_alphabetic()) {
+            release_type = c;
+            rest = &rest[c.len_utf8()..];
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            build = digits.parse().unwrap_or(0);
+        }
+        Ok(UnityVersion{
+            major: numbers[0],
+            minor: numbers[1],
+            patch: numbers[2],
+            release_type,
+            build,
+        })
+    }
+
+    /// true for "0.0.0" and other versions removed by build stripping
+    pub fn is_stripped(&self) -> bool {
+        self.major == 0
+    }
+
+    /// (major, minor) pair
+    pub fn release(&self) -> (u32, u32) {
+        (self.major, self.minor)
+    }
+}
+
+/// "0.0.0f0", the stripped version assumed when neither the file nor --unity-version gives one
+impl Default for UnityVersion {
+    fn default() -> UnityVersion {
+        UnityVersion{
+            major: 0,
+            minor: 0,
+            patch: 0,
+            release_type: 'f',
+            build: 0,
+        }
+    }
+}
+
+impl Ord for UnityVersion {
+    fn cmp(&self, other: &UnityVersion) -> Ordering {
+        (self.major, self.minor, self.patch, self.release_type, self.build)
+            .cmp(&(other.major, other.minor, other.patch, other.release_type, other.build))
+    }
+}
+
+impl PartialOrd for UnityVersion {
+    fn partial_cmp(&self, other: &UnityVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for UnityVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}{}{}", self.major, self.minor, self.patch, self.release_type, self.build)
+    }
+}
+
+impl FromStr for UnityVersion {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<UnityVersion> {
+        UnityVersion::parse(s)
+    }
+}
+
+impl From<UnityVersion> for String {
+    fn from(v: UnityVersion) -> String {
+        v.to_string()
+    }
+}
+
+impl TryFrom<String> for UnityVersion {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<UnityVersion, String> {
+        UnityVersion::parse(&s).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> UnityVersion {
+        UnityVersion::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parse_versions() {
+        let v = version("2018.4.2f1");
+        assert_eq!((v.major, v.minor, v.patch, v.release_type, v.build), (2018, 4, 2, 'f', 1));
+        assert_eq!(v.to_string(), "2018.4.2f1");
+        assert_eq!(version(" 2022.3.10f1\n").release(), (2022, 3));
+
+        // the wildcard of stripped bundle headers and missing parts read as 0
+        let v = version("5.x.x");
+        assert_eq!((v.major, v.minor, v.patch, v.release_type, v.build), (5, 0, 0, 'f', 0));
+        assert_eq!(version("2019").release(), (2019, 0));
+        assert!(!v.is_stripped());
+        assert!(version("0.0.0").is_stripped());
+    }
+
+    #[test]
+    fn parse_malformed_versions() {
+        assert!(UnityVersion::parse("").is_err());
+        assert!(UnityVersion::parse("unity").is_err());
+        assert!(UnityVersion::parse(".4.2f1").is_err());
+        assert!(UnityVersion::parse("99999999999.1.0f1").is_err());
+        assert!("2018.4.2f1".parse::<UnityVersion>().is_ok());
+        assert!(serde_json::from_str::<UnityVersion>("\"garbage\"").is_err());
+        // a release type without a build number reads build 0
+        assert_eq!(version("2018.4.2b").build, 0);
+    }
+
+    #[test]
+    fn order_versions() {
+        assert!(version("2018.4.2f1") < version("2018.4.2p1"));
+        assert!(version("2018.4.2a9") < version("2018.4.2b1"));
+        assert!(version("2018.4.2f1") < version("2018.4.2f2"));
+        assert!(version("2018.4.2p9") < version("2018.4.3a1"));
+        assert!(version("5.6.7f1") < version("2017.1.0f1"));
+        assert!(version("5.x.x") < version("5.0.0f1"));
+        assert_eq!(version("2019.4.0f1"), version("2019.4.0f1"));
+    }
+
+    #[test]
+    fn default_is_stripped() {
+        let v = UnityVersion::default();
+        assert!(v.is_stripped());
+        assert_eq!(v.to_string(), "0.0.0f0");
+        assert_eq!(version("0.0.0"), v);
+        assert!(v < version("5.x.x"));
+        assert_eq!(serde_json::to_string(&v).unwrap(), "\"0.0.0f0\"");
+    }
+}
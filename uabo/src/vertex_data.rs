@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+use crate::object_value::ObjectValue;
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+/// channel index of vertex positions
+pub const CHANNEL_POSITION: usize = 0;
+
+/// element type of a vertex channel
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexFormat {
+    Float32,
+    Float16,
+    UNorm8,
+    SNorm8,
+    UNorm16,
+    SNorm16,
+    UInt8,
+    SInt8,
+    UInt16,
+    SInt16,
+    UInt32,
+    SInt32,
+}
+
+impl VertexFormat {
+    /// VertexAttributeFormat since 2019.1, VertexFormat2017 since 2017.1, VertexChannelFormat before;
+    /// Color is four UNorm8
+    fn new(id: u64, version: &UnityVersion) -> Result<VertexFormat> {
+        use VertexFormat::*;
+        let table: &[VertexFormat] = match version.release() {
+            r if r >= (2019, 1) => &[Float32, Float16, UNorm8, SNorm8, UNorm16, SNorm16, UInt8, SInt8, UInt16, SInt16, UInt32, SInt32],
+            r if r >= (2017, 1) => &[Float32, Float16, UNorm8, UNorm8, SNorm8, UNorm16, SNorm16, UInt8, SInt8, UInt16, SInt16, UInt32, SInt32],
+            _ => &[Float32, Float16, UNorm8, UInt8, UInt32],
+        };
+        let format = table.get(id as usize).copied();
+        format.ok_or_else(|| format!("unknown vertex format {}", id).into())
+    }
+
+    pub fn size(self) -> usize {
+        use VertexFormat::*;
+        match self {
+            UNorm8 | SNorm8 | UInt8 | SInt8 => 1,
+            Float16 | UNorm16 | SNorm16 | UInt16 | SInt16 => 2,
+            Float32 | UInt32 | SInt32 => 4,
+        }
+    }
+
+    /// one element as float; normalized formats are scaled to [0, 1] or [-1, 1]
+    fn read(self, b: &[u8]) -> f32 {
+        use VertexFormat::*;
+        match self {
+            Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Float16 => crate::texture::half_to_f32(u16::from_le_bytes([b[0], b[1]])),
+            UNorm8 => b[0] as f32 / 255.0,
+            SNorm8 => (b[0] as i8 as f32 / 127.0).max(-1.0),
+            UNorm16 => u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0,
+            SNorm16 => (i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0).max(-1.0),
+            UInt8 => b[0] as f32,
+            SInt8 => b[0] as i8 as f32,
+            UInt16 => u16::from_le_bytes([b[0], b[1]]) as f32,
+            SInt16 => i16::from_le_bytes([b[0], b[1]]) as f32,
+            UInt32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            SInt32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+        }
+    }
+}
+
+/// layout of one vertex attribute
+#[derive(Serialize, Clone, Debug)]
+pub struct Channel {
+    pub stream: usize,
+    pub offset: usize,          // byte offset inside the stream's vertex
+    pub format: VertexFormat,
+    pub dimension: usize,       // element count, 0 when the channel is absent
+}
+
+/// VertexData of meshes and sprites: interleaved streams aligned to 16 bytes
+#[derive(Serialize, Clone, Debug)]
+pub struct VertexData {
+    pub vertex_count: usize,
+    pub channels: Vec<Channel>,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+impl VertexData {
+    /// reads m_VertexData as written since 5.0
+    pub fn from_value(v: &ObjectValue, version: &UnityVersion) -> Result<VertexData> {
+        let channels = match v.field("m_Channels")? {
+            ObjectValue::Array(channels) => channels.iter()
+                .map(|c| Ok(Channel{
+                    stream: c.int("stream")? as usize,
+                    offset: c.int("offset")? as usize,
+                    format: VertexFormat::new(c.int("format")? as u64, version)?,
+                    // the high bits hold flags since 2019.1
+                    dimension: (c.int("dimension")? & 0xf) as usize,
+                }))
+                .collect::<Result<Vec<Channel>>>()?,
+            _ => return Err("m_Channels is not an array".into()),
+        };
+        Ok(VertexData{
+            vertex_count: v.int("m_VertexCount")? as usize,
+            channels,
+            data: v.get("m_DataSize").and_then(|d| d.as_bytes()).unwrap_or_default().to_vec(),
+        })
+    }
+
+    /// bytes per vertex of `stream`
+    fn stride(&self, stream: usize) -> usize {
+        self.channels.iter()
+            .filter(|c| c.stream == stream && c.dimension > 0)
+            .map(|c| c.format.size() * c.dimension)
+            .sum()
+    }
+
+    /// start of `stream`; each stream follows the previous one on a 16 byte boundary
+    fn stream_offset(&self, stream: usize) -> Option<usize> {
+        (0..stream).try_fold(0usize, |offset, s| {
+            Some(offset.checked_add(self.vertex_count.checked_mul(self.stride(s))?)?.div_ceil(16) * 16)
+        })
+    }
+
+    /// every vertex of channel `index`, None when the mesh lacks it
+    pub fn channel(&self, index: usize) -> Option<Result<Vec<Vec<f32>>>> {
+        let channel = self.channels.get(index).filter(|c| c.dimension > 0)?;
+        let stride = self.stride(channel.stream);
+        let size = channel.format.size();
+        let start = self.stream_offset(channel.stream).and_then(|s| s.checked_add(channel.offset));
+        let end = start.and_then(|start| stride.checked_mul(self.vertex_count.saturating_sub(1))?.checked_add(start))
+            .and_then(|end| end.checked_add(size * channel.dimension));
+        let (start, end) = match start.zip(end) {
+            Some(range) => range,
+            None => return Some(Err(format!("vertex data of channel {} overflows", index).into())),
+        };
+        if self.vertex_count > 0 && end > self.data.len() {
+            return Some(Err(format!("vertex data too small for channel {}: {} < {}", index, self.data.len(), end).into()));
+        }
+        Some(Ok((0..self.vertex_count)
+            .map(|v| (0..channel.dimension)
+                .map(|d| {
+                    let i = start + v * stride + d * size;
+                    channel.format.read(&self.data[i .. i + size])
+                })
+                .collect())
+            .collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(stream: usize, offset: usize, format: VertexFormat, dimension: usize) -> Channel {
+        Channel{ stream, offset, format, dimension }
+    }
+
+    #[test]
+    fn overflowing_layouts() {
+        let mut vd = VertexData{
+            vertex_count: usize::MAX / 2,
+            channels: vec![channel(0, 0, VertexFormat::Float32, 3), channel(1, 0, VertexFormat::UNorm8, 4)],
+            data: vec![0; 64],
+        };
+        assert!(vd.channel(0).unwrap().is_err());
+        assert!(vd.channel(1).unwrap().is_err());
+        vd.vertex_count = 2;
+        vd.channels[1].offset = usize::MAX;
+        assert!(vd.channel(0).unwrap().is_ok());
+        assert!(vd.channel(1).unwrap().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::AssetBundle;
+use crate::export;
+use crate::object_value::ObjectValue;
+use crate::streaming_info::StreamingInfo;
+use crate::Result;
+
+/// MP4 sample entries naming the video codec, with their codec names
+const MP4_CODECS: [(&[u8; 4], &str); 7] = [
+    (b"avc1", "H.264"), (b"avc3", "H.264"), (b"hvc1", "H.265"), (b"hev1", "H.265"),
+    (b"vp08", "VP8"), (b"vp09", "VP9"), (b"av01", "AV1"),
+];
+
+/// Matroska codec ids
+const WEBM_CODECS: [(&[u8], &str); 4] = [
+    (b"V_VP8", "VP8"), (b"V_VP9", "VP9"), (b"V_AV1", "AV1"), (b"V_MPEG4/ISO/AVC", "H.264"),
+];
+
+const EBML_MAGIC: [u8; 4] = [0x1a, 0x45, 0xdf, 0xa3];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioTrack {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub language: Option<String>,
+}
+
+/// VideoClip metadata; the encoded movie lives in m_ExternalResources
+#[derive(Serialize, Clone, Debug)]
+pub struct VideoClip {
+    pub name: String,
+    pub original_path: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_aspect_ratio: Option<(u32, u32)>,
+    pub frame_rate: f64,
+    pub frame_count: u64,
+    pub seconds: f64,
+    pub format: i64,                // VideoClipFormat of the importer
+    pub split_alpha: bool,
+    pub srgb: bool,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub resource: Option<StreamingInfo>,
+}
+
+impl VideoClip {
+    pub const CLASS_ID: i32 = 329;
+
+    pub fn from_value(v: &ObjectValue) -> Result<VideoClip> {
+        let uint = |name: &str| v.get(name).and_then(|n| n.as_u64()).unwrap_or(0);
+        let languages = v.array("m_AudioLanguage");
+        let audio_tracks = v.array("m_AudioChannelCount").iter().zip(v.array("m_AudioSampleRate"))
+            .enumerate()
+            .map(|(i, (channels, rate))| AudioTrack{
+                channels: channels.as_u64().unwrap_or(0) as u16,
+                sample_rate: rate.as_u64().unwrap_or(0) as u32,
+                language: languages.get(i).and_then(|l| l.as_str()).filter(|l| !l.is_empty()).map(String::from),
+            })
+            .collect();
+        let frame_rate = v.get("m_FrameRate").and_then(|f| f.as_f64()).unwrap_or(0.0);
+        let frame_count = uint("m_FrameCount");
+        Ok(VideoClip{
+            name: v.string("m_Name")?.to_string(),
+            original_path: v.get("m_OriginalPath").and_then(|p| p.as_str()).unwrap_or_default().to_string(),
+            width: uint("Width") as u32,
+            height: uint("Height") as u32,
+            pixel_aspect_ratio: match (uint("m_PixelAspecRatioNum"), uint("m_PixelAspecRatioDen")) {
+                (_, 0) => None,
+                (n, d) => Some((n as u32, d as u32)),
+            },
+            frame_rate,
+            frame_count,
+            seconds: match frame_rate > 0.0 {
+                true => frame_count as f64 / frame_rate,
+                false => 0.0,
+            },
+            format: v.get("m_Format").and_then(|f| f.as_i64()).unwrap_or(0),
+            split_alpha: v.flag("m_HasSplitAlpha"),
+            srgb: v.flag("m_sRGB"),
+            audio_tracks,
+            resource: v.get("m_ExternalResources").and_then(StreamingInfo::from_value),
+        })
+    }
+}
+
+/// container extension and video codec of an encoded movie, from its headers
+pub fn sniff(data: &[u8]) -> (&'static str, Option<&'static str>) {
+    if data.get(4..8) == Some(b"ftyp") {
+        let container = match data.get(8..12) {
+            Some(b"qt  ") => "mov",
+            _ => "mp4",
+        };
+        // sample entries sit in the moov box, which may follow the media data
+        let moov = mp4_box(data, b"moov").unwrap_or_default();
+        let codec = MP4_CODECS.iter().find(|(fourcc, _)| moov.windows(4).any(|w| w == &fourcc[..])).map(|c| c.1);
+        return (container, codec);
+    }
+    if data.get(0..4) == Some(&EBML_MAGIC[..]) {
+        let head = &data[..data.len().min(0x10000)];
+        let codec = WEBM_CODECS.iter().find(|(id, _)| head.windows(id.len()).any(|w| w == *id)).map(|c| c.1);
+        return ("webm", codec);
+    }
+    if data.get(0..4) == Some(b"RIFF") && data.get(8..12) == Some(b"AVI ") {
+        return ("avi", None);
+    }
+    ("bin", None)
+}
+
+/// payload of the first top level MP4 box of `kind`
+fn mp4_box<'d>(data: &'d [u8], kind: &[u8; 4]) -> Option<&'d [u8]> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as u64;
+        let (header, size) = match size {
+            0 => (8, (data.len() - pos) as u64),
+            1 => (16, u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().unwrap())),
+            s => (8, s),
+        };
+        if size < header {
+            return None;
+        }
+        let end = (pos as u64).checked_add(size)?.min(data.len() as u64) as usize;
+        if &data[pos + 4..pos + 8] == kind {
+            return data.get(pos + header as usize..end);
+        }
+        pos = end;
+    }
+    None
+}
+
+/// one clip of video_clips.json
+#[derive(Serialize)]
+struct VideoSummary {
+    #[serde(flatten)]
+    clip: VideoClip,
+    container: Option<&'static str>,
+    codec: Option<&'static str>,
+    file: Option<String>,
+    error: Option<String>,
+}
+
+/// writes the movie of every VideoClip of `bundle` into `dst`, cut from the bundle's .resource
+/// node, and lists their metadata in `dst/video_clips.json`
+pub fn export(bundle: &AssetBundle, dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut summaries = Vec::new();
+    let mut count = 0;
+    for asset in bundle.assets() {
+        for obj in asset.objects() {
+            if asset.class_id(obj) != Some(VideoClip::CLASS_ID) {
+                continue;
+            }
+            let clip = match asset.object_value(obj).and_then(|v| VideoClip::from_value(&v)) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("{} {}: {}", asset.name(), obj.path_id, e);
+                    continue;
+                },
+            };
+            let data = match &clip.resource {
+                Some(r) => r.load(bundle),
+                None => Err("no external resource".into()),
+            };
+            let mut summary = VideoSummary{ clip, container: None, codec: None, file: None, error: None };
+            match data {
+                Ok(data) => {
+                    let (container, codec) = sniff(data);
+                    let path = export::unique_path(dst, &summary.clip.name, container);
+                    fs::write(&path, data)?;
+                    info!("{}x{} {:?} {:?}", summary.clip.width, summary.clip.height, codec, path);
+                    count += 1;
+                    summary.container = Some(container);
+                    summary.codec = codec;
+                    summary.file = path.file_name().map(|f| f.to_string_lossy().into_owned());
+                },
+                Err(e) => {
+                    warn!("{}: {}", summary.clip.name, e);
+                    summary.error = Some(e.to_string());
+                },
+            }
+            summaries.push(summary);
+        }
+    }
+    fs::write(dst.join("video_clips.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxed(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = ((8 + payload.len()) as u32).to_be_bytes().to_vec();
+        b.extend(kind);
+        b.extend(payload);
+        b
+    }
+
+    #[test]
+    fn sniff_containers() {
+        // the codec sits in the moov box written after the media data
+        let mut mp4 = boxed(b"ftyp", b"isom\0\0\0\0");
+        mp4.extend(boxed(b"mdat", b"vp09hvc1"));
+        mp4.extend(boxed(b"moov", &boxed(b"trak", b"....avc1....")));
+        assert_eq!(sniff(&mp4), ("mp4", Some("H.264")));
+        assert_eq!(sniff(&boxed(b"ftyp", b"qt  ")), ("mov", None));
+
+        let mut webm = EBML_MAGIC.to_vec();
+        webm.extend(b"\x86\x85V_VP9");
+        assert_eq!(sniff(&webm), ("webm", Some("VP9")));
+        assert_eq!(sniff(b"RIFF\0\0\0\0AVI LIST"), ("avi", None));
+        assert_eq!(sniff(b"\0\0"), ("bin", None));
+    }
+
+    #[test]
+    fn read_metadata() {
+        let fields: Vec<(&str, ObjectValue)> = vec![
+            ("m_Name", ObjectValue::String("intro".to_string())),
+            ("Width", ObjectValue::UInt(1280)),
+            ("Height", ObjectValue::UInt(720)),
+            ("m_PixelAspecRatioNum", ObjectValue::UInt(1)),
+            ("m_PixelAspecRatioDen", ObjectValue::UInt(0)),
+            ("m_FrameRate", ObjectValue::Float(25.0)),
+            ("m_FrameCount", ObjectValue::UInt(50)),
+            ("m_AudioChannelCount", ObjectValue::Array(vec![ObjectValue::UInt(2)])),
+            ("m_AudioSampleRate", ObjectValue::Array(vec![ObjectValue::UInt(48000)])),
+            ("m_AudioLanguage", ObjectValue::Array(vec![ObjectValue::String(String::new())])),
+            ("m_ExternalResources", ObjectValue::Struct(vec![
+                ("m_Source".to_string(), ObjectValue::String("archive:/CAB-1/CAB-1.resource".to_string())),
+                ("m_Offset".to_string(), ObjectValue::UInt(0)),
+                ("m_Size".to_string(), ObjectValue::UInt(1024)),
+            ])),
+        ];
+        let v = ObjectValue::Struct(fields.into_iter().map(|(n, v)| (n.to_string(), v)).collect());
+        let clip = VideoClip::from_value(&v).unwrap();
+        assert_eq!((clip.width, clip.height, clip.pixel_aspect_ratio, clip.seconds), (1280, 720, None, 2.0));
+        assert_eq!((clip.audio_tracks[0].channels, clip.audio_tracks[0].sample_rate), (2, 48000));
+        assert_eq!(clip.audio_tracks[0].language, None);
+        assert_eq!(clip.resource.unwrap().file_name(), "CAB-1.resource");
+    }
+}
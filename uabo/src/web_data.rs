@@ -0,0 +1,224 @@
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use flate2::read::GzDecoder;
+use log::info;
+use serde::Serialize;
+
+use crate::asset_bundle::{self, Analysis, serialized_file_size};
+use crate::binary_reader::u32_at;
+use crate::unity_version::UnityVersion;
+use crate::Result;
+
+const SIGNATURE: &[u8] = b"UnityWebData1.0\0";
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// file of the package table
+#[derive(Serialize, Clone, Debug)]
+pub struct WebFile {
+    pub name: String,               // path in the build, e.g. data.unity3d or Il2CppData/Metadata/global-metadata.dat
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// UnityWebData1.0 package of a WebGL build (`*.data`), held decompressed
+pub struct WebData {
+    compression: Option<&'static str>,
+    files: Vec<WebFile>,
+    data: Vec<u8>,
+}
+
+/// `data` with its gzip or Brotli wrapper removed, and the wrapper's name
+///
+/// Brotli has no magic, so anything that is neither a package, a bundle nor gzip is
+/// tried as Brotli.
+pub fn decompress(data: Vec<u8>) -> Result<(Vec<u8>, Option<&'static str>)> {
+    if data.starts_with(SIGNATURE) || data.starts_with(b"UnityFS\0") {
+        return Ok((data, None));
+    }
+    let mut out = Vec::new();
+    match data.starts_with(GZIP_MAGIC) {
+        true => {
+            GzDecoder::new(data.as_slice()).read_to_end(&mut out).map_err(|e| format!("gzip: {}", e))?;
+            Ok((out, Some("gzip")))
+        },
+        false => {
+            brotli_decompressor::Decompressor::new(data.as_slice(), 4096).read_to_end(&mut out)
+                .map_err(|e| format!("neither a UnityWebData package, gzip nor Brotli: {}", e))?;
+            Ok((out, Some("brotli")))
+        },
+    }
+}
+
+impl WebData {
+    /// whether `path` holds a package, plain or compressed (`.data`, `.data.gz`, `.data.br`, `.unityweb`)
+    pub fn is_web_data(path: &Path) -> bool {
+        let mut magic = [0u8; 16];
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        path.is_file() && (name.ends_with(".br") || name.ends_with(".unityweb")
+            || File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok()
+                && (magic.starts_with(SIGNATURE) || magic.starts_with(GZIP_MAGIC)))
+    }
+
+    pub fn open(path: &Path) -> Result<WebData> {
+        let data = fs::read(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let (data, compression) = decompress(data).map_err(|e| format!("{:?}: {}", path, e))?;
+        WebData::parse(data, compression).map_err(|e| format!("{:?}: {}", path, e).into())
+    }
+
+    /// reads the file table of the decompressed package `data`
+    pub fn parse(data: Vec<u8>, compression: Option<&'static str>) -> Result<WebData> {
+        if !data.starts_with(SIGNATURE) {
+            return Err("not a UnityWebData1.0 package".into());
+        }
+        // the table runs up to the data of the first file
+        let table_end = u32_at(&data, SIGNATURE.len()).ok_or("truncated table")? as usize;
+        let mut pos = SIGNATURE.len() + 4;
+        let mut files = Vec::new();
+        while pos < table_end {
+            let truncated = || format!("table entry at {} is truncated", pos);
+            let offset = u32_at(&data, pos).ok_or_else(truncated)?;
+            let size = u32_at(&data, pos + 4).ok_or_else(truncated)?;
+            let name_len = u32_at(&data, pos + 8).ok_or_else(truncated)? as usize;
+            let name = data.get(pos + 12..pos + 12 + name_len).ok_or_else(truncated)?;
+            if offset as u64 + size as u64 > data.len() as u64 {
+                return Err(format!("{} runs past the end of the package", String::from_utf8_lossy(name)).into());
+            }
+            files.push(WebFile{ name: String::from_utf8_lossy(name).into_owned(), offset, size });
+            pos += 12 + name_len;
+        }
+        Ok(WebData{ compression, files, data })
+    }
+
+    pub fn compression(&self) -> Option<&'static str> {
+        self.compression
+    }
+
+    pub fn files(&self) -> &[WebFile] {
+        &self.files
+    }
+
+    pub fn file(&self, name: &str) -> Option<&WebFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    pub fn read(&self, file: &WebFile) -> &[u8] {
+        &self.data[file.offset as usize..(file.offset + file.size) as usize]
+    }
+
+    /// data.unity3d, the top level serialized files and Resources/ of the build, by file name
+    pub fn player_files(&self) -> Vec<(String, Vec<u8>)> {
+        self.files.iter()
+            .map(|f| (f.name.strip_prefix("Resources/").unwrap_or(&f.name), f))
+            .filter(|(name, _)| !name.contains('/'))
+            .map(|(name, f)| (name.to_string(), self.read(f).to_vec()))
+            .collect()
+    }
+}
+
+/// Unity content of a package file, row of web.json
+#[derive(Serialize)]
+struct FileSummary {
+    #[serde(flatten)]
+    file: WebFile,
+    kind: &'static str,             // bundle, serialized, resource, other
+    #[serde(flatten)]
+    analysis: Analysis,
+}
+
+/// web.json
+#[derive(Serialize)]
+struct WebReport {
+    compression: Option<&'static str>,
+    files: Vec<FileSummary>,
+}
+
+/// lists every file of `web` in `dst/web.json`, analysing its bundles and serialized files
+pub fn export(web: &WebData, dst: &Path, unity_version: Option<&UnityVersion>) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut files = Vec::new();
+    for file in web.files() {
+        let data = web.read(file);
+        let file_name = file.name.rsplit('/').next().unwrap_or(&file.name);
+        let kind = match () {
+            _ if data.starts_with(b"UnityFS\0") => "bundle",
+            _ if serialized_file_size(data) == Some(data.len() as u64) => "serialized",
+            _ if file_name.ends_with(".resS") || file_name.ends_with(".resource") => "resource",
+            _ => "other",
+        };
+        let analysis = asset_bundle::analyse(kind, &file.name, || Ok(Cursor::new(data)), unity_version);
+        let summary = FileSummary{ file: file.clone(), kind, analysis };
+        info!("{} {} {:?}", kind, file.name, summary.analysis.objects);
+        files.push(summary);
+    }
+    let count = files.iter().filter(|f| f.kind == "bundle" || f.kind == "serialized").count();
+    fs::write(dst.join("web.json"), serde_json::to_string_pretty(&WebReport{ compression: web.compression(), files })?)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use super::*;
+
+    /// package of `(name, contents)`
+    fn package(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let table_end = SIGNATURE.len() + 4 + files.iter().map(|(n, _)| 12 + n.len()).sum::<usize>();
+        let mut out = SIGNATURE.to_vec();
+        out.extend((table_end as u32).to_le_bytes());
+        let mut offset = table_end;
+        for (name, contents) in files {
+            out.extend((offset as u32).to_le_bytes());
+            out.extend((contents.len() as u32).to_le_bytes());
+            out.extend((name.len() as u32).to_le_bytes());
+            out.extend(name.as_bytes());
+            offset += contents.len();
+        }
+        for (_, contents) in files {
+            out.extend(*contents);
+        }
+        out
+    }
+
+    #[test]
+    fn parse_file_table() {
+        let web = WebData::parse(package(&[
+            ("data.unity3d", b"UnityFS\0"),
+            ("Resources/unity_builtin_extra", b"extra"),
+            ("Il2CppData/Metadata/global-metadata.dat", b"meta"),
+        ]), None).unwrap();
+
+        assert_eq!(web.files().iter().map(|f| (f.name.as_str(), f.size)).collect::<Vec<_>>(), vec![
+            ("data.unity3d", 8),
+            ("Resources/unity_builtin_extra", 5),
+            ("Il2CppData/Metadata/global-metadata.dat", 4),
+        ]);
+        assert_eq!(web.read(web.file("Il2CppData/Metadata/global-metadata.dat").unwrap()), b"meta");
+        assert_eq!(web.player_files().into_iter().map(|(n, _)| n).collect::<Vec<_>>(), vec!["data.unity3d", "unity_builtin_extra"]);
+    }
+
+    #[test]
+    fn parse_rejects_bad_tables() {
+        let mut data = package(&[("data.unity3d", b"UnityFS\0")]);
+        assert!(WebData::parse(data[1..].to_vec(), None).is_err());
+        // size running past the end
+        data[SIGNATURE.len() + 8] = 9;
+        assert!(WebData::parse(data.clone(), None).is_err());
+        data.truncate(SIGNATURE.len() + 10);
+        assert!(WebData::parse(data, None).is_err());
+    }
+
+    #[test]
+    fn decompress_gzip() {
+        let data = package(&[("data.unity3d", b"UnityFS\0")]);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&data).unwrap();
+
+        assert_eq!(decompress(gz.finish().unwrap()).unwrap(), (data.clone(), Some("gzip")));
+        assert_eq!(decompress(data.clone()).unwrap(), (data, None));
+        assert!(decompress(vec![0x1f, 0x8b, 8, 0]).is_err());
+        assert!(decompress(b"neither".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+/// `len` bytes of a file from `start`, read and sought in place
+///
+/// Positions, including `SeekFrom::End`, are relative to the window.
+pub struct Window {
+    file: BufReader<File>,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Window {
+    pub fn new(mut file: BufReader<File>, start: u64, len: u64) -> io::Result<Window> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Window{ file, start, len, pos: 0 })
+    }
+
+    /// the same window from its start, sharing the file cursor so only one of both may be used
+    pub fn try_clone(&self) -> io::Result<Window> {
+        Window::new(BufReader::new(self.file.get_ref().try_clone()?), self.start, self.len)
+    }
+}
+
+impl Read for Window {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (buf.len() as u64).min(self.len.saturating_sub(self.pos)) as usize;
+        let n = self.file.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Window {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let pos = match from {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::Current(d) => self.pos as i64 + d,
+            SeekFrom::End(d) => self.len as i64 + d,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the window start"));
+        }
+        self.pos = pos as u64;
+        self.file.seek(SeekFrom::Start(self.start + self.pos))?;
+        Ok(self.pos)
+    }
+}
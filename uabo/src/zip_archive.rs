@@ -0,0 +1,553 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use flate2::read::DeflateDecoder;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::asset_bundle::{self, Analysis, serialized_file_size};
+use crate::binary_reader::{u16_at, u32_at, u64_at};
+use crate::unity_version::UnityVersion;
+use crate::window::Window;
+use crate::Result;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// end of central directory record plus the longest comment
+const END_SEARCH: u64 = 22 + 0xffff;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// file of the central directory
+#[derive(Serialize, Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: u16,                // 0 stored, 8 deflated
+    pub compressed_size: u64,
+    pub size: u64,
+    pub crc32: u32,
+    pub encrypted: bool,
+    #[serde(skip)]
+    header_offset: u64,             // of the local header
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+/// ZIP, APK or OBB archive read through its central directory, without extracting
+pub struct ZipArchive {
+    path: PathBuf,
+    entries: Vec<ZipEntry>,
+}
+
+/// contents of an entry: stored entries stay in the archive, deflated ones are inflated as they
+/// are read or, for parsing, into memory
+pub enum EntryReader {
+    Stored(Window),
+    Inflated(Inflater),
+    Buffered(Cursor<Vec<u8>>),
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            EntryReader::Stored(w) => w.read(buf),
+            EntryReader::Inflated(i) => i.read(buf),
+            EntryReader::Buffered(c) => c.read(buf),
+        }
+    }
+}
+
+impl Seek for EntryReader {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        match self {
+            EntryReader::Stored(w) => w.seek(from),
+            EntryReader::Inflated(i) => i.seek(from),
+            EntryReader::Buffered(c) => c.seek(from),
+        }
+    }
+}
+
+/// deflated entry, streamed without holding it in memory
+///
+/// Seeks only move the position; the next read inflates forward to it, restarting from the
+/// entry start when it lies behind. The inflated length is learnt by a first pass to the end.
+pub struct Inflater {
+    decoder: DeflateDecoder<Window>,
+    inflated: u64,                  // bytes the decoder has produced
+    pos: u64,                       // position of the next read
+    len: Option<u64>,               // inflated length, once the end has been reached
+}
+
+impl Inflater {
+    fn new(window: Window) -> Inflater {
+        Inflater{ decoder: DeflateDecoder::new(window), inflated: 0, pos: 0, len: None }
+    }
+
+    /// inflates up to `pos`, restarting when the decoder is past it
+    fn sync(&mut self) -> io::Result<()> {
+        if self.pos < self.inflated {
+            let window = self.decoder.get_ref().try_clone()?;
+            self.decoder.reset(window);
+            self.inflated = 0;
+        }
+        let skip = self.pos - self.inflated;
+        let skipped = io::copy(&mut (&mut self.decoder).take(skip), &mut io::sink())?;
+        self.inflated += skipped;
+        if skipped < skip {
+            self.len = Some(self.inflated);
+        }
+        Ok(())
+    }
+
+    /// inflated length, inflating the rest of the entry the first time
+    fn len(&mut self) -> io::Result<u64> {
+        if self.len.is_none() {
+            let pos = self.pos;
+            self.pos = u64::MAX;
+            self.sync()?;
+            self.pos = pos;
+        }
+        Ok(self.len.unwrap_or(self.inflated))
+    }
+}
+
+impl Read for Inflater {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.sync()?;
+        if self.inflated < self.pos {
+            return Ok(0);
+        }
+        let n = self.decoder.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.len = Some(self.inflated);
+        }
+        self.inflated += n as u64;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Inflater {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let pos = match from {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::Current(d) => self.pos as i64 + d,
+            SeekFrom::End(d) => self.len()? as i64 + d,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the entry start"));
+        }
+        if pos as u64 > self.len()? {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "seek past the entry end"));
+        }
+        self.pos = pos as u64;
+        Ok(self.pos)
+    }
+}
+
+impl ZipArchive {
+    /// whether `path` is a file starting with a local header or an empty archive
+    pub fn is_archive(path: &Path) -> bool {
+        let mut magic = [0u8; 4];
+        path.is_file() && File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok()
+            && matches!(u32::from_le_bytes(magic), LOCAL_HEADER_SIGNATURE | END_SIGNATURE)
+    }
+
+    pub fn open(path: &Path) -> Result<ZipArchive> {
+        let mut file = File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let file_len = file.seek(SeekFrom::End(0))?;
+
+        // the end record is the last signature in the archive tail
+        let tail_len = file_len.min(END_SEARCH);
+        let mut tail = vec![0u8; tail_len as usize];
+        file.seek(SeekFrom::Start(file_len - tail_len))?;
+        file.read_exact(&mut tail)?;
+        let end = (0..tail.len().saturating_sub(21)).rev()
+            .find(|&p| u32_at(&tail, p) == Some(END_SIGNATURE))
+            .ok_or("no end of central directory record")?;
+        let mut count = u16_at(&tail, end + 10).unwrap() as u64;
+        let mut dir_size = u32_at(&tail, end + 12).unwrap() as u64;
+        let mut dir_offset = u32_at(&tail, end + 16).unwrap() as u64;
+
+        // ZIP64 keeps the real values in a record found through the locator before the end record
+        if let Some(locator) = end.checked_sub(20).filter(|&l| u32_at(&tail, l) == Some(ZIP64_LOCATOR_SIGNATURE)) {
+            let record_offset = u64_at(&tail, locator + 8).unwrap();
+            let mut record = [0u8; 56];
+            file.seek(SeekFrom::Start(record_offset))?;
+            file.read_exact(&mut record)?;
+            if u32_at(&record, 0) != Some(ZIP64_END_SIGNATURE) {
+                return Err("invalid ZIP64 end of central directory record".into());
+            }
+            count = u64_at(&record, 32).unwrap();
+            dir_size = u64_at(&record, 40).unwrap();
+            dir_offset = u64_at(&record, 48).unwrap();
+        }
+        if dir_offset.checked_add(dir_size).map(|e| e > file_len).unwrap_or(true) {
+            return Err(format!("central directory {}..+{} is outside of the archive", dir_offset, dir_size).into());
+        }
+
+        let mut dir = vec![0u8; dir_size as usize];
+        file.seek(SeekFrom::Start(dir_offset))?;
+        file.read_exact(&mut dir)?;
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        for _ in 0..count {
+            if u32_at(&dir, pos) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(format!("invalid central directory header at {}", dir_offset + pos as u64).into());
+            }
+            let header = dir.get(pos..pos + 46).ok_or("central directory truncated")?;
+            let name_len = u16_at(header, 28).unwrap() as usize;
+            let extra_len = u16_at(header, 30).unwrap() as usize;
+            let comment_len = u16_at(header, 32).unwrap() as usize;
+            let name = dir.get(pos + 46..pos + 46 + name_len).ok_or("central directory truncated")?;
+            let extra = dir.get(pos + 46 + name_len..pos + 46 + name_len + extra_len).unwrap_or_default();
+            let mut entry = ZipEntry{
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(header, 10).unwrap(),
+                compressed_size: u32_at(header, 20).unwrap() as u64,
+                size: u32_at(header, 24).unwrap() as u64,
+                crc32: u32_at(header, 16).unwrap(),
+                encrypted: u16_at(header, 8).unwrap() & 1 != 0,
+                header_offset: u32_at(header, 42).unwrap() as u64,
+            };
+            // saturated fields continue in the ZIP64 extra field, in this order
+            let mut e = 0;
+            while e + 4 <= extra.len() {
+                let id = u16_at(extra, e).unwrap();
+                let len = u16_at(extra, e + 2).unwrap() as usize;
+                if id == ZIP64_EXTRA_ID {
+                    let mut field = e + 4;
+                    for value in [&mut entry.size, &mut entry.compressed_size, &mut entry.header_offset] {
+                        if *value == u32::MAX as u64 {
+                            *value = u64_at(extra, field).ok_or("ZIP64 extra field truncated")?;
+                            field += 8;
+                        }
+                    }
+                }
+                e += 4 + len;
+            }
+            entries.push(entry);
+            pos += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(ZipArchive{ path: path.to_path_buf(), entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// compressed bytes of `entry`, after its local header
+    fn window(&self, entry: &ZipEntry) -> Result<Window> {
+        if entry.encrypted {
+            return Err(format!("{} is encrypted", entry.name).into());
+        }
+        let mut file = BufReader::new(File::open(&self.path)?);
+        let mut header = [0u8; 30];
+        file.seek(SeekFrom::Start(entry.header_offset))?;
+        file.read_exact(&mut header)?;
+        if u32_at(&header, 0) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(format!("invalid local header of {}", entry.name).into());
+        }
+        // the local name and extra field may differ from the central directory ones
+        let start = entry.header_offset + 30 + u16_at(&header, 26).unwrap() as u64 + u16_at(&header, 28).unwrap() as u64;
+        Ok(Window::new(file, start, entry.compressed_size)?)
+    }
+
+    /// reader over the contents of `entry`
+    pub fn reader(&self, entry: &ZipEntry) -> Result<EntryReader> {
+        let window = self.window(entry)?;
+        match entry.method {
+            METHOD_STORED => Ok(EntryReader::Stored(window)),
+            METHOD_DEFLATED => Ok(EntryReader::Inflated(Inflater::new(window))),
+            m => Err(format!("{}: unsupported compression method {}", entry.name, m).into()),
+        }
+    }
+
+    /// reader of `entry` for parsers, which seek back and forth: stored entries are read in place,
+    /// deflated ones are inflated into memory once
+    pub fn seekable(&self, entry: &ZipEntry) -> Result<EntryReader> {
+        match entry.method {
+            METHOD_DEFLATED => Ok(EntryReader::Buffered(Cursor::new(self.read(entry)?))),
+            _ => self.reader(entry),
+        }
+    }
+
+    /// leading `len` bytes of `entry`, inflating only what they need
+    pub fn head(&self, entry: &ZipEntry, len: usize) -> Result<Vec<u8>> {
+        let mut head = Vec::with_capacity(len);
+        self.reader(entry)?.take(len as u64).read_to_end(&mut head)
+            .map_err(|e| format!("{}: {}", entry.name, e))?;
+        Ok(head)
+    }
+
+    /// whole contents of `entry`, held in memory; the buffer grows with what is actually read,
+    /// not with the size the central directory claims
+    pub fn read(&self, entry: &ZipEntry) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.reader(entry)?.read_to_end(&mut data)
+            .map_err(|e| format!("{}: {}", entry.name, e))?;
+        Ok(data)
+    }
+}
+
+/// Unity content of an archive entry, row of archive.json
+#[derive(Serialize)]
+struct EntrySummary {
+    #[serde(flatten)]
+    entry: ZipEntry,
+    kind: &'static str,             // bundle, serialized, split, resource
+    #[serde(flatten)]
+    analysis: Analysis,
+}
+
+/// directory of the player's Data folder inside `archive` (`assets/bin/Data/` of an APK)
+pub fn player_data_dir(archive: &ZipArchive) -> Option<String> {
+    archive.entries().iter()
+        .filter_map(|e| e.name.rsplit_once('/').filter(|(_, f)| *f == "globalgamemanagers" || *f == "data.unity3d").map(|(d, _)| d))
+        .min_by_key(|d| d.len())
+        .map(|d| format!("{}/", d))
+}
+
+/// files of the player's Data folder at `dir` inside `archive`, with Resources/ below it
+pub fn player_files(archive: &ZipArchive, dir: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
+    for entry in archive.entries() {
+        let name = match entry.name.strip_prefix(dir) {
+            Some(n) => n.strip_prefix("Resources/").unwrap_or(n),
+            None => continue,
+        };
+        if entry.is_dir() || name.contains('/') {
+            continue;
+        }
+        files.push((name.to_string(), archive.read(entry)?));
+    }
+    Ok(files)
+}
+
+/// scans every entry of `archive` for bundles and serialized files, analysing stored ones in place
+/// and deflated ones once inflated, and lists them in `dst/archive.json`
+pub fn export(archive: &ZipArchive, dst: &Path, unity_version: Option<&UnityVersion>) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut summaries = Vec::new();
+    for entry in archive.entries().iter().filter(|e| !e.is_dir()) {
+        let file_name = entry.name.rsplit('/').next().unwrap_or(&entry.name);
+        let head = match archive.head(entry, 48) {
+            Ok(h) => h,
+            Err(e) => {
+                warn!("{}: {}", entry.name, e);
+                continue;
+            },
+        };
+        let split = file_name.rsplit_once(".split").map(|(_, n)| n.parse::<u32>().is_ok()).unwrap_or(false);
+        let kind = match () {
+            _ if head.starts_with(b"UnityFS\0") => "bundle",
+            _ if serialized_file_size(&head) == Some(entry.size) => "serialized",
+            _ if split => "split",
+            _ if file_name.ends_with(".resS") || file_name.ends_with(".resource") => "resource",
+            _ => continue,
+        };
+        let analysis = asset_bundle::analyse(kind, &entry.name, || archive.seekable(entry), unity_version);
+        let summary = EntrySummary{ entry: entry.clone(), kind, analysis };
+        info!("{} {} {:?}", kind, entry.name, summary.analysis.objects);
+        summaries.push(summary);
+    }
+    fs::write(dst.join("archive.json"), serde_json::to_string_pretty(&summaries)?)?;
+    Ok(summaries.iter().filter(|s| s.kind == "bundle" || s.kind == "serialized").count())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use super::*;
+
+    /// archive of `(name, method, contents)`, with ZIP64 records and extra fields when `zip64`
+    fn zip(files: &[(&str, u16, &[u8])], zip64: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut dir = Vec::new();
+        for (name, method, contents) in files {
+            let data = match *method {
+                METHOD_DEFLATED => {
+                    let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+                    e.write_all(contents).unwrap();
+                    e.finish().unwrap()
+                },
+                _ => contents.to_vec(),
+            };
+            let crc = crc32fast::hash(contents);
+            let offset = out.len() as u32;
+            out.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            out.extend([20, 0, 0, 0]);
+            out.extend(method.to_le_bytes());
+            out.extend([0; 4]);
+            out.extend(crc.to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((contents.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend([0; 2]);
+            out.extend(name.as_bytes());
+            out.extend(&data);
+
+            let mut extra = Vec::new();
+            let (size, compressed_size, header_offset) = match zip64 {
+                true => {
+                    extra.extend(ZIP64_EXTRA_ID.to_le_bytes());
+                    extra.extend(24u16.to_le_bytes());
+                    extra.extend((contents.len() as u64).to_le_bytes());
+                    extra.extend((data.len() as u64).to_le_bytes());
+                    extra.extend((offset as u64).to_le_bytes());
+                    (u32::MAX, u32::MAX, u32::MAX)
+                },
+                false => (contents.len() as u32, data.len() as u32, offset),
+            };
+            dir.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            dir.extend([20, 0, 20, 0, 0, 0]);
+            dir.extend(method.to_le_bytes());
+            dir.extend([0; 4]);
+            dir.extend(crc.to_le_bytes());
+            dir.extend(compressed_size.to_le_bytes());
+            dir.extend(size.to_le_bytes());
+            dir.extend((name.len() as u16).to_le_bytes());
+            dir.extend((extra.len() as u16).to_le_bytes());
+            dir.extend([0; 10]);
+            dir.extend(header_offset.to_le_bytes());
+            dir.extend(name.as_bytes());
+            dir.extend(&extra);
+        }
+        let dir_offset = out.len();
+        out.extend(&dir);
+        let count = files.len() as u16;
+        if zip64 {
+            let record_offset = out.len() as u64;
+            out.extend(ZIP64_END_SIGNATURE.to_le_bytes());
+            out.extend(44u64.to_le_bytes());
+            out.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            out.extend((count as u64).to_le_bytes());
+            out.extend((count as u64).to_le_bytes());
+            out.extend((dir.len() as u64).to_le_bytes());
+            out.extend((dir_offset as u64).to_le_bytes());
+            out.extend(ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            out.extend([0; 4]);
+            out.extend(record_offset.to_le_bytes());
+            out.extend(1u32.to_le_bytes());
+        }
+        out.extend(END_SIGNATURE.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend(count.to_le_bytes());
+        out.extend(count.to_le_bytes());
+        out.extend((dir.len() as u32).to_le_bytes());
+        let dir_offset = match zip64 {
+            true => u32::MAX,
+            false => dir_offset as u32,
+        };
+        out.extend(dir_offset.to_le_bytes());
+        out.extend(3u16.to_le_bytes());
+        out.extend(b"apk");
+        out
+    }
+
+    fn open(name: &str, data: &[u8]) -> ZipArchive {
+        let path = std::env::temp_dir().join(format!("uabo-{}-{}.zip", name, std::process::id()));
+        fs::write(&path, data).unwrap();
+        ZipArchive::open(&path).unwrap()
+    }
+
+    fn close(archive: ZipArchive) {
+        fs::remove_file(&archive.path).unwrap();
+    }
+
+    #[test]
+    fn central_directory() {
+        let text: Vec<u8> = (0..5000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        let archive = open("central", &zip(&[
+            ("assets/", METHOD_STORED, b""),
+            ("assets/level0", METHOD_STORED, b"stored"),
+            ("assets/data.unity3d", METHOD_DEFLATED, &text),
+        ], false));
+
+        let entries = archive.entries();
+        assert_eq!(entries.iter().map(|e| (e.name.as_str(), e.method, e.size)).collect::<Vec<_>>(), vec![
+            ("assets/", METHOD_STORED, 0),
+            ("assets/level0", METHOD_STORED, 6),
+            ("assets/data.unity3d", METHOD_DEFLATED, text.len() as u64),
+        ]);
+        assert!(entries[0].is_dir() && !entries[1].is_dir());
+        assert!(entries[2].compressed_size < entries[2].size);
+        assert_eq!(archive.read(&entries[1]).unwrap(), b"stored");
+        assert_eq!(archive.read(&entries[2]).unwrap(), text);
+        assert_eq!(archive.head(&entries[2], 8).unwrap(), &text[..8]);
+        assert_eq!(player_data_dir(&archive).as_deref(), Some("assets/"));
+        close(archive);
+    }
+
+    #[test]
+    fn inflater_seeks_both_ways() {
+        let text: Vec<u8> = (0..20000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let archive = open("seek", &zip(&[("a.bin", METHOD_DEFLATED, &text)], false));
+        let mut r = archive.reader(&archive.entries()[0]).unwrap();
+        let mut buf = [0u8; 4];
+        for (from, at) in [(SeekFrom::Start(15000), 15000), (SeekFrom::End(-4), 19996), (SeekFrom::Start(3), 3), (SeekFrom::Current(100), 107)] {
+            assert_eq!(r.seek(from).unwrap(), at);
+            r.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, text[at as usize..at as usize + 4]);
+        }
+        assert!(r.seek(SeekFrom::Start(20001)).is_err());
+        close(archive);
+    }
+
+    #[test]
+    fn inflater_caches_its_length() {
+        let text: Vec<u8> = (0..20000u32).map(|i| (i * 3 % 256) as u8).collect();
+        let archive = open("length", &zip(&[("a.bin", METHOD_DEFLATED, &text)], false));
+        let mut inflater = match archive.reader(&archive.entries()[0]).unwrap() {
+            EntryReader::Inflated(i) => i,
+            _ => panic!("deflated entry not inflated"),
+        };
+        let mut buf = [0u8; 4];
+        inflater.read_exact(&mut buf).unwrap();
+        // a remaining() style round trip leaves the decoder where it was
+        assert_eq!(inflater.seek(SeekFrom::End(0)).unwrap(), 20000);
+        assert_eq!(inflater.len, Some(20000));
+        inflater.seek(SeekFrom::Start(4)).unwrap();
+        inflater.seek(SeekFrom::End(0)).unwrap();
+        inflater.seek(SeekFrom::Start(4)).unwrap();
+        assert_eq!(inflater.inflated, 20000);
+        inflater.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, text[4..8]);
+        assert_eq!(inflater.inflated, 8);
+        assert!(matches!(archive.seekable(&archive.entries()[0]).unwrap(), EntryReader::Buffered(_)));
+        close(archive);
+    }
+
+    #[test]
+    fn zip64_extra_field() {
+        let archive = open("zip64", &zip(&[
+            ("globalgamemanagers", METHOD_STORED, b"ggm"),
+            ("data.unity3d", METHOD_DEFLATED, &[9; 300]),
+        ], true));
+
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].size, entries[0].compressed_size, entries[0].header_offset), (3, 3, 0));
+        assert_eq!(entries[1].size, 300);
+        assert_eq!(entries[1].header_offset, 30 + 18 + 3);
+        assert_eq!(archive.read(&entries[0]).unwrap(), b"ggm");
+        assert_eq!(archive.read(&entries[1]).unwrap(), vec![9; 300]);
+        close(archive);
+    }
+}